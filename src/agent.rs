@@ -0,0 +1,485 @@
+//! `enject agent`: a per-user background process that holds derived store keys in
+//! memory for a while, so a password is typed once per session rather than once per
+//! command. It is only ever given keys, never passwords, and only answers
+//! connections from the user it runs as.
+//!
+//! One request per connection, one line each way: `GET <fingerprint>` answers
+//! `KEY <hex>` or `NONE`; `PUT <fingerprint> <hex>`, `FORGET-ALL` and `STOP` answer `OK`.
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::Result;
+use zeroize::Zeroizing;
+
+use crate::store::password::KEY_LEN;
+
+/// Set to anything but an empty string or `0` to use the agent.
+pub const AGENT_ENV: &str = "ENJECT_AGENT";
+
+/// How long the agent keeps a key when `--ttl` isn't given.
+pub const DEFAULT_TTL: Duration = Duration::from_secs(15 * 60);
+
+/// Whether commands should ask the agent for keys and hand it new ones: with
+/// `ENJECT_AGENT` set, or `agent = true` in the store's config.
+pub fn enabled(config_flag: bool) -> bool {
+    config_flag || std::env::var_os(AGENT_ENV).is_some_and(|v| !v.is_empty() && v != "0")
+}
+
+/// `$XDG_RUNTIME_DIR/enject`, or `/tmp/enject-<uid>` without one.
+fn socket_dir() -> PathBuf {
+    match std::env::var_os("XDG_RUNTIME_DIR").filter(|v| !v.is_empty()) {
+        Some(runtime) => PathBuf::from(runtime).join("enject"),
+        None => std::env::temp_dir().join(format!("enject-{}", unix::uid())),
+    }
+}
+
+fn socket_path() -> PathBuf {
+    socket_dir().join("agent.sock")
+}
+
+/// The key the agent holds for the store with this salt fingerprint. An agent that
+/// isn't running, or doesn't answer, just means there is none.
+pub fn fetch_key(fingerprint: &str) -> Option<Zeroizing<[u8; KEY_LEN]>> {
+    let reply = unix::request(&socket_path(), &format!("GET {}", fingerprint)).ok()?;
+    let hex_key = reply.strip_prefix("KEY ")?.trim_end();
+    let mut key = Zeroizing::new([0u8; KEY_LEN]);
+    hex::decode_to_slice(hex_key, &mut key[..]).ok()?;
+    Some(key)
+}
+
+/// Hand the agent a store key to hold until its TTL runs out.
+pub fn store_key(fingerprint: &str, key: &[u8; KEY_LEN]) -> Result<()> {
+    let hex_key = Zeroizing::new(hex::encode(key));
+    let line = Zeroizing::new(format!("PUT {} {}", fingerprint, hex_key.as_str()));
+    unix::expect_ok(&unix::request(&socket_path(), &line)?)
+}
+
+/// Make the agent drop every key it holds. Returns false if no agent is running.
+pub fn forget_all() -> Result<bool> {
+    match unix::request(&socket_path(), "FORGET-ALL") {
+        Ok(reply) => unix::expect_ok(&reply).map(|()| true),
+        Err(_) => Ok(false),
+    }
+}
+
+/// Stop a running agent, which drops its keys. Returns false if none was running.
+pub fn stop() -> Result<bool> {
+    match unix::request(&socket_path(), "STOP") {
+        Ok(reply) => unix::expect_ok(&reply).map(|()| true),
+        Err(_) => Ok(false),
+    }
+}
+
+/// Start the agent in the background and wait until it answers. Returns the socket
+/// it listens on.
+pub fn spawn(ttl: Duration) -> Result<PathBuf> {
+    let path = socket_path();
+    unix::spawn(&path, ttl)?;
+    Ok(path)
+}
+
+/// Run the agent in the foreground until it is stopped.
+pub fn serve(ttl: Duration) -> Result<()> {
+    unix::serve(&socket_dir(), &socket_path(), ttl)
+}
+
+#[cfg(unix)]
+mod unix {
+    use std::collections::HashMap;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::Shutdown;
+    use std::os::fd::AsRawFd;
+    use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::os::unix::process::CommandExt;
+    use std::path::Path;
+    use std::process::{Command, Stdio};
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+
+    use anyhow::{bail, Context, Result};
+    use zeroize::Zeroizing;
+
+    use crate::auth;
+    use crate::store::password::KEY_LEN;
+
+    /// How long either side waits on the other before giving up on a connection.
+    const IO_TIMEOUT: Duration = Duration::from_secs(2);
+    /// Longest request line: `PUT`, a fingerprint and a hex key fit easily.
+    const MAX_REQUEST: u64 = 256;
+    /// How often held keys are checked for expiry.
+    const EXPIRY_INTERVAL: Duration = Duration::from_secs(1);
+
+    pub fn uid() -> u32 {
+        // SAFETY: getuid(2) cannot fail and has no preconditions.
+        unsafe { libc::getuid() }
+    }
+
+    /// The user id of the process at the other end of `stream`.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn peer_uid(stream: &UnixStream) -> std::io::Result<u32> {
+        let mut cred = libc::ucred {
+            pid: 0,
+            uid: 0,
+            gid: 0,
+        };
+        let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+        // SAFETY: `cred` and `len` are valid for writes and `len` holds the size of `cred`.
+        let result = unsafe {
+            libc::getsockopt(
+                stream.as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_PEERCRED,
+                &mut cred as *mut libc::ucred as *mut libc::c_void,
+                &mut len,
+            )
+        };
+        if result != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(cred.uid)
+    }
+
+    /// The user id of the process at the other end of `stream`.
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    fn peer_uid(stream: &UnixStream) -> std::io::Result<u32> {
+        let mut uid = 0;
+        let mut gid = 0;
+        // SAFETY: `uid` and `gid` are valid for writes.
+        let result = unsafe { libc::getpeereid(stream.as_raw_fd(), &mut uid, &mut gid) };
+        if result != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(uid)
+    }
+
+    /// Send one request line and read the reply. Nothing is sent unless the socket's
+    /// directory passes `check_dir` and the process listening runs as this user, so
+    /// keys never go to an agent another user planted.
+    pub fn request(path: &Path, line: &str) -> std::io::Result<Zeroizing<String>> {
+        check_dir(path.parent().unwrap_or(Path::new("/")))?;
+        let mut stream = UnixStream::connect(path)?;
+        if peer_uid(&stream)? != uid() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::PermissionDenied,
+                format!("{} is served by another user", path.display()),
+            ));
+        }
+        stream.set_read_timeout(Some(IO_TIMEOUT))?;
+        stream.set_write_timeout(Some(IO_TIMEOUT))?;
+        stream.write_all(line.as_bytes())?;
+        stream.write_all(b"\n")?;
+        stream.shutdown(Shutdown::Write)?;
+        let mut reply = Zeroizing::new(String::new());
+        stream.read_to_string(&mut reply)?;
+        Ok(reply)
+    }
+
+    pub fn expect_ok(reply: &str) -> Result<()> {
+        match reply.trim_end() {
+            "OK" => Ok(()),
+            other => bail!("The enject agent refused the request: {}", other),
+        }
+    }
+
+    /// Create the socket directory, or check an existing one, so that only this user
+    /// can reach it: owned by us and closed to group and others.
+    fn prepare_dir(dir: &Path) -> Result<()> {
+        std::fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+        Ok(check_dir(dir)?)
+    }
+
+    /// Fail unless `dir` belongs to this user and is closed to group and others.
+    fn check_dir(dir: &Path) -> std::io::Result<()> {
+        let meta = std::fs::metadata(dir)?;
+        if meta.uid() != uid() || meta.mode() & 0o077 != 0 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::PermissionDenied,
+                format!(
+                    "Refusing to use {}: it must belong to you and be closed to other users (mode 0700).",
+                    dir.display()
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    /// A key the agent holds, zeroized when dropped.
+    struct Held {
+        key: Zeroizing<[u8; KEY_LEN]>,
+        expires: Instant,
+    }
+
+    type Keys = Arc<Mutex<HashMap<String, Held>>>;
+
+    pub fn serve(dir: &Path, path: &Path, ttl: Duration) -> Result<()> {
+        prepare_dir(dir)?;
+        if path.exists() {
+            if UnixStream::connect(path).is_ok() {
+                bail!(
+                    "An enject agent is already listening on {}.",
+                    path.display()
+                );
+            }
+            // Left behind by an agent that didn't shut down cleanly
+            std::fs::remove_file(path)?;
+        }
+        let listener = UnixListener::bind(path)
+            .with_context(|| format!("Failed to listen on {}", path.display()))?;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+
+        let keys: Keys = Arc::default();
+        let expiring = Arc::clone(&keys);
+        std::thread::spawn(move || loop {
+            std::thread::sleep(EXPIRY_INTERVAL);
+            if let Ok(mut keys) = expiring.lock() {
+                let now = Instant::now();
+                keys.retain(|_, held| held.expires > now);
+            }
+        });
+
+        let own_uid = uid();
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else { continue };
+            // Another user who got past the directory permissions still gets nothing
+            if peer_uid(&stream).ok() != Some(own_uid) {
+                continue;
+            }
+            if let Ok(Reply::Stop) = answer(stream, &keys, ttl) {
+                break;
+            }
+        }
+        if let Ok(mut keys) = keys.lock() {
+            keys.clear();
+        }
+        let _ = std::fs::remove_file(path);
+        Ok(())
+    }
+
+    enum Reply {
+        Continue,
+        Stop,
+    }
+
+    fn answer(mut stream: UnixStream, keys: &Keys, ttl: Duration) -> std::io::Result<Reply> {
+        stream.set_read_timeout(Some(IO_TIMEOUT))?;
+        stream.set_write_timeout(Some(IO_TIMEOUT))?;
+        let mut line = Zeroizing::new(String::new());
+        BufReader::new((&stream).take(MAX_REQUEST)).read_line(&mut line)?;
+
+        let mut parts = line.split_whitespace();
+        let mut keys = keys
+            .lock()
+            .map_err(|_| std::io::Error::other("key table poisoned"))?;
+        let (reply, next) = match (parts.next(), parts.next(), parts.next()) {
+            (Some("GET"), Some(fingerprint), None) => match keys.get(fingerprint) {
+                Some(held) if held.expires > Instant::now() => {
+                    let hex_key = Zeroizing::new(hex::encode(&held.key[..]));
+                    (
+                        Zeroizing::new(format!("KEY {}\n", hex_key.as_str())),
+                        Reply::Continue,
+                    )
+                }
+                _ => (Zeroizing::new("NONE\n".to_string()), Reply::Continue),
+            },
+            (Some("PUT"), Some(fingerprint), Some(hex_key)) => {
+                let mut key = Zeroizing::new([0u8; KEY_LEN]);
+                match hex::decode_to_slice(hex_key, &mut key[..]) {
+                    Ok(()) => {
+                        let expires = Instant::now() + ttl;
+                        keys.insert(fingerprint.to_string(), Held { key, expires });
+                        (Zeroizing::new("OK\n".to_string()), Reply::Continue)
+                    }
+                    Err(_) => (Zeroizing::new("ERR bad key\n".to_string()), Reply::Continue),
+                }
+            }
+            (Some("FORGET-ALL"), None, None) => {
+                keys.clear();
+                (Zeroizing::new("OK\n".to_string()), Reply::Continue)
+            }
+            (Some("STOP"), None, None) => {
+                keys.clear();
+                (Zeroizing::new("OK\n".to_string()), Reply::Stop)
+            }
+            _ => (
+                Zeroizing::new("ERR unknown request\n".to_string()),
+                Reply::Continue,
+            ),
+        };
+        drop(keys);
+        stream.write_all(reply.as_bytes())?;
+        Ok(next)
+    }
+
+    /// Re-run this executable as `enject agent --foreground` in its own process
+    /// group, detached from the terminal, and wait for it to answer on `path`.
+    pub fn spawn(path: &Path, ttl: Duration) -> Result<()> {
+        if UnixStream::connect(path).is_ok() {
+            bail!(
+                "An enject agent is already listening on {}.",
+                path.display()
+            );
+        }
+        let exe = std::env::current_exe().context("Failed to locate the enject executable")?;
+        let mut command = Command::new(exe);
+        command
+            .args(["agent", "--foreground", "--ttl"])
+            .arg(humantime::format_duration(ttl).to_string())
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .process_group(0);
+        // The agent holds derived keys only, never a password from the caller's shell
+        for var in auth::PASSWORD_ENV_VARS {
+            command.env_remove(var);
+        }
+        let mut child = command
+            .spawn()
+            .context("Failed to start the enject agent")?;
+
+        let started = Instant::now();
+        while started.elapsed() < IO_TIMEOUT {
+            if UnixStream::connect(path).is_ok() {
+                return Ok(());
+            }
+            if let Some(status) = child.try_wait()? {
+                bail!("The enject agent exited early ({}).", status);
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        bail!(
+            "The enject agent did not start listening on {}.",
+            path.display()
+        )
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use tempfile::TempDir;
+
+        /// An agent serving from a thread, with its socket in `dir`.
+        fn start(dir: &TempDir, ttl: Duration) -> std::path::PathBuf {
+            let sock_dir = dir.path().join("agent");
+            let path = sock_dir.join("agent.sock");
+            let serve_path = path.clone();
+            std::thread::spawn(move || serve(&sock_dir, &serve_path, ttl).unwrap());
+            let started = Instant::now();
+            while UnixStream::connect(&path).is_err() {
+                assert!(started.elapsed() < IO_TIMEOUT, "agent did not start");
+                std::thread::sleep(Duration::from_millis(10));
+            }
+            path
+        }
+
+        #[test]
+        fn test_put_get_forget_stop() {
+            let dir = TempDir::new().unwrap();
+            let path = start(&dir, Duration::from_secs(60));
+            let key = hex::encode([7u8; KEY_LEN]);
+
+            assert_eq!(request(&path, "GET abcd").unwrap().as_str(), "NONE\n");
+            expect_ok(&request(&path, &format!("PUT abcd {}", key)).unwrap()).unwrap();
+            assert_eq!(
+                request(&path, "GET abcd").unwrap().as_str(),
+                format!("KEY {}\n", key)
+            );
+            expect_ok(&request(&path, "FORGET-ALL").unwrap()).unwrap();
+            assert_eq!(request(&path, "GET abcd").unwrap().as_str(), "NONE\n");
+
+            expect_ok(&request(&path, "STOP").unwrap()).unwrap();
+            let started = Instant::now();
+            while path.exists() {
+                assert!(started.elapsed() < IO_TIMEOUT, "agent did not stop");
+                std::thread::sleep(Duration::from_millis(10));
+            }
+        }
+
+        #[test]
+        fn test_keys_expire() {
+            let dir = TempDir::new().unwrap();
+            let path = start(&dir, Duration::from_millis(50));
+            let key = hex::encode([7u8; KEY_LEN]);
+            expect_ok(&request(&path, &format!("PUT abcd {}", key)).unwrap()).unwrap();
+            std::thread::sleep(Duration::from_millis(100));
+            assert_eq!(request(&path, "GET abcd").unwrap().as_str(), "NONE\n");
+            request(&path, "STOP").unwrap();
+        }
+
+        #[test]
+        fn test_malformed_requests_are_refused() {
+            let dir = TempDir::new().unwrap();
+            let path = start(&dir, Duration::from_secs(60));
+            assert!(expect_ok(&request(&path, "PUT abcd nothex").unwrap()).is_err());
+            assert!(expect_ok(&request(&path, "HELLO").unwrap()).is_err());
+            request(&path, "STOP").unwrap();
+        }
+
+        #[test]
+        fn test_open_socket_dir_is_refused() {
+            let dir = TempDir::new().unwrap();
+            let open = dir.path().join("open");
+            std::fs::create_dir(&open).unwrap();
+            std::fs::set_permissions(&open, std::fs::Permissions::from_mode(0o755)).unwrap();
+            assert!(prepare_dir(&open).is_err());
+        }
+
+        #[test]
+        fn test_client_refuses_an_open_or_foreign_socket_dir() {
+            let dir = TempDir::new().unwrap();
+            let path = start(&dir, Duration::from_secs(60));
+            let sock_dir = path.parent().unwrap();
+            let key = hex::encode([7u8; KEY_LEN]);
+
+            std::fs::set_permissions(sock_dir, std::fs::Permissions::from_mode(0o770)).unwrap();
+            let err = request(&path, &format!("PUT abcd {}", key)).unwrap_err();
+            assert_eq!(err.kind(), std::io::ErrorKind::PermissionDenied);
+            std::fs::set_permissions(sock_dir, std::fs::Permissions::from_mode(0o700)).unwrap();
+
+            // Only root can hand the directory to another user
+            if uid() == 0 {
+                std::os::unix::fs::chown(sock_dir, Some(65534), None).unwrap();
+                assert!(request(&path, "GET abcd").is_err());
+                std::os::unix::fs::chown(sock_dir, Some(0), None).unwrap();
+            }
+
+            // Nothing was handed over
+            assert_eq!(request(&path, "GET abcd").unwrap().as_str(), "NONE\n");
+            request(&path, "STOP").unwrap();
+        }
+    }
+}
+
+#[cfg(not(unix))]
+mod unix {
+    use std::path::Path;
+    use std::time::Duration;
+
+    use anyhow::{bail, Result};
+    use zeroize::Zeroizing;
+
+    pub fn uid() -> u32 {
+        0
+    }
+
+    pub fn request(_path: &Path, _line: &str) -> std::io::Result<Zeroizing<String>> {
+        Err(std::io::ErrorKind::Unsupported.into())
+    }
+
+    pub fn expect_ok(_reply: &str) -> Result<()> {
+        bail!("The enject agent is only available on Unix.")
+    }
+
+    pub fn spawn(_path: &Path, _ttl: Duration) -> Result<()> {
+        bail!("The enject agent is only available on Unix.")
+    }
+
+    pub fn serve(_dir: &Path, _path: &Path, _ttl: Duration) -> Result<()> {
+        bail!("The enject agent is only available on Unix.")
+    }
+}
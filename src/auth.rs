@@ -0,0 +1,455 @@
+#[cfg(not(unix))]
+use std::io::BufRead;
+use std::io::IsTerminal;
+#[cfg(unix)]
+use std::io::Read;
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use secrecy::{ExposeSecret, SecretString};
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::error::EnjectError;
+
+/// Environment variable holding the project store password for non-interactive use.
+pub const PASSWORD_ENV: &str = "ENJECT_PASSWORD";
+/// Environment variable holding the global store password for non-interactive use.
+pub const GLOBAL_PASSWORD_ENV: &str = "ENJECT_GLOBAL_PASSWORD";
+
+/// Environment variable holding the password for a member added with `enject member add`.
+pub const MEMBER_PASSWORD_ENV: &str = "ENJECT_MEMBER_PASSWORD";
+
+/// Set (to anything but an empty string) to ignore store keys cached in the OS keychain.
+pub const NO_KEYCHAIN_ENV: &str = "ENJECT_NO_KEYCHAIN";
+
+/// All password variables — stripped from every subprocess environment.
+pub const PASSWORD_ENV_VARS: [&str; 3] = [PASSWORD_ENV, GLOBAL_PASSWORD_ENV, MEMBER_PASSWORD_ENV];
+
+/// Whether cached store keys may be used: not with `--no-keychain` or `ENJECT_NO_KEYCHAIN`.
+pub fn keychain_allowed(no_keychain_flag: bool) -> bool {
+    !no_keychain_flag && std::env::var_os(NO_KEYCHAIN_ENV).is_none_or(|v| v.is_empty())
+}
+
+/// How many times a password typed at the prompt may be wrong before giving up.
+pub const PASSWORD_ATTEMPTS: u32 = 3;
+
+/// Obtain the password for `store_name` as `password` does and `unlock` with it.
+/// A typed password that `unlock` rejects as wrong is asked for again, up to
+/// `PASSWORD_ATTEMPTS` times; one from the environment, a file or stdin gets a
+/// single try, since asking again would get the same answer.
+pub fn unlock_with_retries<F>(
+    store_name: &str,
+    env_var: &str,
+    password_file: Option<&Path>,
+    from_stdin: bool,
+    mut unlock: F,
+) -> Result<SecretString>
+where
+    F: FnMut(&SecretString) -> Result<()>,
+{
+    let (mut password, prompted) = password(store_name, env_var, password_file, from_stdin)?;
+    let mut remaining = PASSWORD_ATTEMPTS;
+    loop {
+        match unlock(&password) {
+            Ok(()) => return Ok(password),
+            Err(e) if prompted && remaining > 1 && is_wrong_password(&e) => {
+                remaining -= 1;
+                crate::ui::warn(format_args!(
+                    "Wrong password, {} attempt{} remaining.",
+                    remaining,
+                    if remaining == 1 { "" } else { "s" }
+                ));
+                password = prompt(&format!("{} password: ", store_name))?;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Whether `error` says the password didn't open the store.
+fn is_wrong_password(error: &anyhow::Error) -> bool {
+    matches!(
+        error.downcast_ref::<EnjectError>(),
+        Some(EnjectError::DecryptionFailed)
+    )
+}
+
+/// Obtain the password for `store_name`, and whether it was typed at the prompt.
+/// Sources, in order of precedence: `env_var`, then `password_file`, then the first
+/// line of stdin when stdin is not a terminal (and `from_stdin` allows it), then an
+/// interactive prompt.
+pub fn password(
+    store_name: &str,
+    env_var: &str,
+    password_file: Option<&Path>,
+    from_stdin: bool,
+) -> Result<(SecretString, bool)> {
+    if let Some(password) = from_env(env_var) {
+        return Ok((password, false));
+    }
+    if let Some(path) = password_file {
+        return Ok((read_password_file(path)?, false));
+    }
+    if from_stdin && !std::io::stdin().is_terminal() {
+        return Ok((read_stdin_password()?, false));
+    }
+    Ok((prompt(&format!("{} password: ", store_name))?, true))
+}
+
+/// Read a password from the first line of a piped stdin.
+///
+/// Only the password line is consumed: everything after it stays in the pipe for
+/// the subprocess spawned by `enject run`.
+pub fn read_stdin_password() -> Result<SecretString> {
+    let line = read_stdin_line().context("Failed to read password from stdin")?;
+    let line = String::from_utf8(line.to_vec()).context("Password on stdin is not valid UTF-8")?;
+    if line.is_empty() {
+        bail!("No password on stdin. Pipe the store password as the first line of input.");
+    }
+    Ok(SecretString::new(line))
+}
+
+/// Read one line from stdin a byte at a time, bypassing std's buffered `Stdin`
+/// so no bytes past the newline are consumed.
+#[cfg(unix)]
+fn read_stdin_line() -> std::io::Result<Zeroizing<Vec<u8>>> {
+    use std::os::fd::AsFd;
+
+    let mut stdin = std::fs::File::from(std::io::stdin().as_fd().try_clone_to_owned()?);
+    let mut line = Zeroizing::new(Vec::new());
+    let mut byte = [0u8; 1];
+    loop {
+        if stdin.read(&mut byte)? == 0 || byte[0] == b'\n' {
+            break;
+        }
+        line.push(byte[0]);
+    }
+    byte.zeroize();
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+    Ok(line)
+}
+
+/// Windows has no cheap way to read unbuffered from a console pipe, so this goes
+/// through std's buffered `Stdin`; data piped after the password may be consumed.
+#[cfg(not(unix))]
+fn read_stdin_line() -> std::io::Result<Zeroizing<Vec<u8>>> {
+    let mut line = Zeroizing::new(String::new());
+    std::io::stdin().lock().read_line(&mut line)?;
+    let trimmed = line.trim_end_matches(['\r', '\n']);
+    Ok(Zeroizing::new(trimmed.as_bytes().to_vec()))
+}
+
+/// Read a password from the first line of `path`, without its line ending.
+pub fn read_password_file(path: &Path) -> Result<SecretString> {
+    let content = Zeroizing::new(
+        std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read password file {}", path.display()))?,
+    );
+    let line = content.lines().next().unwrap_or("");
+    if line.is_empty() {
+        bail!("Password file {} is empty.", path.display());
+    }
+    Ok(SecretString::new(line.to_string()))
+}
+
+/// Obtain a password for a brand-new store: from `env_var` when set, otherwise by
+/// prompting twice with confirmation. Either way it must pass `check_strength`
+/// unless `allow_weak`.
+pub fn new_password(store_name: &str, env_var: &str, allow_weak: bool) -> Result<SecretString> {
+    if let Some(password) = from_env(env_var) {
+        if !allow_weak {
+            check_strength(&password).with_context(|| format!("{} is too weak", env_var))?;
+        }
+        return Ok(password);
+    }
+    prompt_new_password(store_name, allow_weak)
+}
+
+/// Prompt for a new password for the named store (twice, with confirmation).
+pub fn prompt_new_password(store_name: &str, allow_weak: bool) -> Result<SecretString> {
+    let password = Zeroizing::new(
+        rpassword::prompt_password(format!("New {} password: ", store_name))
+            .context("Failed to read password")?,
+    );
+    let confirm = Zeroizing::new(
+        rpassword::prompt_password(format!("Confirm {} password: ", store_name))
+            .context("Failed to read password confirmation")?,
+    );
+
+    if password != confirm {
+        bail!("Passwords do not match.");
+    }
+    if password.is_empty() {
+        bail!("{} password must not be empty.", store_name);
+    }
+
+    let password = SecretString::new(password.to_string());
+    if !allow_weak {
+        check_strength(&password)?;
+    }
+    Ok(password)
+}
+
+/// The least estimated strength, in bits, a new store password needs.
+pub const MIN_PASSWORD_BITS: f64 = 50.0;
+
+/// Passwords and keyboard runs that guessers try first. One counts as a single guess
+/// from a short list wherever it appears in a password.
+const COMMON_PASSWORDS: [&str; 32] = [
+    "password", "passwort", "passw0rd", "123456", "12345678", "qwerty", "qwertz", "azerty", "asdf",
+    "zxcv", "letmein", "welcome", "admin", "login", "secret", "iloveyou", "monkey", "dragon",
+    "master", "shadow", "sunshine", "princess", "football", "baseball", "superman", "trustno1",
+    "changeme", "default", "abc123", "111111", "000000", "enject",
+];
+
+/// Reject `password` when its estimated strength is below `MIN_PASSWORD_BITS`,
+/// saying what would make it stronger.
+pub fn check_strength(password: &SecretString) -> Result<()> {
+    let estimate = estimate_strength(password.expose_secret());
+    if estimate.bits >= MIN_PASSWORD_BITS {
+        return Ok(());
+    }
+    let advice = if let Some(word) = estimate.common {
+        format!("avoid common passwords like '{}'", word)
+    } else if estimate.patterned {
+        "avoid repeated characters and runs like 'aaa' or '1234'".to_string()
+    } else if password.expose_secret().chars().count() < 12 {
+        "use at least 12 characters, or a few unrelated words".to_string()
+    } else {
+        "add another word or two".to_string()
+    };
+    bail!(
+        "Password too weak (about {:.0} bits; {:.0} needed): {}. \
+         Pass --allow-weak-password for a throwaway store.",
+        estimate.bits,
+        MIN_PASSWORD_BITS,
+        advice
+    )
+}
+
+/// What `estimate_strength` made of a password.
+struct Strength {
+    bits: f64,
+    /// The first entry of `COMMON_PASSWORDS` the password contains.
+    common: Option<&'static str>,
+    /// Whether repeats or ascending/descending runs were discounted.
+    patterned: bool,
+}
+
+/// A rough guess-count estimate: each character is worth the bits of the character
+/// classes the password draws on, except that a character repeating or continuing a
+/// run of its predecessor is worth one bit, and a common password anywhere in it is
+/// worth as much as one pick from `COMMON_PASSWORDS`.
+fn estimate_strength(password: &str) -> Strength {
+    let lower = password.to_lowercase();
+    let common = COMMON_PASSWORDS
+        .iter()
+        .copied()
+        .find(|word| lower.contains(word));
+    let mut rest = lower.clone();
+    let mut bits = 0.0;
+    for word in COMMON_PASSWORDS {
+        if rest.contains(word) {
+            rest = rest.replace(word, "\0");
+            bits += (COMMON_PASSWORDS.len() as f64).log2();
+        }
+    }
+
+    let pool: u32 = [
+        (password.chars().any(|c| c.is_ascii_lowercase()), 26),
+        (password.chars().any(|c| c.is_ascii_uppercase()), 26),
+        (password.chars().any(|c| c.is_ascii_digit()), 10),
+        (
+            password
+                .chars()
+                .any(|c| c.is_ascii_punctuation() || c == ' '),
+            33,
+        ),
+        (!password.is_ascii(), 100),
+    ]
+    .iter()
+    .filter(|(used, _)| *used)
+    .map(|(_, size)| size)
+    .sum();
+    let char_bits = f64::from(pool.max(1)).log2();
+
+    let mut patterned = false;
+    let mut previous: Option<char> = None;
+    for c in rest.chars() {
+        if c == '\0' {
+            previous = None;
+            continue;
+        }
+        let continues = previous.is_some_and(|p| (c as i64 - p as i64).abs() <= 1);
+        if continues {
+            patterned = true;
+            bits += 1.0;
+        } else {
+            bits += char_bits;
+        }
+        previous = Some(c);
+    }
+    Strength {
+        bits,
+        common,
+        patterned,
+    }
+}
+
+/// Prompt on the terminal without echoing.
+pub fn prompt(prompt: &str) -> Result<SecretString> {
+    let password = rpassword::prompt_password(prompt)
+        .with_context(|| format!("Failed to read {}", prompt.trim_end_matches(": ")))?;
+    Ok(SecretString::new(password))
+}
+
+/// Read a password from `env_var`. Empty values are treated as unset.
+fn from_env(env_var: &str) -> Option<SecretString> {
+    std::env::var(env_var)
+        .ok()
+        .filter(|v| !v.is_empty())
+        .map(SecretString::new)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use secrecy::ExposeSecret;
+
+    #[test]
+    fn test_password_read_from_env() {
+        std::env::set_var("ENJECT_TEST_PASSWORD_SET", "test-password-do-not-use");
+        let password = password("Enject store", "ENJECT_TEST_PASSWORD_SET", None, true)
+            .unwrap()
+            .0;
+        assert_eq!(password.expose_secret(), "test-password-do-not-use");
+    }
+
+    #[test]
+    fn test_env_password_wins_over_file() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("password");
+        std::fs::write(&path, "from-file\n").unwrap();
+
+        std::env::set_var("ENJECT_TEST_PASSWORD_PRECEDENCE", "from-env");
+        let from_env = password(
+            "Enject store",
+            "ENJECT_TEST_PASSWORD_PRECEDENCE",
+            Some(&path),
+            true,
+        );
+        assert_eq!(from_env.unwrap().0.expose_secret(), "from-env");
+
+        let from_file = password(
+            "Enject store",
+            "ENJECT_TEST_PASSWORD_UNSET",
+            Some(&path),
+            true,
+        );
+        assert_eq!(from_file.unwrap().0.expose_secret(), "from-file");
+    }
+
+    #[test]
+    fn test_password_file_uses_first_line_only() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("password");
+        std::fs::write(&path, "test-password-do-not-use\r\nsecond line\n").unwrap();
+        let password = read_password_file(&path).unwrap();
+        assert_eq!(password.expose_secret(), "test-password-do-not-use");
+    }
+
+    #[test]
+    fn test_empty_or_missing_password_file_returns_err() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("password");
+        std::fs::write(&path, "\n").unwrap();
+        assert!(read_password_file(&path).is_err());
+        assert!(read_password_file(&dir.path().join("missing")).is_err());
+    }
+
+    #[test]
+    fn test_password_from_env_gets_one_attempt() {
+        std::env::set_var("ENJECT_TEST_PASSWORD_RETRY", "wrong-password");
+        let mut attempts = 0;
+        let result = unlock_with_retries(
+            "Enject store",
+            "ENJECT_TEST_PASSWORD_RETRY",
+            None,
+            true,
+            |_| {
+                attempts += 1;
+                Err(EnjectError::DecryptionFailed.into())
+            },
+        );
+        assert!(is_wrong_password(&result.unwrap_err()));
+        assert_eq!(attempts, 1);
+    }
+
+    #[test]
+    fn test_wrong_password_is_found_through_context() {
+        let error = anyhow::Error::from(EnjectError::DecryptionFailed).context("Failed to unlock");
+        assert!(is_wrong_password(&error));
+        let error = anyhow::Error::from(EnjectError::StoreChangedOnDisk);
+        assert!(!is_wrong_password(&error));
+    }
+
+    #[test]
+    fn test_weak_passwords_are_rejected() {
+        for weak in [
+            "password",
+            "Password1!",
+            "letmein2024",
+            "aaaaaaaaaaaaaaaa",
+            "abcdefgh12345678",
+            "qwertyuiop",
+            "Tr0ub4",
+        ] {
+            let estimate = estimate_strength(weak);
+            assert!(
+                estimate.bits < MIN_PASSWORD_BITS,
+                "{} scored {}",
+                weak,
+                estimate.bits
+            );
+            assert!(check_strength(&SecretString::new(weak.into())).is_err());
+        }
+    }
+
+    #[test]
+    fn test_strong_passwords_are_accepted() {
+        for strong in [
+            "test-password-do-not-use",
+            "correct horse battery staple",
+            "kV8#qz!Lm2@wPx",
+            "glacier-tulip-Orbit-93",
+        ] {
+            let estimate = estimate_strength(strong);
+            assert!(
+                estimate.bits >= MIN_PASSWORD_BITS,
+                "{} scored {}",
+                strong,
+                estimate.bits
+            );
+            assert!(check_strength(&SecretString::new(strong.into())).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_weakness_feedback_names_the_problem() {
+        let error = check_strength(&SecretString::new("mypassword".into())).unwrap_err();
+        assert!(error.to_string().contains("'password'"), "{}", error);
+        let error = check_strength(&SecretString::new("zzzzzzzzz1".into())).unwrap_err();
+        assert!(error.to_string().contains("repeated"), "{}", error);
+        let error = check_strength(&SecretString::new("kV8#qz".into())).unwrap_err();
+        assert!(error.to_string().contains("12 characters"), "{}", error);
+    }
+
+    #[test]
+    fn test_empty_env_password_is_ignored() {
+        std::env::set_var("ENJECT_TEST_PASSWORD_EMPTY", "");
+        assert!(from_env("ENJECT_TEST_PASSWORD_EMPTY").is_none());
+        assert!(from_env("ENJECT_TEST_PASSWORD_UNSET").is_none());
+    }
+}
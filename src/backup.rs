@@ -0,0 +1,151 @@
+//! The single-file format of `enject backup`: a store directory's files, packed
+//! as they are on disk. The store inside stays encrypted; nothing is decrypted to
+//! make or read a backup.
+
+use crate::error::EnjectError;
+
+/// Starts every backup file.
+const MAGIC: &[u8; 8] = b"ENJECTBK";
+/// Format version written by `pack`. A newer version is refused rather than misread.
+const VERSION: u8 = 1;
+
+/// One file of a store directory, by its path relative to that directory.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BackupFile {
+    pub name: String,
+    pub contents: Vec<u8>,
+}
+
+/// Pack `files` as `MAGIC`, `VERSION`, then for each file a big-endian u16 name
+/// length, the name, a big-endian u64 content length and the contents.
+pub fn pack(files: &[BackupFile]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(
+        MAGIC.len()
+            + 1
+            + files
+                .iter()
+                .map(|f| 10 + f.name.len() + f.contents.len())
+                .sum::<usize>(),
+    );
+    out.extend_from_slice(MAGIC);
+    out.push(VERSION);
+    for file in files {
+        out.extend_from_slice(&(file.name.len() as u16).to_be_bytes());
+        out.extend_from_slice(file.name.as_bytes());
+        out.extend_from_slice(&(file.contents.len() as u64).to_be_bytes());
+        out.extend_from_slice(&file.contents);
+    }
+    out
+}
+
+/// Read back what `pack` wrote. Every name must pass `is_backup_name`, so unpacking
+/// can never write outside the store directory.
+pub fn unpack(data: &[u8]) -> Result<Vec<BackupFile>, EnjectError> {
+    let invalid =
+        |reason: &str| EnjectError::CorruptStore(format!("not a valid backup: {}", reason));
+    let rest = data
+        .strip_prefix(MAGIC.as_slice())
+        .ok_or_else(|| invalid("missing the ENJECTBK header"))?;
+    let (&version, mut rest) = rest.split_first().ok_or_else(|| invalid("truncated"))?;
+    if version != VERSION {
+        return Err(EnjectError::UnsupportedStoreFormat(format!(
+            "backup format version {} (this enject reads version {})",
+            version, VERSION
+        )));
+    }
+
+    let mut files = Vec::new();
+    while !rest.is_empty() {
+        let (len, tail) = take(rest, 2).ok_or_else(|| invalid("truncated"))?;
+        let name_len = u16::from_be_bytes([len[0], len[1]]) as usize;
+        let (name, tail) = take(tail, name_len).ok_or_else(|| invalid("truncated"))?;
+        let name = String::from_utf8(name.to_vec()).map_err(|_| invalid("bad file name"))?;
+        if !is_backup_name(&name) {
+            return Err(invalid(&format!("unexpected file {:?}", name)));
+        }
+        let (len, tail) = take(tail, 8).ok_or_else(|| invalid("truncated"))?;
+        let mut len_bytes = [0u8; 8];
+        len_bytes.copy_from_slice(len);
+        let contents_len =
+            usize::try_from(u64::from_be_bytes(len_bytes)).map_err(|_| invalid("truncated"))?;
+        let (contents, tail) = take(tail, contents_len).ok_or_else(|| invalid("truncated"))?;
+        files.push(BackupFile {
+            name,
+            contents: contents.to_vec(),
+        });
+        rest = tail;
+    }
+    Ok(files)
+}
+
+/// Files a backup may hold: `config.toml`, `store`, and members' key files
+/// `members/<name>.key` with names as `enject member add` allows them.
+pub fn is_backup_name(name: &str) -> bool {
+    match name.strip_prefix("members/") {
+        Some(key_file) => key_file.strip_suffix(".key").is_some_and(|member| {
+            !member.is_empty()
+                && !member.starts_with('.')
+                && member
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+        }),
+        None => name == "config.toml" || name == "store",
+    }
+}
+
+fn take(data: &[u8], len: usize) -> Option<(&[u8], &[u8])> {
+    (data.len() >= len).then(|| data.split_at(len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(name: &str, contents: &[u8]) -> BackupFile {
+        BackupFile {
+            name: name.into(),
+            contents: contents.to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_pack_unpack_roundtrip() {
+        let files = vec![
+            file("config.toml", b"backend = \"password\"\n"),
+            file("store", &[0u8, 1, 2, 255]),
+            file("members/alice.key", b""),
+        ];
+        let packed = pack(&files);
+        assert!(packed.starts_with(b"ENJECTBK\x01"));
+        assert_eq!(unpack(&packed).unwrap(), files);
+    }
+
+    #[test]
+    fn test_unpack_rejects_bad_input() {
+        assert!(unpack(b"not a backup").is_err());
+        let packed = pack(&[file("store", b"ciphertext")]);
+        assert!(unpack(&packed[..packed.len() - 1]).is_err());
+
+        let mut future = packed.clone();
+        future[8] = 2;
+        assert!(matches!(
+            unpack(&future),
+            Err(EnjectError::UnsupportedStoreFormat(_))
+        ));
+    }
+
+    #[test]
+    fn test_unpack_rejects_paths_outside_the_store_dir() {
+        for name in [
+            "../store",
+            "/etc/passwd",
+            "members/../../x.key",
+            "members/.key",
+            "notes.txt",
+        ] {
+            assert!(!is_backup_name(name), "{}", name);
+            assert!(unpack(&pack(&[file(name, b"x")])).is_err(), "{}", name);
+        }
+        assert!(is_backup_name("members/bob-2.key"));
+    }
+}
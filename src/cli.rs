@@ -1,50 +1,871 @@
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand, ValueEnum};
 use std::path::PathBuf;
+use std::time::Duration;
 
 #[derive(Parser, Debug)]
 #[command(
     name = "enject",
     about = "Keep secrets out of .env files — and out of AI context.",
-    version
+    version,
+    after_help = EXIT_CODES
 )]
 pub struct Cli {
+    #[command(flatten)]
+    pub global: GlobalOpts,
+
     #[command(subcommand)]
     pub command: Command,
 }
 
+/// The exit codes in `error.rs`, for `--help`.
+const EXIT_CODES: &str = "\
+Exit codes:
+  0  Success
+  1  Any other failure
+  2  Invalid arguments
+  3  Store not initialized
+  4  Wrong password, or no identity that decrypts the store
+  5  Secret not found
+  6  Malformed .env template
+  7  Store locked by another enject process, or changed on disk; try again
+`run` exits with the command's own status, and `doctor` with 1 on warnings and 2 on failures.";
+
+/// Options accepted by every subcommand.
+#[derive(Args, Debug, Default)]
+pub struct GlobalOpts {
+    /// Operate on the project in this directory instead of the current one. Paths
+    /// given to other options stay relative to the current directory.
+    #[arg(short = 'C', long, global = true, value_name = "DIR")]
+    pub project_dir: Option<PathBuf>,
+
+    /// Read the store password from the first line of this file instead of prompting.
+    /// ENJECT_PASSWORD takes precedence when both are set.
+    #[arg(long, global = true, value_name = "PATH")]
+    pub password_file: Option<PathBuf>,
+
+    /// Don't use a store key cached by `enject unlock --remember`.
+    /// Setting ENJECT_NO_KEYCHAIN does the same.
+    #[arg(long, global = true)]
+    pub no_keychain: bool,
+
+    /// Keep config.toml and the store in this directory instead of the project's
+    /// .enject/. Overrides ENJECT_DIR.
+    #[arg(long, global = true, value_name = "DIR")]
+    pub store_dir: Option<PathBuf>,
+
+    /// Use the current directory as the project root instead of looking for the
+    /// nearest parent directory with a store.
+    #[arg(long, global = true)]
+    pub no_discover: bool,
+
+    /// Decrypt an age store with this identity file (an age key file or an
+    /// unencrypted SSH private key). Repeatable. Defaults to the files in
+    /// ~/.config/age, then ~/.ssh/id_ed25519 and ~/.ssh/id_rsa.
+    #[arg(long, global = true, value_name = "PATH")]
+    pub identity: Vec<PathBuf>,
+
+    /// Output format. JSON is a single document on stdout, with key names and
+    /// outcomes but never values; list, check, diff, status, delete and set support it.
+    #[arg(long, global = true, value_enum, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+
+    /// Log to stderr what enject does: the store directory and templates used, and
+    /// the names (never values) of injected variables. Twice adds KDF and decryption timings.
+    #[arg(short, long, global = true, action = clap::ArgAction::Count, conflicts_with = "quiet")]
+    pub verbose: u8,
+
+    /// Print only results and errors, not confirmations and hints.
+    #[arg(short, long, global = true)]
+    pub quiet: bool,
+
+    /// When to color warnings, errors and confirmations. `auto` colors only a
+    /// terminal, and never when NO_COLOR is set.
+    #[arg(long, global = true, value_enum, default_value_t = ColorChoice::Auto, value_name = "WHEN")]
+    pub color: ColorChoice,
+}
+
 #[derive(Subcommand, Debug)]
 pub enum Command {
     /// Initialize a new enject store in the current directory.
-    Init,
+    Init(InitArgs),
 
-    /// Add or update a secret (value is prompted interactively).
-    Set {
+    /// Add or update a secret. The value is prompted for, or read from a pipe.
+    Set(SetArgs),
+
+    /// List all stored secret key names (never values).
+    List(ListArgs),
+
+    /// Show a secret's description, tags and timestamps (never its value).
+    Info {
         /// The secret key name.
         key: String,
     },
 
-    /// List all stored secret key names (never values).
-    List,
+    /// List the kept versions of a secret with timestamps and fingerprints (never values).
+    History {
+        /// The secret key name.
+        key: String,
+    },
 
-    /// Delete a secret from the store.
-    Delete {
-        /// The secret key name to delete.
+    /// Make a previous version of a secret current again.
+    RestoreVersion {
+        /// The secret key name.
         key: String,
+        /// The version number shown by `history` (1 is the previous value).
+        version: usize,
+    },
+
+    /// Delete secrets from the store.
+    Delete(DeleteArgs),
+
+    /// Rename a secret, keeping its value. Offers to update en:// references in .env.
+    Rename {
+        /// The current secret key name.
+        old: String,
+        /// The new secret key name.
+        new: String,
+        /// Overwrite `new` without asking if it already exists.
+        #[arg(long)]
+        force: bool,
     },
 
     /// Resolve .env template and run a subprocess with injected secrets.
-    Run {
-        /// Command and arguments to run (everything after --).
-        #[arg(last = true, required = true)]
-        cmd: Vec<String>,
+    Run(RunArgs),
+
+    /// Validate .env references against the store without running anything.
+    Check {
+        /// Validate .env with .env.<PROFILE> layered on top, as `run --profile` would use it.
+        #[arg(long)]
+        profile: Option<String>,
+
+        /// Report duplicate keys as warnings instead of errors.
+        #[arg(long)]
+        allow_duplicates: bool,
+
+        /// Report expired referenced secrets as errors instead of warnings.
+        #[arg(long)]
+        strict_expiry: bool,
+    },
+
+    /// Compare .env with the store: missing secrets, unreferenced secrets, and plain values shadowing secrets.
+    Diff {
+        /// Compare .env with .env.<PROFILE> layered on top, as `run --profile` would use it.
+        #[arg(long)]
+        profile: Option<String>,
+    },
+
+    /// Check .env files for plain values that look like secrets, e.g. from a git pre-commit hook.
+    Scan {
+        /// Files, or directories to search for .env files. Defaults to the .env files staged in git.
+        #[arg(conflicts_with = "install_hook")]
+        paths: Vec<PathBuf>,
+
+        /// Write a .git/hooks/pre-commit that runs `enject scan`.
+        #[arg(long)]
+        install_hook: bool,
+
+        /// Replace an existing pre-commit hook.
+        #[arg(long, requires = "install_hook")]
+        force: bool,
+    },
+
+    /// Write a .env.example from .env, with references emptied, for committing.
+    Template {
+        /// Write a .env.example (currently the only kind of template).
+        #[arg(long, required = true)]
+        example: bool,
+
+        /// Where to write it. Defaults to .env.example in the project root.
+        output: Option<PathBuf>,
+
+        /// Write `<name>` placeholders for references instead of empty values.
+        #[arg(long)]
+        show_refs: bool,
+
+        /// Leave plain values empty too.
+        #[arg(long)]
+        blank_all: bool,
+
+        /// Overwrite an existing output file.
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Print a shell completion script for subcommands and flags.
+    Completions {
+        /// The shell to generate the script for.
+        #[arg(value_enum)]
+        shell: clap_complete::Shell,
+    },
+
+    /// Summarize the project: store location, KDF settings, .env contents, .gitignore coverage.
+    Status {
+        /// Unlock the store to report how many secrets it holds.
+        #[arg(long)]
+        unlock: bool,
+    },
+
+    /// Diagnose common setup problems. Exits 0 when all checks pass, 1 on warnings, 2 on failures.
+    Doctor {
+        /// Also unlock the store to check that it opens.
+        #[arg(long)]
+        unlock: bool,
+    },
+
+    /// Rename a legacy .enveil/ store to .enject/ and rewrite ev:// references to en://.
+    Migrate {
+        /// Don't ask for confirmation.
+        #[arg(short, long)]
+        yes: bool,
     },
 
     /// Import a plaintext .env file: encrypt all values, rewrite as en:// template.
-    Import {
-        /// Path to the plaintext .env file to import.
+    Import(ImportArgs),
+
+    /// Re-encrypt the store with a new Enject store password, salt, or KDF parameters.
+    Rotate(RotateArgs),
+
+    /// Check that the store decrypts with its password, and report its size and secret count.
+    Verify {
+        /// Also check that every entry serializes back unchanged.
+        #[arg(long)]
+        deep: bool,
+    },
+
+    /// Write the config and the still-encrypted store to a single backup file.
+    Backup {
+        /// The backup file to create. An existing file is never overwritten.
+        output: PathBuf,
+    },
+
+    /// Restore a file written by `enject backup`, once it unlocks with its password.
+    /// An existing store is moved aside to .enject.old-<time>/.
+    Restore {
+        /// The backup file.
         file: PathBuf,
+        /// Replace an existing store without asking (required when stdin is not a terminal).
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Check the store password. With --remember, cache the derived store key in
+    /// the OS keychain so later commands don't ask for the password.
+    Unlock {
+        /// Cache the store key in the OS keychain until `enject lock`.
+        #[arg(long)]
+        remember: bool,
+    },
+
+    /// Remove the store key cached by `enject unlock --remember`, and make a running
+    /// `enject agent` forget every key it holds.
+    Lock,
+
+    /// Start a background agent that holds derived store keys for this session, so
+    /// the password is asked for once. Used when ENJECT_AGENT=1 or `agent = true` is
+    /// in config.toml. Unix only.
+    Agent {
+        /// How long the agent keeps each key, e.g. 30m or 2h. Defaults to 15m.
+        #[arg(long, value_name = "DURATION")]
+        ttl: Option<String>,
+        /// Run in the foreground instead of detaching.
+        #[arg(long, conflicts_with = "stop")]
+        foreground: bool,
+        /// Stop a running agent; its keys are wiped.
+        #[arg(long)]
+        stop: bool,
+    },
+
+    /// List, add or remove the public keys an age store is encrypted to.
+    Recipients {
+        #[command(subcommand)]
+        command: RecipientsCommand,
+    },
+
+    /// List, add or remove the named members who can unlock a shared store. Adding
+    /// the first member to a password store converts it to an age store.
+    Member {
+        #[command(subcommand)]
+        command: MemberCommand,
+    },
+
+    /// Manage the user-level global store shared across projects (en://global/...).
+    Global {
+        #[command(subcommand)]
+        command: GlobalCommand,
     },
+}
+
+impl Command {
+    /// Whether the command has `--format json` output.
+    pub fn supports_json(&self) -> bool {
+        match self {
+            Command::Set(_)
+            | Command::List(_)
+            | Command::Delete(_)
+            | Command::Check { .. }
+            | Command::Diff { .. }
+            | Command::Status { .. } => true,
+            Command::Global { command } => matches!(
+                command,
+                GlobalCommand::Set(_) | GlobalCommand::List(_) | GlobalCommand::Delete(_)
+            ),
+            _ => false,
+        }
+    }
+}
+
+/// Arguments for `enject run`.
+#[derive(Args, Debug)]
+pub struct RunArgs {
+    /// Template to resolve instead of ./.env (relative to the current directory).
+    /// Repeatable: files are layered in order, later files override earlier ones
+    /// key by key. Use `-` to read a template from stdin.
+    #[arg(long, value_name = "PATH", conflicts_with = "profile")]
+    pub env_file: Vec<PathBuf>,
+
+    /// Layer .env.<PROFILE> on top of .env. Defaults to `default_profile` in config.toml.
+    #[arg(long)]
+    pub profile: Option<String>,
+
+    /// Keep enject running as the parent of the command instead of replacing
+    /// itself with it (Unix). Signals are forwarded to the command.
+    #[arg(long)]
+    pub no_exec: bool,
+
+    /// Keep running, and restart the command whenever a template or the store file
+    /// changes. The store is not asked for its password again. If the changed files
+    /// don't resolve, the error is printed and the command keeps running as it was.
+    #[arg(long)]
+    pub watch: bool,
+
+    /// Stop the command if it is still running after this long (`90`, `90s`, `10m`,
+    /// `1h30m`): SIGTERM, then SIGKILL after --kill-after. enject then exits with 124,
+    /// as GNU timeout does.
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration, conflicts_with_all = ["procfile", "watch"])]
+    pub timeout: Option<Duration>,
+
+    /// With --timeout, how long the command gets to exit after SIGTERM before it is
+    /// killed.
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration, default_value = "10s", requires = "timeout")]
+    pub kill_after: Duration,
+
+    /// Instead of one command, run every `name: command` line of this Procfile at
+    /// once, each with the injected environment and its output prefixed with its
+    /// name. When one exits, the others are stopped.
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["cmd", "watch"])]
+    pub procfile: Option<PathBuf>,
+
+    /// Run the command in this directory. The template and store are still the
+    /// project's, found from where enject was started (or -C).
+    #[arg(long, value_name = "DIR")]
+    pub cwd: Option<PathBuf>,
+
+    /// Inject every secret in the store, named by its key uppercased. A template,
+    /// if there is one, is layered on top; without --all, .env is required.
+    #[arg(long)]
+    pub all: bool,
+
+    /// With --all, put this in front of every variable name, e.g. SECRET_.
+    #[arg(long, requires = "all")]
+    pub prefix: Option<String>,
+
+    /// Only pass the template variables matching this name or pattern (`*` and `?`).
+    /// Repeatable. Each must match a variable the template sets.
+    #[arg(long, value_name = "KEY")]
+    pub only: Vec<String>,
+
+    /// Don't pass the template variables matching this name or pattern. Repeatable.
+    #[arg(long, value_name = "KEY")]
+    pub except: Vec<String>,
+
+    /// Pipe the command's stdout and stderr through enject, replacing every injected
+    /// secret value of 6 or more characters with ***MASKED(<KEY>)***. The command no
+    /// longer writes to the terminal directly, so it won't see a TTY on those streams.
+    #[arg(long)]
+    pub mask: bool,
+
+    /// Start the command from an empty environment: only the template's variables
+    /// and PATH, HOME, TERM and LANG from enject's own environment.
+    #[arg(long)]
+    pub isolated: bool,
+
+    /// With --isolated, also pass this variable through from enject's environment.
+    /// Repeatable.
+    #[arg(long, value_name = "VAR", requires = "isolated")]
+    pub keep: Vec<String>,
+
+    /// Allow a key to be assigned more than once in a template; the last assignment wins.
+    #[arg(long)]
+    pub allow_duplicates: bool,
+
+    /// Refuse to run when a referenced secret has expired, instead of only warning.
+    #[arg(long)]
+    pub strict_expiry: bool,
+
+    /// Run this string with your shell instead of a command after --: `$SHELL -c`,
+    /// or `/bin/sh -c` when SHELL is unset (`%COMSPEC% /C` on Windows). The string is
+    /// passed as is; secrets reach the shell only through the environment.
+    #[arg(long, value_name = "COMMAND", conflicts_with_all = ["cmd", "script", "procfile", "list_scripts"])]
+    pub shell: Option<String>,
+
+    /// Replace en://name and en://global/name in the command's arguments with the
+    /// secrets, for commands that only take a secret as an argument. Other users of
+    /// the machine can see a process's arguments, so prefer the environment.
+    #[arg(long, conflicts_with_all = ["shell", "procfile"])]
+    pub subst_args: bool,
+
+    /// Print the scripts in the [scripts] table of config.toml and exit.
+    #[arg(long, conflicts_with_all = ["cmd", "script", "procfile"])]
+    pub list_scripts: bool,
+
+    /// A script from the [scripts] table of config.toml, then extra arguments for it.
+    /// When no script has that name, the command to run, as if after --. Options for
+    /// enject go before it.
+    #[arg(
+        value_name = "SCRIPT",
+        allow_hyphen_values = true,
+        conflicts_with_all = ["cmd", "procfile"]
+    )]
+    pub script: Vec<String>,
+
+    /// Command and arguments to run (everything after --).
+    #[arg(
+        last = true,
+        required_unless_present_any = ["procfile", "script", "list_scripts", "shell"]
+    )]
+    pub cmd: Vec<String>,
+}
+
+/// Arguments for `enject list` and `enject global list`.
+#[derive(Args, Debug)]
+pub struct ListArgs {
+    /// Only list keys matching this pattern (`*` and `?`), e.g. 'stripe_*'.
+    pub pattern: Option<String>,
+
+    /// Only list keys starting with this prefix.
+    #[arg(long)]
+    pub prefix: Option<String>,
+
+    /// Only list secrets with this tag.
+    #[arg(long)]
+    pub tag: Option<String>,
+
+    /// Show the date each secret was last updated.
+    #[arg(short, long)]
+    pub long: bool,
+
+    /// Only list secrets that no en:// reference in .env or a .env.<profile> file uses.
+    #[arg(long, conflicts_with = "missing")]
+    pub unused: bool,
+
+    /// List secrets referenced from .env or a .env.<profile> file but absent from the
+    /// store, one per line. Fails if there are any.
+    #[arg(long, conflicts_with_all = ["pattern", "prefix", "tag", "long"])]
+    pub missing: bool,
+}
+
+/// Arguments for `enject delete` and `enject global delete`.
+#[derive(Args, Debug)]
+pub struct DeleteArgs {
+    /// The secret key names to delete.
+    #[arg(required_unless_present = "glob")]
+    pub keys: Vec<String>,
+
+    /// Also delete every secret whose name matches this pattern (`*` and `?`).
+    /// Matches are listed before anything is deleted. Repeatable.
+    #[arg(long, value_name = "PATTERN")]
+    pub glob: Vec<String>,
+
+    /// Delete without asking for confirmation (required when stdin is not a terminal).
+    #[arg(short, long)]
+    pub force: bool,
+
+    /// Keep the deleted values in the store's history so `restore-version` can bring
+    /// them back.
+    #[arg(long)]
+    pub keep_history: bool,
+}
+
+/// Arguments for `enject set` and `enject global set`.
+#[derive(Args, Debug)]
+pub struct SetArgs {
+    /// The secret key name. Several names set them all with one unlock, prompting
+    /// for each value in turn.
+    #[arg(required_unless_present = "interactive")]
+    pub keys: Vec<String>,
+
+    /// Prompt for key names and values in a loop until an empty key name.
+    #[arg(long, conflicts_with_all = ["keys", "stdin", "from_file"])]
+    pub interactive: bool,
+
+    /// Read the value from stdin even when it is a terminal.
+    #[arg(long, conflicts_with = "from_file")]
+    pub stdin: bool,
+
+    /// Store the entire contents of this file (e.g. a PEM key or a JSON credential).
+    #[arg(long, value_name = "PATH")]
+    pub from_file: Option<PathBuf>,
+
+    /// Overwrite an existing secret without asking.
+    #[arg(long, conflicts_with = "if_absent")]
+    pub force: bool,
+
+    /// Do nothing for a key that already exists (for idempotent setup scripts).
+    #[arg(long)]
+    pub if_absent: bool,
+
+    /// Store a newly generated random value. It is never printed.
+    #[arg(long, conflicts_with_all = ["stdin", "from_file", "interactive"])]
+    pub generate: bool,
+
+    /// Number of characters to generate.
+    #[arg(long, default_value_t = 32, requires = "generate", value_parser = clap::value_parser!(u16).range(1..))]
+    pub length: u16,
+
+    /// Characters to generate from.
+    #[arg(long, value_enum, default_value_t = Charset::Alnum, requires = "generate")]
+    pub charset: Charset,
+
+    /// What the secret is for, shown by `info`. An empty string removes it.
+    #[arg(long)]
+    pub description: Option<String>,
+
+    /// Tag the secret (repeatable). Replaces any existing tags.
+    #[arg(long = "tag", value_name = "TAG")]
+    pub tags: Vec<String>,
+
+    /// When the secret is due for rotation: a duration such as 90d, a date such as
+    /// 2025-06-30, or never to remove the expiry.
+    #[arg(long, value_name = "WHEN")]
+    pub expires: Option<String>,
+
+    /// Save even if the store file was replaced on disk while waiting for input,
+    /// discarding that change.
+    #[arg(long)]
+    pub force_save: bool,
+}
+
+/// Arguments for `enject init` and `enject global init`.
+#[derive(Args, Debug)]
+pub struct InitArgs {
+    #[command(flatten)]
+    pub kdf: KdfArgs,
+
+    /// Encrypt the store with this cipher.
+    #[arg(long, value_parser = CIPHERS, default_value = "aes256gcm")]
+    pub cipher: String,
+
+    /// Where secrets are kept: a password-encrypted file, the OS keychain (needs a
+    /// build with the `keyring` feature), or a file encrypted to age public keys
+    /// (needs the `age` feature).
+    #[arg(long, value_parser = BACKENDS, default_value = "password")]
+    pub backend: String,
+
+    /// Public key to encrypt an age store to (`age1...` or an SSH public key).
+    /// Repeatable; at least one is needed with `--backend age`.
+    #[arg(long, value_name = "KEY")]
+    pub recipient: Vec<String>,
+
+    /// Accept a password that fails the strength check, e.g. for a throwaway test store.
+    #[arg(long)]
+    pub allow_weak_password: bool,
+
+    /// Replace an existing project store. Shows what will be lost and asks for the
+    /// project directory's name; the old store directory is kept as .enject.old-<time>/.
+    #[arg(long)]
+    pub force: bool,
+
+    /// Leave .gitignore alone. By default, in a git repository, init makes sure it
+    /// ignores .enject/ and the .env.bak files `import` leaves behind.
+    #[arg(long)]
+    pub no_gitignore: bool,
+}
+
+/// Arguments for `enject rotate` and `enject global rotate`.
+#[derive(Args, Debug)]
+pub struct RotateArgs {
+    #[command(flatten)]
+    pub kdf: KdfArgs,
+
+    /// Keep the current password; only the salt, cipher and KDF parameters change.
+    #[arg(long)]
+    pub keep_password: bool,
+
+    /// Convert the store to this cipher.
+    #[arg(long, value_parser = CIPHERS)]
+    pub cipher: Option<String>,
+
+    /// Accept a new password that fails the strength check.
+    #[arg(long)]
+    pub allow_weak_password: bool,
+}
+
+/// Names accepted by `--cipher`, as written to `config.toml`.
+const CIPHERS: [&str; 2] = ["aes256gcm", "xchacha20"];
+const BACKENDS: [&str; 3] = ["password", "keyring", "age"];
+
+/// A duration given as bare seconds (`90`) or with units (`90s`, `10m`, `1h30m`).
+fn parse_duration(input: &str) -> Result<Duration, String> {
+    if let Ok(secs) = input.parse::<u64>() {
+        return Ok(Duration::from_secs(secs));
+    }
+    humantime::parse_duration(input)
+        .map_err(|_| format!("'{}' is not a duration; use e.g. 90, 90s or 10m", input))
+}
+
+/// Argon2id cost settings. Anything left out keeps its current value. Memory below
+/// 19 MiB or zero passes are refused.
+#[derive(Args, Debug, Default)]
+pub struct KdfArgs {
+    /// Use a named set of Argon2id parameters.
+    #[arg(long, visible_alias = "kdf-preset", value_enum, conflicts_with_all = ["m_cost", "t_cost", "p_cost"])]
+    pub preset: Option<KdfPreset>,
+
+    /// Time Argon2id on this machine and pick parameters that take about 500ms to unlock.
+    #[arg(long, conflicts_with_all = ["preset", "m_cost", "t_cost", "p_cost"])]
+    pub calibrate: bool,
+
+    /// Argon2id memory cost in KiB.
+    #[arg(long, value_name = "KIB")]
+    pub m_cost: Option<u32>,
+
+    /// Argon2id iterations.
+    #[arg(long, value_name = "N")]
+    pub t_cost: Option<u32>,
+
+    /// Argon2id parallelism (lanes).
+    #[arg(long, value_name = "N")]
+    pub p_cost: Option<u32>,
+}
+
+/// Named Argon2id parameter sets.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum KdfPreset {
+    /// 19 MiB, 2 iterations, 1 lane: the OWASP minimum, for slow machines.
+    Interactive,
+    /// 64 MiB, 3 iterations, 4 lanes: the default.
+    Moderate,
+    /// 256 MiB, 4 iterations, 4 lanes.
+    Paranoid,
+}
+
+/// Alphabets for `enject set --generate`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Charset {
+    /// 0-9 and a-f.
+    Hex,
+    /// A-Z, a-z, 0-9, `+` and `/` (no padding).
+    Base64,
+    /// A-Z, a-z and 0-9.
+    Alnum,
+    /// Every printable ASCII character except space.
+    Ascii,
+}
+
+/// Arguments for `enject import`.
+#[derive(Args, Debug)]
+pub struct ImportArgs {
+    /// Path to the plaintext .env file to import.
+    pub file: PathBuf,
+
+    /// Store secrets under lowercased names (DATABASE_URL=en://database_url).
+    #[arg(long)]
+    pub lowercase: bool,
+
+    /// Don't ask for confirmation. Without --backup, no backup is written.
+    #[arg(short, long)]
+    pub yes: bool,
+
+    /// Back up the file (as .env.bak) before rewriting it, without asking.
+    #[arg(long, conflicts_with = "no_backup")]
+    pub backup: bool,
+
+    /// Don't write a backup, without asking.
+    #[arg(long)]
+    pub no_backup: bool,
+
+    /// Import only keys matching this pattern (`*` and `?` wildcards). Repeatable.
+    #[arg(long, value_name = "KEY")]
+    pub only: Vec<String>,
+
+    /// Leave keys matching this pattern as plain values. Repeatable.
+    #[arg(long, value_name = "KEY")]
+    pub exclude: Vec<String>,
+
+    /// What to do with a key whose secret is already stored with a different value.
+    /// Without it, import asks for each one.
+    #[arg(long, value_enum, value_name = "POLICY")]
+    pub on_conflict: Option<ConflictPolicy>,
+
+    /// Show which secrets would be stored and how the file would be rewritten,
+    /// without changing either.
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Save even if the store file was replaced on disk while waiting for input,
+    /// discarding that change.
+    #[arg(long, conflicts_with = "dry_run")]
+    pub force_save: bool,
+}
+
+/// How `enject import` settles a secret that is already stored with a different value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ConflictPolicy {
+    /// Keep the stored value; the line still becomes an en:// reference.
+    Keep,
+    /// Replace the stored value with the one from the file.
+    Overwrite,
+    /// Stop without changing the store or the file.
+    Abort,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum GlobalCommand {
+    /// Initialize the global store in your user config directory.
+    Init(InitArgs),
+
+    /// Add or update a global secret. The value is prompted for, or read from a pipe.
+    Set(SetArgs),
+
+    /// List all global secret key names (never values).
+    List(ListArgs),
+
+    /// Show a global secret's description, tags and timestamps (never its value).
+    Info {
+        /// The secret key name.
+        key: String,
+    },
+
+    /// List the kept versions of a global secret (never values).
+    History {
+        /// The secret key name.
+        key: String,
+    },
+
+    /// Make a previous version of a global secret current again.
+    RestoreVersion {
+        /// The secret key name.
+        key: String,
+        /// The version number shown by `history` (1 is the previous value).
+        version: usize,
+    },
+
+    /// Delete secrets from the global store.
+    Delete(DeleteArgs),
+
+    /// Re-encrypt the global store with a new password, salt, or KDF parameters.
+    Rotate(RotateArgs),
+
+    /// Check the global store password, and with --remember cache its key in the OS keychain.
+    Unlock {
+        /// Cache the store key in the OS keychain until `enject global lock`.
+        #[arg(long)]
+        remember: bool,
+    },
+
+    /// Remove the global store key cached by `enject global unlock --remember`.
+    Lock,
+
+    /// List, add or remove the public keys the global age store is encrypted to.
+    Recipients {
+        #[command(subcommand)]
+        command: RecipientsCommand,
+    },
+
+    /// List, add or remove the named members who can unlock the global store.
+    /// Adding the first member to a password store converts it to an age store.
+    Member {
+        #[command(subcommand)]
+        command: MemberCommand,
+    },
+}
+
+/// Subcommands of `enject member` and `enject global member`.
+#[derive(Subcommand, Debug)]
+pub enum MemberCommand {
+    /// List the members and how each unlocks the store.
+    List,
+
+    /// Add a member who unlocks the store with their own password (prompted for, or
+    /// ENJECT_MEMBER_PASSWORD), or with the private key for `--recipient`.
+    Add {
+        /// The member's name, e.g. their username.
+        name: String,
+        /// Use this public key (`age1...` or SSH) instead of a password.
+        #[arg(long, value_name = "KEY")]
+        recipient: Option<String>,
+        /// When converting a password store, the member name the current password
+        /// is kept under. Defaults to $USER.
+        #[arg(long = "as", value_name = "NAME")]
+        as_name: Option<String>,
+        /// Accept a member password that fails the strength check.
+        #[arg(long)]
+        allow_weak_password: bool,
+    },
+
+    /// Remove a member and re-encrypt the store under a new data key.
+    Remove {
+        /// The member's name, as shown by `member list`.
+        name: String,
+    },
+}
+
+/// Subcommands of `enject recipients` and `enject global recipients`.
+#[derive(Subcommand, Debug)]
+pub enum RecipientsCommand {
+    /// List the public keys the store is encrypted to.
+    List,
+
+    /// Encrypt the store to another public key (`age1...` or an SSH public key).
+    Add {
+        /// The recipient's public key.
+        recipient: String,
+    },
+
+    /// Stop encrypting the store to a public key. Anyone who copied the store
+    /// earlier can still decrypt that copy; rotate the secrets it held.
+    Remove {
+        /// The recipient's public key, as shown by `recipients list`.
+        recipient: String,
+    },
+}
+
+/// Output format for commands that report results.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Human-readable text.
+    #[default]
+    Text,
+    /// A single JSON document on stdout.
+    Json,
+}
+
+/// When to color output.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum ColorChoice {
+    /// Color a stream when it is a terminal and NO_COLOR is unset.
+    #[default]
+    Auto,
+    /// Always color.
+    Always,
+    /// Never color.
+    Never,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    /// Re-encrypt the store with a new Enject store password.
-    Rotate,
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("90"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("90s"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("10m"), Ok(Duration::from_secs(600)));
+        assert_eq!(parse_duration("1h 30m"), Ok(Duration::from_secs(5400)));
+        assert_eq!(parse_duration("1h30m"), Ok(Duration::from_secs(5400)));
+        assert_eq!(parse_duration("500ms"), Ok(Duration::from_millis(500)));
+        for input in ["", "soon", "-5", "10 parsecs"] {
+            assert!(parse_duration(input).is_err(), "{:?}", input);
+        }
+    }
 }
@@ -0,0 +1,38 @@
+use anyhow::{Context, Result};
+
+use crate::agent;
+use crate::commands::inform;
+
+/// `enject agent` — start the key agent in the background (or the foreground), or
+/// stop a running one.
+pub fn run(ttl: Option<&str>, foreground: bool, stop: bool) -> Result<()> {
+    if stop {
+        if agent::stop()? {
+            inform(format_args!("enject agent stopped; its keys are wiped."));
+        } else {
+            inform(format_args!("No enject agent is running."));
+        }
+        return Ok(());
+    }
+
+    let ttl = match ttl {
+        Some(ttl) => humantime::parse_duration(ttl)
+            .with_context(|| format!("Invalid --ttl {:?}; use a duration such as 30m", ttl))?,
+        None => agent::DEFAULT_TTL,
+    };
+    if foreground {
+        return agent::serve(ttl);
+    }
+
+    let path = agent::spawn(ttl)?;
+    inform(format_args!(
+        "enject agent listening on {}; it keeps each key for {}.",
+        path.display(),
+        humantime::format_duration(ttl)
+    ));
+    inform(format_args!(
+        "Set {}=1 (or `agent = true` in config.toml) so commands use it.",
+        agent::AGENT_ENV
+    ));
+    Ok(())
+}
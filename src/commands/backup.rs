@@ -0,0 +1,174 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+
+use crate::backup::{self, BackupFile};
+use crate::cli::GlobalOpts;
+use crate::commands::{
+    archive_store_dir, confirm, inform, is_interactive, project_root, read_config, store_dir,
+    StoreTarget,
+};
+use crate::config;
+use crate::ui;
+
+/// `enject backup` — pack the project's config, encrypted store and member key
+/// files into one file. Nothing is decrypted, so no password is needed.
+pub fn run(opts: &GlobalOpts, output: &Path) -> Result<()> {
+    let root = project_root(opts)?;
+    let dir = store_dir(opts, &root);
+    let cfg = match config::store_dir_override(opts.store_dir.as_deref()) {
+        Some(dir) => config::read_store_dir(&dir)?,
+        None => read_config(&root)?,
+    };
+    if cfg.backend == "keyring" {
+        bail!(
+            "This store keeps its secrets in the OS keychain; there is no store file to back up."
+        );
+    }
+
+    let mut files = Vec::new();
+    for name in ["config.toml", "store"] {
+        let path = dir.join(name);
+        if path.exists() {
+            files.push(read_file(&dir, name)?);
+        }
+    }
+    let members = dir.join("members");
+    if members.is_dir() {
+        let mut names = Vec::new();
+        for entry in std::fs::read_dir(&members)
+            .with_context(|| format!("Failed to read {}", members.display()))?
+        {
+            let name = format!("members/{}", entry?.file_name().to_string_lossy());
+            if backup::is_backup_name(&name) {
+                names.push(name);
+            }
+        }
+        names.sort();
+        for name in names {
+            files.push(read_file(&dir, &name)?);
+        }
+    }
+
+    let mut out = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(output)
+        .with_context(|| format!("Failed to create {}", output.display()))?;
+    out.write_all(&backup::pack(&files))?;
+    out.sync_all()?;
+    ui::success(format_args!(
+        "Backed up {} to {} ({} file(s)). The store inside is still encrypted; keep its password.",
+        dir.display(),
+        output.display(),
+        files.len()
+    ));
+    Ok(())
+}
+
+/// `enject restore` — unpack a backup into the project's store directory. The
+/// backup is unpacked beside it and must unlock before anything is replaced; an
+/// existing store is moved aside, not deleted.
+pub fn run_restore(opts: &GlobalOpts, file: &Path, force: bool) -> Result<()> {
+    let data = std::fs::read(file).with_context(|| format!("Failed to read {}", file.display()))?;
+    let files = backup::unpack(&data)?;
+    if !files.iter().any(|f| f.name == "config.toml") {
+        bail!(
+            "{} holds no config.toml; it is not an enject backup.",
+            file.display()
+        );
+    }
+
+    let root = project_root(opts)?;
+    let dir = store_dir(opts, &root);
+    let staging = sibling(&dir, "restoring");
+    if staging.exists() {
+        std::fs::remove_dir_all(&staging)
+            .with_context(|| format!("Failed to clear {}", staging.display()))?;
+    }
+    let count = unpack_and_verify(opts, &root, &staging, &files).inspect_err(|_| {
+        let _ = std::fs::remove_dir_all(&staging);
+    })?;
+
+    let existing = config::config_path_in(&dir).exists();
+    if existing && !force {
+        let replace = is_interactive()
+            && confirm(&format!(
+                "Replace the store in {}? It will be moved aside, not deleted.",
+                dir.display()
+            ))?;
+        if !replace {
+            let _ = std::fs::remove_dir_all(&staging);
+            bail!("Not restored; pass --force to replace the existing store without asking.");
+        }
+    }
+    if existing {
+        let archive = archive_store_dir(&dir)?;
+        inform(format_args!(
+            "Moved the current store to {}.",
+            archive.display()
+        ));
+    }
+    std::fs::rename(&staging, &dir)
+        .with_context(|| format!("Failed to move the restored store into {}", dir.display()))?;
+    ui::success(format_args!(
+        "Restored {} secret(s) into {}.",
+        count,
+        dir.display()
+    ));
+    Ok(())
+}
+
+/// Write `files` into `staging` and unlock the store there, returning how many
+/// secrets it holds. The password is asked for; cached keys are not used.
+fn unpack_and_verify(
+    opts: &GlobalOpts,
+    root: &Path,
+    staging: &Path,
+    files: &[BackupFile],
+) -> Result<usize> {
+    for file in files {
+        let path = staging.join(&file.name);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        std::fs::write(&path, &file.contents)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+    }
+    let cfg = config::read_store_dir(staging)?;
+    if cfg.backend == "keyring" {
+        bail!("The backup is of a keyring store, whose secrets were never in it.");
+    }
+
+    let mut target = StoreTarget::local_from(opts, root, cfg);
+    target.config_dir = staging.to_path_buf();
+    target.store_path = config::store_path_in(staging);
+    target.keychain = false;
+    target.agent = false;
+    let store = target
+        .unlock_read_only()
+        .context("The backup does not unlock; nothing was restored")?;
+    let count = store.list()?.len();
+    Ok(count)
+}
+
+fn read_file(dir: &Path, name: &str) -> Result<BackupFile> {
+    let path = dir.join(name);
+    let contents =
+        std::fs::read(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+    Ok(BackupFile {
+        name: name.to_string(),
+        contents,
+    })
+}
+
+/// `<dir>.<suffix>`, next to `dir`.
+fn sibling(dir: &Path, suffix: &str) -> PathBuf {
+    let name = dir
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    dir.with_file_name(format!("{}.{}", name, suffix))
+}
@@ -0,0 +1,20 @@
+use std::io::Write;
+
+use anyhow::Result;
+use clap::CommandFactory;
+use clap_complete::Shell;
+
+use crate::cli::Cli;
+
+/// `enject completions` — print a completion script for `shell` to stdout. It
+/// completes subcommands and flags only: secret names would need the store
+/// unlocked, or a plaintext list of them kept on disk.
+pub fn run(shell: Shell) -> Result<()> {
+    let mut command = Cli::command();
+    let name = command.get_name().to_string();
+    // Generated into memory first: writing to a closed pipe panics inside clap_complete
+    let mut script = Vec::new();
+    clap_complete::generate(shell, &mut command, name, &mut script);
+    std::io::stdout().write_all(&script)?;
+    Ok(())
+}
@@ -1,30 +1,225 @@
-use anyhow::{Context, Result};
-use secrecy::SecretString;
+use std::path::Path;
 
-use crate::config;
-use crate::store::password::PasswordStore;
-use crate::store::Store;
+use anyhow::{bail, Context, Result};
+use serde::Serialize;
 
-pub fn run(key: &str) -> Result<()> {
-    let root = config::project_root()?;
-    let cfg = config::read(&root)?;
+use crate::cli::{DeleteArgs, OutputFormat};
+use crate::commands::{confirm, glob_match, inform, is_interactive, note, StoreTarget};
+use crate::env_template::{self, Scope};
+use crate::ui;
 
-    let password = rpassword::prompt_password("Enject store password: ")
-        .context("Failed to read Enject store password")?;
-    let password = SecretString::new(password);
+/// The secrets a delete will remove, and the arguments that matched nothing.
+#[derive(Debug, Default, PartialEq)]
+struct Selection {
+    /// Named keys in argument order, then pattern matches in store order, without repeats.
+    keys: Vec<String>,
+    missing: Vec<String>,
+    unmatched_patterns: Vec<String>,
+}
+
+/// `enject delete --format json`: what was deleted and what wasn't found.
+#[derive(Debug, Serialize)]
+struct Report<'a> {
+    deleted: &'a [String],
+    not_found: &'a [String],
+    unmatched_patterns: &'a [String],
+    history_kept: bool,
+}
+
+/// Delete the named secrets and every secret matching a `--glob` pattern with one
+/// unlock and one save. Names that don't exist are reported but don't stop the rest.
+/// `scope` says which kind of `en://` reference in the project `.env` points at `target`.
+pub fn run(
+    target: &StoreTarget,
+    scope: Scope,
+    args: &DeleteArgs,
+    format: OutputFormat,
+) -> Result<()> {
+    let mut store = target.unlock()?;
+
+    let selection = select(&store.list()?, &args.keys, &args.glob);
+    let report = |deleted: &[String]| -> Result<()> {
+        if format == OutputFormat::Json {
+            let report = Report {
+                deleted,
+                not_found: &selection.missing,
+                unmatched_patterns: &selection.unmatched_patterns,
+                history_kept: args.keep_history && !deleted.is_empty(),
+            };
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        }
+        Ok(())
+    };
+    for key in &selection.missing {
+        note(format, format_args!("Secret '{}' not found.", key));
+    }
+    for pattern in &selection.unmatched_patterns {
+        note(format, format_args!("No secret matches '{}'.", pattern));
+    }
+    if selection.keys.is_empty() {
+        return report(&[]);
+    }
 
-    let store_path = config::store_path(&root);
-    let mut store = PasswordStore::new(store_path, cfg.kdf_params(), cfg.salt_bytes()?);
-    store
-        .unlock(&password)
-        .context("Failed to unlock store — wrong password?")?;
+    if !args.glob.is_empty() {
+        note(
+            format,
+            format_args!("Matched {} secret(s):", selection.keys.len()),
+        );
+        for key in &selection.keys {
+            note(format, format_args!("  {}", key));
+        }
+    }
+    warn_env_refs(&target.project_root, scope, &selection.keys);
+
+    if !args.force {
+        if !is_interactive() {
+            bail!("Refusing to delete without confirmation. Re-run with --force.");
+        }
+        let question = match selection.keys.as_slice() {
+            [key] => format!("Delete secret '{}'? This cannot be undone.", key),
+            keys => format!(
+                "Delete these {} secrets? This cannot be undone.",
+                keys.len()
+            ),
+        };
+        if !confirm(&question)? {
+            note(format, format_args!("Nothing deleted."));
+            return report(&[]);
+        }
+    }
 
-    if store.delete(key)? {
-        store.save(&password).context("Failed to save store")?;
-        println!("Secret '{}' deleted.", key);
-    } else {
-        println!("Secret '{}' not found.", key);
+    for key in &selection.keys {
+        if args.keep_history {
+            store.delete_keeping_history(key)?;
+        } else {
+            store.delete(key)?;
+        }
     }
+    store.persist().context("Failed to save store")?;
 
+    if format == OutputFormat::Json {
+        return report(&selection.keys);
+    }
+    match selection.keys.as_slice() {
+        [key] => ui::success(format_args!("Secret '{}' deleted.", key)),
+        keys => ui::success(format_args!(
+            "Deleted {} secret(s): {}.",
+            keys.len(),
+            keys.join(", ")
+        )),
+    }
+    if args.keep_history {
+        inform(format_args!(
+            "Their values are kept; bring one back with: {} restore-version <key> 1",
+            target.command
+        ));
+    }
     Ok(())
 }
+
+/// Warn about every line of the project `.env` that still references a key about
+/// to be deleted. Problems reading the file just skip the check.
+fn warn_env_refs(root: &Path, scope: Scope, keys: &[String]) {
+    let Ok(content) = std::fs::read_to_string(root.join(".env")) else {
+        return;
+    };
+    for (line_no, env_key, name) in referencing_lines(&content, scope, keys) {
+        let prefix = match scope {
+            Scope::Local => "en://",
+            Scope::Global => "en://global/",
+        };
+        ui::warn(format_args!(
+            ".env line {} ({}) still references {}{}; the next run will fail.",
+            line_no, env_key, prefix, name
+        ));
+    }
+}
+
+/// Line number, env key and secret name for each reference in `content` to one of `keys`.
+fn referencing_lines<'a>(
+    content: &str,
+    scope: Scope,
+    keys: &'a [String],
+) -> Vec<(usize, String, &'a str)> {
+    let (lines, _) = env_template::parse_all(content);
+    let mut found = Vec::new();
+    for parsed in &lines {
+        let Some(env_key) = parsed.line.key() else {
+            continue;
+        };
+        for secret in parsed.line.refs() {
+            if secret.scope != scope || secret.default.is_some() {
+                continue;
+            }
+            if let Some(key) = keys.iter().find(|key| key.as_str() == secret.name) {
+                found.push((parsed.line_no, env_key.to_string(), key.as_str()));
+            }
+        }
+    }
+    found
+}
+
+fn select(stored: &[String], keys: &[String], patterns: &[String]) -> Selection {
+    let mut selection = Selection::default();
+    for key in keys {
+        if !stored.contains(key) {
+            selection.missing.push(key.clone());
+        } else if !selection.keys.contains(key) {
+            selection.keys.push(key.clone());
+        }
+    }
+    for pattern in patterns {
+        let mut matched = false;
+        for key in stored.iter().filter(|key| glob_match(pattern, key)) {
+            matched = true;
+            if !selection.keys.contains(key) {
+                selection.keys.push(key.clone());
+            }
+        }
+        if !matched {
+            selection.unmatched_patterns.push(pattern.clone());
+        }
+    }
+    selection
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(names: &[&str]) -> Vec<String> {
+        names.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_select_keys_and_patterns() {
+        let stored = strings(&["db_url", "stripe_pk", "stripe_sk", "stripe_webhook"]);
+        let selection = select(
+            &stored,
+            &strings(&["db_url", "gone", "stripe_sk", "db_url"]),
+            &strings(&["stripe_*", "sentry_*"]),
+        );
+        assert_eq!(
+            selection,
+            Selection {
+                keys: strings(&["db_url", "stripe_sk", "stripe_pk", "stripe_webhook"]),
+                missing: strings(&["gone"]),
+                unmatched_patterns: strings(&["sentry_*"]),
+            }
+        );
+    }
+
+    #[test]
+    fn test_referencing_lines() {
+        let content = "PORT=3000\nDB=en://db_url\nSHARED=en://global/db_url\n\
+                       OPT=en://db_url?default=x\nURL=x:en://db_url@host\n";
+        let keys = strings(&["db_url"]);
+        let local: Vec<_> = referencing_lines(content, Scope::Local, &keys)
+            .into_iter()
+            .map(|(line, key, _)| (line, key))
+            .collect();
+        assert_eq!(local, vec![(2, "DB".to_string()), (5, "URL".to_string())]);
+        let global = referencing_lines(content, Scope::Global, &keys);
+        assert_eq!(global, vec![(3, "SHARED".to_string(), "db_url")]);
+    }
+}
@@ -0,0 +1,239 @@
+use std::collections::{BTreeSet, HashSet};
+
+use anyhow::{bail, Context, Result};
+use serde::Serialize;
+
+use crate::cli::{GlobalOpts, OutputFormat};
+use crate::commands::run::template_paths;
+use crate::commands::{project_root, StoreTarget};
+use crate::env_template::{self, EnvLine, ParsedLine, Scope};
+
+/// A template reference to a secret the store doesn't hold.
+#[derive(Debug, Serialize, PartialEq)]
+struct Missing {
+    secret: String,
+    file: String,
+    line: usize,
+    key: String,
+    /// The reference has a `?default=` that `run` falls back to.
+    has_default: bool,
+}
+
+/// A plain template value assigned to a variable whose name is also a secret in
+/// the store, as if a real value was pasted back over its reference.
+#[derive(Debug, Serialize, PartialEq)]
+struct Shadowed {
+    key: String,
+    secret: String,
+    file: String,
+    line: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct Diff {
+    files: Vec<String>,
+    missing: Vec<Missing>,
+    missing_global: Vec<Missing>,
+    unreferenced: Vec<String>,
+    shadowed: Vec<Shadowed>,
+}
+
+impl Diff {
+    /// References that `run` would fail on: missing, with no default.
+    fn failing(&self) -> usize {
+        self.missing
+            .iter()
+            .chain(&self.missing_global)
+            .filter(|m| !m.has_default)
+            .count()
+    }
+}
+
+/// `enject diff` — compare the template with the store: references with no secret,
+/// secrets nothing references, and plain values shadowing stored secrets. Fails if
+/// a reference without a default has no secret.
+pub fn run(opts: &GlobalOpts, profile: Option<&str>, format: OutputFormat) -> Result<()> {
+    let root = project_root(opts)?;
+    let local = StoreTarget::local(opts)?;
+    let profile = profile.or(local.config.default_profile.as_deref());
+
+    let mut templates = Vec::new();
+    for path in template_paths(&root, profile)? {
+        let lines = env_template::parse_file(&path)
+            .with_context(|| format!("Failed to parse {}", path.display()))?;
+        templates.push((path.display().to_string(), lines));
+    }
+
+    let local_keys: HashSet<String> = local.unlock_read_only()?.list()?.into_iter().collect();
+    let global_keys = if templates
+        .iter()
+        .flat_map(|(_, lines)| lines)
+        .any(|l| l.line.refs().iter().any(|r| r.scope == Scope::Global))
+    {
+        let mut global = StoreTarget::global(opts)?;
+        // --password-file belongs to the project store
+        global.password_file = None;
+        let keys: HashSet<String> = global.unlock_read_only()?.list()?.into_iter().collect();
+        Some(keys)
+    } else {
+        None
+    };
+
+    let diff = diff(&templates, &local_keys, global_keys.as_ref());
+    match format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&diff)?),
+        OutputFormat::Text => print_text(&diff),
+    }
+    let failing = diff.failing();
+    if failing > 0 {
+        bail!("{} referenced secret(s) are missing.", failing);
+    }
+    Ok(())
+}
+
+fn diff(
+    templates: &[(String, Vec<ParsedLine>)],
+    local_keys: &HashSet<String>,
+    global_keys: Option<&HashSet<String>>,
+) -> Diff {
+    let mut missing = Vec::new();
+    let mut missing_global = Vec::new();
+    let mut shadowed = Vec::new();
+    let mut referenced = HashSet::new();
+    for (file, lines) in templates {
+        for parsed in lines {
+            let Some(key) = parsed.line.key() else {
+                continue;
+            };
+            if let EnvLine::Plain { .. } = parsed.line {
+                let lowercase = key.to_lowercase();
+                let secret = [key, lowercase.as_str()]
+                    .into_iter()
+                    .find(|name| local_keys.contains(*name));
+                if let Some(secret) = secret {
+                    shadowed.push(Shadowed {
+                        key: key.to_string(),
+                        secret: secret.to_string(),
+                        file: file.clone(),
+                        line: parsed.line_no,
+                    });
+                }
+            }
+            for secret in parsed.line.refs() {
+                let (keys, out) = match secret.scope {
+                    Scope::Local => {
+                        referenced.insert(secret.name);
+                        (Some(local_keys), &mut missing)
+                    }
+                    Scope::Global => (global_keys, &mut missing_global),
+                };
+                if keys.is_some_and(|keys| !keys.contains(secret.name)) {
+                    out.push(Missing {
+                        secret: secret.name.to_string(),
+                        file: file.clone(),
+                        line: parsed.line_no,
+                        key: key.to_string(),
+                        has_default: secret.default.is_some(),
+                    });
+                }
+            }
+        }
+    }
+    let unreferenced: BTreeSet<&String> = local_keys
+        .iter()
+        .filter(|name| !referenced.contains(name.as_str()))
+        .collect();
+
+    Diff {
+        files: templates.iter().map(|(file, _)| file.clone()).collect(),
+        missing,
+        missing_global,
+        unreferenced: unreferenced.into_iter().cloned().collect(),
+        shadowed,
+    }
+}
+
+fn print_text(diff: &Diff) {
+    print_missing("Missing secrets", &diff.missing);
+    if !diff.missing_global.is_empty() {
+        print_missing("Missing global secrets", &diff.missing_global);
+    }
+
+    println!("Unreferenced secrets ({}):", diff.unreferenced.len());
+    for name in &diff.unreferenced {
+        println!("  {}", name);
+    }
+
+    println!("Plain values shadowing secrets ({}):", diff.shadowed.len());
+    for s in &diff.shadowed {
+        println!(
+            "  {}:{}: {} is plain text, but the store has '{}'",
+            s.file, s.line, s.key, s.secret
+        );
+    }
+}
+
+fn print_missing(title: &str, missing: &[Missing]) {
+    println!("{} ({}):", title, missing.len());
+    for m in missing {
+        let default = if m.has_default {
+            " (its default will be used)"
+        } else {
+            ""
+        };
+        println!(
+            "  {}:{}: {} references '{}'{}",
+            m.file, m.line, m.key, m.secret, default
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(names: &[&str]) -> HashSet<String> {
+        names.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn templates(content: &str) -> Vec<(String, Vec<ParsedLine>)> {
+        vec![(
+            ".env".to_string(),
+            env_template::parse_numbered(content).unwrap(),
+        )]
+    }
+
+    #[test]
+    fn test_diff_sections() {
+        let templates = templates(
+            "DB=en://db\nAPI=en://api?default=x\nTOKEN=hunter2\nPORT=3000\nG=en://global/g\n",
+        );
+        let diff = diff(&templates, &keys(&["db", "token", "old"]), Some(&keys(&[])));
+
+        let missing: Vec<_> = diff.missing.iter().map(|m| m.secret.as_str()).collect();
+        assert_eq!(missing, vec!["api"]);
+        assert!(diff.missing[0].has_default);
+        assert_eq!(diff.missing_global.len(), 1);
+        assert_eq!(diff.missing_global[0].line, 5);
+        assert_eq!(diff.unreferenced, vec!["old", "token"]);
+        assert_eq!(
+            diff.shadowed,
+            vec![Shadowed {
+                key: "TOKEN".into(),
+                secret: "token".into(),
+                file: ".env".into(),
+                line: 3,
+            }]
+        );
+        assert_eq!(diff.failing(), 1);
+    }
+
+    #[test]
+    fn test_diff_without_global_store_skips_global_refs() {
+        let templates = templates("DB=en://db\nG=en://global/g\n");
+        let diff = diff(&templates, &keys(&["db"]), None);
+        assert!(diff.missing.is_empty() && diff.missing_global.is_empty());
+        assert!(diff.unreferenced.is_empty() && diff.shadowed.is_empty());
+        assert_eq!(diff.failing(), 0);
+    }
+}
@@ -0,0 +1,412 @@
+use std::path::Path;
+
+use anyhow::Result;
+
+use crate::cli::GlobalOpts;
+use crate::commands::{project_root, store_dir, StoreTarget};
+use crate::config::{self, Config};
+use crate::env_template;
+use crate::migration::{self, MigrationNeeded};
+
+/// How a check came out. The order is by severity, so the worst is the maximum.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Level {
+    Pass,
+    Warn,
+    Fail,
+}
+
+impl Level {
+    fn label(self) -> &'static str {
+        match self {
+            Level::Pass => "PASS",
+            Level::Warn => "WARN",
+            Level::Fail => "FAIL",
+        }
+    }
+}
+
+/// The outcome of one check, with what to do about it unless it passed.
+#[derive(Debug)]
+struct Finding {
+    level: Level,
+    check: &'static str,
+    message: String,
+    fix: Option<String>,
+}
+
+impl Finding {
+    fn pass(check: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            level: Level::Pass,
+            check,
+            message: message.into(),
+            fix: None,
+        }
+    }
+
+    fn warn(check: &'static str, message: impl Into<String>, fix: impl Into<String>) -> Self {
+        Self {
+            level: Level::Warn,
+            check,
+            message: message.into(),
+            fix: Some(fix.into()),
+        }
+    }
+
+    fn fail(check: &'static str, message: impl Into<String>, fix: impl Into<String>) -> Self {
+        Self {
+            level: Level::Fail,
+            check,
+            message: message.into(),
+            fix: Some(fix.into()),
+        }
+    }
+}
+
+/// `enject doctor` — check the project's setup and print one line per check. Never
+/// prompts unless `unlock` asks to try the password. Returns the exit code: 0 when
+/// everything passed, 1 for warnings, 2 for failures.
+pub fn run(opts: &GlobalOpts, unlock: bool) -> Result<i32> {
+    let root = project_root(opts)?;
+    let dir = store_dir(opts, &root);
+    let mut findings = Vec::new();
+
+    let (finding, cfg) = check_config(&dir);
+    findings.push(finding);
+    if let Some(cfg) = &cfg {
+        findings.push(check_salt(cfg));
+        findings.push(check_store_file(&dir, cfg));
+        findings.extend(check_permissions(&dir));
+    }
+    findings.extend(check_legacy(&root));
+    findings.extend(check_env_backups(&root));
+    findings.push(check_env(&root));
+    if let (true, Some(cfg)) = (unlock, cfg) {
+        findings.push(check_unlock(StoreTarget::local_from(opts, &root, cfg)));
+    }
+
+    for finding in &findings {
+        println!(
+            "{}  {:<12} {}",
+            finding.level.label(),
+            finding.check,
+            finding.message
+        );
+        if let Some(fix) = &finding.fix {
+            println!("      {:<12} -> {}", "", fix);
+        }
+    }
+    let worst = findings
+        .iter()
+        .map(|f| f.level)
+        .max()
+        .unwrap_or(Level::Pass);
+    Ok(match worst {
+        Level::Pass => 0,
+        Level::Warn => 1,
+        Level::Fail => 2,
+    })
+}
+
+/// `config.toml` exists and parses.
+fn check_config(dir: &Path) -> (Finding, Option<Config>) {
+    let path = config::config_path_in(dir);
+    if !path.exists() {
+        let finding = Finding::fail(
+            "config",
+            format!("{} not found", path.display()),
+            "Run `enject init` to create a store here.",
+        );
+        return (finding, None);
+    }
+    match config::read_store_dir(dir) {
+        Ok(cfg) => (
+            Finding::pass(
+                "config",
+                format!("{} ({} backend)", path.display(), cfg.backend),
+            ),
+            Some(cfg),
+        ),
+        Err(e) => (
+            Finding::fail(
+                "config",
+                format!("{} does not parse: {}", path.display(), e),
+                "Fix the file by hand, or restore it from a backup.",
+            ),
+            None,
+        ),
+    }
+}
+
+/// The salt is valid hex of a sensible length.
+fn check_salt(cfg: &Config) -> Finding {
+    match cfg.salt_bytes() {
+        Ok(salt) if salt.len() >= 16 => Finding::pass("salt", format!("{} bytes", salt.len())),
+        Ok(salt) => Finding::warn(
+            "salt",
+            format!("only {} bytes", salt.len()),
+            "Run `enject rotate --keep-password` to re-encrypt under a fresh 32-byte salt.",
+        ),
+        Err(e) => Finding::fail(
+            "salt",
+            e.to_string(),
+            "The salt in config.toml must be the one the store was written with; restore it from a backup.",
+        ),
+    }
+}
+
+/// The store file exists, can be read and isn't empty. A keyring store has none.
+fn check_store_file(dir: &Path, cfg: &Config) -> Finding {
+    if cfg.backend == "keyring" {
+        return Finding::pass("store", "kept in the OS keychain; no store file");
+    }
+    let path = config::store_path_in(dir);
+    match std::fs::read(&path) {
+        Ok(data) if data.is_empty() => Finding::fail(
+            "store",
+            format!("{} is empty", path.display()),
+            "Restore the store from a backup.",
+        ),
+        Ok(data) => Finding::pass("store", format!("{} ({} bytes)", path.display(), data.len())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Finding::warn(
+            "store",
+            format!("{} not found", path.display()),
+            "It is created on the first `enject set`; if secrets were saved, restore it from a backup.",
+        ),
+        Err(e) => Finding::fail(
+            "store",
+            format!("{} can't be read: {}", path.display(), e),
+            "Check the file's owner and permissions.",
+        ),
+    }
+}
+
+/// Neither the config nor the store is readable by other users.
+#[cfg(unix)]
+fn check_permissions(dir: &Path) -> Vec<Finding> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mut findings = Vec::new();
+    for path in [config::config_path_in(dir), config::store_path_in(dir)] {
+        let Ok(metadata) = std::fs::metadata(&path) else {
+            continue;
+        };
+        let mode = metadata.permissions().mode();
+        if mode & 0o004 != 0 {
+            findings.push(Finding::warn(
+                "permissions",
+                format!(
+                    "{} is world-readable (mode {:o})",
+                    path.display(),
+                    mode & 0o777
+                ),
+                format!("chmod 600 {}", path.display()),
+            ));
+        }
+    }
+    if findings.is_empty() {
+        findings.push(Finding::pass("permissions", "not readable by other users"));
+    }
+    findings
+}
+
+#[cfg(not(unix))]
+fn check_permissions(_dir: &Path) -> Vec<Finding> {
+    Vec::new()
+}
+
+/// No legacy `.enveil/` directory or `ev://` references remain.
+fn check_legacy(root: &Path) -> Vec<Finding> {
+    let mut findings: Vec<Finding> = migration::pending(root)
+        .unwrap_or_default()
+        .iter()
+        .map(|pending| {
+            let message = match pending {
+                MigrationNeeded::LegacyDir(_) => {
+                    "the store is in a legacy .enveil/ directory".to_string()
+                }
+                MigrationNeeded::LegacyRefs { path, lines } => format!(
+                    "{} has legacy ev:// reference(s) on line(s) {}",
+                    path.file_name().unwrap_or_default().to_string_lossy(),
+                    migration::line_list(lines)
+                ),
+            };
+            Finding::warn("legacy", message, "Run `enject migrate`.")
+        })
+        .collect();
+    if findings.is_empty() {
+        findings.push(Finding::pass("legacy", "no .enveil/ or ev:// left"));
+    }
+    findings
+}
+
+/// No plaintext backups written by `enject import` are lying around.
+fn check_env_backups(root: &Path) -> Vec<Finding> {
+    let mut backups: Vec<String> = std::fs::read_dir(root)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .filter(|name| name.ends_with(".env.bak"))
+        .collect();
+    backups.sort();
+    if backups.is_empty() {
+        return vec![Finding::pass("backups", "no plaintext .env.bak files")];
+    }
+    backups
+        .into_iter()
+        .map(|name| {
+            Finding::warn(
+                "backups",
+                format!("{} holds plaintext values from `enject import`", name),
+                format!("Delete {} once the imported secrets work.", name),
+            )
+        })
+        .collect()
+}
+
+/// `.env` exists and parses.
+fn check_env(root: &Path) -> Finding {
+    let path = root.join(".env");
+    let content = match std::fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Finding::warn(
+                ".env",
+                "not found",
+                "Create .env with en:// references for `enject run`.",
+            )
+        }
+        Err(e) => {
+            return Finding::fail(
+                ".env",
+                format!("can't be read: {}", e),
+                "Check the file's owner and permissions.",
+            )
+        }
+    };
+    let (lines, errors) = env_template::parse_all(&content);
+    match errors.first() {
+        None => Finding::pass(".env", format!("{} line(s) parse", lines.len())),
+        Some(e) => Finding::fail(
+            ".env",
+            format!("{} line(s) don't parse; {}", errors.len(), e),
+            "Run `enject check` for every problem with its line number.",
+        ),
+    }
+}
+
+/// The store opens.
+fn check_unlock(target: StoreTarget) -> Finding {
+    match target
+        .unlock_read_only()
+        .and_then(|store| Ok(store.list()?.len()))
+    {
+        Ok(count) => Finding::pass("unlock", format!("opens, {} secret(s)", count)),
+        Err(e) => Finding::fail(
+            "unlock",
+            format!("{:#}", e),
+            "Check the password; if it's right, restore the store from a backup.",
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn config() -> Config {
+        Config::default_new(hex::encode([7u8; 32]))
+    }
+
+    #[test]
+    fn test_check_config() {
+        let dir = TempDir::new().unwrap();
+        let (finding, cfg) = check_config(dir.path());
+        assert_eq!(finding.level, Level::Fail);
+        assert!(cfg.is_none());
+
+        std::fs::write(dir.path().join("config.toml"), "backend = ").unwrap();
+        let (finding, _) = check_config(dir.path());
+        assert_eq!(finding.level, Level::Fail);
+        assert!(finding.message.contains("does not parse"));
+
+        config::write_in(dir.path(), &config()).unwrap();
+        let (finding, cfg) = check_config(dir.path());
+        assert_eq!(finding.level, Level::Pass);
+        assert!(cfg.is_some());
+    }
+
+    #[test]
+    fn test_check_salt() {
+        assert_eq!(check_salt(&config()).level, Level::Pass);
+        let mut cfg = config();
+        cfg.salt = "abcd".into();
+        assert_eq!(check_salt(&cfg).level, Level::Warn);
+        cfg.salt = "not hex".into();
+        assert_eq!(check_salt(&cfg).level, Level::Fail);
+    }
+
+    #[test]
+    fn test_check_store_file() {
+        let dir = TempDir::new().unwrap();
+        assert_eq!(check_store_file(dir.path(), &config()).level, Level::Warn);
+        std::fs::write(dir.path().join("store"), b"").unwrap();
+        assert_eq!(check_store_file(dir.path(), &config()).level, Level::Fail);
+        std::fs::write(dir.path().join("store"), b"ENJECT...").unwrap();
+        assert_eq!(check_store_file(dir.path(), &config()).level, Level::Pass);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_check_permissions_flags_world_readable_files() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = TempDir::new().unwrap();
+        let store = dir.path().join("store");
+        std::fs::write(&store, b"x").unwrap();
+        std::fs::set_permissions(&store, std::fs::Permissions::from_mode(0o600)).unwrap();
+        assert_eq!(check_permissions(dir.path())[0].level, Level::Pass);
+
+        std::fs::set_permissions(&store, std::fs::Permissions::from_mode(0o644)).unwrap();
+        let findings = check_permissions(dir.path());
+        assert_eq!(findings[0].level, Level::Warn);
+        assert!(findings[0].fix.as_ref().unwrap().starts_with("chmod 600"));
+    }
+
+    #[test]
+    fn test_check_legacy_and_backups() {
+        let dir = TempDir::new().unwrap();
+        assert_eq!(check_legacy(dir.path())[0].level, Level::Pass);
+        assert_eq!(check_env_backups(dir.path())[0].level, Level::Pass);
+
+        std::fs::create_dir(dir.path().join(".enveil")).unwrap();
+        std::fs::write(dir.path().join(".env"), "DB=ev://db\n").unwrap();
+        std::fs::write(dir.path().join(".env.env.bak"), "DB=hunter2\n").unwrap();
+        let findings = check_legacy(dir.path());
+        assert_eq!(findings.len(), 2);
+        assert!(findings.iter().all(|f| f.level == Level::Warn));
+        let findings = check_env_backups(dir.path());
+        assert_eq!(findings[0].level, Level::Warn);
+        assert!(findings[0].message.starts_with(".env.env.bak"));
+    }
+
+    #[test]
+    fn test_check_env() {
+        let dir = TempDir::new().unwrap();
+        assert_eq!(check_env(dir.path()).level, Level::Warn);
+        std::fs::write(dir.path().join(".env"), "PORT=3000\nDB=en://db\n").unwrap();
+        assert_eq!(check_env(dir.path()).level, Level::Pass);
+        std::fs::write(dir.path().join(".env"), "PORT=3000\nnot a line\n").unwrap();
+        let finding = check_env(dir.path());
+        assert_eq!(finding.level, Level::Fail);
+        assert!(finding.message.contains("line 2"), "{}", finding.message);
+    }
+
+    #[test]
+    fn test_levels_order_by_severity() {
+        assert!(Level::Fail > Level::Warn && Level::Warn > Level::Pass);
+    }
+}
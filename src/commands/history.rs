@@ -0,0 +1,59 @@
+use anyhow::Result;
+use blake2::{Blake2b512, Digest};
+use secrecy::{ExposeSecret, SecretString};
+
+use crate::commands::StoreTarget;
+use crate::error::EnjectError;
+
+/// List the versions of `key` kept in the store: number, when each was set, and a
+/// short fingerprint to tell them apart. Values are never printed.
+pub fn run(target: &StoreTarget, key: &str) -> Result<()> {
+    let store = target.unlock_read_only()?;
+
+    let versions = store.history(key)?;
+    if versions.is_empty() {
+        return Err(EnjectError::SecretNotFound(key.to_string()).into());
+    }
+    if versions[0].number != 0 {
+        println!(
+            "Secret '{}' was deleted; restore a version with: {} restore-version {} <n>",
+            key, target.command, key
+        );
+    }
+    println!("VERSION  SET AT                FINGERPRINT");
+    for version in &versions {
+        let current = if version.number == 0 {
+            "  (current)"
+        } else {
+            ""
+        };
+        println!(
+            "{:<7}  {:<20}  {}{}",
+            version.number,
+            version.set_at,
+            fingerprint(&version.value),
+            current
+        );
+    }
+    Ok(())
+}
+
+/// The first 8 hex digits of the value's BLAKE2b hash: enough to see whether two
+/// versions differ, too short to stand in for the value.
+fn fingerprint(value: &SecretString) -> String {
+    let digest = Blake2b512::digest(value.expose_secret().as_bytes());
+    hex::encode(&digest[..4])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fingerprint_is_short_and_stable() {
+        let a = fingerprint(&SecretString::new("one".to_string()));
+        assert_eq!(a.len(), 8);
+        assert_eq!(a, fingerprint(&SecretString::new("one".to_string())));
+        assert_ne!(a, fingerprint(&SecretString::new("two".to_string())));
+    }
+}
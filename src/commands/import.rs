@@ -1,134 +1,779 @@
 use anyhow::{bail, Context, Result};
-use secrecy::SecretString;
+use secrecy::{ExposeSecret, SecretString};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::Write as _;
 use std::io::{self, BufRead, Write};
-use std::path::Path;
 
-use crate::config;
-use crate::env_template::{self, templatize, EnvLine};
-use crate::store::password::PasswordStore;
-use crate::store::Store;
+use crate::cli::{ConflictPolicy, GlobalOpts, ImportArgs};
+use crate::commands::{confirm, glob_match, inform, is_interactive, parse_template, StoreTarget};
+use crate::env_template::{self, templatize, EnvLine, LineStyle, ParsedLine};
+use crate::ui;
 
-pub fn run(file: &Path) -> Result<()> {
+pub fn run(opts: &GlobalOpts, args: &ImportArgs) -> Result<()> {
+    let file = args.file.as_path();
     if !file.exists() {
         bail!("File not found: {}", file.display());
     }
 
-    let root = config::project_root()?;
-    let cfg = config::read(&root)?;
+    let target = StoreTarget::local(opts)?;
 
-    // Count importable secrets so the warning is specific
-    let lines = env_template::parse_file(file).context("Failed to parse import file")?;
-    let secret_count = lines
+    let lines = parse_template(file).context("Failed to parse import file")?;
+    let original = std::fs::read_to_string(file)?;
+
+    if !lines
         .iter()
-        .filter(|l| matches!(l, EnvLine::Plain { .. }))
-        .count();
+        .any(|l| matches!(l.line, EnvLine::Plain { .. }))
+    {
+        bail!(
+            "No plain KEY=value pairs found in {}. Nothing to import.",
+            file.display()
+        );
+    }
 
+    // Plan first so the warning is specific and bad filters fail before any prompt
+    let mut plan = plan(&original, &lines, &Options::from(args))?;
+    let secret_count = plan.imported().count();
     if secret_count == 0 {
         bail!(
-            "No plain KEY=value pairs found in {}. Nothing to import.",
+            "Every plain value in {} is excluded or interpolated. Nothing to import.",
             file.display()
         );
     }
 
+    if args.dry_run {
+        let store = target.unlock_read_only()?;
+        // Without a policy, show conflicts as they are rather than asking about them
+        plan.check_store(
+            |name| Ok(store.get(name)?),
+            |entry| match args.on_conflict {
+                Some(policy) => resolve_conflict(policy, entry),
+                None => Ok(Action::Conflict),
+            },
+        )?;
+        print!(
+            "{}",
+            plan.render(&file.display().to_string(), &original, &lines)
+        );
+        println!();
+        println!(
+            "Dry run: neither the store nor {} was changed.",
+            file.display()
+        );
+        return Ok(());
+    }
+
     // Warning
-    println!();
-    println!("WARNING: enject import will:");
-    println!(
+    eprintln!();
+    ui::danger(format_args!("WARNING: enject import will:"));
+    eprintln!(
         "  1. Encrypt {} secret(s) from {} into your enject store",
         secret_count,
         file.display()
     );
-    println!(
+    eprintln!(
         "  2. Overwrite {} in place, replacing secret values with en:// references",
         file.display()
     );
-    println!();
-    println!("This is destructive. If anything goes wrong (wrong password, etc.),");
-    println!("your original secret values may be unrecoverable from the file.");
-    println!();
+    eprintln!();
+    eprintln!("This is destructive. If anything goes wrong (wrong password, etc.),");
+    eprintln!("your original secret values may be unrecoverable from the file.");
+    eprintln!();
 
-    // Backup prompt
-    let backup_path = file.with_extension("env.bak");
-    print!(
-        "Create a backup at {} before importing? [y/N]: ",
-        backup_path.display()
-    );
-    io::stdout().flush()?;
+    // Decide everything up front: with stdin not a terminal, a prompt would
+    // wait for an answer that never comes.
+    if !args.yes && !is_interactive() {
+        bail!(
+            "stdin is not a terminal, so import cannot ask for confirmation. \
+             Re-run with --yes (and --backup or --no-backup) to import non-interactively."
+        );
+    }
 
-    let mut answer = String::new();
-    io::stdin().lock().read_line(&mut answer)?;
-    let wants_backup = answer.trim().eq_ignore_ascii_case("y");
+    let backup_path = file.with_extension("env.bak");
+    let wants_backup = if args.backup || args.no_backup || args.yes {
+        args.backup
+    } else {
+        confirm(&format!(
+            "Create a backup at {} before importing?",
+            backup_path.display()
+        ))?
+    };
 
     if wants_backup {
         std::fs::copy(file, &backup_path).context("Failed to create backup file")?;
-        println!();
-        println!("Backup written to {}", backup_path.display());
-        println!();
-        println!(
+        eprintln!();
+        eprintln!("Backup written to {}", backup_path.display());
+        eprintln!();
+        ui::danger(format_args!(
             "IMPORTANT: {} still contains your plaintext secrets.",
             backup_path.display()
-        );
-        println!("Move it somewhere safe or delete it before giving any AI tool");
-        println!("access to this directory.");
-        println!();
+        ));
+        eprintln!("Move it somewhere safe or delete it before giving any AI tool");
+        eprintln!("access to this directory.");
+        eprintln!();
+    } else if args.yes && !args.no_backup {
+        inform(format_args!(
+            "No backup written (pass --backup to keep one)."
+        ));
     }
 
     // Final confirmation before proceeding
-    print!("Proceed with import? [y/N]: ");
-    io::stdout().flush()?;
-
-    let mut confirm = String::new();
-    io::stdin().lock().read_line(&mut confirm)?;
-    if !confirm.trim().eq_ignore_ascii_case("y") {
-        println!("Import cancelled.");
+    if !args.yes && !confirm("Proceed with import?")? {
+        inform(format_args!("Import cancelled."));
         return Ok(());
     }
 
     println!();
 
-    let password = rpassword::prompt_password("Enject store password: ")
-        .context("Failed to read Enject store password")?;
-    let password = SecretString::new(password);
-
-    let store_path = config::store_path(&root);
-    let mut store = PasswordStore::new(store_path, cfg.kdf_params(), cfg.salt_bytes()?);
-    store
-        .unlock(&password)
-        .context("Failed to unlock store — wrong password?")?;
-
-    let mut imported = 0usize;
-    for line in &lines {
-        if let EnvLine::Plain { key, value } = line {
-            store.set(key, SecretString::new(value.clone()))?;
-            imported += 1;
-        }
-    }
+    let mut store = target.unlock()?;
+    // Every conflict is settled before anything is written, so an abort changes nothing
+    plan.check_store(
+        |name| Ok(store.get(name)?),
+        |entry| match args.on_conflict {
+            Some(policy) => resolve_conflict(policy, entry),
+            None if is_interactive() => ask_conflict(entry),
+            None => bail!(
+                "Secret '{}' is already in the store with a different value. \
+                 Re-run with --on-conflict keep|overwrite|abort.",
+                entry.secret_name
+            ),
+        },
+    )?;
 
-    store.save(&password).context("Failed to save store")?;
+    for entry in plan.imported() {
+        store.set(&entry.secret_name, entry.value.clone())?;
+    }
+    if args.force_save {
+        store.overwrite_changes();
+    }
+    store.persist().context("Failed to save store")?;
 
     // Rewrite the source file as an en:// template
-    let new_lines = templatize(&lines);
-    let output = new_lines.join("\n");
     let tmp_path = file.with_extension("env.tmp");
     {
         let mut tmp = std::fs::File::create(&tmp_path)?;
-        write!(tmp, "{}", output)?;
+        write!(tmp, "{}", plan.template())?;
         tmp.sync_all()?;
     }
     std::fs::rename(&tmp_path, file)?;
 
-    println!(
+    ui::success(format_args!(
         "Imported {} secret(s). {} rewritten as en:// template.",
-        imported,
+        plan.imported().count(),
         file.display()
-    );
+    ));
+    print!("{}", plan.summary());
     if wants_backup {
-        println!();
-        println!(
+        eprintln!();
+        ui::danger(format_args!(
             "Remember: delete or move {} — it still contains plaintext secrets.",
             backup_path.display()
-        );
+        ));
     }
 
     Ok(())
 }
+
+/// The store name a plain `key` is imported under.
+fn secret_name(key: &str, lowercase: bool) -> String {
+    if lowercase {
+        key.to_lowercase()
+    } else {
+        key.to_string()
+    }
+}
+
+/// How plain keys are chosen and named, from the command line.
+struct Options<'a> {
+    lowercase: bool,
+    only: &'a [String],
+    exclude: &'a [String],
+}
+
+impl<'a> From<&'a ImportArgs> for Options<'a> {
+    fn from(args: &'a ImportArgs) -> Self {
+        Self {
+            lowercase: args.lowercase,
+            only: &args.only,
+            exclude: &args.exclude,
+        }
+    }
+}
+
+/// What importing a file will do, worked out before anything is written.
+struct ImportPlan {
+    /// One entry per plain key, sorted by key.
+    entries: Vec<PlanEntry>,
+    /// The parsed file, rewritten by `template`.
+    lines: Vec<EnvLine>,
+    eol: &'static str,
+    trailing_newline: bool,
+}
+
+struct PlanEntry {
+    key: String,
+    secret_name: String,
+    action: Action,
+    value: SecretString,
+}
+
+#[derive(Debug, PartialEq)]
+enum Action {
+    /// The store has no secret by this name yet.
+    Create,
+    /// The store already holds this exact value: nothing to write.
+    Unchanged,
+    /// The store holds a different value and nobody has decided yet (dry run only).
+    Conflict,
+    /// Conflict: the file's value replaces the store's.
+    Overwrite,
+    /// Conflict: the store's value is kept and the line still becomes a reference.
+    KeepStore,
+    /// Conflict: neither stored nor rewritten; the line stays plain.
+    SkipConflict,
+    /// Not matched by `--only`; stays a plain line.
+    Skip,
+    /// Matched by `--exclude`; stays a plain line.
+    Exclude,
+    /// The value interpolates `${VAR}` (or escapes `$$`), which only a plain line
+    /// resolves; stays a plain line.
+    Interpolated,
+}
+
+impl Action {
+    /// Whether the key's line is rewritten as an en:// reference.
+    fn rewrites(&self) -> bool {
+        !matches!(
+            self,
+            Action::SkipConflict | Action::Skip | Action::Exclude | Action::Interpolated
+        )
+    }
+}
+
+/// Whether `run` would expand `$` in a plain value, so storing it as written
+/// would change what the variable resolves to.
+fn interpolates(value: &str, style: &LineStyle) -> bool {
+    !style.literal && (value.contains("${") || value.contains("$$"))
+}
+
+/// Plan the import of `lines`, parsed from `original`. Errors if an `--only`
+/// pattern matches no key, or if `--lowercase` would fold two different keys into
+/// one secret. A key assigned more than once is imported with its last value, and
+/// one whose value interpolates other variables is left plain.
+fn plan(original: &str, lines: &[ParsedLine], options: &Options) -> Result<ImportPlan> {
+    let mut entries: BTreeMap<&str, PlanEntry> = BTreeMap::new();
+    let mut owners: HashMap<String, &str> = HashMap::new();
+    for parsed in lines {
+        let EnvLine::Plain { key, value, style } = &parsed.line else {
+            continue;
+        };
+        let matches_any = |patterns: &[String]| patterns.iter().any(|p| glob_match(p, key));
+        let action = if !options.only.is_empty() && !matches_any(options.only) {
+            Action::Skip
+        } else if matches_any(options.exclude) {
+            Action::Exclude
+        } else if interpolates(value, style) {
+            Action::Interpolated
+        } else {
+            Action::Create
+        };
+        let name = secret_name(key, options.lowercase);
+        if action == Action::Create {
+            if let Some(other) = owners.insert(name.clone(), key) {
+                if other != key {
+                    bail!(
+                        "'{}' and '{}' would both be stored as '{}'. Rename one, or import without --lowercase.",
+                        other,
+                        key,
+                        name
+                    );
+                }
+            }
+        }
+        entries.insert(
+            key,
+            PlanEntry {
+                key: key.clone(),
+                secret_name: name,
+                action,
+                value: SecretString::new(value.clone()),
+            },
+        );
+    }
+
+    for pattern in options.only {
+        if !entries.keys().any(|key| glob_match(pattern, key)) {
+            bail!("--only {} matches no plain key in the file.", pattern);
+        }
+    }
+
+    Ok(ImportPlan {
+        entries: entries.into_values().collect(),
+        lines: lines.iter().map(|parsed| parsed.line.clone()).collect(),
+        eol: env_template::line_ending(original),
+        trailing_newline: original.ends_with('\n'),
+    })
+}
+
+impl ImportPlan {
+    /// The entries whose values go into the store.
+    fn imported(&self) -> impl Iterator<Item = &PlanEntry> {
+        self.entries
+            .iter()
+            .filter(|e| matches!(e.action, Action::Create | Action::Overwrite))
+    }
+
+    /// Compare every entry to be created with what `stored` returns for its secret
+    /// name. An identical value becomes a no-op; a different one is settled by
+    /// `decide`, which may abort the import by returning an error.
+    fn check_store(
+        &mut self,
+        stored: impl Fn(&str) -> Result<Option<SecretString>>,
+        mut decide: impl FnMut(&PlanEntry) -> Result<Action>,
+    ) -> Result<()> {
+        for entry in &mut self.entries {
+            if entry.action != Action::Create {
+                continue;
+            }
+            match stored(&entry.secret_name)? {
+                None => {}
+                Some(value) if value.expose_secret() == entry.value.expose_secret() => {
+                    entry.action = Action::Unchanged;
+                }
+                Some(_) => entry.action = decide(entry)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// The file's new content: the same template with every rewritten key's value
+    /// replaced by its en:// reference.
+    fn template(&self) -> String {
+        let names: HashMap<String, String> = self
+            .entries
+            .iter()
+            .filter(|entry| entry.action.rewrites())
+            .map(|entry| (entry.key.clone(), entry.secret_name.clone()))
+            .collect();
+        let mut template = templatize(&self.lines, &names).join(self.eol);
+        if self.trailing_newline {
+            template.push_str(self.eol);
+        }
+        template
+    }
+
+    /// The keys with each outcome, one line per non-empty group.
+    fn summary(&self) -> String {
+        let keys = |wanted: Action| -> Vec<&str> {
+            self.entries
+                .iter()
+                .filter(|e| e.action == wanted)
+                .map(|e| e.key.as_str())
+                .collect()
+        };
+        let mut out = String::new();
+        for (label, group) in [
+            ("Imported", keys(Action::Create)),
+            (
+                "Conflicts overwritten with the file value",
+                keys(Action::Overwrite),
+            ),
+            (
+                "Conflicts that kept the store value",
+                keys(Action::KeepStore),
+            ),
+            (
+                "Conflicts skipped (left as plain values)",
+                keys(Action::SkipConflict),
+            ),
+            (
+                "Already stored with the same value",
+                keys(Action::Unchanged),
+            ),
+            ("Skipped (not matched by --only)", keys(Action::Skip)),
+            ("Excluded", keys(Action::Exclude)),
+            (
+                "Skipped (interpolates other variables)",
+                keys(Action::Interpolated),
+            ),
+        ] {
+            if !group.is_empty() {
+                let _ = writeln!(out, "  {}: {}", label, group.join(", "));
+            }
+        }
+        out
+    }
+
+    /// A table of the plain keys and what happens to each, followed by a unified
+    /// diff of the template rewrite. Values are shown as `<redacted>`, never in
+    /// plaintext.
+    fn render(&self, file: &str, original: &str, lines: &[ParsedLine]) -> String {
+        let key_width = self.entries.iter().map(|e| e.key.len()).max().unwrap_or(0);
+        let key_width = key_width.max("KEY".len());
+        let name_width = self
+            .entries
+            .iter()
+            .map(|e| e.secret_name.len())
+            .max()
+            .unwrap_or(0)
+            .max("SECRET".len());
+
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "{:<key_width$}  {:<name_width$}  ACTION",
+            "KEY", "SECRET"
+        );
+        for entry in &self.entries {
+            let action = match entry.action {
+                Action::Create => "create",
+                Action::Unchanged => "unchanged (already stored)",
+                Action::Conflict => "conflict (differs from the store)",
+                Action::Overwrite => "overwrite",
+                Action::KeepStore => "keep store value",
+                Action::SkipConflict => "keep plain (conflict)",
+                Action::Skip => "keep plain (not matched by --only)",
+                Action::Exclude => "keep plain (excluded)",
+                Action::Interpolated => "keep plain (interpolated)",
+            };
+            let secret_name = if entry.action.rewrites() {
+                entry.secret_name.as_str()
+            } else {
+                "-"
+            };
+            let _ = writeln!(
+                out,
+                "{:<key_width$}  {:<name_width$}  {}",
+                entry.key, secret_name, action
+            );
+        }
+
+        // Keys the user marked as not secret are shown as they are
+        let secret_keys = self
+            .entries
+            .iter()
+            .filter(|e| !matches!(e.action, Action::Skip | Action::Exclude))
+            .map(|e| e.key.as_str())
+            .collect();
+        let before = redacted(original, lines, &secret_keys);
+        let after = self.template().replace("\r\n", "\n");
+        let diff = similar::TextDiff::from_lines(&before, &after);
+        let _ = write!(
+            out,
+            "\n{}",
+            diff.unified_diff()
+                .header(file, &format!("{} (after import)", file))
+        );
+        out
+    }
+}
+
+/// The outcome `policy` (from `--on-conflict`) gives a conflicting entry.
+fn resolve_conflict(policy: ConflictPolicy, entry: &PlanEntry) -> Result<Action> {
+    match policy {
+        ConflictPolicy::Keep => Ok(Action::KeepStore),
+        ConflictPolicy::Overwrite => Ok(Action::Overwrite),
+        ConflictPolicy::Abort => bail!(
+            "Secret '{}' is already in the store with a different value. \
+             Import aborted; neither the store nor the file was changed.",
+            entry.secret_name
+        ),
+    }
+}
+
+/// Ask on the terminal what to do about a conflicting entry.
+fn ask_conflict(entry: &PlanEntry) -> Result<Action> {
+    loop {
+        eprint!(
+            "Secret '{}' (from {}) is already in the store with a different value.\n\
+             [k]eep the store value, [o]verwrite it with the file value, or [s]kip {}? ",
+            entry.secret_name, entry.key, entry.key
+        );
+        io::stderr().flush()?;
+
+        let mut answer = String::new();
+        if io::stdin().lock().read_line(&mut answer)? == 0 {
+            bail!("No answer on stdin. Re-run with --on-conflict keep|overwrite|abort.");
+        }
+        match answer.trim().to_ascii_lowercase().as_str() {
+            "k" | "keep" => return Ok(Action::KeepStore),
+            "o" | "overwrite" => return Ok(Action::Overwrite),
+            "s" | "skip" => return Ok(Action::SkipConflict),
+            _ => println!("Please answer k, o or s."),
+        }
+    }
+}
+
+/// `original` with the value of every key in `secret_keys` masked, so a diff of it
+/// never shows a secret. A multiline value collapses to its single masked line.
+fn redacted(original: &str, lines: &[ParsedLine], secret_keys: &HashSet<&str>) -> String {
+    let content = original.strip_prefix('\u{feff}').unwrap_or(original);
+    let content = content.replace("\r\n", "\n");
+    let mut masked = HashMap::new();
+    for (idx, parsed) in lines.iter().enumerate() {
+        if let EnvLine::Plain { key, style, .. } = &parsed.line {
+            if !secret_keys.contains(key.as_str()) {
+                continue;
+            }
+            let end = lines.get(idx + 1).map(|next| next.line_no);
+            let mask = format!("{}{}=<redacted>{}", style.prefix(), key, style.suffix());
+            masked.insert(parsed.line_no, (mask, end));
+        }
+    }
+
+    let mut out = String::new();
+    let mut skip_until = None;
+    for (idx, line) in content.split_inclusive('\n').enumerate() {
+        let line_no = idx + 1;
+        if skip_until.is_some_and(|end| line_no < end) {
+            continue;
+        }
+        skip_until = None;
+        match masked.get(&line_no) {
+            Some((mask, end)) => {
+                out.push_str(mask);
+                if line.ends_with('\n') {
+                    out.push('\n');
+                }
+                skip_until = Some(end.unwrap_or(usize::MAX));
+            }
+            None => out.push_str(line),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(lowercase: bool) -> Options<'static> {
+        Options {
+            lowercase,
+            only: &[],
+            exclude: &[],
+        }
+    }
+
+    /// Plan `content` against a store holding `stored`, settling conflicts with `policy`.
+    fn plan_for(
+        content: &str,
+        stored: &[(&str, &str)],
+        policy: ConflictPolicy,
+        lowercase: bool,
+    ) -> Result<ImportPlan> {
+        let lines = env_template::parse_numbered(content).unwrap();
+        let stored: HashMap<String, String> = stored
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        let mut plan = plan(content, &lines, &options(lowercase))?;
+        plan.check_store(
+            |name| Ok(stored.get(name).map(|v| SecretString::new(v.clone()))),
+            |entry| resolve_conflict(policy, entry),
+        )?;
+        Ok(plan)
+    }
+
+    /// Import `content`, then parse and resolve the rewritten template against the
+    /// secrets the plan would store.
+    fn round_trip(content: &str, lowercase: bool) -> HashMap<String, String> {
+        let plan = plan_for(content, &[], ConflictPolicy::Abort, lowercase).unwrap();
+        let store: HashMap<String, String> = plan
+            .imported()
+            .map(|e| (e.secret_name.clone(), e.value.expose_secret().clone()))
+            .collect();
+        let (parsed, errors) = env_template::parse_all(&plan.template());
+        assert!(errors.is_empty());
+        let parsed: Vec<EnvLine> = parsed.into_iter().map(|p| p.line).collect();
+        env_template::resolve_layered(&[parsed], &store, &HashMap::new()).unwrap()
+    }
+
+    #[test]
+    fn test_import_round_trip_keeps_key_case() {
+        let resolved = round_trip("DATABASE_URL=postgres://x\nApiKey=abc\n", false);
+        assert_eq!(resolved["DATABASE_URL"], "postgres://x");
+        assert_eq!(resolved["ApiKey"], "abc");
+    }
+
+    #[test]
+    fn test_import_round_trip_leaves_interpolated_values_plain() {
+        std::env::set_var("ENJECT_IMPORT_TEST_HOST", "db.internal");
+        let content = "DB_HOST=${ENJECT_IMPORT_TEST_HOST}\nDATABASE_URL=postgres://app@${DB_HOST}/db\nPRICE=5$$\nLITERAL='${DB_HOST}'\n";
+        let resolved = round_trip(content, false);
+        assert_eq!(resolved["DATABASE_URL"], "postgres://app@db.internal/db");
+        assert_eq!(resolved["PRICE"], "5$");
+        assert_eq!(resolved["LITERAL"], "${DB_HOST}");
+
+        let plan = plan_for(content, &[], ConflictPolicy::Abort, false).unwrap();
+        assert_eq!(
+            plan.template(),
+            "DB_HOST=${ENJECT_IMPORT_TEST_HOST}\nDATABASE_URL=postgres://app@${DB_HOST}/db\nPRICE=5$$\nLITERAL=en://LITERAL\n"
+        );
+        assert_eq!(
+            plan.summary(),
+            "  Imported: LITERAL\n  Skipped (interpolates other variables): DATABASE_URL, DB_HOST, PRICE\n"
+        );
+    }
+
+    #[test]
+    fn test_import_round_trip_lowercase() {
+        let content = "DATABASE_URL=postgres://x\n";
+        let resolved = round_trip(content, true);
+        assert_eq!(resolved["DATABASE_URL"], "postgres://x");
+        assert_eq!(
+            plan_for(content, &[], ConflictPolicy::Abort, true)
+                .unwrap()
+                .template(),
+            "DATABASE_URL=en://database_url\n"
+        );
+    }
+
+    #[test]
+    fn test_lowercase_collision_is_an_error() {
+        let content = "Token=a\nTOKEN=b\n";
+        let lines = env_template::parse_numbered(content).unwrap();
+        assert!(plan(content, &lines, &options(false)).is_ok());
+        assert!(plan(content, &lines, &options(true)).is_err());
+    }
+
+    #[test]
+    fn test_plan_keeps_last_value() {
+        let plan = plan_for("B=1\nA=2\nB=3\n", &[], ConflictPolicy::Abort, false).unwrap();
+        let summary: Vec<_> = plan
+            .entries
+            .iter()
+            .map(|e| (e.key.as_str(), e.value.expose_secret().as_str()))
+            .collect();
+        assert_eq!(summary, vec![("A", "2"), ("B", "3")]);
+    }
+
+    #[test]
+    fn test_conflict_policies() {
+        let content = "NEW=n\nSAME=s\nDIFF=file\n";
+        let stored = [("SAME", "s"), ("DIFF", "store")];
+
+        let plan = plan_for(content, &stored, ConflictPolicy::Keep, false).unwrap();
+        let actions: Vec<_> = plan.entries.iter().map(|e| &e.action).collect();
+        assert_eq!(
+            actions,
+            vec![&Action::KeepStore, &Action::Create, &Action::Unchanged]
+        );
+        // Identical values are no-ops and don't count as imported
+        let imported: Vec<_> = plan.imported().map(|e| e.key.as_str()).collect();
+        assert_eq!(imported, vec!["NEW"]);
+        assert_eq!(
+            plan.template(),
+            "NEW=en://NEW\nSAME=en://SAME\nDIFF=en://DIFF\n"
+        );
+        assert_eq!(
+            plan.summary(),
+            "  Imported: NEW\n  Conflicts that kept the store value: DIFF\n  Already stored with the same value: SAME\n"
+        );
+
+        let plan = plan_for(content, &stored, ConflictPolicy::Overwrite, false).unwrap();
+        let imported: Vec<_> = plan.imported().map(|e| e.key.as_str()).collect();
+        assert_eq!(imported, vec!["DIFF", "NEW"]);
+
+        let err = plan_for(content, &stored, ConflictPolicy::Abort, false)
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("'DIFF'"));
+    }
+
+    #[test]
+    fn test_skipped_conflict_stays_plain() {
+        let content = "DIFF=file\n";
+        let lines = env_template::parse_numbered(content).unwrap();
+        let mut plan = plan(content, &lines, &options(false)).unwrap();
+        plan.check_store(
+            |_| Ok(Some(SecretString::new("store".into()))),
+            |_| Ok(Action::SkipConflict),
+        )
+        .unwrap();
+        assert_eq!(plan.imported().count(), 0);
+        assert_eq!(plan.template(), "DIFF=file\n");
+    }
+
+    #[test]
+    fn test_only_and_exclude() {
+        let content = "PORT=3000\nDATABASE_URL=postgres://x\nREDIS_URL=redis://y\nAPI_KEY=k\n";
+        let lines = env_template::parse_numbered(content).unwrap();
+        let only = ["*_URL".to_string(), "API_KEY".to_string()];
+        let exclude = ["REDIS_*".to_string()];
+        let plan = plan(
+            content,
+            &lines,
+            &Options {
+                lowercase: false,
+                only: &only,
+                exclude: &exclude,
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            plan.template(),
+            "PORT=3000\nDATABASE_URL=en://DATABASE_URL\nREDIS_URL=redis://y\nAPI_KEY=en://API_KEY\n"
+        );
+        assert_eq!(
+            plan.summary(),
+            "  Imported: API_KEY, DATABASE_URL\n  Skipped (not matched by --only): PORT\n  Excluded: REDIS_URL\n"
+        );
+
+        let rendered = plan.render(".env", content, &lines);
+        assert!(rendered.contains("PORT          -             keep plain (not matched by --only)"));
+        assert!(!rendered.contains("postgres://x"));
+    }
+
+    #[test]
+    fn test_only_matching_nothing_is_an_error() {
+        let content = "PORT=3000\n";
+        let lines = env_template::parse_numbered(content).unwrap();
+        let only = ["*_URL".to_string()];
+        let err = plan(
+            content,
+            &lines,
+            &Options {
+                lowercase: false,
+                only: &only,
+                exclude: &[],
+            },
+        )
+        .err()
+        .unwrap();
+        assert!(err
+            .to_string()
+            .contains("--only *_URL matches no plain key"));
+    }
+
+    #[test]
+    fn test_render_dry_run_snapshot() {
+        let content = "# app\nexport API_KEY=sk_live_123 # stripe\nPEM=\"-----BEGIN-----\nabc\n-----END-----\"\nDB=en://db\n";
+        let lines = env_template::parse_numbered(content).unwrap();
+        let mut plan = plan(content, &lines, &options(true)).unwrap();
+        plan.check_store(
+            |name| Ok((name == "pem").then(|| SecretString::new("old".into()))),
+            |entry| resolve_conflict(ConflictPolicy::Overwrite, entry),
+        )
+        .unwrap();
+        let rendered = plan.render(".env", content, &lines);
+        assert_eq!(
+            rendered,
+            "KEY      SECRET   ACTION
+API_KEY  api_key  create
+PEM      pem      overwrite
+
+--- .env
++++ .env (after import)
+@@ -1,4 +1,4 @@
+ # app
+-export API_KEY=<redacted> # stripe
+-PEM=<redacted>
++export API_KEY=en://api_key # stripe
++PEM=en://pem
+ DB=en://db
+"
+        );
+        assert!(!rendered.contains("sk_live_123"));
+        assert!(!rendered.contains("abc"));
+    }
+}
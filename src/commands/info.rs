@@ -0,0 +1,38 @@
+use std::time::SystemTime;
+
+use anyhow::Result;
+
+use crate::commands::StoreTarget;
+use crate::error::EnjectError;
+use crate::expiry::{self, Expiry};
+
+/// Print what the store knows about `key` besides its value.
+pub fn run(target: &StoreTarget, key: &str) -> Result<()> {
+    let store = target.unlock_read_only()?;
+
+    let meta = store
+        .metadata(key)?
+        .ok_or_else(|| EnjectError::SecretNotFound(key.to_string()))?;
+
+    println!("Key:         {}", key);
+    println!(
+        "Description: {}",
+        meta.description.as_deref().unwrap_or("(none)")
+    );
+    if meta.tags.is_empty() {
+        println!("Tags:        (none)");
+    } else {
+        println!("Tags:        {}", meta.tags.join(", "));
+    }
+    println!("Created:     {}", meta.created_at);
+    println!("Updated:     {}", meta.updated_at);
+    if let Some(expires_at) = &meta.expires_at {
+        let note = match expiry::status(expires_at, SystemTime::now()) {
+            Expiry::Expired => " (expired)",
+            Expiry::Soon => " (expires soon)",
+            Expiry::Valid => "",
+        };
+        println!("Expires:     {}{}", expires_at, note);
+    }
+    Ok(())
+}
@@ -1,64 +1,353 @@
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+
 use anyhow::{bail, Context, Result};
 use rand::RngCore;
 use secrecy::SecretString;
 
+use crate::auth;
+use crate::cli::{GlobalOpts, InitArgs};
+use crate::commands::{
+    archive_store_dir, check_age_recipient, confirm, create_age_store, inform, is_interactive,
+    kdf_params, open_keyring, store_dir, StoreTarget,
+};
 use crate::config;
-use crate::store::password::PasswordStore;
+use crate::store::password::{KdfParams, PasswordStore};
+use crate::ui;
+
+pub fn run(opts: &GlobalOpts, args: &InitArgs) -> Result<()> {
+    let root = config::project_root(opts.project_dir.as_deref(), false)?;
+    let mut dir = store_dir(opts, &root);
+
+    let replacing = config::config_path_in(&dir).exists();
+    if replacing {
+        if !args.force {
+            bail!(
+                "enject is already initialized in {}. \
+                 To start over, run `enject init --force`; the old store is kept in an archive.",
+                root.display()
+            );
+        }
+        confirm_replace(opts, &root, &dir)?;
+    }
+
+    inform(format_args!("Initializing enject store..."));
+
+    let (cfg, salt) = new_config(args)?;
+    // Prompt for Enject store password (twice, with confirmation)
+    let password = match cfg.backend.as_str() {
+        "password" => Some(auth::new_password(
+            "Enject store",
+            auth::PASSWORD_ENV,
+            args.allow_weak_password,
+        )?),
+        _ => None,
+    };
+
+    if replacing {
+        let archive = archive_store_dir(&dir)?;
+        inform(format_args!(
+            "Moved the old store to {}.",
+            archive.display()
+        ));
+        // With a legacy .enveil/ archived, the new store goes in .enject/
+        dir = store_dir(opts, &root);
+    }
+
+    if cfg.backend == "keyring" {
+        create_keyring_store(&cfg)?;
+        config::write_in(&dir, &cfg).context("Failed to write config")?;
+    } else if cfg.backend == "age" {
+        config::write_in(&dir, &cfg).context("Failed to write config")?;
+        create_recipient_store(&config::store_path_in(&dir), &cfg)?;
+    } else if let Some(password) = password {
+        // Write config first — this creates the store directory
+        config::write_in(&dir, &cfg).context("Failed to write config")?;
+
+        create_store(&config::store_path_in(&dir), &cfg, salt, &password)?;
+    }
+
+    ui::success(format_args!("Initialized."));
+    if !args.no_gitignore {
+        // Only a store in the project's own .enject/ needs ignoring
+        update_gitignore(&root, dir == config::enject_dir(&root))?;
+    }
+    inform(format_args!(""));
+    inform(format_args!(
+        "  1. Add a secret:       enject set some_api_key"
+    ));
+    inform(format_args!(
+        "  2. Reference in .env:  API_KEY=en://some_api_key"
+    ));
+    inform(format_args!(
+        "  3. Run your app:       enject run -- npm start"
+    ));
+    inform(format_args!(""));
+    inform(format_args!(
+        "The en:// name must match the key you used in 'enject set'."
+    ));
+    inform(format_args!(
+        "The left side (DATABASE_URL) is what your app sees."
+    ));
+
+    Ok(())
+}
 
-pub fn run() -> Result<()> {
-    let root = config::project_root()?;
-    let cfg_path = config::config_path(&root);
+/// `enject global init` — create the user-level store shared across projects.
+pub fn run_global(args: &InitArgs) -> Result<()> {
+    let dir = config::global_dir()?;
 
-    if cfg_path.exists() {
+    if args.force {
+        bail!("--force only applies to project stores. To reinitialize the global store, delete {} first.", dir.display());
+    }
+    if config::read_global(&dir).is_ok() {
         bail!(
-            "enject is already initialized in this directory. \
-             To reinitialize, delete .enject/ first."
+            "The global store is already initialized at {}. \
+             To reinitialize, delete that directory first.",
+            dir.display()
         );
     }
 
-    println!("Initializing enject store...");
+    inform(format_args!(
+        "Initializing global enject store at {}...",
+        dir.display()
+    ));
 
-    // Generate a fresh 32-byte salt
-    let mut salt = vec![0u8; 32];
-    rand::thread_rng().fill_bytes(&mut salt);
-    let salt_hex = hex::encode(&salt);
+    let (cfg, salt) = new_config(args)?;
+    if cfg.backend == "keyring" {
+        create_keyring_store(&cfg)?;
+        config::write_global(&dir, &cfg).context("Failed to write global config")?;
+    } else if cfg.backend == "age" {
+        config::write_global(&dir, &cfg).context("Failed to write global config")?;
+        create_recipient_store(&config::global_store_path(&dir), &cfg)?;
+    } else {
+        let password = auth::new_password(
+            "Enject global store",
+            auth::GLOBAL_PASSWORD_ENV,
+            args.allow_weak_password,
+        )?;
 
-    let cfg = config::Config::default_new(salt_hex);
+        config::write_global(&dir, &cfg).context("Failed to write global config")?;
+        create_store(&config::global_store_path(&dir), &cfg, salt, &password)?;
+    }
 
-    // Prompt for Enject store password (twice, with confirmation)
-    let password = prompt_new_password()?;
+    ui::success(format_args!("Initialized."));
+    inform(format_args!(""));
+    inform(format_args!(
+        "  1. Add a shared secret:  enject global set some_api_key"
+    ));
+    inform(format_args!(
+        "  2. Reference in .env:    API_KEY=en://global/some_api_key"
+    ));
 
-    // Write config first — this creates the .enject/ directory
-    config::write(&root, &cfg).context("Failed to write config")?;
+    Ok(())
+}
 
-    let store_path = config::store_path(&root);
-    PasswordStore::create_empty(&store_path, cfg.kdf_params(), salt, &password)
+/// Show what replacing the store in `dir` destroys, then have the user type the
+/// project directory's name. Anything else, including no answer at all, refuses.
+fn confirm_replace(opts: &GlobalOpts, root: &Path, dir: &Path) -> Result<()> {
+    ui::danger(format_args!("This replaces the existing store:"));
+    eprintln!("  Config:  {}", config::config_path_in(dir).display());
+    let store_path = config::store_path_in(dir);
+    if store_path.exists() {
+        eprintln!("  Store:   {}", store_path.display());
+    }
+    match config::read_store_dir(dir) {
+        Ok(cfg) if cfg.backend == "keyring" => eprintln!(
+            "  Secrets: in the OS keychain, filed under project id {}; they stay there.",
+            cfg.project_id.as_deref().unwrap_or("(missing)")
+        ),
+        Ok(cfg) => {
+            let count = StoreTarget::local_from(opts, root, cfg)
+                .unlock_read_only()
+                .and_then(|store| Ok(store.list()?.len()));
+            match count {
+                Ok(count) => eprintln!("  Secrets: {}", count),
+                Err(_) => eprintln!("  Secrets: unknown (the store could not be unlocked)"),
+            }
+        }
+        Err(e) => eprintln!("  Config unreadable: {}", e),
+    }
+    eprintln!("It is moved aside, not deleted, but the new store starts empty.");
+
+    let name = root
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| root.display().to_string());
+    eprint!("Type the project directory name ({}) to confirm: ", name);
+    io::stderr().flush()?;
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    if answer.trim() != name {
+        bail!("Not confirmed; the existing store is untouched.");
+    }
+    Ok(())
+}
+
+/// Build a fresh config with a newly generated 32-byte salt and the KDF parameters
+/// `args` asks for (the defaults when it asks for none).
+fn new_config(args: &InitArgs) -> Result<(config::Config, Vec<u8>)> {
+    let params = kdf_params(&args.kdf, KdfParams::default())?;
+    if params != KdfParams::default() {
+        inform(format_args!(
+            "Using Argon2id m_cost={} KiB, t_cost={}, p_cost={}.",
+            params.m_cost, params.t_cost, params.p_cost
+        ));
+    }
+
+    if args.backend == "age" {
+        if args.recipient.is_empty() {
+            bail!("An age store needs at least one --recipient (an age1... or SSH public key).");
+        }
+        for recipient in &args.recipient {
+            check_age_recipient(recipient)?;
+        }
+    } else if !args.recipient.is_empty() {
+        bail!("--recipient only applies with --backend age.");
+    }
+
+    let mut salt = vec![0u8; 32];
+    rand::thread_rng().fill_bytes(&mut salt);
+    let salt_hex = hex::encode(&salt);
+    let mut cfg = config::Config::default_new(salt_hex);
+    if args.backend == "keyring" {
+        let mut id = [0u8; 16];
+        rand::thread_rng().fill_bytes(&mut id);
+        cfg.backend = args.backend.clone();
+        cfg.project_id = Some(hex::encode(id));
+    } else if args.backend == "age" {
+        cfg.backend = args.backend.clone();
+        cfg.recipients = args.recipient.clone();
+    }
+    cfg.cipher = args.cipher.clone();
+    cfg.m_cost = params.m_cost;
+    cfg.t_cost = params.t_cost;
+    cfg.p_cost = params.p_cost;
+    Ok((cfg, salt))
+}
+
+fn create_store(
+    store_path: &Path,
+    cfg: &config::Config,
+    salt: Vec<u8>,
+    password: &SecretString,
+) -> Result<()> {
+    PasswordStore::create_empty(store_path, cfg.cipher()?, cfg.kdf_params(), salt, password)
         .context("Failed to create encrypted store")?;
+    Ok(())
+}
 
-    println!("Initialized.");
-    println!();
-    println!("  1. Add a secret:       enject set some_api_key");
-    println!("  2. Reference in .env:  API_KEY=en://some_api_key");
-    println!("  3. Run your app:       enject run -- npm start");
-    println!();
-    println!("The en:// name must match the key you used in 'enject set'.");
-    println!("The left side (DATABASE_URL) is what your app sees.");
+/// Write an empty index for a keyring-backed store, which also checks the OS
+/// keychain can be reached before anything else is written.
+fn create_keyring_store(cfg: &config::Config) -> Result<()> {
+    open_keyring(cfg)?
+        .persist()
+        .context("Failed to create the store in the OS keychain")?;
+    inform(format_args!(
+        "Secrets will be kept in the OS keychain; no password is needed."
+    ));
+    Ok(())
+}
 
+/// Write an empty store encrypted to the config's recipients.
+fn create_recipient_store(store_path: &Path, cfg: &config::Config) -> Result<()> {
+    create_age_store(store_path, &cfg.recipients).context("Failed to create encrypted store")?;
+    inform(format_args!(
+        "Secrets will be encrypted to {} recipient(s); no password is needed.",
+        cfg.recipients.len()
+    ));
     Ok(())
 }
 
-pub fn prompt_new_password() -> Result<SecretString> {
-    let password = rpassword::prompt_password("New Enject store password: ")
-        .context("Failed to read password")?;
-    let confirm = rpassword::prompt_password("Confirm Enject store password: ")
-        .context("Failed to read password confirmation")?;
+/// What `init` wants a project's `.gitignore` to cover: the store directory and the
+/// archives `init --force` leaves, and the plaintext backups `import` writes next to
+/// the files it converts.
+const GITIGNORE_ENTRIES: [&str; 4] = [".enject/", ".enject.old-*/", ".env.bak", "*.env.bak"];
 
-    if password != confirm {
-        bail!("Passwords do not match.");
+/// In a git repository, add whatever `GITIGNORE_ENTRIES` the project's `.gitignore`
+/// lacks (leaving out `.enject/` unless `ignore_dir`). A missing `.gitignore` is
+/// only created if the user agrees.
+fn update_gitignore(root: &Path, ignore_dir: bool) -> Result<()> {
+    if !root.ancestors().any(|dir| dir.join(".git").exists()) {
+        return Ok(());
+    }
+    let path = root.join(".gitignore");
+    let existing = match std::fs::read_to_string(&path) {
+        Ok(content) => Some(content),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e).context("Failed to read .gitignore"),
+    };
+    let mut missing = missing_gitignore_entries(existing.as_deref().unwrap_or(""));
+    if !ignore_dir {
+        missing.retain(|entry| !entry.starts_with(".enject"));
     }
-    if password.is_empty() {
-        bail!("Enject store password must not be empty.");
+    if missing.is_empty() {
+        return Ok(());
+    }
+
+    let list = missing.join(", ");
+    let mut content = match existing {
+        Some(content) => content,
+        None if is_interactive() && confirm(&format!("Create a .gitignore with {}?", list))? => {
+            String::new()
+        }
+        None => {
+            inform(format_args!(
+                "No .gitignore found. Create one with {} so the store is never committed.",
+                list
+            ));
+            return Ok(());
+        }
+    };
+    if !content.is_empty() && !content.ends_with('\n') {
+        content.push('\n');
     }
+    content.push_str("# enject\n");
+    for entry in &missing {
+        content.push_str(entry);
+        content.push('\n');
+    }
+    std::fs::write(&path, content).context("Failed to update .gitignore")?;
+    ui::success(format_args!("Added {} to .gitignore.", list));
+    Ok(())
+}
 
-    Ok(SecretString::new(password))
+/// The entries of `GITIGNORE_ENTRIES` that `gitignore` doesn't already cover.
+fn missing_gitignore_entries(gitignore: &str) -> Vec<&'static str> {
+    GITIGNORE_ENTRIES
+        .into_iter()
+        .filter(|entry| match entry.strip_suffix('/') {
+            Some(".enject") => {
+                !config::gitignore_covers(gitignore, ".enject")
+                    && !config::gitignore_covers(gitignore, ".enveil")
+            }
+            Some(dir) => !config::gitignore_covers(gitignore, dir),
+            None => !gitignore
+                .lines()
+                .map(|line| line.trim().trim_start_matches('/'))
+                .any(|line| line == *entry),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_missing_gitignore_entries() {
+        assert_eq!(
+            missing_gitignore_entries("target/\n"),
+            vec![".enject/", ".enject.old-*/", ".env.bak", "*.env.bak"]
+        );
+        assert_eq!(
+            missing_gitignore_entries("/.enject\n.env.bak\n.enject.old-*\n"),
+            vec!["*.env.bak"]
+        );
+        // A legacy .enveil/ entry is left as it is
+        assert_eq!(
+            missing_gitignore_entries(".enveil/\n.enject.old-*/\n*.env.bak\n  /.env.bak\n"),
+            Vec::<&str>::new()
+        );
+    }
 }
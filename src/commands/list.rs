@@ -1,32 +1,266 @@
-use anyhow::{Context, Result};
-use secrecy::SecretString;
-
-use crate::config;
-use crate::store::password::PasswordStore;
-use crate::store::Store;
-
-pub fn run() -> Result<()> {
-    let root = config::project_root()?;
-    let cfg = config::read(&root)?;
-
-    let password = rpassword::prompt_password("Enject store password: ")
-        .context("Failed to read Enject store password")?;
-    let password = SecretString::new(password);
-
-    let store_path = config::store_path(&root);
-    let mut store = PasswordStore::new(store_path, cfg.kdf_params(), cfg.salt_bytes()?);
-    store
-        .unlock(&password)
-        .context("Failed to unlock store — wrong password?")?;
-
-    let keys = store.list()?;
-    if keys.is_empty() {
-        println!("No secrets stored. Add one with: enject set <key>");
-    } else {
-        for key in &keys {
-            println!("{}", key);
+use std::collections::BTreeSet;
+use std::path::Path;
+use std::time::SystemTime;
+
+use anyhow::{bail, Context, Result};
+use serde::Serialize;
+
+use crate::cli::{ListArgs, OutputFormat};
+use crate::commands::{glob_match, StoreTarget};
+use crate::env_template::{self, Scope};
+use crate::error::EnjectError;
+use crate::expiry::{self, Expiry};
+use crate::store::{SecretMetadata, Store};
+
+/// `enject list --format json`. Holds key names only; values never reach this type.
+#[derive(Debug, Serialize)]
+struct Listing {
+    store: String,
+    backend: String,
+    secrets: Vec<Entry>,
+}
+
+/// `enject list --missing --format json`.
+#[derive(Debug, Serialize)]
+struct Missing<'a> {
+    missing: &'a [&'a String],
+}
+
+#[derive(Debug, Serialize)]
+struct Entry {
+    name: String,
+    created_at: String,
+    updated_at: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    tags: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expires_at: Option<String>,
+    expired: bool,
+}
+
+/// List key names in sorted order. With a pattern or `--prefix`, only matching keys
+/// are listed, and matching none is an error so scripts can test for a key.
+/// `--unused` and `--missing` compare the store with the project's `scope` references.
+pub fn run(
+    target: &StoreTarget,
+    scope: Scope,
+    args: &ListArgs,
+    format: OutputFormat,
+) -> Result<()> {
+    let store = target.unlock_read_only()?;
+
+    let now = SystemTime::now();
+    let mut keys = store.list()?;
+    if args.unused || args.missing {
+        let refs = TemplateRefs::read(&target.project_root, scope)?;
+        if args.missing {
+            let missing: Vec<_> = refs
+                .required
+                .iter()
+                .filter(|name| !keys.contains(name))
+                .collect();
+            match format {
+                OutputFormat::Json => println!(
+                    "{}",
+                    serde_json::to_string_pretty(&Missing { missing: &missing })?
+                ),
+                OutputFormat::Text => {
+                    for name in &missing {
+                        println!("{}", name);
+                    }
+                }
+            }
+            if !missing.is_empty() {
+                bail!(
+                    "{} referenced secret(s) missing from the {}.",
+                    missing.len(),
+                    target.name
+                );
+            }
+            return Ok(());
+        }
+        keys.retain(|key| !refs.all.contains(key));
+    }
+    if args.pattern.is_some() || args.prefix.is_some() || args.tag.is_some() {
+        let mut matched = Vec::new();
+        for key in keys {
+            if matches(args, &key) && has_tag(&*store, args, &key)? {
+                matched.push(key);
+            }
+        }
+        keys = matched;
+        if keys.is_empty() {
+            bail!("No secret matches the filter.");
+        }
+    }
+    match format {
+        OutputFormat::Json => {
+            let listing = Listing {
+                store: target.store_path.display().to_string(),
+                backend: target.config.backend.clone(),
+                secrets: keys
+                    .into_iter()
+                    .map(|name| {
+                        let meta = store
+                            .metadata(&name)?
+                            .ok_or_else(|| EnjectError::SecretNotFound(name.clone()))?;
+                        let expired = meta
+                            .expires_at
+                            .as_deref()
+                            .is_some_and(|e| expiry::status(e, now) == Expiry::Expired);
+                        Ok(Entry {
+                            name,
+                            created_at: meta.created_at,
+                            updated_at: meta.updated_at,
+                            description: meta.description,
+                            tags: meta.tags,
+                            expires_at: meta.expires_at,
+                            expired,
+                        })
+                    })
+                    .collect::<Result<_>>()?,
+            };
+            println!("{}", serde_json::to_string_pretty(&listing)?);
+        }
+        OutputFormat::Text if keys.is_empty() && !args.unused => {
+            if !crate::logging::quiet() {
+                println!(
+                    "No secrets stored. Add one with: {} set <key>",
+                    target.command
+                );
+            }
+        }
+        OutputFormat::Text if args.long => {
+            let width = keys.iter().map(|k| k.len()).max().unwrap_or(0);
+            for key in &keys {
+                let meta = store.metadata(key)?;
+                let updated = meta
+                    .as_ref()
+                    .map(|m| expiry::date(&m.updated_at))
+                    .unwrap_or_default();
+                let line = format!("{:<width$}  {}", key, updated, width = width);
+                match meta.as_ref().and_then(|m| expiry_marker(m, now)) {
+                    Some(marker) => println!("{}  {}", line, marker),
+                    None => println!("{}", line),
+                }
+            }
+        }
+        // --unused output is meant for piping into `delete`, so it stays bare
+        OutputFormat::Text if args.unused => {
+            for key in &keys {
+                println!("{}", key);
+            }
+        }
+        OutputFormat::Text => {
+            for key in &keys {
+                let meta = store.metadata(key)?;
+                match meta.as_ref().and_then(|m| expiry_marker(m, now)) {
+                    Some(marker) => println!("{}  {}", key, marker),
+                    None => println!("{}", key),
+                }
+            }
         }
     }
 
     Ok(())
 }
+
+/// The secret names one store's references in the project templates point at.
+#[derive(Debug, Default)]
+struct TemplateRefs {
+    /// Every referenced name.
+    all: BTreeSet<String>,
+    /// Names referenced at least once without a `?default=`.
+    required: BTreeSet<String>,
+}
+
+impl TemplateRefs {
+    /// Collect `scope` references from `.env` and every `.env.<profile>` file in the
+    /// project root.
+    fn read(root: &Path, scope: Scope) -> Result<Self> {
+        if !root.join(".env").exists() {
+            bail!(
+                ".env file not found in {}; nothing to compare the store with.",
+                root.display()
+            );
+        }
+        let mut refs = Self::default();
+        let entries = std::fs::read_dir(root)
+            .with_context(|| format!("Failed to read {}", root.display()))?;
+        for entry in entries {
+            let path = entry?.path();
+            let is_template = path.file_name().and_then(|n| n.to_str()).is_some_and(|n| {
+                n == ".env" || n.strip_prefix(".env.").is_some_and(|p| !p.is_empty())
+            });
+            if !is_template || !path.is_file() {
+                continue;
+            }
+            let content = std::fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            refs.add(&content, scope);
+        }
+        Ok(refs)
+    }
+
+    fn add(&mut self, content: &str, scope: Scope) {
+        let (lines, _) = env_template::parse_all(content);
+        for secret in lines.iter().flat_map(|parsed| parsed.line.refs()) {
+            if secret.scope != scope {
+                continue;
+            }
+            self.all.insert(secret.name.to_string());
+            if secret.default.is_none() {
+                self.required.insert(secret.name.to_string());
+            }
+        }
+    }
+}
+
+/// `(expired <date>)` or `(expires <date>)` for a secret that has expired or
+/// expires soon.
+fn expiry_marker(meta: &SecretMetadata, now: SystemTime) -> Option<String> {
+    let expires_at = meta.expires_at.as_deref()?;
+    match expiry::status(expires_at, now) {
+        Expiry::Expired => Some(format!("(expired {})", expiry::date(expires_at))),
+        Expiry::Soon => Some(format!("(expires {})", expiry::date(expires_at))),
+        Expiry::Valid => None,
+    }
+}
+
+fn has_tag(store: &dyn Store, args: &ListArgs, key: &str) -> Result<bool> {
+    let Some(tag) = &args.tag else {
+        return Ok(true);
+    };
+    Ok(store
+        .metadata(key)?
+        .is_some_and(|meta| meta.tags.contains(tag)))
+}
+
+fn matches(args: &ListArgs, key: &str) -> bool {
+    args.pattern.as_deref().is_none_or(|p| glob_match(p, key))
+        && args.prefix.as_deref().is_none_or(|p| key.starts_with(p))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_template_refs_by_scope() {
+        let mut refs = TemplateRefs::default();
+        refs.add(
+            "A=en://one\nB=en://global/shared\nC=en://opt?default=x\nD=x:en://two@en://one\n",
+            Scope::Local,
+        );
+        refs.add("E=en://three\n", Scope::Local);
+        let all: Vec<_> = refs.all.iter().map(String::as_str).collect();
+        assert_eq!(all, vec!["one", "opt", "three", "two"]);
+        let required: Vec<_> = refs.required.iter().map(String::as_str).collect();
+        assert_eq!(required, vec!["one", "three", "two"]);
+
+        let mut global = TemplateRefs::default();
+        global.add("A=en://one\nB=en://global/shared\n", Scope::Global);
+        assert_eq!(global.all.iter().collect::<Vec<_>>(), vec!["shared"]);
+    }
+}
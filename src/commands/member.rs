@@ -0,0 +1,244 @@
+use anyhow::Result;
+
+use crate::cli::MemberCommand;
+use crate::commands::StoreTarget;
+
+/// `enject member` — manage the named members of a shared (age) store. Each member
+/// unlocks it with their own password or private key; none of them shares a password.
+#[cfg(feature = "age")]
+pub fn run(target: &StoreTarget, command: &MemberCommand) -> Result<()> {
+    match command {
+        MemberCommand::List => shared::list(target),
+        MemberCommand::Add {
+            name,
+            recipient,
+            as_name,
+            allow_weak_password,
+        } => shared::add(
+            target,
+            name,
+            recipient.as_deref(),
+            as_name.as_deref(),
+            *allow_weak_password,
+        ),
+        MemberCommand::Remove { name } => shared::remove(target, name),
+    }
+}
+
+#[cfg(not(feature = "age"))]
+pub fn run(_target: &StoreTarget, _command: &MemberCommand) -> Result<()> {
+    Err(crate::commands::no_age_support().into())
+}
+
+#[cfg(feature = "age")]
+mod shared {
+    use anyhow::{bail, Context, Result};
+    use rand::RngCore;
+    use secrecy::SecretString;
+
+    use crate::auth;
+    use crate::commands::{
+        check_age_recipient, current_user, forget_key, inform, save_age_config, unlock_age,
+        StoreTarget, MEMBER_KEY,
+    };
+    use crate::config::{self, Config};
+    use crate::store::age::{self, AgeStore};
+    use crate::store::password::PasswordStore;
+    use crate::store::Store;
+    use crate::ui;
+
+    pub fn list(target: &StoreTarget) -> Result<()> {
+        if target.config.members.is_empty() {
+            inform(format_args!(
+                "The {} has no members. Add one with `{} member add <NAME>`.",
+                target.name, target.command
+            ));
+            return Ok(());
+        }
+        for (name, recipient) in &target.config.members {
+            if config::member_key_path(&target.config_dir, name).exists() {
+                println!("{}  (password)", name);
+            } else {
+                println!("{}  {}", name, recipient);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn add(
+        target: &StoreTarget,
+        name: &str,
+        recipient: Option<&str>,
+        as_name: Option<&str>,
+        allow_weak: bool,
+    ) -> Result<()> {
+        check_name(name)?;
+        if let Some(recipient) = recipient {
+            check_age_recipient(recipient)?;
+        }
+        match target.config.backend.as_str() {
+            "password" => return convert(target, name, recipient, as_name, allow_weak),
+            "age" => {}
+            other => bail!(
+                "The {} uses the '{}' backend, which has no members.",
+                target.name,
+                other
+            ),
+        }
+        if target.config.members.contains_key(name) {
+            bail!("'{}' is already a member of the {}.", name, target.name);
+        }
+
+        let store = unlock_age(target, false)?;
+        let mut config = target.config.clone();
+        let member_recipient = new_member(target, &config, name, recipient, allow_weak)?;
+        config.members.insert(name.to_string(), member_recipient);
+        save_age_config(target, store, config)?;
+        ui::success(format_args!(
+            "Added member '{}'; the store is re-encrypted.",
+            name
+        ));
+        Ok(())
+    }
+
+    pub fn remove(target: &StoreTarget, name: &str) -> Result<()> {
+        let mut config = target.config.clone();
+        if config.members.remove(name).is_none() {
+            bail!("'{}' is not a member of the {}.", name, target.name);
+        }
+        if config.age_recipients().is_empty() {
+            bail!("Refusing to remove the last member: nobody could decrypt the store.");
+        }
+
+        let store = unlock_age(target, false)?;
+        save_age_config(target, store, config)?;
+        let key_path = config::member_key_path(&target.config_dir, name);
+        if key_path.exists() {
+            std::fs::remove_file(&key_path)
+                .with_context(|| format!("Failed to remove {}", key_path.display()))?;
+        }
+        ui::success(format_args!(
+            "Removed member '{}'; the store is re-encrypted under a new data key.",
+            name
+        ));
+        inform(format_args!(
+            "Copies made before now still open for them. Rotate the secrets they could read."
+        ));
+        Ok(())
+    }
+
+    /// Turn a password store into an age store shared by the current password's
+    /// holder, as member `as_name`, and the new member `name`.
+    fn convert(
+        target: &StoreTarget,
+        name: &str,
+        recipient: Option<&str>,
+        as_name: Option<&str>,
+        allow_weak: bool,
+    ) -> Result<()> {
+        let Some(own_name) = as_name.map(str::to_string).or_else(current_user) else {
+            bail!("Could not tell who you are; pass --as <NAME> to name yourself as a member.");
+        };
+        check_name(&own_name)?;
+        if own_name == name {
+            bail!(
+                "'{}' would be both you and the new member; pass --as <NAME>.",
+                name
+            );
+        }
+
+        let (mut store, password) = target.unlock_with_password(false)?;
+        let old_fingerprint = target.key_fingerprint()?;
+        let mut config = target.config.clone();
+        config.backend = "age".into();
+
+        let (own_key, own_recipient) = age::generate_identity();
+        write_member_key(target, &config, &own_name, &own_key, &password)?;
+        config.members.insert(own_name.clone(), own_recipient);
+        let member_recipient = new_member(target, &config, name, recipient, allow_weak)?;
+        config.members.insert(name.to_string(), member_recipient);
+
+        AgeStore::convert(&mut store, config.age_recipients())
+            .context("Failed to write the converted store")?;
+        config::write_in(&target.config_dir, &config).context("Failed to update config")?;
+        drop(store);
+        age::commit_conversion(&target.store_path)
+            .context("Failed to move the converted store into place")?;
+        // The password store's cached key opens nothing now
+        let _ = forget_key(&old_fingerprint);
+
+        ui::success(format_args!(
+            "Converted the {} to a shared store. You are member '{}' and still unlock it with your current password.",
+            target.name, own_name
+        ));
+        ui::success(format_args!("Added member '{}'.", name));
+        Ok(())
+    }
+
+    /// The recipient for new member `name`: `recipient` if given, otherwise the
+    /// public half of a fresh key pair kept under the member's own password.
+    fn new_member(
+        target: &StoreTarget,
+        config: &Config,
+        name: &str,
+        recipient: Option<&str>,
+        allow_weak: bool,
+    ) -> Result<String> {
+        if let Some(recipient) = recipient {
+            return Ok(recipient.to_string());
+        }
+        let password = auth::new_password(
+            &format!("member '{}'", name),
+            auth::MEMBER_PASSWORD_ENV,
+            allow_weak,
+        )?;
+        let (key, recipient) = age::generate_identity();
+        write_member_key(target, config, name, &key, &password)?;
+        Ok(recipient)
+    }
+
+    /// Keep a member's age secret key in a one-secret password store under their
+    /// password, with the store's cipher and KDF parameters and a salt of its own.
+    fn write_member_key(
+        target: &StoreTarget,
+        config: &Config,
+        name: &str,
+        key: &SecretString,
+        password: &SecretString,
+    ) -> Result<()> {
+        let path = config::member_key_path(&target.config_dir, name);
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        let mut salt = vec![0u8; 32];
+        rand::thread_rng().fill_bytes(&mut salt);
+        let mut key_file = PasswordStore::create_empty(
+            &path,
+            config.cipher()?,
+            config.kdf_params(),
+            salt,
+            password,
+        )
+        .with_context(|| format!("Failed to create {}", path.display()))?;
+        key_file.set(MEMBER_KEY, key.clone())?;
+        key_file.save(password)?;
+        Ok(())
+    }
+
+    /// Member names become file names, so keep them to letters, digits, `.`, `_` and `-`.
+    fn check_name(name: &str) -> Result<()> {
+        let valid = !name.is_empty()
+            && !name.starts_with('.')
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'));
+        if !valid {
+            bail!(
+                "Invalid member name '{}': use letters, digits, '.', '_' and '-'.",
+                name
+            );
+        }
+        Ok(())
+    }
+}
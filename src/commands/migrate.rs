@@ -0,0 +1,46 @@
+use anyhow::{bail, Result};
+
+use crate::cli::GlobalOpts;
+use crate::commands::{confirm, inform, is_interactive, project_root};
+use crate::migration;
+use crate::ui;
+
+/// `enject migrate` — update what the project inherited from enveil: rename its
+/// `.enveil/` store directory and rewrite `ev://` references in its templates.
+/// Everything changed is backed up first. Other commands only warn about these.
+pub fn run(opts: &GlobalOpts, yes: bool) -> Result<()> {
+    let root = project_root(opts)?;
+    let pending = migration::pending(&root)?;
+    if pending.is_empty() {
+        inform(format_args!("Nothing to migrate."));
+        return Ok(());
+    }
+
+    eprintln!("enject migrate will:");
+    for migration in &pending {
+        eprintln!("  - {}", migration);
+    }
+    eprintln!();
+    if !yes {
+        if !is_interactive() {
+            bail!(
+                "stdin is not a terminal, so migrate cannot ask for confirmation. \
+                 Re-run with --yes to migrate non-interactively."
+            );
+        }
+        if !confirm("Migrate?")? {
+            inform(format_args!("Migration cancelled."));
+            return Ok(());
+        }
+    }
+
+    for migration in &pending {
+        let backup = migration::apply(migration)?;
+        ui::success(format_args!(
+            "Migrated {} (backup at {}).",
+            migration.path().display(),
+            backup.display()
+        ));
+    }
+    Ok(())
+}
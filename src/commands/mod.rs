@@ -1,7 +1,726 @@
+pub mod agent;
+pub mod backup;
+pub mod check;
+pub mod completions;
 pub mod delete;
+pub mod diff;
+pub mod doctor;
+pub mod history;
 pub mod import;
+pub mod info;
 pub mod init;
 pub mod list;
+pub mod member;
+pub mod migrate;
+pub mod recipients;
+pub mod rename;
+pub mod restore_version;
 pub mod rotate;
 pub mod run;
+pub mod scan;
 pub mod set;
+pub mod status;
+pub mod template;
+pub mod unlock;
+pub mod verify;
+
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use anyhow::{bail, Context, Result};
+use secrecy::SecretString;
+
+use crate::auth;
+use crate::cli::{GlobalOpts, KdfArgs, KdfPreset, OutputFormat};
+use crate::config::{self, Config};
+use crate::env_template::{self, ParsedLine};
+use crate::error::EnjectError;
+use crate::migration;
+#[cfg(feature = "age")]
+use crate::store::age::AgeStore;
+#[cfg(feature = "keyring")]
+use crate::store::keyring::KeyringStore;
+#[cfg(not(feature = "keyring"))]
+use crate::store::password::KEY_LEN;
+use crate::store::password::{self, KdfParams, PasswordStore};
+use crate::store::Store;
+use crate::ui;
+#[cfg(not(feature = "keyring"))]
+use zeroize::Zeroizing;
+
+/// The store a command operates on: the per-project store or the user-level global store.
+pub struct StoreTarget {
+    pub config: Config,
+    /// Root of the project the command runs in, whose `.env` references the store.
+    pub project_root: PathBuf,
+    /// Directory holding `config.toml` and the store.
+    pub config_dir: PathBuf,
+    pub store_path: PathBuf,
+    /// Human-readable store name used in prompts, e.g. "Enject store".
+    pub name: &'static str,
+    /// Command prefix used in hints, e.g. "enject" or "enject global".
+    pub command: &'static str,
+    /// Environment variable that supplies the password non-interactively.
+    pub password_env: &'static str,
+    /// File whose first line supplies the password (`--password-file`).
+    pub password_file: Option<PathBuf>,
+    /// Whether a piped stdin may supply the password. Off when stdin carries other input.
+    pub stdin_password: bool,
+    /// Whether a store key cached by `enject unlock --remember` may be used.
+    pub keychain: bool,
+    /// Whether to ask `enject agent` for the store key and hand it new ones.
+    pub agent: bool,
+    /// Identity files given with `--identity` for decrypting an age store.
+    #[cfg_attr(not(feature = "age"), allow(dead_code))]
+    pub identities: Vec<PathBuf>,
+}
+
+impl StoreTarget {
+    /// The store in the current project's `.enject/` directory.
+    pub fn local(opts: &GlobalOpts) -> Result<Self> {
+        let root = project_root(opts)?;
+        let config = match config::store_dir_override(opts.store_dir.as_deref()) {
+            Some(dir) => config::read_store_dir(&dir)?,
+            None => read_config(&root)?,
+        };
+        Ok(Self::local_from(opts, &root, config))
+    }
+
+    /// The project store for an already-loaded `config`.
+    pub fn local_from(opts: &GlobalOpts, root: &Path, config: Config) -> Self {
+        let dir = store_dir(opts, root);
+        tracing::debug!(
+            "Project root {}; store directory {} ({} backend)",
+            root.display(),
+            dir.display(),
+            config.backend
+        );
+        Self {
+            project_root: root.to_path_buf(),
+            store_path: config::store_path_in(&dir),
+            config_dir: dir,
+            name: "Enject store",
+            command: "enject",
+            password_env: auth::PASSWORD_ENV,
+            password_file: opts.password_file.clone(),
+            stdin_password: true,
+            keychain: auth::keychain_allowed(opts.no_keychain),
+            agent: crate::agent::enabled(config.agent),
+            identities: opts.identity.clone(),
+            config,
+        }
+    }
+
+    /// The user-level global store referenced by `en://global/` refs.
+    pub fn global(opts: &GlobalOpts) -> Result<Self> {
+        let dir = config::global_dir()?;
+        let config = config::read_global(&dir)?;
+        tracing::debug!(
+            "Global store directory {} ({} backend)",
+            dir.display(),
+            config.backend
+        );
+        Ok(Self {
+            project_root: project_root(opts)?,
+            store_path: config::global_store_path(&dir),
+            config_dir: dir,
+            name: "Enject global store",
+            command: "enject global",
+            password_env: auth::GLOBAL_PASSWORD_ENV,
+            password_file: opts.password_file.clone(),
+            stdin_password: true,
+            keychain: auth::keychain_allowed(opts.no_keychain),
+            agent: crate::agent::enabled(config.agent),
+            identities: opts.identity.clone(),
+            config,
+        })
+    }
+
+    /// Construct the (still locked) password store for this target.
+    pub fn open(&self) -> Result<PasswordStore> {
+        Ok(PasswordStore::new(
+            self.store_path.clone(),
+            self.config.kdf_params(),
+            self.config.salt_bytes()?,
+        )
+        .with_cipher(self.config.cipher()?)
+        .with_history_depth(self.config.history_depth))
+    }
+
+    /// Open this target's store with the backend its config names. A password store
+    /// is decrypted with its cached key or its password, and locks out other processes
+    /// until dropped.
+    pub fn unlock(&self) -> Result<Box<dyn Store>> {
+        self.unlock_store(false)
+    }
+
+    /// Like `unlock`, for commands that only read the store: other readers don't wait.
+    pub fn unlock_read_only(&self) -> Result<Box<dyn Store>> {
+        self.unlock_store(true)
+    }
+
+    fn unlock_store(&self, read_only: bool) -> Result<Box<dyn Store>> {
+        match self.config.backend.as_str() {
+            "keyring" => return open_keyring(&self.config),
+            "age" => return open_age(self, read_only),
+            _ => {}
+        }
+        Ok(Box::new(self.unlock_password_store(read_only)?))
+    }
+
+    /// The password store unlocked with the key cached in the OS keychain, the
+    /// agent's key, or the password, whichever is found first.
+    pub fn unlock_password_store(&self, read_only: bool) -> Result<PasswordStore> {
+        if let Some(store) = self.unlock_cached(read_only)? {
+            return Ok(store);
+        }
+        if let Some(store) = self.unlock_from_agent(read_only)? {
+            return Ok(store);
+        }
+        let (store, _password) = self.unlock_with_password(read_only)?;
+        self.share_with_agent(&store);
+        Ok(store)
+    }
+
+    /// Obtain the store password and decrypt the password store, ignoring any cached
+    /// key. Returns the unlocked store together with the password for a later `save`.
+    pub fn unlock_with_password(&self, read_only: bool) -> Result<(PasswordStore, SecretString)> {
+        self.require_password_backend()?;
+        let mut store = self.open()?;
+        let password = self.unlock_with_retries(|password| {
+            unlocked(if read_only {
+                store.unlock_read_only(password)
+            } else {
+                store.unlock(password)
+            })
+        })?;
+        Ok((store, password))
+    }
+
+    /// Obtain this store's password and `unlock` with it, asking again when a typed
+    /// password is wrong. See `auth::unlock_with_retries`.
+    pub fn unlock_with_retries<F>(&self, unlock: F) -> Result<SecretString>
+    where
+        F: FnMut(&SecretString) -> Result<()>,
+    {
+        auth::unlock_with_retries(
+            self.name,
+            self.password_env,
+            self.password_file.as_deref(),
+            self.stdin_password,
+            unlock,
+        )
+    }
+
+    /// The password store opened with the key `enject unlock --remember` cached, if
+    /// there is one and it still opens the store. A stale key, say from before a
+    /// rotation, is removed from the keychain so the password is asked for instead.
+    fn unlock_cached(&self, read_only: bool) -> Result<Option<PasswordStore>> {
+        if !self.keychain {
+            return Ok(None);
+        }
+        let fingerprint = self.key_fingerprint()?;
+        // A keychain that can't be reached just means asking for the password
+        let Ok(Some(key)) = remembered_key(&fingerprint) else {
+            return Ok(None);
+        };
+        let mut store = self.open()?;
+        match store.unlock_with_key(&key, read_only) {
+            Ok(()) => Ok(Some(store)),
+            Err(EnjectError::DecryptionFailed) => {
+                ui::warn(format_args!(
+                    "The key cached for the {} no longer opens it; removing it from the OS keychain.",
+                    self.name
+                ));
+                forget_key(&fingerprint)?;
+                Ok(None)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// The password store opened with the key `enject agent` holds for it, if the
+    /// agent is in use and has one. A key that doesn't open the store is ignored.
+    fn unlock_from_agent(&self, read_only: bool) -> Result<Option<PasswordStore>> {
+        if !self.agent {
+            return Ok(None);
+        }
+        let Some(key) = crate::agent::fetch_key(&self.key_fingerprint()?) else {
+            return Ok(None);
+        };
+        let mut store = self.open()?;
+        match store.unlock_with_key(&key, read_only) {
+            Ok(()) => Ok(Some(store)),
+            Err(EnjectError::DecryptionFailed) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Give `enject agent` the key `store` was just unlocked with, if the agent is in
+    /// use. An agent that isn't running is no reason to fail the command.
+    pub fn share_with_agent(&self, store: &PasswordStore) {
+        if !self.agent {
+            return;
+        }
+        if let (Some(key), Ok(fingerprint)) = (store.derived_key(), self.key_fingerprint()) {
+            let _ = crate::agent::store_key(&fingerprint, key);
+        }
+    }
+
+    /// Names this store's cached key in the OS keychain and the agent.
+    pub fn key_fingerprint(&self) -> Result<String> {
+        Ok(password::salt_fingerprint(&self.config.salt_bytes()?))
+    }
+
+    fn require_password_backend(&self) -> Result<()> {
+        match self.config.backend.as_str() {
+            "password" => Ok(()),
+            other => Err(EnjectError::Config(format!(
+                "This needs a password-encrypted store, but {} uses the '{}' backend.",
+                self.name, other
+            ))
+            .into()),
+        }
+    }
+}
+
+/// The project this invocation works on: `-C` or the current directory, or the
+/// nearest parent with a store unless `--no-discover`.
+pub fn project_root(opts: &GlobalOpts) -> Result<PathBuf> {
+    Ok(config::project_root(
+        opts.project_dir.as_deref(),
+        !opts.no_discover,
+    )?)
+}
+
+/// The directory holding the project store's `config.toml` and store file:
+/// `--store-dir` or `ENJECT_DIR` if set, otherwise the project's `.enject/`.
+pub fn store_dir(opts: &GlobalOpts, root: &Path) -> PathBuf {
+    config::store_dir_override(opts.store_dir.as_deref())
+        .unwrap_or_else(|| config::enject_dir(root))
+}
+
+/// `config::read`, warning when the store is still in a legacy `.enveil/`.
+pub fn read_config(project_root: &Path) -> Result<Config> {
+    if migration::legacy_dir(project_root).is_some() {
+        ui::warn(format_args!(
+            "found legacy .enveil/ store. Run `enject migrate` to rename it to .enject/."
+        ));
+    }
+    Ok(config::read(project_root)?)
+}
+
+/// `env_template::parse_file`, warning when the template still has legacy `ev://`
+/// references. They resolve as they are; `enject migrate` rewrites them.
+pub fn parse_template(path: &Path) -> Result<Vec<ParsedLine>> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let lines = env_template::parse_numbered(&content)?;
+    let legacy: Vec<usize> = lines
+        .iter()
+        .filter(|parsed| parsed.legacy)
+        .map(|parsed| parsed.line_no)
+        .collect();
+    if !legacy.is_empty() {
+        ui::warn(format_args!(
+            "{} contains legacy ev:// reference(s) on line(s) {}. Run `enject migrate` to update them to en://.",
+            path.display(),
+            migration::line_list(&legacy)
+        ));
+    }
+    env_template::log_parsed(&path.display(), &lines);
+    Ok(lines)
+}
+
+/// Move the store directory `dir` aside to `<dir>.old-<UTC timestamp>` and return
+/// the new path.
+pub fn archive_store_dir(dir: &Path) -> Result<PathBuf> {
+    let stamp = humantime::format_rfc3339_seconds(SystemTime::now())
+        .to_string()
+        .replace(['-', ':'], "");
+    let name = dir
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let archive = dir.with_file_name(format!("{}.old-{}", name, stamp));
+    if archive.exists() {
+        bail!(
+            "{} already exists; try again in a second.",
+            archive.display()
+        );
+    }
+    std::fs::rename(dir, &archive)
+        .with_context(|| format!("Failed to move {} aside", dir.display()))?;
+    Ok(archive)
+}
+
+/// The OS keychain store `config` names.
+#[cfg(feature = "keyring")]
+pub fn open_keyring(config: &Config) -> Result<Box<dyn Store>> {
+    let project_id = config.project_id.as_deref().ok_or_else(|| {
+        EnjectError::Config("The keyring backend needs a project_id in config.toml.".into())
+    })?;
+    let store = KeyringStore::open(project_id)?.with_history_depth(config.history_depth);
+    Ok(Box::new(store))
+}
+
+#[cfg(not(feature = "keyring"))]
+pub fn open_keyring(_config: &Config) -> Result<Box<dyn Store>> {
+    Err(no_keychain_support().into())
+}
+
+/// The secret in a member's key file that holds their age secret key.
+#[cfg(feature = "age")]
+pub const MEMBER_KEY: &str = "age-identity";
+
+/// The age store `target` names, decrypted with the `--identity` files, or else
+/// with whichever of the usual identity files can be read. If none of them fit and
+/// the store has password members, the password opens the matching member's key.
+#[cfg(feature = "age")]
+pub fn unlock_age(target: &StoreTarget, read_only: bool) -> Result<AgeStore> {
+    let mut store = age_store(target);
+    match store.unlock(read_only) {
+        Err(EnjectError::NoMatchingIdentity) if has_password_members(target) => {
+            let identity = unlock_member_key(target)?;
+            let mut store = age_store(target).with_identity(&identity)?;
+            store.unlock(read_only)?;
+            Ok(store)
+        }
+        result => {
+            result?;
+            Ok(store)
+        }
+    }
+}
+
+#[cfg(feature = "age")]
+fn age_store(target: &StoreTarget) -> AgeStore {
+    let recipients = target.config.age_recipients();
+    let store = if target.identities.is_empty() {
+        AgeStore::new(
+            target.store_path.clone(),
+            recipients,
+            config::default_age_identities(),
+        )
+        .skipping_unusable_identities()
+    } else {
+        AgeStore::new(
+            target.store_path.clone(),
+            recipients,
+            target.identities.clone(),
+        )
+    };
+    store.with_history_depth(target.config.history_depth)
+}
+
+#[cfg(feature = "age")]
+fn has_password_members(target: &StoreTarget) -> bool {
+    target
+        .config
+        .members
+        .keys()
+        .any(|name| config::member_key_path(&target.config_dir, name).exists())
+}
+
+/// The age secret key of the member whose key file the store password opens. The
+/// current user's file is tried first, so usually only one key derivation runs.
+#[cfg(feature = "age")]
+fn unlock_member_key(target: &StoreTarget) -> Result<SecretString> {
+    let user = current_user();
+    let mut names: Vec<&String> = target.config.members.keys().collect();
+    names.sort_by_key(|name| user.as_ref() != Some(*name));
+
+    let mut identity = None;
+    let result = target.unlock_with_retries(|password| {
+        for name in &names {
+            let path = config::member_key_path(&target.config_dir, name);
+            if !path.exists() {
+                continue;
+            }
+            let mut key_file = PasswordStore::new(path, target.config.kdf_params(), Vec::new());
+            match key_file.unlock_read_only(password) {
+                Ok(()) => {
+                    identity = Some(key_file.get(MEMBER_KEY)?.ok_or_else(|| {
+                        EnjectError::CorruptStore(format!(
+                            "Member '{}' has an empty key file.",
+                            name
+                        ))
+                    })?);
+                    return Ok(());
+                }
+                Err(EnjectError::DecryptionFailed) => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Err(EnjectError::DecryptionFailed.into())
+    });
+    match (result, identity) {
+        (Ok(_), Some(identity)) => Ok(identity),
+        (Err(e), _) if !matches!(e.downcast_ref(), Some(EnjectError::DecryptionFailed)) => Err(e),
+        _ => Err(EnjectError::NoMatchingIdentity.into()),
+    }
+}
+
+/// The login name of whoever runs enject, from `$USER` (`%USERNAME%` on Windows).
+#[cfg(feature = "age")]
+pub fn current_user() -> Option<String> {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .ok()
+        .filter(|user| !user.is_empty())
+}
+
+#[cfg(feature = "age")]
+fn open_age(target: &StoreTarget, read_only: bool) -> Result<Box<dyn Store>> {
+    Ok(Box::new(unlock_age(target, read_only)?))
+}
+
+#[cfg(not(feature = "age"))]
+fn open_age(_target: &StoreTarget, _read_only: bool) -> Result<Box<dyn Store>> {
+    Err(no_age_support().into())
+}
+
+/// Write an empty store file encrypted to `recipients`.
+#[cfg(feature = "age")]
+pub fn create_age_store(store_path: &Path, recipients: &[String]) -> Result<()> {
+    AgeStore::create_empty(store_path, recipients.to_vec())?;
+    Ok(())
+}
+
+#[cfg(not(feature = "age"))]
+pub fn create_age_store(_store_path: &Path, _recipients: &[String]) -> Result<()> {
+    Err(no_age_support().into())
+}
+
+/// Re-encrypt the age store `target` names to the recipients and members in
+/// `config`, under a fresh data key. See `save_age_config`.
+#[cfg(feature = "age")]
+pub fn reencrypt_age(target: &StoreTarget, config: Config) -> Result<()> {
+    save_age_config(target, unlock_age(target, false)?, config)
+}
+
+#[cfg(not(feature = "age"))]
+pub fn reencrypt_age(_target: &StoreTarget, _config: Config) -> Result<()> {
+    Err(no_age_support().into())
+}
+
+/// Write `config`, then re-encrypt the unlocked `store` to its recipients. The
+/// config goes first: if the store can't be written, the next save still reaches
+/// everyone the config names.
+#[cfg(feature = "age")]
+pub fn save_age_config(target: &StoreTarget, mut store: AgeStore, config: Config) -> Result<()> {
+    store.set_recipients(config.age_recipients())?;
+    config::write_in(&target.config_dir, &config).context("Failed to update config")?;
+    store
+        .persist()
+        .context("Failed to re-encrypt the store to the new recipients")?;
+    Ok(())
+}
+
+#[cfg(feature = "age")]
+pub use crate::store::age::check_recipient as check_age_recipient;
+
+#[cfg(not(feature = "age"))]
+pub fn check_age_recipient(_recipient: &str) -> crate::store::Result<()> {
+    Err(no_age_support())
+}
+
+#[cfg(not(feature = "age"))]
+pub fn no_age_support() -> EnjectError {
+    EnjectError::Age(
+        "this enject was built without age support. Rebuild it with `--features age`.".into(),
+    )
+}
+
+#[cfg(feature = "keyring")]
+pub use crate::store::keyring::{forget_key, remember_key, remembered_key};
+
+/// Without keychain support there is never a cached key.
+#[cfg(not(feature = "keyring"))]
+pub fn remembered_key(
+    _fingerprint: &str,
+) -> crate::store::Result<Option<Zeroizing<[u8; KEY_LEN]>>> {
+    Ok(None)
+}
+
+#[cfg(not(feature = "keyring"))]
+pub fn remember_key(_fingerprint: &str, _key: &[u8; KEY_LEN]) -> crate::store::Result<()> {
+    Err(no_keychain_support())
+}
+
+#[cfg(not(feature = "keyring"))]
+pub fn forget_key(_fingerprint: &str) -> crate::store::Result<bool> {
+    Ok(false)
+}
+
+#[cfg(not(feature = "keyring"))]
+fn no_keychain_support() -> EnjectError {
+    EnjectError::Keychain(
+        "this enject was built without keychain support. Rebuild it with `--features keyring`."
+            .into(),
+    )
+}
+
+/// Explain a failed unlock. A held lock is reported as it is rather than as a
+/// possibly wrong password.
+fn unlocked(result: crate::store::Result<()>) -> Result<()> {
+    match result {
+        Err(e @ EnjectError::StoreLocked(_)) => Err(e.into()),
+        other => other.context("Failed to unlock store — wrong password?"),
+    }
+}
+
+/// How long `--calibrate` aims for one key derivation to take.
+const CALIBRATION_TARGET: Duration = Duration::from_millis(500);
+
+/// The KDF parameters `args` asks for: a preset, calibrated values, or `current`
+/// with any explicit costs swapped in. Parameters other than `current` must pass
+/// `KdfParams::validate`.
+pub fn kdf_params(args: &KdfArgs, current: KdfParams) -> Result<KdfParams> {
+    let params = match args.preset {
+        Some(KdfPreset::Interactive) => KdfParams::interactive(),
+        Some(KdfPreset::Moderate) => KdfParams::moderate(),
+        Some(KdfPreset::Paranoid) => KdfParams::paranoid(),
+        None if args.calibrate => {
+            inform(format_args!("Calibrating Argon2id on this machine..."));
+            password::calibrate(CALIBRATION_TARGET)?
+        }
+        None => KdfParams {
+            m_cost: args.m_cost.unwrap_or(current.m_cost),
+            t_cost: args.t_cost.unwrap_or(current.t_cost),
+            p_cost: args.p_cost.unwrap_or(current.p_cost),
+        },
+    };
+    if params != current {
+        params.validate()?;
+    }
+    Ok(params)
+}
+
+/// Ask a yes/no question on the terminal. Anything but `y`/`Y` counts as no. The
+/// question goes to stderr, so stdout keeps only results, such as a JSON document.
+pub fn confirm(question: &str) -> Result<bool> {
+    eprint!("{} [y/N]: ", question);
+    io::stderr().flush()?;
+
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    Ok(answer.trim().eq_ignore_ascii_case("y"))
+}
+
+/// Print an informational message on stdout: a confirmation or hint that a script
+/// has no use for. `--quiet` turns these off.
+pub fn inform(message: std::fmt::Arguments) {
+    if !crate::logging::quiet() {
+        println!("{}", message);
+    }
+}
+
+/// Like `inform`, but on stderr, for notes printed while stdout belongs to the
+/// command `run` starts.
+pub fn inform_stderr(message: std::fmt::Arguments) {
+    if !crate::logging::quiet() {
+        eprintln!("{}", message);
+    }
+}
+
+/// Print a message for the user: on stdout, or on stderr with `--format json`, where
+/// stdout carries nothing but the JSON document.
+pub fn note(format: OutputFormat, message: std::fmt::Arguments) {
+    match format {
+        OutputFormat::Text => println!("{}", message),
+        OutputFormat::Json => eprintln!("{}", message),
+    }
+}
+
+/// True when the user can answer a prompt on stdin.
+pub fn is_interactive() -> bool {
+    io::stdin().is_terminal()
+}
+
+/// Shell-style match of `key` against `pattern`: `*` matches any run of characters
+/// and `?` any single one. Everything else matches itself, case-sensitively.
+pub fn glob_match(pattern: &str, key: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let key: Vec<char> = key.chars().collect();
+    let (mut p, mut k) = (0, 0);
+    // Where to resume after the last `*`: the pattern index past it, and the key
+    // index it is currently taken to match up to
+    let mut backtrack = None;
+    while k < key.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p + 1, k));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == key[k] => {
+                p += 1;
+                k += 1;
+            }
+            _ => match backtrack {
+                Some((star_p, star_k)) => {
+                    backtrack = Some((star_p, star_k + 1));
+                    p = star_p;
+                    k = star_k + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("*_URL", "DATABASE_URL"));
+        assert!(glob_match("*_URL", "_URL"));
+        assert!(!glob_match("*_URL", "DATABASE_URLS"));
+        assert!(glob_match("API_?EY", "API_KEY"));
+        assert!(glob_match("*KEY*", "MY_KEY_ID"));
+        assert!(glob_match("A*B*C", "AxxBxxBxC"));
+        assert!(!glob_match("PORT", "port"));
+        assert!(glob_match("*", ""));
+    }
+
+    #[test]
+    fn test_kdf_params_preset_or_overrides() {
+        let current = KdfParams {
+            m_cost: 8192,
+            t_cost: 1,
+            p_cost: 1,
+        };
+        assert_eq!(
+            kdf_params(&KdfArgs::default(), current.clone()).unwrap(),
+            current
+        );
+        let args = KdfArgs {
+            t_cost: Some(5),
+            ..KdfArgs::default()
+        };
+        // Still below the memory minimum, so any change is refused
+        assert!(kdf_params(&args, current.clone()).is_err());
+        let args = KdfArgs {
+            m_cost: Some(32768),
+            t_cost: Some(5),
+            ..KdfArgs::default()
+        };
+        assert_eq!(
+            kdf_params(&args, current.clone()).unwrap(),
+            KdfParams {
+                m_cost: 32768,
+                t_cost: 5,
+                p_cost: 1,
+            }
+        );
+        let args = KdfArgs {
+            preset: Some(KdfPreset::Paranoid),
+            ..KdfArgs::default()
+        };
+        assert_eq!(kdf_params(&args, current).unwrap(), KdfParams::paranoid());
+    }
+}
@@ -0,0 +1,58 @@
+use anyhow::{bail, Result};
+
+use crate::cli::RecipientsCommand;
+use crate::commands::{check_age_recipient, inform, reencrypt_age, StoreTarget};
+use crate::ui;
+
+/// `enject recipients` — list the public keys an age store is encrypted to, or
+/// change them and re-encrypt the store to the new set.
+pub fn run(target: &StoreTarget, command: &RecipientsCommand) -> Result<()> {
+    if target.config.backend != "age" {
+        bail!(
+            "The {} uses the '{}' backend; recipients only apply to stores created with `{} init --backend age`.",
+            target.name,
+            target.config.backend,
+            target.command
+        );
+    }
+
+    let mut config = target.config.clone();
+    match command {
+        RecipientsCommand::List => {
+            for recipient in &config.recipients {
+                println!("{}", recipient);
+            }
+        }
+        RecipientsCommand::Add { recipient } => {
+            check_age_recipient(recipient)?;
+            if config.recipients.contains(recipient) {
+                inform(format_args!("{} is already a recipient.", recipient));
+                return Ok(());
+            }
+            config.recipients.push(recipient.clone());
+            reencrypt_age(target, config)?;
+            ui::success(format_args!(
+                "Added {}; the store is re-encrypted.",
+                recipient
+            ));
+        }
+        RecipientsCommand::Remove { recipient } => {
+            if !config.recipients.contains(recipient) {
+                bail!("{} is not a recipient of the {}.", recipient, target.name);
+            }
+            config.recipients.retain(|r| r != recipient);
+            if config.age_recipients().is_empty() {
+                bail!("Refusing to remove the last recipient: nobody could decrypt the store.");
+            }
+            reencrypt_age(target, config)?;
+            ui::success(format_args!(
+                "Removed {}; the store is re-encrypted.",
+                recipient
+            ));
+            inform(format_args!(
+                "Copies made before now still open with that key. Rotate the secrets it could read."
+            ));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,90 @@
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+
+use crate::commands::{confirm, inform, is_interactive, StoreTarget};
+use crate::env_template;
+use crate::error::EnjectError;
+use crate::store::Annotation;
+use crate::ui;
+
+pub fn run(target: &StoreTarget, old: &str, new: &str, force: bool) -> Result<()> {
+    if old == new {
+        bail!("Old and new key names are the same.");
+    }
+
+    let mut store = target.unlock()?;
+
+    let value = store
+        .get(old)?
+        .ok_or_else(|| EnjectError::SecretNotFound(old.to_string()))?;
+
+    if store.get(new)?.is_some() && !force {
+        if !is_interactive() {
+            bail!(
+                "Secret '{}' already exists. Re-run with --force to overwrite it.",
+                new
+            );
+        }
+        if !confirm(&format!("Secret '{}' already exists — overwrite?", new))? {
+            inform(format_args!("Rename cancelled."));
+            return Ok(());
+        }
+    }
+
+    // Both changes land in the in-memory map and are written in a single save
+    store.set(new, value)?;
+    if let Some(meta) = store.metadata(old)? {
+        let annotation = Annotation {
+            description: meta.description,
+            tags: Some(meta.tags),
+            expires_at: meta.expires_at,
+        };
+        store.annotate(new, annotation)?;
+    }
+    store.delete(old)?;
+    store.persist().context("Failed to save store")?;
+
+    ui::success(format_args!("Secret '{}' renamed to '{}'.", old, new));
+
+    update_env_refs(&target.project_root, old, new)
+}
+
+/// Offer to point `en://old` references in the project `.env` at the new name.
+fn update_env_refs(root: &Path, old: &str, new: &str) -> Result<()> {
+    let env_path = root.join(".env");
+    if !env_path.exists() {
+        return Ok(());
+    }
+
+    let content = std::fs::read_to_string(&env_path).context("Failed to read .env")?;
+    let (renamed, count) = env_template::rename_refs(&content, old, new);
+    if count == 0 {
+        return Ok(());
+    }
+
+    let question = format!(
+        "\n{} contains {} reference(s) to en://{}. Update them to en://{}?",
+        env_path.display(),
+        count,
+        old,
+        new
+    );
+    if !is_interactive() || !confirm(&question)? {
+        ui::warn(format_args!(
+            "left {} unchanged. Update en://{} to en://{} before the next run.",
+            env_path.display(),
+            old,
+            new
+        ));
+        return Ok(());
+    }
+
+    env_template::write_atomic(&env_path, &renamed).context("Failed to rewrite .env")?;
+    inform(format_args!(
+        "Updated {} reference(s) in {}.",
+        count,
+        env_path.display()
+    ));
+    Ok(())
+}
@@ -0,0 +1,37 @@
+use anyhow::{bail, Context, Result};
+
+use crate::commands::StoreTarget;
+use crate::error::EnjectError;
+use crate::ui;
+
+/// Make previous version `number` of `key` (as numbered by `history`) current again.
+pub fn run(target: &StoreTarget, key: &str, number: usize) -> Result<()> {
+    let mut store = target.unlock()?;
+
+    let versions = store.history(key)?;
+    if versions.is_empty() {
+        return Err(EnjectError::SecretNotFound(key.to_string()).into());
+    }
+    let Some(set_at) = versions
+        .iter()
+        .find(|v| v.number == number && number > 0)
+        .map(|v| v.set_at.clone())
+    else {
+        bail!(
+            "Secret '{}' has no version {}. See the versions with: {} history {}",
+            key,
+            number,
+            target.command,
+            key
+        );
+    };
+
+    store.restore_version(key, number)?;
+    store.persist().context("Failed to save store")?;
+
+    ui::success(format_args!(
+        "Secret '{}' restored to version {} (set at {}).",
+        key, number, set_at
+    ));
+    Ok(())
+}
@@ -1,31 +1,272 @@
 use anyhow::{Context, Result};
+use rand::RngCore;
 use secrecy::SecretString;
 
-use crate::commands::init::prompt_new_password;
+use crate::auth::prompt_new_password;
+use crate::cli::RotateArgs;
+use crate::commands::{forget_key, inform, kdf_params, reencrypt_age, StoreTarget};
 use crate::config;
-use crate::store::password::PasswordStore;
+use crate::store::password::{Cipher, KdfParams, PasswordStore};
+use crate::ui;
 
-pub fn run() -> Result<()> {
-    let root = config::project_root()?;
-    let cfg = config::read(&root)?;
+/// Unlock with the current password and parameters, then re-encrypt under a fresh
+/// salt, a new password unless `--keep-password`, and any new cipher or KDF parameters.
+/// An age store has no password: it is re-encrypted under a new data key instead.
+pub fn run(target: &StoreTarget, args: &RotateArgs) -> Result<()> {
+    if target.config.backend == "age" {
+        reencrypt_age(target, target.config.clone())?;
+        ui::success(format_args!(
+            "{} re-encrypted under a new data key for its current members and recipients.",
+            target.name
+        ));
+        return Ok(());
+    }
+    let (store, old_password) = target.unlock_with_password(false)?;
+    let old_fingerprint = target.key_fingerprint()?;
+    let current = store.kdf_params().clone();
+    let params = kdf_params(&args.kdf, current.clone())?;
+    let current_cipher = store.cipher();
+    let cipher = match &args.cipher {
+        Some(name) => Cipher::from_name(name)?,
+        None => current_cipher,
+    };
 
-    let old_password = rpassword::prompt_password("Current Enject store password: ")
-        .context("Failed to read current Enject store password")?;
-    let old_password = SecretString::new(old_password);
+    let password = if args.keep_password {
+        old_password
+    } else {
+        println!("Enter a new {} password.", target.name);
+        prompt_new_password(target.name, args.allow_weak_password)?
+    };
 
-    let store_path = config::store_path(&root);
-    let mut store = PasswordStore::new(store_path, cfg.kdf_params(), cfg.salt_bytes()?);
-    store
-        .unlock(&old_password)
-        .context("Failed to unlock store — wrong password?")?;
+    rekey(target, store, &password, cipher, &params)?;
+    // The cached key belongs to the old salt and would no longer open the store
+    if let Ok(true) = forget_key(&old_fingerprint) {
+        inform(format_args!(
+            "Removed the cached key from the OS keychain. Run `{} unlock --remember` to cache the new one.",
+            target.command
+        ));
+    }
+
+    if args.keep_password {
+        ui::success(format_args!(
+            "{} re-encrypted with a new salt.",
+            target.name
+        ));
+    } else {
+        ui::success(format_args!(
+            "{} password rotated successfully.",
+            target.name
+        ));
+    }
+    if cipher != current_cipher {
+        inform(format_args!(
+            "Cipher: {} (was {}).",
+            cipher.name(),
+            current_cipher.name()
+        ));
+    }
+    if params != current {
+        inform(format_args!(
+            "KDF parameters: m_cost={}, t_cost={}, p_cost={} (were m_cost={}, t_cost={}, p_cost={}).",
+            params.m_cost,
+            params.t_cost,
+            params.p_cost,
+            current.m_cost,
+            current.t_cost,
+            current.p_cost
+        ));
+    }
+    Ok(())
+}
 
-    println!("Enter a new Enject store password.");
-    let new_password = prompt_new_password()?;
+/// Re-encrypt the unlocked `store` under `password`, `cipher`, `params` and a freshly
+/// generated salt. The re-keyed store is written beside the old one, then the config is updated,
+/// then the new store replaces the old: a crash at any point leaves a store that
+/// `unlock` can open with either the old or the new credentials.
+fn rekey(
+    target: &StoreTarget,
+    mut store: PasswordStore,
+    password: &SecretString,
+    cipher: Cipher,
+    params: &KdfParams,
+) -> Result<()> {
+    let mut salt = vec![0u8; 32];
+    rand::thread_rng().fill_bytes(&mut salt);
+
+    let mut config = target.config.clone();
+    config.salt = hex::encode(&salt);
+    config.cipher = cipher.name().into();
+    config.m_cost = params.m_cost;
+    config.t_cost = params.t_cost;
+    config.p_cost = params.p_cost;
+    store.rekey(cipher, config.kdf_params(), salt);
 
     store
-        .save(&new_password)
+        .save_pending(password)
         .context("Failed to re-encrypt store with new password")?;
-
-    println!("Enject store password rotated successfully.");
+    config::write_in(&target.config_dir, &config).context("Failed to update config")?;
+    store
+        .commit_pending()
+        .context("Failed to replace the store with the re-encrypted one")?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::Store;
+    use secrecy::ExposeSecret;
+    use tempfile::TempDir;
+
+    fn target(dir: &TempDir) -> StoreTarget {
+        let mut config = config::Config::default_new(hex::encode([7u8; 32]));
+        // Very low cost for fast tests
+        config.m_cost = 8192;
+        config.t_cost = 1;
+        config.p_cost = 1;
+        config::write_in(dir.path(), &config).unwrap();
+        StoreTarget {
+            config,
+            project_root: dir.path().to_path_buf(),
+            config_dir: dir.path().to_path_buf(),
+            store_path: dir.path().join("store"),
+            name: "Enject store",
+            command: "enject",
+            password_env: "ENJECT_TEST_UNUSED_PASSWORD",
+            password_file: None,
+            stdin_password: false,
+            keychain: false,
+            agent: false,
+            identities: Vec::new(),
+        }
+    }
+
+    /// Unlock the store as a later command would: with the config on disk.
+    fn reopen(dir: &TempDir, password: &SecretString) -> crate::store::Result<PasswordStore> {
+        let config = config::read_global(dir.path()).unwrap();
+        let mut store = PasswordStore::new(
+            dir.path().join("store"),
+            config.kdf_params(),
+            config.salt_bytes().unwrap(),
+        );
+        store.unlock(password).map(|()| store)
+    }
+
+    #[test]
+    fn test_rotation_changes_salt_and_new_credentials_unlock() {
+        let dir = TempDir::new().unwrap();
+        let target = target(&dir);
+        let old = SecretString::new("test-password-do-not-use".to_string());
+        let new = SecretString::new("new-test-password-do-not-use".to_string());
+
+        let mut store = target.open().unwrap();
+        store.unlock(&old).unwrap();
+        store.set("k", SecretString::new("v".to_string())).unwrap();
+        store.save(&old).unwrap();
+
+        rekey(
+            &target,
+            store,
+            &new,
+            Cipher::Aes256Gcm,
+            &target.config.kdf_params(),
+        )
+        .unwrap();
+
+        let config = config::read_global(dir.path()).unwrap();
+        assert_ne!(config.salt, target.config.salt);
+        assert_eq!(config.m_cost, target.config.m_cost);
+        let store = reopen(&dir, &new).unwrap();
+        assert_eq!(store.get("k").unwrap().unwrap().expose_secret(), "v");
+        drop(store);
+        assert!(reopen(&dir, &old).is_err());
+        assert!(!dir.path().join("store.rekeyed").exists());
+    }
+
+    #[test]
+    fn test_rotation_upgrades_kdf_params_with_same_password() {
+        let dir = TempDir::new().unwrap();
+        let target = target(&dir);
+        let password = SecretString::new("test-password-do-not-use".to_string());
+        let mut store = target.open().unwrap();
+        store.unlock(&password).unwrap();
+        store.set("k", SecretString::new("v".to_string())).unwrap();
+        store.save(&password).unwrap();
+
+        let params = KdfParams {
+            m_cost: 9216,
+            t_cost: 2,
+            p_cost: 1,
+        };
+        rekey(&target, store, &password, Cipher::Aes256Gcm, &params).unwrap();
+
+        let config = config::read_global(dir.path()).unwrap();
+        assert_eq!(config.kdf_params(), params);
+        let store = reopen(&dir, &password).unwrap();
+        assert_eq!(store.get("k").unwrap().unwrap().expose_secret(), "v");
+    }
+
+    #[test]
+    fn test_rotation_converts_cipher() {
+        let dir = TempDir::new().unwrap();
+        let target = target(&dir);
+        let password = SecretString::new("test-password-do-not-use".to_string());
+        let mut store = target.open().unwrap();
+        store.unlock(&password).unwrap();
+        store.set("k", SecretString::new("v".to_string())).unwrap();
+        store.save(&password).unwrap();
+
+        rekey(
+            &target,
+            store,
+            &password,
+            Cipher::XChaCha20Poly1305,
+            &target.config.kdf_params(),
+        )
+        .unwrap();
+
+        let config = config::read_global(dir.path()).unwrap();
+        assert_eq!(config.cipher, "xchacha20");
+        let store = reopen(&dir, &password).unwrap();
+        assert_eq!(store.cipher(), Cipher::XChaCha20Poly1305);
+        assert_eq!(store.get("k").unwrap().unwrap().expose_secret(), "v");
+    }
+
+    #[test]
+    fn test_interrupted_rotation_recovers_on_unlock() {
+        let dir = TempDir::new().unwrap();
+        let target = target(&dir);
+        let old = SecretString::new("test-password-do-not-use".to_string());
+        let new = SecretString::new("new-test-password-do-not-use".to_string());
+        let mut store = target.open().unwrap();
+        store.unlock(&old).unwrap();
+        store.set("k", SecretString::new("v".to_string())).unwrap();
+        store.save(&old).unwrap();
+        drop(store);
+
+        // Write the re-keyed store, then stop as if the process had crashed
+        let crash_after_pending = || {
+            let mut rekeyed = target.open().unwrap();
+            rekeyed.unlock(&old).unwrap();
+            rekeyed.rekey(Cipher::Aes256Gcm, target.config.kdf_params(), vec![9u8; 32]);
+            rekeyed.save_pending(&new).unwrap();
+        };
+
+        // The old password still opens the old store, and the leftover is dropped
+        crash_after_pending();
+        let store = reopen(&dir, &old).unwrap();
+        assert_eq!(store.get("k").unwrap().unwrap().expose_secret(), "v");
+        assert!(!dir.path().join("store.rekeyed").exists());
+        drop(store);
+        assert!(reopen(&dir, &new).is_err());
+
+        // The new password opens the re-keyed store and moves it into place, whether
+        // or not the config was updated before the crash
+        crash_after_pending();
+        let store = reopen(&dir, &new).unwrap();
+        assert_eq!(store.get("k").unwrap().unwrap().expose_secret(), "v");
+        assert!(!dir.path().join("store.rekeyed").exists());
+        drop(store);
+        assert!(reopen(&dir, &old).is_err());
+    }
+}
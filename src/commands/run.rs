@@ -1,55 +1,848 @@
-use anyhow::{Context, Result};
-use secrecy::{ExposeSecret, SecretString};
-use std::collections::HashMap;
+use anyhow::{anyhow, bail, Context, Result};
+use secrecy::ExposeSecret;
+use std::collections::{BTreeMap, HashMap};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::ExitStatus;
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, SystemTime};
 
-use crate::config;
-use crate::env_template;
+use crate::cli::{GlobalOpts, RunArgs};
+use crate::commands::{
+    glob_match, inform, inform_stderr, parse_template, project_root, StoreTarget,
+};
+use crate::config::{self, Script};
+use crate::env_template::{self, EnvLine, ParsedLine, Part, Scope};
+use crate::error::EnjectError;
+use crate::expiry;
+use crate::mask::{self, Masker};
+use crate::procfile;
 use crate::runner;
-use crate::store::password::PasswordStore;
+use crate::store::password::KEY_LEN;
 use crate::store::Store;
+use crate::ui;
+use crate::watch;
+use zeroize::Zeroizing;
 
-pub fn run(cmd: Vec<String>) -> Result<()> {
-    let root = config::project_root()?;
-    let cfg = config::read(&root)?;
+pub fn run(opts: &GlobalOpts, args: RunArgs) -> Result<ExitStatus> {
+    let root = project_root(opts)?;
+    // Checked before anything prompts for a password
+    let current_dir = match &args.cwd {
+        Some(dir) => Some(
+            std::fs::canonicalize(dir)
+                .ok()
+                .filter(|dir| dir.is_dir())
+                .ok_or_else(|| anyhow!("--cwd {} is not a directory.", dir.display()))?,
+        ),
+        None => None,
+    };
+    let processes = match &args.procfile {
+        Some(path) => {
+            let content = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            Some(
+                procfile::parse(&content)
+                    .with_context(|| format!("Failed to parse {}", path.display()))?,
+            )
+        }
+        None => None,
+    };
+    let mut local = StoreTarget::local(opts)?;
+    if args.list_scripts {
+        print_scripts(&local);
+        return Ok(ExitStatus::default());
+    }
+    let cmd = command_line(&args, &local.config.scripts)?;
+    let args_use_global = args.subst_args && check_subst_args(&cmd)?;
 
-    // Parse the .env template
-    let env_path = root.join(".env");
-    if !env_path.exists() {
-        anyhow::bail!(
-            ".env file not found in current directory. \
-             Create one with en:// references and try again."
-        );
+    // Parse the .env template(s), in precedence order
+    let profile = args
+        .profile
+        .as_deref()
+        .or(local.config.default_profile.as_deref());
+    let paths = if args.env_file.is_empty() {
+        if args.all && args.profile.is_none() && !root.join(".env").exists() {
+            Vec::new()
+        } else {
+            template_paths(&root, profile)?
+        }
+    } else {
+        if args
+            .env_file
+            .iter()
+            .filter(|p| *p == Path::new(STDIN_PATH))
+            .count()
+            > 1
+        {
+            bail!("--env-file - can only be given once.");
+        }
+        args.env_file.clone()
+    };
+    if args.watch && paths.iter().any(|p| p == Path::new(STDIN_PATH)) {
+        bail!("--watch can't watch a template read from stdin.");
+    }
+    let layers = load_layers(&paths, args.allow_duplicates)?;
+    if paths.iter().any(|p| p == Path::new(STDIN_PATH)) {
+        // stdin carried a template, so it can't also carry the password
+        local.stdin_password = false;
+    }
+
+    // Only touch the global store when a template actually references it.
+    // Read its config up front so an uninitialized global store fails before any prompt.
+    // --password-file belongs to the project store; the global one uses its own env var or a prompt.
+    let global = if args_use_global
+        || layers
+            .iter()
+            .any(|lines| env_template::has_global_refs(lines))
+    {
+        let mut global = StoreTarget::global(opts)?;
+        global.password_file = None;
+        global.stdin_password = local.stdin_password;
+        Some(global)
+    } else {
+        None
+    };
+
+    let (local, store) = Reopenable::unlock(local)?;
+    let (global, global_store) = match global {
+        Some(global) => {
+            let (global, store) = Reopenable::unlock(global)?;
+            (Some(global), Some(store))
+        }
+        None => (None, None),
+    };
+    let prepared = prepare(&args, &cmd, &layers, &*store, global_store.as_deref())?;
+
+    // Release the stores and their locks so writers aren't held up while the command runs
+    drop(store);
+    drop(global_store);
+    // Only --watch reopens the stores; otherwise their derived keys go now too
+    let reopen = if args.watch {
+        Some((local, global))
+    } else {
+        drop(local);
+        drop(global);
+        None
+    };
+
+    // Hand off to runner — secrets exist only in process memory from here
+    let inherit = if args.isolated {
+        let mut keep: Vec<String> = runner::ISOLATED_ALLOWLIST
+            .iter()
+            .map(|var| var.to_string())
+            .collect();
+        keep.extend(args.keep.iter().cloned());
+        runner::Inherit::Only(keep)
+    } else {
+        runner::Inherit::All
+    };
+    let options = runner::ExecOptions {
+        replace_process: !args.no_exec,
+        inherit,
+        current_dir,
+        mask: prepared.mask.map(Arc::new),
+        prefix: None,
+        timeout: args.timeout,
+        kill_after: args.kill_after,
+    };
+    if let Some(processes) = processes {
+        let commands: Vec<(String, Vec<String>)> = processes
+            .iter()
+            .map(|p| (p.name.clone(), runner::shell_command(&p.command)))
+            .collect();
+        return runner::exec_group(&commands, &prepared.env, &options, STOP_GRACE);
+    }
+    if let Some((local, global)) = reopen {
+        let watched = Watched {
+            args: &args,
+            cmd: &cmd,
+            paths: &paths,
+            local,
+            global,
+        };
+        return watched.run(prepared.argv, prepared.env, options);
+    }
+    runner::exec(&prepared.argv, &prepared.env, &options)
+}
+
+/// What to run: the command after `--`, else the `--shell` string run by the user's
+/// shell, else the script the first positional argument names with the rest
+/// appended to it (after a `--`, as npm allows), else the positional arguments as
+/// the command. Empty with `--procfile`.
+fn command_line(args: &RunArgs, scripts: &BTreeMap<String, Script>) -> Result<Vec<String>> {
+    if !args.cmd.is_empty() {
+        return Ok(args.cmd.clone());
+    }
+    if let Some(line) = &args.shell {
+        return Ok(runner::user_shell_command(line));
+    }
+    match args.script.split_first() {
+        Some((name, extra)) => match scripts.get(name) {
+            Some(script) => {
+                let extra = extra.strip_prefix(&["--".to_string()]).unwrap_or(extra);
+                script_command(script, name, extra)
+            }
+            None => Ok(args.script.clone()),
+        },
+        None => Ok(Vec::new()),
+    }
+}
+
+/// The command line `script` runs, with `extra` arguments appended. A shell string
+/// takes none: they would have to be quoted into it.
+fn script_command(script: &Script, name: &str, extra: &[String]) -> Result<Vec<String>> {
+    match script {
+        Script::Args(args) if args.is_empty() => {
+            bail!("Script '{}' in config.toml is an empty list.", name)
+        }
+        Script::Args(args) => Ok(args.iter().chain(extra).cloned().collect()),
+        Script::Shell(_) if !extra.is_empty() => bail!(
+            "Script '{}' is a shell string, which takes no extra arguments.",
+            name
+        ),
+        Script::Shell(line) => Ok(runner::shell_command(line)),
+    }
+}
+
+/// `run --list-scripts`: the `[scripts]` table of the project's config.toml.
+fn print_scripts(local: &StoreTarget) {
+    let path = config::config_path_in(&local.config_dir);
+    let scripts = &local.config.scripts;
+    if scripts.is_empty() {
+        inform(format_args!(
+            "No scripts. Add a [scripts] table to {}.",
+            path.display()
+        ));
+        return;
+    }
+    let width = scripts.keys().map(String::len).max().unwrap_or(0);
+    for (name, script) in scripts {
+        println!("{:<width$}  {}", name, script, width = width);
+    }
+}
+
+/// How long a command stopped by `--watch`, or by another `--procfile` process
+/// exiting, gets to exit after SIGTERM before it is killed.
+const STOP_GRACE: Duration = Duration::from_secs(5);
+
+/// How long `--watch` waits for a burst of changes, such as an editor's save, to
+/// settle before restarting.
+const WATCH_SETTLE: Duration = Duration::from_millis(200);
+
+/// What `--watch` hears about while the command runs.
+enum WatchEvent {
+    Changed,
+    /// SIGINT, SIGTERM or SIGHUP.
+    #[cfg_attr(not(unix), allow(dead_code))]
+    Stop,
+}
+
+/// `run --watch`: the templates and stores the command's environment came from.
+struct Watched<'a> {
+    args: &'a RunArgs,
+    cmd: &'a [String],
+    paths: &'a [PathBuf],
+    local: Reopenable,
+    global: Option<Reopenable>,
+}
+
+impl Watched<'_> {
+    /// Run the command, and restart it whenever a template or store file changes.
+    /// When the changed files don't resolve, the error is printed and the command
+    /// keeps running with its previous environment; the next change tries again.
+    /// A command that exits on its own is started again by the next change. SIGINT,
+    /// SIGTERM or SIGHUP stop the command and end the watch.
+    fn run(
+        &self,
+        argv: Vec<String>,
+        env: HashMap<String, String>,
+        mut options: runner::ExecOptions,
+    ) -> Result<ExitStatus> {
+        let mut files = self.paths.to_vec();
+        files.push(self.local.target.store_path.clone());
+        if let Some(global) = &self.global {
+            files.push(global.target.store_path.clone());
+        }
+
+        let (tx, rx) = mpsc::channel();
+        let changes = tx.clone();
+        let _watcher = watch::files(&files, move || {
+            let _ = changes.send(WatchEvent::Changed);
+        })?;
+        // Caught before the command starts, as `exec` does
+        #[cfg(unix)]
+        let signals = runner::catch_signals(move |_| {
+            let _ = tx.send(WatchEvent::Stop);
+        })?;
+
+        let mut running = Some(runner::spawn(&argv, &env, &options)?);
+        drop(argv);
+        drop(env);
+        let mut status = ExitStatus::default();
+        loop {
+            let event = match rx.recv_timeout(WATCH_SETTLE) {
+                Ok(WatchEvent::Changed) => settle(&rx),
+                Ok(event) => event,
+                Err(mpsc::RecvTimeoutError::Timeout) => {
+                    if let Some(child) = &mut running {
+                        if let Some(exited) = child.try_wait()? {
+                            inform_stderr(format_args!(
+                                "The command exited ({}); waiting for changes to start it again.",
+                                exited
+                            ));
+                            status = exited;
+                            running = None;
+                        }
+                    }
+                    continue;
+                }
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            };
+            if let WatchEvent::Stop = event {
+                break;
+            }
+
+            let prepared = match self.reload() {
+                Ok(prepared) => prepared,
+                Err(e) => {
+                    let still = if running.is_some() {
+                        "The command keeps running with its previous environment."
+                    } else {
+                        "The command stays stopped."
+                    };
+                    ui::error(format_args!(
+                        "{:#}\n{} Waiting for the next change.",
+                        e, still
+                    ));
+                    continue;
+                }
+            };
+            inform_stderr(format_args!("Change detected; restarting the command."));
+            if let Some(child) = running.take() {
+                status = child.terminate(STOP_GRACE)?;
+            }
+            options.mask = prepared.mask.map(Arc::new);
+            running = Some(runner::spawn(&prepared.argv, &prepared.env, &options)?);
+        }
+
+        if let Some(child) = running {
+            status = child.terminate(STOP_GRACE)?;
+        }
+        #[cfg(unix)]
+        signals.close();
+        Ok(status)
     }
-    let lines = env_template::parse_file(&env_path).context("Failed to parse .env")?;
 
-    // Unlock the local store
-    let password = rpassword::prompt_password("Enject store password: ")
-        .context("Failed to read Enject store password")?;
-    let password = SecretString::new(password);
+    /// Read the templates and stores again, without prompting for passwords.
+    fn reload(&self) -> Result<Prepared> {
+        let layers = load_layers(self.paths, self.args.allow_duplicates)?;
+        let store = self.local.reopen()?;
+        let global_store = match &self.global {
+            Some(global) => Some(global.reopen()?),
+            None if layers
+                .iter()
+                .any(|lines| env_template::has_global_refs(lines)) =>
+            {
+                bail!("The template now references the global store; restart enject to unlock it.");
+            }
+            None => None,
+        };
+        prepare(
+            self.args,
+            self.cmd,
+            &layers,
+            &*store,
+            global_store.as_deref(),
+        )
+    }
+}
+
+/// Wait for a burst of changes to settle, returning `Stop` if it arrives meanwhile.
+fn settle(rx: &mpsc::Receiver<WatchEvent>) -> WatchEvent {
+    loop {
+        match rx.recv_timeout(WATCH_SETTLE) {
+            Ok(WatchEvent::Changed) => continue,
+            Ok(stop) => return stop,
+            Err(_) => return WatchEvent::Changed,
+        }
+    }
+}
+
+/// An unlocked store that can be opened again without asking for its password,
+/// as `--watch` does after each change: a password store is reopened with the key
+/// it was first unlocked with, other backends by unlocking them as usual.
+struct Reopenable {
+    target: StoreTarget,
+    key: Option<Zeroizing<[u8; KEY_LEN]>>,
+}
+
+impl Reopenable {
+    fn unlock(target: StoreTarget) -> Result<(Self, Box<dyn Store>)> {
+        if matches!(target.config.backend.as_str(), "keyring" | "age") {
+            let store = target.unlock_read_only()?;
+            return Ok((Self { target, key: None }, store));
+        }
+        let store = target.unlock_password_store(true)?;
+        let key = store.derived_key().map(|key| Zeroizing::new(*key));
+        Ok((Self { target, key }, Box::new(store)))
+    }
 
-    let store_path = config::store_path(&root);
-    let mut store = PasswordStore::new(store_path, cfg.kdf_params(), cfg.salt_bytes()?);
-    store
-        .unlock(&password)
-        .context("Failed to unlock store — wrong password?")?;
+    fn reopen(&self) -> Result<Box<dyn Store>> {
+        let Some(key) = &self.key else {
+            return self.target.unlock_read_only();
+        };
+        let mut store = self.target.open()?;
+        store
+            .unlock_with_key(key, true)
+            .context("The store no longer opens with the key it was unlocked with")?;
+        Ok(Box::new(store))
+    }
+}
+
+/// The command line and environment to run with, and what to mask in its output.
+struct Prepared {
+    argv: Vec<String>,
+    env: HashMap<String, String>,
+    mask: Option<Masker>,
+}
 
-    // Build the local secrets map (expose only to resolve, not to disk/stdout)
-    let local_secrets = build_secrets_map(&store)?;
+/// Resolve the template layers against the unlocked stores into the command's
+/// environment, and with `--subst-args` the references in `cmd`, as `args` asks.
+fn prepare(
+    args: &RunArgs,
+    cmd: &[String],
+    layers: &[Vec<EnvLine>],
+    store: &dyn Store,
+    global_store: Option<&dyn Store>,
+) -> Result<Prepared> {
+    // Build the secrets maps (expose only to resolve, not to disk/stdout)
+    let local_secrets = build_secrets_map(store)?;
+    let global_secrets = match global_store {
+        Some(store) => build_secrets_map(store)?,
+        None => HashMap::new(),
+    };
 
-    // TODO: global store support — for now, global refs will produce a clear error
-    let global_secrets: HashMap<String, String> = HashMap::new();
+    // With --all, every secret is injected under its own name, below the template
+    let store_env = if args.all {
+        store_env(&local_secrets, args.prefix.as_deref().unwrap_or(""))?
+    } else {
+        HashMap::new()
+    };
 
-    // Resolve all en:// references — hard-errors on any unresolved ref
-    let resolved = env_template::resolve(&lines, &local_secrets, &global_secrets)
+    // Resolve all en:// references — hard-errors on any unresolved ref in any layer
+    let template_env = env_template::resolve_layered(layers, &local_secrets, &global_secrets)
         .context("Failed to resolve .env references")?;
 
-    // Hand off to runner — secrets exist only in process memory from here
-    runner::exec(&cmd, &resolved)?;
+    let (argv, substituted) = if args.subst_args {
+        (
+            substitute_args(cmd, &local_secrets, &global_secrets)?,
+            arg_secrets(cmd, &local_secrets, &global_secrets),
+        )
+    } else {
+        (cmd.to_vec(), Vec::new())
+    };
+
+    let mask = if args.mask {
+        let mut values = mask_values(layers, &template_env, &local_secrets, &global_secrets);
+        values.extend(
+            store_env
+                .iter()
+                .map(|(var, value)| (var.as_str(), value.as_str())),
+        );
+        values.extend(
+            substituted
+                .iter()
+                .map(|(name, value)| (name.as_str(), *value)),
+        );
+        let masker = Masker::new(values)?;
+        if masker.is_empty() {
+            ui::warn(format_args!(
+                "--mask has nothing to mask: no injected secret is {} or more characters long.",
+                mask::MIN_MASKED_LEN
+            ));
+        }
+        Some(masker)
+    } else {
+        None
+    };
+
+    let mut resolved = store_env;
+    resolved.extend(template_env);
+
+    // Filter only after resolving, so every reference is still validated
+    let env = select_keys(resolved, &args.only, &args.except)?;
+    let mut names: Vec<&str> = env.keys().map(String::as_str).collect();
+    names.sort_unstable();
+    tracing::debug!(
+        "Injecting {} variable(s): {}",
+        names.len(),
+        names.join(", ")
+    );
+
+    check_expiry(layers, store, global_store, args.strict_expiry)?;
+    Ok(Prepared { argv, env, mask })
+}
+
+/// Check `cmd` for `--subst-args` before anything is unlocked: the program name may
+/// not hold a reference. Warns that substituted values can be seen by other users,
+/// and returns whether any argument references the global store.
+fn check_subst_args(cmd: &[String]) -> Result<bool> {
+    let mut found = false;
+    let mut global = false;
+    for (i, arg) in cmd.iter().enumerate() {
+        for part in env_template::split_refs(arg)? {
+            match part {
+                Part::Text(_) => continue,
+                Part::Local(_) => {}
+                Part::Global(_) => global = true,
+            }
+            if i == 0 {
+                bail!(
+                    "--subst-args does not substitute into the program name ({}), only its arguments.",
+                    arg
+                );
+            }
+            found = true;
+        }
+    }
+    if found {
+        ui::warn(format_args!(
+            "--subst-args puts secret values on the command line, where other users \
+             of this machine can read them (ps, /proc). Prefer passing secrets through the \
+             environment where the command allows it."
+        ));
+    } else {
+        inform_stderr(format_args!(
+            "Note: --subst-args found no en:// references in the command's arguments."
+        ));
+    }
+    Ok(global)
+}
+
+/// `cmd` with the `en://` references in its arguments replaced by their secrets.
+fn substitute_args(
+    cmd: &[String],
+    local_secrets: &HashMap<String, String>,
+    global_secrets: &HashMap<String, String>,
+) -> Result<Vec<String>> {
+    let mut argv = Vec::new();
+    let mut missing = Vec::new();
+    for arg in cmd {
+        let parts = env_template::split_refs(arg)?;
+        match env_template::substitute(&parts, local_secrets, global_secrets) {
+            Ok(arg) => argv.push(arg),
+            Err(EnjectError::MissingSecrets(secrets)) => {
+                for secret in secrets {
+                    if !missing.contains(&secret) {
+                        missing.push(secret);
+                    }
+                }
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+    if !missing.is_empty() {
+        return Err(anyhow::Error::new(EnjectError::MissingSecrets(missing))
+            .context("Failed to resolve en:// references in the command's arguments"));
+    }
+    Ok(argv)
+}
+
+/// The secrets `substitute_args` puts into `cmd`, each under its reference, for
+/// `--mask`.
+fn arg_secrets<'a>(
+    cmd: &[String],
+    local_secrets: &'a HashMap<String, String>,
+    global_secrets: &'a HashMap<String, String>,
+) -> Vec<(String, &'a str)> {
+    let mut secrets = Vec::new();
+    for arg in cmd {
+        for part in env_template::split_refs(arg).unwrap_or_default() {
+            let found = match part {
+                Part::Text(_) => None,
+                Part::Local(name) => local_secrets
+                    .get(&name)
+                    .map(|value| (format!("en://{}", name), value.as_str())),
+                Part::Global(name) => global_secrets
+                    .get(&name)
+                    .map(|value| (format!("en://global/{}", name), value.as_str())),
+            };
+            secrets.extend(found);
+        }
+    }
+    secrets
+}
+
+/// Parse the template files, in precedence order, into layers of lines.
+fn load_layers(paths: &[PathBuf], allow_duplicates: bool) -> Result<Vec<Vec<EnvLine>>> {
+    let mut layers = Vec::new();
+    for path in paths {
+        let lines = load_template(path)?;
+        reject_duplicates(path, &lines, allow_duplicates)?;
+        layers.push(
+            lines
+                .into_iter()
+                .map(|parsed| parsed.line)
+                .collect::<Vec<_>>(),
+        );
+    }
+    Ok(layers)
+}
+
+/// Warn on stderr about each referenced secret past its expiry date, or with
+/// `strict` refuse to run.
+fn check_expiry(
+    layers: &[Vec<EnvLine>],
+    local: &dyn Store,
+    global: Option<&dyn Store>,
+    strict: bool,
+) -> Result<()> {
+    let now = SystemTime::now();
+    let local_expired = expiry::expired(local, now)?;
+    let global_expired = match global {
+        Some(store) => expiry::expired(store, now)?,
+        None => BTreeMap::new(),
+    };
 
+    let mut expired = BTreeMap::new();
+    for secret in layers.iter().flatten().flat_map(|line| line.refs()) {
+        let (found, name, command) = match secret.scope {
+            Scope::Local => (
+                local_expired.get(secret.name),
+                secret.name.to_string(),
+                "enject",
+            ),
+            Scope::Global => (
+                global_expired.get(secret.name),
+                format!("global/{}", secret.name),
+                "enject global",
+            ),
+        };
+        if let Some(expires_at) = found {
+            expired.insert(name, (expires_at, command, secret.name));
+        }
+    }
+    if expired.is_empty() {
+        return Ok(());
+    }
+    if strict {
+        let names: Vec<_> = expired.keys().map(String::as_str).collect();
+        bail!(
+            "Referenced secret(s) past their expiry date: {}. Rotate them, or run without \
+             --strict-expiry.",
+            names.join(", ")
+        );
+    }
+    for (name, (expires_at, command, key)) in &expired {
+        ui::warn(format_args!(
+            "secret '{}' expired on {}. Rotate it with: {} set {} --force --expires <when>",
+            name,
+            expiry::date(expires_at),
+            command,
+            key
+        ));
+    }
     Ok(())
 }
 
-fn build_secrets_map(store: &PasswordStore) -> Result<HashMap<String, String>> {
+/// `--env-file -` reads the template from stdin.
+const STDIN_PATH: &str = "-";
+
+/// The default template layers: `.env` in the project root, then `.env.<profile>` on top when a
+/// profile is active. Both files must exist.
+pub fn template_paths(root: &Path, profile: Option<&str>) -> Result<Vec<PathBuf>> {
+    let base = root.join(".env");
+    if !base.exists() {
+        bail!(
+            ".env file not found in {}. \
+             Create one with en:// references and try again.",
+            root.display()
+        );
+    }
+    let mut paths = vec![base];
+    if let Some(profile) = profile {
+        let path = config::profile_template(root, profile)?;
+        if !path.exists() {
+            bail!(
+                "Profile '{}' not found: {} does not exist.",
+                profile,
+                path.display()
+            );
+        }
+        paths.push(path);
+    }
+    Ok(paths)
+}
+
+/// Parse one template file (relative to the current directory, `-` for stdin).
+fn load_template(path: &Path) -> Result<Vec<ParsedLine>> {
+    if path == Path::new(STDIN_PATH) {
+        let mut content = String::new();
+        std::io::stdin()
+            .read_to_string(&mut content)
+            .context("Failed to read template from stdin")?;
+        let lines = env_template::parse_numbered(&content)
+            .context("Failed to parse template from stdin")?;
+        env_template::log_parsed(&"stdin", &lines);
+        return Ok(lines);
+    }
+    if !path.exists() {
+        bail!("Env file not found: {}", path.display());
+    }
+    parse_template(path).with_context(|| format!("Failed to parse {}", path.display()))
+}
+
+/// Fail if a template assigns the same key twice, unless `allow` says the last
+/// assignment should silently win. Repeats of an identical assignment only warn.
+fn reject_duplicates(path: &Path, lines: &[ParsedLine], allow: bool) -> Result<()> {
+    let name = if path == Path::new(STDIN_PATH) {
+        "stdin".to_string()
+    } else {
+        path.display().to_string()
+    };
+
+    let mut conflicts = Vec::new();
+    for dup in env_template::duplicate_keys(lines) {
+        let message = format!(
+            "{}: '{}' is assigned on line {} and again on line {}",
+            name, dup.key, dup.first_line, dup.line
+        );
+        if dup.identical {
+            if !allow {
+                ui::warn(format_args!("{} (same value).", message));
+            }
+        } else if !allow {
+            conflicts.push(message);
+        }
+    }
+
+    if !conflicts.is_empty() {
+        bail!(
+            "Duplicate keys in template:\n  {}\n\
+             Remove the extra assignments, or pass --allow-duplicates to let the last one win.",
+            conflicts.join("\n  ")
+        );
+    }
+    Ok(())
+}
+
+/// Every secret as an environment variable for `--all`: `prefix` plus the key
+/// uppercased, with anything but letters, digits and `_` replaced by `_`. Renamed
+/// keys are reported; two keys that end up as the same variable are an error.
+fn store_env(secrets: &HashMap<String, String>, prefix: &str) -> Result<HashMap<String, String>> {
+    let mut keys: Vec<&String> = secrets.keys().collect();
+    keys.sort();
+    let mut env = HashMap::new();
+    let mut sources: HashMap<String, &str> = HashMap::new();
+    for key in keys {
+        let (var, sanitized) = env_var_name(prefix, key);
+        if sanitized {
+            inform_stderr(format_args!(
+                "Note: secret '{}' is injected as {}.",
+                key, var
+            ));
+        }
+        if let Some(other) = sources.insert(var.clone(), key) {
+            bail!(
+                "Secrets '{}' and '{}' would both be injected as {}. Rename one of them.",
+                other,
+                key,
+                var
+            );
+        }
+        env.insert(var, secrets[key].clone());
+    }
+    Ok(env)
+}
+
+/// `prefix` + `key` as a valid environment variable name, and whether anything
+/// had to be replaced beyond uppercasing.
+fn env_var_name(prefix: &str, key: &str) -> (String, bool) {
+    let mut sanitized = false;
+    let mut name: String = format!("{}{}", prefix, key.to_uppercase())
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' {
+                c
+            } else {
+                sanitized = true;
+                '_'
+            }
+        })
+        .collect();
+    if name.starts_with(|c: char| c.is_ascii_digit()) {
+        name.insert(0, '_');
+        sanitized = true;
+    }
+    (name, sanitized)
+}
+
+/// The values `--mask` hides, each under the variable it was injected as: the
+/// value of every variable whose final assignment references a secret, and each
+/// secret it references.
+fn mask_values<'a>(
+    layers: &'a [Vec<EnvLine>],
+    resolved: &'a HashMap<String, String>,
+    local_secrets: &'a HashMap<String, String>,
+    global_secrets: &'a HashMap<String, String>,
+) -> Vec<(&'a str, &'a str)> {
+    let mut last_assignment = HashMap::new();
+    for line in layers.iter().flatten() {
+        if let Some(key) = line.key() {
+            last_assignment.insert(key, line);
+        }
+    }
+    let mut values = Vec::new();
+    for (key, line) in last_assignment {
+        let refs = line.refs();
+        if refs.is_empty() {
+            continue;
+        }
+        if let Some(value) = resolved.get(key) {
+            values.push((key, value.as_str()));
+        }
+        for secret in refs {
+            let secrets = match secret.scope {
+                Scope::Local => local_secrets,
+                Scope::Global => global_secrets,
+            };
+            if let Some(value) = secrets.get(secret.name) {
+                values.push((key, value.as_str()));
+            }
+        }
+    }
+    // Stable key names when two variables share a value
+    values.sort();
+    values
+}
+
+/// The resolved variables `--only` and `--except` let through: those matching some
+/// `only` pattern (all of them when there are none), minus those matching an
+/// `except` pattern. An `only` pattern that matches no variable is an error.
+fn select_keys(
+    mut env: HashMap<String, String>,
+    only: &[String],
+    except: &[String],
+) -> Result<HashMap<String, String>> {
+    let unmatched: Vec<&str> = only
+        .iter()
+        .filter(|pattern| !env.keys().any(|key| glob_match(pattern, key)))
+        .map(String::as_str)
+        .collect();
+    if !unmatched.is_empty() {
+        bail!(
+            "--only names variable(s) the template doesn't set: {}",
+            unmatched.join(", ")
+        );
+    }
+    env.retain(|key, _| {
+        (only.is_empty() || only.iter().any(|pattern| glob_match(pattern, key)))
+            && !except.iter().any(|pattern| glob_match(pattern, key))
+    });
+    Ok(env)
+}
+
+fn build_secrets_map(store: &dyn Store) -> Result<HashMap<String, String>> {
     let keys = store.list()?;
     let mut map = HashMap::new();
     for key in keys {
@@ -59,3 +852,170 @@ fn build_secrets_map(store: &PasswordStore) -> Result<HashMap<String, String>> {
     }
     Ok(map)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::memory::MemoryStore;
+    use secrecy::SecretString;
+
+    fn env(keys: &[&str]) -> HashMap<String, String> {
+        keys.iter()
+            .map(|k| (k.to_string(), format!("value of {}", k)))
+            .collect()
+    }
+
+    fn sorted_keys(env: &HashMap<String, String>) -> Vec<&str> {
+        let mut keys: Vec<&str> = env.keys().map(String::as_str).collect();
+        keys.sort();
+        keys
+    }
+
+    #[test]
+    fn test_mask_values_covers_secret_bearing_variables_only() {
+        let (lines, _) = env_template::parse_all(
+            "PORT=3000\nDB=en://db\nURL=postgres://app:en://db@host\nTOKEN=en://global/token\nHOST=plain-host\n",
+        );
+        let layers = vec![lines.into_iter().map(|p| p.line).collect::<Vec<_>>()];
+        let local = env(&["db"]);
+        let global = env(&["token"]);
+        let mut resolved = env(&["PORT", "HOST"]);
+        resolved.insert("DB".into(), local["db"].clone());
+        resolved.insert("URL".into(), format!("postgres://app:{}@host", local["db"]));
+        resolved.insert("TOKEN".into(), global["token"].clone());
+
+        let values = mask_values(&layers, &resolved, &local, &global);
+        let keys: Vec<&str> = values.iter().map(|(key, _)| *key).collect();
+        assert_eq!(keys, vec!["DB", "DB", "TOKEN", "TOKEN", "URL", "URL"]);
+        assert!(values
+            .iter()
+            .all(|(_, v)| *v != "value of PORT" && *v != "value of HOST"));
+    }
+
+    #[test]
+    fn test_command_line_expands_scripts() {
+        use clap::Parser;
+
+        let scripts: BTreeMap<String, Script> = [(
+            "test".to_string(),
+            Script::Args(vec!["cargo".into(), "test".into()]),
+        )]
+        .into_iter()
+        .collect();
+        let command = |argv: &[&str]| {
+            let cli =
+                crate::cli::Cli::try_parse_from(["enject", "run"].iter().chain(argv)).unwrap();
+            let crate::cli::Command::Run(args) = cli.command else {
+                panic!("not a run command");
+            };
+            command_line(&args, &scripts).unwrap()
+        };
+        assert_eq!(command(&["test"]), vec!["cargo", "test"]);
+        assert_eq!(
+            command(&["test", "--", "--lib"]),
+            vec!["cargo", "test", "--lib"]
+        );
+        assert_eq!(command(&["test", "-q"]), vec!["cargo", "test", "-q"]);
+        // Not a script: run as given, flags and all
+        assert_eq!(command(&["echo", "-n", "hi"]), vec!["echo", "-n", "hi"]);
+        // After --, a script name is just a command
+        assert_eq!(command(&["--", "test", "-f", "x"]), vec!["test", "-f", "x"]);
+    }
+
+    #[test]
+    fn test_script_command() {
+        let extra = vec!["--lib".to_string()];
+        let args = Script::Args(vec!["cargo".into(), "test".into()]);
+        assert_eq!(
+            script_command(&args, "test", &extra).unwrap(),
+            vec!["cargo", "test", "--lib"]
+        );
+        assert!(script_command(&Script::Args(Vec::new()), "empty", &[]).is_err());
+        let shell = Script::Shell("npm run dev | tee dev.log".into());
+        assert_eq!(script_command(&shell, "dev", &[]).unwrap().len(), 3);
+        assert!(script_command(&shell, "dev", &extra).is_err());
+    }
+
+    #[test]
+    fn test_env_var_name_uppercases_and_sanitizes() {
+        assert_eq!(env_var_name("", "db_url"), ("DB_URL".to_string(), false));
+        assert_eq!(
+            env_var_name("SECRET_", "stripe.live-key"),
+            ("SECRET_STRIPE_LIVE_KEY".to_string(), true)
+        );
+        assert_eq!(
+            env_var_name("", "1password"),
+            ("_1PASSWORD".to_string(), true)
+        );
+    }
+
+    #[test]
+    fn test_store_env_rejects_colliding_names() {
+        let injected = store_env(&env(&["api-key", "db"]), "APP_").unwrap();
+        assert_eq!(sorted_keys(&injected), vec!["APP_API_KEY", "APP_DB"]);
+        assert_eq!(injected["APP_API_KEY"], "value of api-key");
+
+        let err = store_env(&env(&["api-key", "api_key"]), "").unwrap_err();
+        assert!(err.to_string().contains("API_KEY"), "{}", err);
+    }
+
+    #[test]
+    fn test_select_keys_only_and_except() {
+        // PORT is a plain template line; it is filtered like any secret
+        let all = env(&["DATABASE_URL", "REDIS_URL", "API_KEY", "PORT"]);
+        let only = vec!["*_URL".to_string(), "PORT".to_string()];
+
+        let selected = select_keys(all.clone(), &only, &[]).unwrap();
+        assert_eq!(
+            sorted_keys(&selected),
+            vec!["DATABASE_URL", "PORT", "REDIS_URL"]
+        );
+        assert_eq!(selected["PORT"], "value of PORT");
+
+        let selected = select_keys(all.clone(), &only, &["REDIS_*".to_string()]).unwrap();
+        assert_eq!(sorted_keys(&selected), vec!["DATABASE_URL", "PORT"]);
+
+        let selected = select_keys(all.clone(), &[], &["PORT".to_string()]).unwrap();
+        assert_eq!(
+            sorted_keys(&selected),
+            vec!["API_KEY", "DATABASE_URL", "REDIS_URL"]
+        );
+    }
+
+    #[test]
+    fn test_select_keys_rejects_an_unmatched_only() {
+        let all = env(&["DATABASE_URL", "PORT"]);
+        let only = vec![
+            "PORT".to_string(),
+            "MISSING".to_string(),
+            "S3_*".to_string(),
+        ];
+        let err = select_keys(all, &only, &[]).unwrap_err().to_string();
+        assert!(err.contains("MISSING, S3_*"), "{}", err);
+    }
+
+    #[test]
+    fn test_build_secrets_map_holds_every_live_secret() {
+        let mut store = MemoryStore::new();
+        store
+            .set("db", SecretString::new("postgres://x".to_string()))
+            .unwrap();
+        store
+            .set("api", SecretString::new("k".to_string()))
+            .unwrap();
+        store
+            .set("gone", SecretString::new("v".to_string()))
+            .unwrap();
+        store.delete_keeping_history("gone").unwrap();
+
+        let map = build_secrets_map(&store).unwrap();
+        assert_eq!(map.len(), 2);
+        assert_eq!(map["db"], "postgres://x");
+        assert_eq!(map["api"], "k");
+    }
+
+    #[test]
+    fn test_build_secrets_map_of_empty_store() {
+        assert!(build_secrets_map(&MemoryStore::new()).unwrap().is_empty());
+    }
+}
@@ -1,34 +1,407 @@
-use anyhow::{Context, Result};
-use secrecy::SecretString;
+use std::io::{BufRead, Read, Write};
+use std::path::Path;
+use std::time::SystemTime;
 
-use crate::config;
-use crate::store::password::PasswordStore;
-use crate::store::Store;
+use anyhow::{anyhow, bail, Context, Result};
+use rand::rngs::OsRng;
+use rand::Rng;
+use secrecy::{ExposeSecret, SecretString};
+use serde::Serialize;
+use zeroize::Zeroizing;
 
-pub fn run(key: &str) -> Result<()> {
-    let root = config::project_root()?;
-    let cfg = config::read(&root)?;
+use crate::cli::{Charset, OutputFormat, SetArgs};
+use crate::commands::{confirm, inform, is_interactive, note, StoreTarget};
+use crate::expiry;
+use crate::store::Annotation;
+use crate::ui;
 
-    let password = rpassword::prompt_password("Enject store password: ")
-        .context("Failed to read Enject store password")?;
-    let password = SecretString::new(password);
+/// `enject set --format json`: which keys were saved, never their values.
+#[derive(Debug, Default, Serialize)]
+struct Report {
+    created: Vec<String>,
+    updated: Vec<String>,
+    unchanged: Vec<String>,
+}
+
+impl Report {
+    fn print_json(&self) -> Result<()> {
+        println!("{}", serde_json::to_string_pretty(self)?);
+        Ok(())
+    }
+}
+
+/// Store one secret, or several in a batch. A single value is read from
+/// `--from-file`, or from stdin when `--stdin` is given or stdin is not a terminal,
+/// and otherwise prompted for without echo.
+pub fn run(mut target: StoreTarget, args: &SetArgs, format: OutputFormat) -> Result<()> {
+    // Checked before anything is read or unlocked
+    let annotation = annotation(args)?;
+    let key = match args.keys.as_slice() {
+        [key] if !args.interactive => key.as_str(),
+        _ if args.stdin || args.from_file.is_some() || args.generate => {
+            bail!("--stdin, --from-file and --generate take a single key.")
+        }
+        keys => return set_many(&target, keys, args, &annotation, format),
+    };
+
+    let secret = if let Some(path) = &args.from_file {
+        let secret = read_value_file(path)?;
+        note(
+            format,
+            format_args!(
+                "Read {} byte(s) from {}.",
+                secret.expose_secret().len(),
+                path.display()
+            ),
+        );
+        secret
+    } else if args.generate {
+        generate(usize::from(args.length), args.charset)
+    } else if args.stdin || !is_interactive() {
+        // stdin carries the value, so the password must come from elsewhere; the
+        // prompt reads the terminal directly
+        target.stdin_password = false;
+        read_value(std::io::stdin().lock()).context("Failed to read secret value from stdin")?
+    } else {
+        prompt_value(key)?
+    };
+
+    let mut store = target.unlock()?;
+    let mut report = Report::default();
+    let exists = store.get(key)?.is_some();
+    if exists && !may_overwrite(key, args, format)? {
+        report.unchanged.push(key.to_string());
+        return match format {
+            OutputFormat::Json => report.print_json(),
+            OutputFormat::Text => Ok(()),
+        };
+    }
+    store.set(key, secret)?;
+    store.annotate(key, annotation)?;
+    if args.force_save {
+        store.overwrite_changes();
+    }
+    store.persist().context("Failed to save store")?;
+
+    if format == OutputFormat::Json {
+        if exists {
+            report.updated.push(key.to_string());
+        } else {
+            report.created.push(key.to_string());
+        }
+        return report.print_json();
+    }
+    if args.generate {
+        ui::success(format_args!(
+            "Secret '{}' saved (generated, {} characters).",
+            key, args.length
+        ));
+    } else {
+        ui::success(format_args!("Secret '{}' saved.", key));
+    }
+    Ok(())
+}
+
+/// A random value of `length` characters drawn uniformly from `charset`, using the
+/// operating system's RNG.
+fn generate(length: usize, charset: Charset) -> SecretString {
+    const ALNUM: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+    let alphabet: Vec<u8> = match charset {
+        Charset::Hex => b"0123456789abcdef".to_vec(),
+        Charset::Base64 => format!("{}+/", ALNUM).into_bytes(),
+        Charset::Alnum => ALNUM.as_bytes().to_vec(),
+        Charset::Ascii => (b'!'..=b'~').collect(),
+    };
+    let mut rng = OsRng;
+    let value: String = (0..length)
+        .map(|_| char::from(alphabet[rng.gen_range(0..alphabet.len())]))
+        .collect();
+    SecretString::new(value)
+}
+
+/// The metadata changes asked for by `--description`, `--tag` and `--expires`.
+/// Without them a secret keeps whatever it had.
+fn annotation(args: &SetArgs) -> Result<Annotation> {
+    let tags = match args.tags.as_slice() {
+        [] => None,
+        tags => Some(normalize_tags(tags)?),
+    };
+    let expires_at = args
+        .expires
+        .as_deref()
+        .map(|input| expiry::parse(input, SystemTime::now()))
+        .transpose()?;
+    Ok(Annotation {
+        description: args.description.clone(),
+        tags,
+        expires_at,
+    })
+}
+
+/// Trimmed, sorted and de-duplicated tags. A tag may not be empty or contain whitespace.
+fn normalize_tags(tags: &[String]) -> Result<Vec<String>> {
+    let mut normalized = Vec::new();
+    for tag in tags {
+        let tag = tag.trim();
+        if tag.is_empty() || tag.contains(char::is_whitespace) {
+            bail!(
+                "Invalid tag {:?}: tags must be non-empty and without spaces.",
+                tag
+            );
+        }
+        normalized.push(tag.to_string());
+    }
+    normalized.sort();
+    normalized.dedup();
+    Ok(normalized)
+}
+
+/// Whether `key`, which already exists, may be replaced: always with `--force`, never
+/// with `--if-absent`, and otherwise only if the user confirms on a terminal.
+fn may_overwrite(key: &str, args: &SetArgs, format: OutputFormat) -> Result<bool> {
+    if args.force {
+        return Ok(true);
+    }
+    if args.if_absent {
+        note(
+            format,
+            format_args!("Secret '{}' already exists; left unchanged.", key),
+        );
+        return Ok(false);
+    }
+    if !is_interactive() {
+        bail!(
+            "Secret '{}' already exists. Re-run with --force to overwrite it.",
+            key
+        );
+    }
+    if confirm(&format!("Secret '{}' already exists — overwrite?", key))? {
+        Ok(true)
+    } else {
+        note(format, format_args!("Secret '{}' left unchanged.", key));
+        Ok(false)
+    }
+}
 
-    let store_path = config::store_path(&root);
-    let mut store = PasswordStore::new(store_path, cfg.kdf_params(), cfg.salt_bytes()?);
-    store
-        .unlock(&password)
-        .context("Failed to unlock store — wrong password?")?;
+/// Set `keys`, or with `--interactive` keys named at a prompt until an empty name,
+/// with a single unlock and a single save. Any failure part way leaves the store
+/// as it was.
+fn set_many(
+    target: &StoreTarget,
+    keys: &[String],
+    args: &SetArgs,
+    annotation: &Annotation,
+    format: OutputFormat,
+) -> Result<()> {
+    if !is_interactive() {
+        bail!("Setting several secrets prompts for each value, so stdin must be a terminal.");
+    }
+    let mut store = target.unlock()?;
+
+    let mut listed = keys.iter();
+    let mut report = Report::default();
+    loop {
+        let key = if args.interactive {
+            let key = prompt_key()?;
+            if key.is_empty() {
+                break;
+            }
+            key
+        } else {
+            match listed.next() {
+                Some(key) => key.clone(),
+                None => break,
+            }
+        };
+        if report.created.contains(&key) || report.updated.contains(&key) {
+            bail!("'{}' is set twice in this batch. Nothing was saved.", key);
+        }
+
+        let exists = store.get(&key)?.is_some();
+        if exists && !may_overwrite(&key, args, format)? {
+            report.unchanged.push(key);
+            continue;
+        }
+        let value = prompt_value(&key).context("Nothing was saved")?;
+        if exists {
+            report.updated.push(key.clone());
+        } else {
+            report.created.push(key.clone());
+        }
+        store.set(&key, value)?;
+        store.annotate(&key, annotation.clone())?;
+    }
 
+    let (created, updated) = (&report.created, &report.updated);
+    if created.is_empty() && updated.is_empty() {
+        return match format {
+            OutputFormat::Json => report.print_json(),
+            OutputFormat::Text => {
+                inform(format_args!("No secrets set."));
+                Ok(())
+            }
+        };
+    }
+    if args.force_save {
+        store.overwrite_changes();
+    }
+    store.persist().context("Failed to save store")?;
+
+    if format == OutputFormat::Json {
+        return report.print_json();
+    }
+    ui::success(format_args!(
+        "Saved {} secret(s).",
+        created.len() + updated.len()
+    ));
+    if !created.is_empty() {
+        inform(format_args!("  Created: {}", created.join(", ")));
+    }
+    if !updated.is_empty() {
+        inform(format_args!("  Updated: {}", updated.join(", ")));
+    }
+    Ok(())
+}
+
+/// Prompt on the terminal for a secret's value without echoing it.
+fn prompt_value(key: &str) -> Result<SecretString> {
     let secret = rpassword::prompt_password(format!("Value for '{}': ", key))
         .context("Failed to read secret value")?;
     if secret.is_empty() {
-        anyhow::bail!("Secret value must not be empty.");
+        bail!("Secret value must not be empty.");
     }
-    let secret = SecretString::new(secret);
+    Ok(SecretString::new(secret))
+}
 
-    store.set(key, secret)?;
-    store.save(&password).context("Failed to save store")?;
+/// Ask for the next key name in `--interactive` mode. Empty means done.
+fn prompt_key() -> Result<String> {
+    eprint!("Key name (empty to finish): ");
+    std::io::stderr().flush()?;
+    let mut key = String::new();
+    std::io::stdin().lock().read_line(&mut key)?;
+    Ok(key.trim().to_string())
+}
 
-    println!("Secret '{}' saved.", key);
-    Ok(())
+/// Read a file's entire contents, byte for byte, as a secret value.
+fn read_value_file(path: &Path) -> Result<SecretString> {
+    let bytes = Zeroizing::new(
+        std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?,
+    );
+    let value = std::str::from_utf8(&bytes).map_err(|e| {
+        anyhow!(
+            "{} is not valid UTF-8 (invalid byte at offset {}). Only text files can be stored; \
+             base64-encode binary files first.",
+            path.display(),
+            e.valid_up_to()
+        )
+    })?;
+    if value.is_empty() {
+        bail!(
+            "{} is empty. Secret value must not be empty.",
+            path.display()
+        );
+    }
+    Ok(SecretString::new(value.to_string()))
+}
+
+/// Read a whole value from `input`, dropping exactly one trailing newline (as
+/// `echo` or `openssl rand -hex` add) and keeping every other byte.
+fn read_value(mut input: impl Read) -> Result<SecretString> {
+    let mut value = Zeroizing::new(String::new());
+    input
+        .read_to_string(&mut value)
+        .context("Secret value is not valid UTF-8")?;
+    let trimmed = value
+        .strip_suffix("\r\n")
+        .or_else(|| value.strip_suffix('\n'))
+        .unwrap_or(&value);
+    if trimmed.is_empty() {
+        bail!("Secret value must not be empty.");
+    }
+    Ok(SecretString::new(trimmed.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_tags() {
+        let tags = ["prod", " payments ", "prod"].map(String::from);
+        assert_eq!(normalize_tags(&tags).unwrap(), vec!["payments", "prod"]);
+        assert!(normalize_tags(&["two words".to_string()]).is_err());
+        assert!(normalize_tags(&[" ".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_read_value_trims_one_newline() {
+        let value = read_value("line one\nline two\n\n".as_bytes()).unwrap();
+        assert_eq!(value.expose_secret(), "line one\nline two\n");
+        let value = read_value("abc\r\n".as_bytes()).unwrap();
+        assert_eq!(value.expose_secret(), "abc");
+        let value = read_value(" padded ".as_bytes()).unwrap();
+        assert_eq!(value.expose_secret(), " padded ");
+    }
+
+    #[test]
+    fn test_read_value_file_keeps_contents_exactly() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("key.pem");
+        let pem = "-----BEGIN KEY-----\r\nabc\n-----END KEY-----\n";
+        std::fs::write(&path, pem).unwrap();
+        assert_eq!(read_value_file(&path).unwrap().expose_secret(), pem);
+
+        std::fs::write(&path, "").unwrap();
+        assert!(read_value_file(&path)
+            .unwrap_err()
+            .to_string()
+            .contains("is empty"));
+
+        std::fs::write(&path, [b'o', b'k', 0xff]).unwrap();
+        assert!(read_value_file(&path)
+            .unwrap_err()
+            .to_string()
+            .contains("not valid UTF-8 (invalid byte at offset 2)"));
+    }
+
+    #[test]
+    fn test_generate_respects_length_and_charset() {
+        let allowed = |charset, c: char| match charset {
+            Charset::Hex => matches!(c, '0'..='9' | 'a'..='f'),
+            Charset::Base64 => c.is_ascii_alphanumeric() || c == '+' || c == '/',
+            Charset::Alnum => c.is_ascii_alphanumeric(),
+            Charset::Ascii => c.is_ascii_graphic(),
+        };
+        for charset in [
+            Charset::Hex,
+            Charset::Base64,
+            Charset::Alnum,
+            Charset::Ascii,
+        ] {
+            for length in [1, 32, 200] {
+                let value = generate(length, charset);
+                let value = value.expose_secret();
+                assert_eq!(value.len(), length);
+                assert!(
+                    value.chars().all(|c| allowed(charset, c)),
+                    "{:?}: {}",
+                    charset,
+                    value
+                );
+            }
+        }
+        // Two draws colliding would mean the RNG isn't being used
+        assert_ne!(
+            generate(32, Charset::Alnum).expose_secret(),
+            generate(32, Charset::Alnum).expose_secret()
+        );
+    }
+
+    #[test]
+    fn test_read_value_rejects_empty_input() {
+        for input in ["", "\n"] {
+            let err = read_value(input.as_bytes()).unwrap_err();
+            assert!(err.to_string().contains("must not be empty"));
+        }
+    }
 }
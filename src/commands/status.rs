@@ -0,0 +1,259 @@
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::cli::{GlobalOpts, OutputFormat};
+use crate::commands::{project_root, StoreTarget};
+use crate::config::{self, Config};
+use crate::env_template::{self, EnvLine, ParsedLine, Scope};
+use crate::error::EnjectError;
+
+/// Counts of each kind of line in a `.env` template.
+#[derive(Debug, Default, PartialEq, Serialize)]
+struct TemplateCounts {
+    local_refs: usize,
+    global_refs: usize,
+    legacy_refs: usize,
+    plain: usize,
+}
+
+impl TemplateCounts {
+    fn from_lines(lines: &[ParsedLine]) -> Self {
+        let mut counts = Self::default();
+        for parsed in lines {
+            match &parsed.line {
+                EnvLine::Plain { .. } => counts.plain += 1,
+                EnvLine::Passthrough(_) => {}
+                line if parsed.legacy => counts.legacy_refs += line.refs().len(),
+                line => {
+                    for secret in line.refs() {
+                        match secret.scope {
+                            Scope::Local => counts.local_refs += 1,
+                            Scope::Global => counts.global_refs += 1,
+                        }
+                    }
+                }
+            }
+        }
+        counts
+    }
+}
+
+/// Everything `enject status` reports; `--format json` prints it as is.
+#[derive(Debug, Serialize)]
+struct Status {
+    project: String,
+    store: StoreStatus,
+    env: EnvStatus,
+    gitignore_covers_store: bool,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+enum StoreStatus {
+    Initialized {
+        dir: String,
+        legacy_dir: bool,
+        backend: String,
+        config_version: u32,
+        /// The keychain entry's name (`keyring` backend).
+        #[serde(skip_serializing_if = "Option::is_none")]
+        project_id: Option<String>,
+        /// How many public keys the store is encrypted to (`age` backend).
+        #[serde(skip_serializing_if = "Option::is_none")]
+        recipients: Option<usize>,
+        /// Cipher and KDF settings (password backend).
+        #[serde(skip_serializing_if = "Option::is_none")]
+        encryption: Option<Encryption>,
+        /// Only counted with `--unlock`.
+        secrets: Option<usize>,
+    },
+    NotInitialized,
+    Unreadable {
+        error: String,
+    },
+}
+
+#[derive(Debug, Serialize)]
+struct Encryption {
+    cipher: String,
+    kdf: String,
+    m_cost: u32,
+    t_cost: u32,
+    p_cost: u32,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+enum EnvStatus {
+    NotFound,
+    Parsed(TemplateCounts),
+    Unparseable { error: String },
+}
+
+pub fn run(opts: &GlobalOpts, unlock: bool, format: OutputFormat) -> Result<()> {
+    let root = project_root(opts)?;
+
+    let store_dir = config::store_dir_override(opts.store_dir.as_deref());
+    let loaded = match &store_dir {
+        Some(dir) => config::read_store_dir(dir),
+        None => config::read(&root),
+    };
+    let store = match loaded {
+        Ok(cfg) => {
+            let legacy_dir = store_dir.is_none() && config::uses_legacy_dir(&root);
+            let dir = store_dir.unwrap_or_else(|| config::enject_dir(&root));
+            let secrets = if unlock {
+                let store = StoreTarget::local_from(opts, &root, cfg.clone()).unlock_read_only()?;
+                Some(store.list()?.len())
+            } else {
+                None
+            };
+            initialized(dir.display().to_string(), legacy_dir, cfg, secrets)
+        }
+        Err(EnjectError::StoreNotInitialized | EnjectError::StoreDirNotInitialized(_)) => {
+            StoreStatus::NotInitialized
+        }
+        Err(e) => StoreStatus::Unreadable {
+            error: e.to_string(),
+        },
+    };
+
+    let env_path = root.join(".env");
+    let env = if !env_path.exists() {
+        EnvStatus::NotFound
+    } else {
+        match env_template::parse_file(&env_path) {
+            Ok(lines) => EnvStatus::Parsed(TemplateCounts::from_lines(&lines)),
+            Err(e) => EnvStatus::Unparseable {
+                error: e.to_string(),
+            },
+        }
+    };
+
+    let gitignore = std::fs::read_to_string(root.join(".gitignore")).unwrap_or_default();
+    let status = Status {
+        project: root.display().to_string(),
+        store,
+        env,
+        gitignore_covers_store: config::gitignore_covers(&gitignore, ".enject"),
+    };
+    match format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&status)?),
+        OutputFormat::Text => print_text(&status),
+    }
+    Ok(())
+}
+
+fn initialized(dir: String, legacy_dir: bool, cfg: Config, secrets: Option<usize>) -> StoreStatus {
+    let (project_id, recipients, encryption) = match cfg.backend.as_str() {
+        "keyring" => (cfg.project_id, None, None),
+        "age" => (None, Some(cfg.recipients.len()), None),
+        _ => (
+            None,
+            None,
+            Some(Encryption {
+                cipher: cfg.cipher,
+                kdf: cfg.kdf,
+                m_cost: cfg.m_cost,
+                t_cost: cfg.t_cost,
+                p_cost: cfg.p_cost,
+            }),
+        ),
+    };
+    StoreStatus::Initialized {
+        dir,
+        legacy_dir,
+        backend: cfg.backend,
+        config_version: cfg.version,
+        project_id,
+        recipients,
+        encryption,
+        secrets,
+    }
+}
+
+fn print_text(status: &Status) {
+    println!("Project:     {}", status.project);
+    match &status.store {
+        StoreStatus::Initialized {
+            dir,
+            legacy_dir,
+            backend,
+            config_version,
+            project_id,
+            recipients,
+            encryption,
+            secrets,
+        } => {
+            if *legacy_dir {
+                println!("Store dir:   {} (legacy — rename it to .enject/)", dir);
+            } else {
+                println!("Store dir:   {}", dir);
+            }
+            if let Some(e) = encryption {
+                println!(
+                    "Backend:     {} ({}, {}, m_cost={}, t_cost={}, p_cost={}), config version {}",
+                    backend, e.cipher, e.kdf, e.m_cost, e.t_cost, e.p_cost, config_version
+                );
+            } else if let Some(recipients) = recipients {
+                println!(
+                    "Backend:     age ({} recipient(s)), config version {}",
+                    recipients, config_version
+                );
+            } else {
+                println!(
+                    "Backend:     keyring (OS keychain, project id {}), config version {}",
+                    project_id.as_deref().unwrap_or("missing"),
+                    config_version
+                );
+            }
+            match secrets {
+                Some(count) => println!("Secrets:     {}", count),
+                None => println!("Secrets:     (locked — pass --unlock to count)"),
+            }
+        }
+        StoreStatus::NotInitialized => {
+            println!("Store:       not initialized — run `enject init`");
+        }
+        StoreStatus::Unreadable { error } => println!("Store:       config unreadable: {}", error),
+    }
+
+    match &status.env {
+        EnvStatus::NotFound => println!(".env:        not found"),
+        EnvStatus::Parsed(c) => println!(
+            ".env:        {} en:// ref(s) ({} global), {} legacy ev:// ref(s), {} plain value(s)",
+            c.local_refs + c.global_refs,
+            c.global_refs,
+            c.legacy_refs,
+            c.plain
+        ),
+        EnvStatus::Unparseable { error } => println!(".env:        does not parse: {}", error),
+    }
+
+    if status.gitignore_covers_store {
+        println!(".gitignore:  covers .enject/");
+    } else {
+        println!(".gitignore:  does NOT cover .enject/ — add it so the store is never committed");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_template_counts() {
+        let (lines, _) = env_template::parse_all(
+            "# comment\nA=en://a\nB=en://global/b\nC=ev://c\nD=ev://global/d\nPORT=3000\n\n",
+        );
+        assert_eq!(
+            TemplateCounts::from_lines(&lines),
+            TemplateCounts {
+                local_refs: 1,
+                global_refs: 1,
+                legacy_refs: 2,
+                plain: 1,
+            }
+        );
+    }
+}
@@ -0,0 +1,64 @@
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+
+use crate::cli::GlobalOpts;
+use crate::commands::project_root;
+use crate::env_template::{self, EnvLine};
+use crate::ui;
+
+/// `enject template --example` — write a `.env.example` from `.env`, with every
+/// reference emptied. Plain values are copied unless `blank_all`; the ones that
+/// look like secrets are warned about.
+pub fn run_example(
+    opts: &GlobalOpts,
+    output: Option<&Path>,
+    show_refs: bool,
+    blank_all: bool,
+    force: bool,
+) -> Result<()> {
+    let root = project_root(opts)?;
+    let env_path = root.join(".env");
+    if !env_path.exists() {
+        bail!(".env file not found in {}.", root.display());
+    }
+    let output = match output {
+        Some(path) => path.to_path_buf(),
+        None => root.join(".env.example"),
+    };
+    if output.exists() && !force {
+        bail!(
+            "{} already exists. Pass --force to overwrite it.",
+            output.display()
+        );
+    }
+
+    let content = std::fs::read_to_string(&env_path)
+        .with_context(|| format!("Failed to read {}", env_path.display()))?;
+    let parsed = env_template::parse_numbered(&content)
+        .with_context(|| format!("Failed to parse {}", env_path.display()))?;
+    let lines: Vec<EnvLine> = parsed.into_iter().map(|p| p.line).collect();
+
+    if !blank_all {
+        for line in &lines {
+            if let EnvLine::Plain { key, value, .. } = line {
+                if env_template::looks_like_secret(value) {
+                    ui::warn(format_args!(
+                        "{} has a plain value that looks like a secret; it is copied as-is. Use --blank-all to leave every value empty.",
+                        key
+                    ));
+                }
+            }
+        }
+    }
+
+    let eol = env_template::line_ending(&content);
+    let mut example = env_template::example(&lines, show_refs, blank_all).join(eol);
+    if content.ends_with('\n') {
+        example.push_str(eol);
+    }
+    env_template::write_atomic(&output, &example)
+        .with_context(|| format!("Failed to write {}", output.display()))?;
+    ui::success(format_args!("Wrote {}.", output.display()));
+    Ok(())
+}
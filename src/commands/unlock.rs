@@ -0,0 +1,51 @@
+use anyhow::{anyhow, Context, Result};
+
+use crate::agent;
+use crate::commands::{forget_key, inform, remember_key, StoreTarget};
+use crate::ui;
+
+/// Check the store password, and with `remember` cache the key derived from it in
+/// the OS keychain, so later commands open the store without asking.
+pub fn run(target: &StoreTarget, remember: bool) -> Result<()> {
+    let (store, _password) = target.unlock_with_password(true)?;
+    target.share_with_agent(&store);
+    if !remember {
+        ui::success(format_args!("{} password is correct.", target.name));
+        return Ok(());
+    }
+
+    let key = store.derived_key().ok_or_else(|| {
+        anyhow!(
+            "The {} has nothing saved yet, so there is no key to remember. Add a secret first.",
+            target.name
+        )
+    })?;
+    remember_key(&target.key_fingerprint()?, key)
+        .context("Failed to save the store key in the OS keychain")?;
+
+    ui::success(format_args!(
+        "{} key saved in the OS keychain. Run `{} lock` to remove it.",
+        target.name, target.command
+    ));
+    Ok(())
+}
+
+/// Remove the key `enject unlock --remember` cached for this store, and wipe every
+/// key a running agent holds.
+pub fn run_lock(target: &StoreTarget) -> Result<()> {
+    if forget_key(&target.key_fingerprint()?)? {
+        ui::success(format_args!(
+            "{} key removed from the OS keychain.",
+            target.name
+        ));
+    } else {
+        inform(format_args!(
+            "No {} key is cached in the OS keychain.",
+            target.name
+        ));
+    }
+    if agent::forget_all()? {
+        inform(format_args!("The enject agent forgot every key it held."));
+    }
+    Ok(())
+}
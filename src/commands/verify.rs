@@ -0,0 +1,56 @@
+use anyhow::{bail, Result};
+
+use crate::commands::StoreTarget;
+use crate::error::EnjectError;
+use crate::store::Store;
+
+/// `enject verify` — check that the store still opens with its password, without
+/// listing or resolving anything. Cached keys and the agent are not used. With
+/// `deep`, also check that every entry would be written back unchanged.
+pub fn run(target: &StoreTarget, deep: bool) -> Result<()> {
+    let size = std::fs::metadata(&target.store_path).map(|m| m.len()).ok();
+    let store = match target.unlock_with_password(true) {
+        Ok((store, _password)) => store,
+        Err(e) => match e.downcast_ref::<EnjectError>() {
+            Some(EnjectError::DecryptionFailed) => bail!(
+                "The {} did not decrypt: wrong password, or the file was modified.",
+                target.name
+            ),
+            Some(EnjectError::CorruptStore(reason)) => {
+                bail!("The {} is corrupt: {}", target.name, reason)
+            }
+            _ => return Err(e),
+        },
+    };
+    let count = store.list()?.len();
+    match size {
+        Some(size) => println!(
+            "{} OK: {} secret(s), {} ({} bytes).",
+            target.name,
+            count,
+            target.store_path.display(),
+            size
+        ),
+        None => println!(
+            "{} OK: nothing saved yet ({} does not exist).",
+            target.name,
+            target.store_path.display()
+        ),
+    }
+
+    if deep {
+        let problems = store.problems()?;
+        if !problems.is_empty() {
+            for problem in &problems {
+                eprintln!("  {}", problem);
+            }
+            bail!(
+                "The {} has {} structural problem(s); a save would carry them forward.",
+                target.name,
+                problems.len()
+            );
+        }
+        println!("Every entry serializes back unchanged.");
+    }
+    Ok(())
+}
@@ -1,42 +1,161 @@
-use std::io::{BufRead, IsTerminal, Write};
+//! `config.toml`: where a store lives and how it is encrypted, plus the project
+//! settings kept beside it.
+
+use std::collections::BTreeMap;
+use std::io::Write;
 use std::path::{Path, PathBuf};
 
 use serde::{Deserialize, Serialize};
 
 use crate::error::EnjectError;
-use crate::store::password::KdfParams;
+use crate::store::password::{Cipher, KdfParams};
 
-const CONFIG_DIR: &str = ".enject";
-const LEGACY_CONFIG_DIR: &str = ".enveil";
+/// Name of the store directory in a project root.
+pub const CONFIG_DIR: &str = ".enject";
+/// Name of the store directory before enveil was renamed enject.
+pub const LEGACY_CONFIG_DIR: &str = ".enveil";
 const CONFIG_FILE: &str = "config.toml";
 const STORE_FILE: &str = "store";
-
-#[derive(Debug, Serialize, Deserialize)]
+const GLOBAL_DIR_NAME: &str = "enject";
+/// Environment variable naming the directory that holds `config.toml` and the store,
+/// in place of the project's `.enject/`. `--store-dir` takes precedence.
+pub const STORE_DIR_ENV: &str = "ENJECT_DIR";
+/// Written by `init`. Version 2 stores keep created/updated timestamps per secret;
+/// version 1 stores are a flat key/value map, migrated when next saved.
+pub const CONFIG_VERSION: u32 = 2;
+
+/// A store's `config.toml`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Config {
+    /// Where secrets are kept: `password`, `keyring` or `age`.
     pub backend: String,
+    /// Format version of the store; see `CONFIG_VERSION`.
     pub version: u32,
+    /// Key derivation function for the password backend. Always `argon2id`.
     pub kdf: String,
+    /// Cipher for new store files: `aes256gcm` or `xchacha20`. An existing store's
+    /// header says which one it was written with.
+    #[serde(default = "default_cipher")]
+    pub cipher: String,
+    /// Argon2id memory cost in KiB.
     pub m_cost: u32,
+    /// Argon2id iterations.
     pub t_cost: u32,
+    /// Argon2id parallelism.
     pub p_cost: u32,
     /// Hex-encoded 32-byte salt for Argon2id.
     pub salt: String,
+    /// Profile applied by `enject run`/`check` when `--profile` is not given.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_profile: Option<String>,
+    /// How many previous values each secret keeps for `enject restore-version`.
+    #[serde(default = "default_history_depth")]
+    pub history_depth: usize,
+    /// Names this store's credentials in the OS keychain (`keyring` backend only).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub project_id: Option<String>,
+    /// Public keys the store is encrypted to (`age` backend only).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub recipients: Vec<String>,
+    /// Named recipients added with `enject member add`, name to public key (`age`
+    /// backend only). A member with a password has their key in `members/<name>.key`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub members: BTreeMap<String, String>,
+    /// Ask a running `enject agent` for this store's key, and hand it the key after
+    /// the password is entered (same as setting ENJECT_AGENT).
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub agent: bool,
+    /// Commands `enject run <name>` runs by name, from the `[scripts]` table.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub scripts: BTreeMap<String, Script>,
+    /// Settings for `enject scan`, from the `[scan]` table.
+    #[serde(default, skip_serializing_if = "ScanConfig::is_default")]
+    pub scan: ScanConfig,
+}
+
+/// The `[scan]` table: when `enject scan` takes a plain value for a secret.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScanConfig {
+    /// Values shorter than this are not checked for entropy. Known token prefixes
+    /// and private keys are flagged at any length.
+    pub min_length: usize,
+    /// Shannon entropy, in bits per character, above which a value is flagged.
+    pub min_entropy: f64,
+    /// Keys whose values are never flagged, e.g. `["*_PUBLIC_KEY", "SENTRY_DSN"]`.
+    pub allow_keys: Vec<String>,
+}
+
+impl Default for ScanConfig {
+    fn default() -> Self {
+        Self {
+            min_length: crate::env_template::SECRET_MIN_LEN,
+            min_entropy: crate::env_template::SECRET_MIN_BITS_PER_CHAR,
+            allow_keys: Vec::new(),
+        }
+    }
+}
+
+impl ScanConfig {
+    fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// A named command in `[scripts]`: an argument list run as is, or a string run by
+/// the platform shell, e.g. `test = ["cargo", "test"]` or `dev = "npm run dev | tee dev.log"`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Script {
+    /// A program and its arguments.
+    Args(Vec<String>),
+    /// A command line for `sh -c` (`cmd /C` on Windows).
+    Shell(String),
+}
+
+impl std::fmt::Display for Script {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Script::Args(args) => write!(f, "{}", args.join(" ")),
+            Script::Shell(line) => write!(f, "{}", line),
+        }
+    }
+}
+
+fn default_history_depth() -> usize {
+    3
+}
+
+fn default_cipher() -> String {
+    Cipher::default().name().into()
 }
 
 impl Config {
+    /// A password-backend config with the default KDF parameters and cipher, and
+    /// `salt_hex` as its salt.
     pub fn default_new(salt_hex: String) -> Self {
         let kdf = KdfParams::default();
         Self {
             backend: "password".into(),
-            version: 1,
+            version: CONFIG_VERSION,
             kdf: "argon2id".into(),
+            cipher: default_cipher(),
             m_cost: kdf.m_cost,
             t_cost: kdf.t_cost,
             p_cost: kdf.p_cost,
             salt: salt_hex,
+            default_profile: None,
+            history_depth: default_history_depth(),
+            project_id: None,
+            recipients: Vec::new(),
+            members: BTreeMap::new(),
+            agent: false,
+            scripts: BTreeMap::new(),
+            scan: ScanConfig::default(),
         }
     }
 
+    /// The Argon2id parameters set in this config.
     pub fn kdf_params(&self) -> KdfParams {
         KdfParams {
             m_cost: self.m_cost,
@@ -45,6 +164,24 @@ impl Config {
         }
     }
 
+    /// The cipher new store files are written with.
+    pub fn cipher(&self) -> Result<Cipher, EnjectError> {
+        Cipher::from_name(&self.cipher)
+    }
+
+    /// Every public key an age store is encrypted to: the anonymous recipients, then
+    /// the members'.
+    pub fn age_recipients(&self) -> Vec<String> {
+        let mut all = self.recipients.clone();
+        for recipient in self.members.values() {
+            if !all.contains(recipient) {
+                all.push(recipient.clone());
+            }
+        }
+        all
+    }
+
+    /// The Argon2id salt, decoded from hex.
     pub fn salt_bytes(&self) -> Result<Vec<u8>, EnjectError> {
         hex::decode(&self.salt)
             .map_err(|_| EnjectError::Config("Invalid salt hex in config.toml".into()))
@@ -67,17 +204,45 @@ pub fn enject_dir(project_root: &Path) -> PathBuf {
 
 /// Returns the config file path for a given project root.
 pub fn config_path(project_root: &Path) -> PathBuf {
-    enject_dir(project_root).join(CONFIG_FILE)
+    config_path_in(&enject_dir(project_root))
+}
+
+/// Returns the config file path inside a store directory.
+pub fn config_path_in(dir: &Path) -> PathBuf {
+    dir.join(CONFIG_FILE)
+}
+
+/// Returns the store file path inside a store directory.
+pub fn store_path_in(dir: &Path) -> PathBuf {
+    dir.join(STORE_FILE)
+}
+
+/// The store directory named by `flag` (`--store-dir`) or else `ENJECT_DIR`, if
+/// either is set. It replaces the project's `.enject/`.
+pub fn store_dir_override(flag: Option<&Path>) -> Option<PathBuf> {
+    flag.map(Path::to_path_buf).or_else(|| {
+        std::env::var_os(STORE_DIR_ENV)
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+    })
 }
 
-/// Returns the store file path for a given project root.
-pub fn store_path(project_root: &Path) -> PathBuf {
-    enject_dir(project_root).join(STORE_FILE)
+/// Read and parse config from a store directory named with `--store-dir` or
+/// `ENJECT_DIR`. Never prompts.
+pub fn read_store_dir(dir: &Path) -> Result<Config, EnjectError> {
+    let path = config_path_in(dir);
+    if !path.exists() {
+        return Err(EnjectError::StoreDirNotInitialized(
+            dir.display().to_string(),
+        ));
+    }
+    let raw = std::fs::read_to_string(&path)?;
+    toml::from_str(&raw).map_err(|e| EnjectError::Config(e.to_string()))
 }
 
-/// Read and parse config from the given project root. Returns an error if not initialized.
+/// Read and parse config from the given project root, from a legacy `.enveil/`
+/// directory if there is no `.enject/`. Returns an error if not initialized.
 pub fn read(project_root: &Path) -> Result<Config, EnjectError> {
-    maybe_migrate_dir(project_root);
     let path = config_path(project_root);
     if !path.exists() {
         return Err(EnjectError::StoreNotInitialized);
@@ -86,87 +251,178 @@ pub fn read(project_root: &Path) -> Result<Config, EnjectError> {
     toml::from_str(&raw).map_err(|e| EnjectError::Config(e.to_string()))
 }
 
-/// Write config to the given project root. Creates the `.enject` directory if needed.
+/// Write `config.toml` into the project's store directory, creating it if needed.
 pub fn write(project_root: &Path, config: &Config) -> Result<(), EnjectError> {
-    let dir = enject_dir(project_root);
-    std::fs::create_dir_all(&dir)?;
-    let path = dir.join(CONFIG_FILE);
+    write_in(&enject_dir(project_root), config)
+}
+
+/// Write `config.toml` into `dir` (a project's `.enject/` or the global directory),
+/// creating it if needed. The file is replaced atomically, so a crash leaves either
+/// the old config or the new one.
+pub fn write_in(dir: &Path, config: &Config) -> Result<(), EnjectError> {
+    std::fs::create_dir_all(dir)?;
     let raw = toml::to_string(config).map_err(|e| EnjectError::Config(e.to_string()))?;
-    std::fs::write(path, raw)?;
+    let tmp_path = dir.join(format!(".config.tmp.{}", rand::random::<u64>()));
+    {
+        let mut tmp = std::fs::File::create(&tmp_path)?;
+        tmp.write_all(raw.as_bytes())?;
+        tmp.sync_all()?;
+    }
+    std::fs::rename(&tmp_path, dir.join(CONFIG_FILE))?;
     Ok(())
 }
 
-/// Returns the current project root (cwd).
-pub fn project_root() -> Result<PathBuf, EnjectError> {
-    std::env::current_dir().map_err(EnjectError::Io)
+/// Returns the template for a named profile: `.env.<profile>` in the project root.
+pub fn profile_template(project_root: &Path, profile: &str) -> Result<PathBuf, EnjectError> {
+    if profile.is_empty() || profile.contains(['/', '\\']) || profile.starts_with('.') {
+        return Err(EnjectError::Config(format!(
+            "Invalid profile name {:?}.",
+            profile
+        )));
+    }
+    Ok(project_root.join(format!(".env.{}", profile)))
 }
 
-/// If `.enveil/` exists but `.enject/` does not, offer to migrate.
-/// Copies `.enveil/` to `.enveil.bak/` as a backup, then renames to `.enject/`.
-/// Errors are non-fatal — a failure falls through to using the legacy path.
-fn maybe_migrate_dir(project_root: &Path) {
-    let new_dir = project_root.join(CONFIG_DIR);
-    let old_dir = project_root.join(LEGACY_CONFIG_DIR);
+/// True if the directory currently in use is the legacy `.enveil/` one.
+pub fn uses_legacy_dir(project_root: &Path) -> bool {
+    enject_dir(project_root).ends_with(LEGACY_CONFIG_DIR)
+}
 
-    if new_dir.exists() || !old_dir.exists() {
-        return;
-    }
+/// True if a `.gitignore` body contains a pattern that ignores the top-level
+/// directory `dir_name` (e.g. `.enject`, `.enject/`, `/.enject/`, `.enject/*`).
+pub fn gitignore_covers(gitignore: &str, dir_name: &str) -> bool {
+    gitignore.lines().map(str::trim).any(|line| {
+        let pattern = line.strip_prefix('/').unwrap_or(line);
+        let pattern = pattern
+            .strip_suffix("/**")
+            .or_else(|| pattern.strip_suffix("/*"))
+            .unwrap_or(pattern);
+        pattern.strip_suffix('/').unwrap_or(pattern) == dir_name
+    })
+}
 
-    if !std::io::stdin().is_terminal() {
-        println!(
-            "Warning: found legacy .enveil/ store. Rename it to .enject/ to silence this warning."
-        );
-        return;
-    }
+/// Returns the user-level directory holding the global store:
+/// `$XDG_CONFIG_HOME/enject`, falling back to `~/.config/enject`.
+pub fn global_dir() -> Result<PathBuf, EnjectError> {
+    global_dir_from(
+        std::env::var_os("XDG_CONFIG_HOME"),
+        std::env::var_os("HOME"),
+    )
+}
 
-    println!("Warning: found legacy .enveil/ store.");
-    print!("Rename .enveil/ to .enject/? A backup will be kept at .enveil.bak/ [y/N]: ");
-    if std::io::stdout().flush().is_err() {
-        return;
+fn global_dir_from(
+    xdg_config_home: Option<std::ffi::OsString>,
+    home: Option<std::ffi::OsString>,
+) -> Result<PathBuf, EnjectError> {
+    if let Some(xdg) = xdg_config_home.filter(|v| !v.is_empty()) {
+        return Ok(PathBuf::from(xdg).join(GLOBAL_DIR_NAME));
     }
+    let home = home.filter(|v| !v.is_empty()).ok_or_else(|| {
+        EnjectError::Config("Could not locate the global store: HOME is not set.".into())
+    })?;
+    Ok(PathBuf::from(home).join(".config").join(GLOBAL_DIR_NAME))
+}
 
-    let mut answer = String::new();
-    if std::io::stdin().lock().read_line(&mut answer).is_err() {
-        return;
+/// Identity files tried for an `age` store when no `--identity` is given: every file
+/// in `$XDG_CONFIG_HOME/age` (or `~/.config/age`), then `~/.ssh/id_ed25519` and
+/// `~/.ssh/id_rsa` where they exist.
+#[cfg(feature = "age")]
+pub fn default_age_identities() -> Vec<PathBuf> {
+    let home = std::env::var_os("HOME")
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from);
+    let age_dir = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| home.as_ref().map(|h| h.join(".config")))
+        .map(|dir| dir.join("age"));
+
+    let mut paths: Vec<PathBuf> = age_dir
+        .and_then(|dir| std::fs::read_dir(dir).ok())
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.is_file())
+        .collect();
+    paths.sort();
+    if let Some(home) = home {
+        for name in ["id_ed25519", "id_rsa"] {
+            let path = home.join(".ssh").join(name);
+            if path.is_file() {
+                paths.push(path);
+            }
+        }
     }
+    paths
+}
 
-    if !answer.trim().eq_ignore_ascii_case("y") {
-        println!("Skipping. Rename .enveil/ to .enject/ to silence this warning.");
-        return;
-    }
+/// Where member `name` of the store configured in `config_dir` keeps their
+/// password-protected age key.
+#[cfg(feature = "age")]
+pub fn member_key_path(config_dir: &Path, name: &str) -> PathBuf {
+    config_dir.join("members").join(format!("{}.key", name))
+}
 
-    let backup = project_root.join(".enveil.bak");
-    if let Err(e) = copy_dir_all(&old_dir, &backup) {
-        println!(
-            "Warning: could not create backup: {}. Migration skipped.",
-            e
-        );
-        return;
-    }
-    if let Err(e) = std::fs::rename(&old_dir, &new_dir) {
-        println!(
-            "Warning: could not rename .enveil/ to .enject/: {}. Using legacy path.",
-            e
-        );
-        let _ = std::fs::remove_dir_all(&backup);
-        return;
+/// Returns the global store file path for a given global directory.
+pub fn global_store_path(global_dir: &Path) -> PathBuf {
+    store_path_in(global_dir)
+}
+
+/// Read and parse the global store config. Returns an error if the global store is not initialized.
+pub fn read_global(global_dir: &Path) -> Result<Config, EnjectError> {
+    let path = global_dir.join(CONFIG_FILE);
+    if !path.exists() {
+        return Err(EnjectError::GlobalStoreNotInitialized(
+            global_dir.display().to_string(),
+        ));
     }
+    let raw = std::fs::read_to_string(&path)?;
+    toml::from_str(&raw).map_err(|e| EnjectError::Config(e.to_string()))
+}
 
-    println!("Migrated .enveil/ to .enject/ (backup at .enveil.bak/).");
+/// Write the global store config. Creates the global directory if needed.
+pub fn write_global(global_dir: &Path, config: &Config) -> Result<(), EnjectError> {
+    write_in(global_dir, config)
 }
 
-fn copy_dir_all(src: &Path, dst: &Path) -> std::io::Result<()> {
-    std::fs::create_dir_all(dst)?;
-    for entry in std::fs::read_dir(src)? {
-        let entry = entry?;
-        let dst_path = dst.join(entry.file_name());
-        if entry.file_type()?.is_dir() {
-            copy_dir_all(&entry.path(), &dst_path)?;
-        } else {
-            std::fs::copy(entry.path(), &dst_path)?;
+/// Returns the project root: the nearest of `start` (the cwd if `None`, else relative
+/// to it) and its parents, up to `$HOME`, that holds a store; or `start` itself when
+/// `discover` is off or none does.
+pub fn project_root(start: Option<&Path>, discover: bool) -> Result<PathBuf, EnjectError> {
+    let cwd = std::env::current_dir().map_err(EnjectError::Io)?;
+    let start = match start {
+        Some(dir) => {
+            let dir = cwd.join(dir);
+            if !dir.is_dir() {
+                return Err(EnjectError::Config(format!(
+                    "Project directory {} does not exist.",
+                    dir.display()
+                )));
+            }
+            dir
         }
+        None => cwd,
+    };
+    if !discover {
+        return Ok(start);
     }
-    Ok(())
+    let home = std::env::var_os("HOME").map(PathBuf::from);
+    Ok(discover_root(&start, home.as_deref()))
+}
+
+/// The nearest of `start` and its parents with a `.enject/` (or legacy `.enveil/`)
+/// directory, looking no higher than `home` or the filesystem root. Falls back to
+/// `start`, so `init` and the not-initialized hint still refer to it.
+pub fn discover_root(start: &Path, home: Option<&Path>) -> PathBuf {
+    for dir in start.ancestors() {
+        if dir.join(CONFIG_DIR).is_dir() || dir.join(LEGACY_CONFIG_DIR).is_dir() {
+            return dir.to_path_buf();
+        }
+        if Some(dir) == home {
+            break;
+        }
+    }
+    start.to_path_buf()
 }
 
 #[cfg(test)]
@@ -189,7 +445,7 @@ mod tests {
 
         let loaded = read(root).unwrap();
         assert_eq!(loaded.backend, "password");
-        assert_eq!(loaded.version, 1);
+        assert_eq!(loaded.version, CONFIG_VERSION);
         assert_eq!(loaded.kdf, "argon2id");
         assert_eq!(loaded.salt, salt);
         assert_eq!(loaded.m_cost, KdfParams::default().m_cost);
@@ -197,6 +453,36 @@ mod tests {
         assert_eq!(loaded.p_cost, KdfParams::default().p_cost);
     }
 
+    #[test]
+    fn test_scripts_table() {
+        let dir = TempDir::new().unwrap();
+        let mut config = Config::default_new(fake_salt_hex());
+        config.scripts.insert(
+            "test".into(),
+            Script::Args(vec!["cargo".into(), "test".into()]),
+        );
+        config.scripts.insert(
+            "dev".into(),
+            Script::Shell("npm run dev | tee dev.log".into()),
+        );
+        write(dir.path(), &config).unwrap();
+
+        let loaded = read(dir.path()).unwrap();
+        assert_eq!(loaded.scripts, config.scripts);
+    }
+
+    #[test]
+    fn test_scan_table_is_optional_and_partial() {
+        let config = Config::default_new(fake_salt_hex());
+        let raw = toml::to_string(&config).unwrap();
+        assert!(!raw.contains("[scan]"));
+
+        let raw = format!("{}\n[scan]\nallow_keys = [\"*_PUBLIC_KEY\"]\n", raw);
+        let loaded: Config = toml::from_str(&raw).unwrap();
+        assert_eq!(loaded.scan.allow_keys, vec!["*_PUBLIC_KEY"]);
+        assert_eq!(loaded.scan.min_length, ScanConfig::default().min_length);
+    }
+
     #[test]
     fn test_read_missing_config_returns_not_initialized() {
         let dir = TempDir::new().unwrap();
@@ -221,10 +507,13 @@ mod tests {
         let legacy_dir = root.join(".enveil");
         std::fs::create_dir_all(&legacy_dir).unwrap();
 
-        // enject_dir, config_path, store_path should all resolve to .enveil/
+        // enject_dir, config_path and the store path should all resolve to .enveil/
         assert_eq!(enject_dir(root), root.join(".enveil"));
         assert_eq!(config_path(root), root.join(".enveil").join("config.toml"));
-        assert_eq!(store_path(root), root.join(".enveil").join("store"));
+        assert_eq!(
+            store_path_in(&enject_dir(root)),
+            root.join(".enveil").join("store")
+        );
     }
 
     #[test]
@@ -243,6 +532,97 @@ mod tests {
         assert_eq!(enject_dir(root), root.join(".enject"));
     }
 
+    #[test]
+    fn test_discover_root_walks_up_to_the_nearest_store() {
+        let dir = TempDir::new().unwrap();
+        let nested = dir.path().join("backend").join("tests");
+        std::fs::create_dir_all(&nested).unwrap();
+        std::fs::create_dir(dir.path().join(CONFIG_DIR)).unwrap();
+        assert_eq!(discover_root(&nested, None), dir.path());
+
+        // A store in between is nearer
+        std::fs::create_dir(dir.path().join("backend").join(LEGACY_CONFIG_DIR)).unwrap();
+        assert_eq!(discover_root(&nested, None), dir.path().join("backend"));
+        assert_eq!(discover_root(dir.path(), None), dir.path());
+    }
+
+    #[test]
+    fn test_discover_root_stops_at_home() {
+        let dir = TempDir::new().unwrap();
+        let home = dir.path().join("home");
+        let nested = home.join("project").join("src");
+        std::fs::create_dir_all(&nested).unwrap();
+        std::fs::create_dir(dir.path().join(CONFIG_DIR)).unwrap();
+        assert_eq!(discover_root(&nested, Some(&home)), nested);
+        assert_eq!(discover_root(&nested, None), dir.path());
+
+        std::fs::create_dir(home.join(CONFIG_DIR)).unwrap();
+        assert_eq!(discover_root(&nested, Some(&home)), home);
+    }
+
+    #[test]
+    fn test_store_dir_has_its_own_not_initialized_error() {
+        let dir = TempDir::new().unwrap();
+        let err = read_store_dir(dir.path()).unwrap_err();
+        assert!(matches!(err, EnjectError::StoreDirNotInitialized(_)));
+
+        let config = Config::default_new(fake_salt_hex());
+        write_in(dir.path(), &config).unwrap();
+        assert_eq!(read_store_dir(dir.path()).unwrap().salt, config.salt);
+    }
+
+    #[test]
+    fn test_store_dir_flag_wins_over_env() {
+        std::env::set_var(STORE_DIR_ENV, "/from/env");
+        let flag = Path::new("/from/flag");
+        assert_eq!(store_dir_override(Some(flag)).unwrap(), flag);
+        assert_eq!(store_dir_override(None).unwrap(), Path::new("/from/env"));
+        std::env::remove_var(STORE_DIR_ENV);
+        assert!(store_dir_override(None).is_none());
+    }
+
+    #[test]
+    fn test_gitignore_covers() {
+        assert!(gitignore_covers("target/\n.enject/\n", ".enject"));
+        assert!(gitignore_covers("/.enject", ".enject"));
+        assert!(gitignore_covers("  .enject/**  ", ".enject"));
+        assert!(!gitignore_covers("# .enject/\n", ".enject"));
+        assert!(!gitignore_covers(".enject/store\n", ".enject"));
+        assert!(!gitignore_covers("", ".enject"));
+    }
+
+    #[test]
+    fn test_default_profile_roundtrip_and_optional() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path();
+
+        // Configs written before profiles existed have no default_profile key
+        let config = Config::default_new(fake_salt_hex());
+        write(root, &config).unwrap();
+        let raw = std::fs::read_to_string(config_path(root)).unwrap();
+        assert!(!raw.contains("default_profile"));
+        assert_eq!(read(root).unwrap().default_profile, None);
+
+        let mut config = config;
+        config.default_profile = Some("staging".into());
+        write(root, &config).unwrap();
+        assert_eq!(
+            read(root).unwrap().default_profile.as_deref(),
+            Some("staging")
+        );
+    }
+
+    #[test]
+    fn test_profile_template_path() {
+        let root = Path::new("/project");
+        assert_eq!(
+            profile_template(root, "staging").unwrap(),
+            root.join(".env.staging")
+        );
+        assert!(profile_template(root, "../etc").is_err());
+        assert!(profile_template(root, "").is_err());
+    }
+
     #[test]
     fn test_kdf_params_roundtrip() {
         let dir = TempDir::new().unwrap();
@@ -255,4 +635,43 @@ mod tests {
         assert_eq!(params.t_cost, 3);
         assert_eq!(params.p_cost, 4);
     }
+
+    #[test]
+    fn test_global_dir_prefers_xdg_config_home() {
+        let dir = global_dir_from(Some("/xdg".into()), Some("/home/u".into())).unwrap();
+        assert_eq!(dir, PathBuf::from("/xdg").join("enject"));
+    }
+
+    #[test]
+    fn test_global_dir_falls_back_to_home_config() {
+        let dir = global_dir_from(None, Some("/home/u".into())).unwrap();
+        assert_eq!(dir, PathBuf::from("/home/u").join(".config").join("enject"));
+
+        let dir = global_dir_from(Some("".into()), Some("/home/u".into())).unwrap();
+        assert_eq!(dir, PathBuf::from("/home/u").join(".config").join("enject"));
+    }
+
+    #[test]
+    fn test_global_dir_without_home_returns_err() {
+        assert!(global_dir_from(None, None).is_err());
+    }
+
+    #[test]
+    fn test_global_config_roundtrip() {
+        let dir = TempDir::new().unwrap();
+        let global = dir.path().join("enject");
+        let config = Config::default_new(fake_salt_hex());
+        write_global(&global, &config).unwrap();
+
+        let loaded = read_global(&global).unwrap();
+        assert_eq!(loaded.salt, config.salt);
+        assert_eq!(global_store_path(&global), global.join("store"));
+    }
+
+    #[test]
+    fn test_read_missing_global_config_returns_not_initialized() {
+        let dir = TempDir::new().unwrap();
+        let err = read_global(dir.path()).unwrap_err();
+        assert!(matches!(err, EnjectError::GlobalStoreNotInitialized(_)));
+    }
 }
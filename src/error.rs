@@ -1,25 +1,204 @@
+//! The error type shared by the store, config and template code, and the exit
+//! codes the CLI maps it to.
+
+use std::fmt;
+
 use thiserror::Error;
 
+// Exit codes, kept stable so scripts can tell failures apart. `--help` lists them;
+// 2 is left to clap, which uses it for invalid arguments.
+
+/// Any failure without a code of its own.
+pub const EXIT_FAILURE: i32 = 1;
+/// No store where one was expected.
+pub const EXIT_NOT_INITIALIZED: i32 = 3;
+/// The password, key or identity doesn't open the store.
+pub const EXIT_WRONG_PASSWORD: i32 = 4;
+/// A referenced secret isn't in the store.
+pub const EXIT_SECRET_NOT_FOUND: i32 = 5;
+/// A template doesn't parse.
+pub const EXIT_PARSE: i32 = 6;
+/// Another process holds the store, or changed it since it was read.
+pub const EXIT_STORE_BUSY: i32 = 7;
+
+/// Everything that can go wrong reading or writing a store, its config or a
+/// template. The messages are written for the person running the CLI.
 #[derive(Debug, Error)]
 pub enum EnjectError {
+    /// The project has no `.enject/config.toml`.
     #[error("Store not initialized. Run `enject init` first.")]
     StoreNotInitialized,
 
+    /// The global store directory, given here, has no config.toml.
+    #[error(
+        "Global store not initialized (no config.toml in {0}). Run `enject global init` first."
+    )]
+    GlobalStoreNotInitialized(String),
+
+    /// The store directory from `--store-dir` or `ENJECT_DIR`, given here, has no config.toml.
+    #[error("The store directory {0} (from --store-dir or ENJECT_DIR) has no config.toml. Run `enject init` to create a store there.")]
+    StoreDirNotInitialized(String),
+
+    /// The key didn't decrypt the store: a wrong password, or a damaged store.
     #[error("Wrong Enject store password, or store is corrupted.")]
     DecryptionFailed,
 
+    /// The store decrypted but its contents don't make sense.
     #[error("Store is corrupted: {0}")]
     CorruptStore(String),
 
+    /// Another process holds the lock file given here.
+    #[error("Another enject process is modifying the store ({0} is still locked). Try again once it finishes.")]
+    StoreLocked(String),
+
+    /// Saving would overwrite changes another process made since the store was read.
+    #[error("The store changed on disk since it was unlocked. Re-run the command to apply it to the current store.")]
+    StoreChangedOnDisk,
+
+    /// The store file is of a format or version this build can't read.
+    #[error("Unsupported store format: {0}")]
+    UnsupportedStoreFormat(String),
+
+    /// A secret asked for by name isn't in the store.
     #[error("Secret '{0}' not found in store. Add it with: enject set {0}")]
     SecretNotFound(String),
 
+    /// Every secret a template references that its store doesn't have, in the
+    /// order they are referenced.
+    #[error("{}", list_missing(.0))]
+    MissingSecrets(Vec<MissingSecret>),
+
+    /// A `#path` reference names a field the secret's JSON value doesn't have.
+    #[error("Cannot read '#{path}' from secret '{secret}': {reason}")]
+    SecretField {
+        /// The secret's name.
+        secret: String,
+        /// The `#path` that was asked for.
+        path: String,
+        /// Why the field can't be read.
+        reason: String,
+    },
+
+    /// An expiry that is neither a duration, a date nor `never`.
+    #[error("Invalid expiry {0:?}. Use a duration such as 90d or 12w, a date such as 2025-06-30, or never.")]
+    InvalidExpiry(String),
+
+    /// Argon2id parameters too weak to accept.
+    #[error("Unsafe KDF parameters: {0}")]
+    UnsafeKdf(String),
+
+    /// None of the identities given can decrypt the age store.
+    #[error("None of your identities can decrypt the store. Pass --identity <PATH>, or the password of a store member; passphrase-protected SSH keys aren't supported.")]
+    #[cfg(feature = "age")]
+    NoMatchingIdentity,
+
+    /// Encrypting or decrypting an age store failed.
+    #[error("age: {0}")]
+    Age(String),
+
+    /// The OS keychain refused a read or write.
+    #[error("OS keychain error: {0}")]
+    Keychain(String),
+
+    /// A config.toml that doesn't parse, or a setting that makes no sense.
     #[error("Config error: {0}")]
     Config(String),
 
+    /// A template line that doesn't parse.
+    #[error("Parse error: {0}")]
+    Parse(String),
+
+    /// Every line of a template that doesn't parse, in file order.
+    #[error("{}", list_lines(.0))]
+    TemplateErrors(Vec<TemplateError>),
+
+    /// Reading or writing a file failed.
     #[error("I/O error: {0}")]
     Io(#[from] std::io::Error),
 
+    /// The store contents couldn't be encoded or decoded.
     #[error("Serialization error: {0}")]
     Serialization(String),
 }
+
+/// A line of a `.env` template that doesn't parse.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TemplateError {
+    /// Where the line starts in the file; a multi-line value spans several.
+    pub line: usize,
+    /// What is wrong with it.
+    pub message: String,
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+/// A secret referenced by a template that its store doesn't have.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MissingSecret {
+    /// The secret's name.
+    pub name: String,
+    /// True for an `en://global/` reference, missing from the global store.
+    pub global: bool,
+}
+
+impl MissingSecret {
+    /// The command that adds the secret.
+    pub fn set_command(&self) -> String {
+        if self.global {
+            format!("enject global set {}", self.name)
+        } else {
+            format!("enject set {}", self.name)
+        }
+    }
+}
+
+fn list_missing(missing: &[MissingSecret]) -> String {
+    if let [secret] = missing {
+        let store = if secret.global {
+            "global store"
+        } else {
+            "store"
+        };
+        return format!(
+            "Secret '{}' not found in {}. Add it with: {}",
+            secret.name,
+            store,
+            secret.set_command()
+        );
+    }
+    let mut message = format!("{} secrets not found. Add them with:", missing.len());
+    for secret in missing {
+        message.push_str("\n  ");
+        message.push_str(&secret.set_command());
+    }
+    message
+}
+
+fn list_lines(errors: &[TemplateError]) -> String {
+    let lines: Vec<String> = errors.iter().map(ToString::to_string).collect();
+    lines.join("\n")
+}
+
+impl EnjectError {
+    /// The process exit code for a command that fails with this error.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            EnjectError::StoreNotInitialized
+            | EnjectError::GlobalStoreNotInitialized(_)
+            | EnjectError::StoreDirNotInitialized(_) => EXIT_NOT_INITIALIZED,
+            EnjectError::DecryptionFailed => EXIT_WRONG_PASSWORD,
+            #[cfg(feature = "age")]
+            EnjectError::NoMatchingIdentity => EXIT_WRONG_PASSWORD,
+            EnjectError::SecretNotFound(_) | EnjectError::MissingSecrets(_) => {
+                EXIT_SECRET_NOT_FOUND
+            }
+            EnjectError::Parse(_) | EnjectError::TemplateErrors(_) => EXIT_PARSE,
+            EnjectError::StoreLocked(_) | EnjectError::StoreChangedOnDisk => EXIT_STORE_BUSY,
+            _ => EXIT_FAILURE,
+        }
+    }
+}
@@ -0,0 +1,134 @@
+//! Secret expiry dates: parsing `enject set --expires` and checking stored dates.
+
+use std::collections::BTreeMap;
+use std::time::{Duration, SystemTime};
+
+use crate::error::EnjectError;
+use crate::store::Store;
+
+/// How close to its expiry a secret is reported as expiring soon.
+const EXPIRING_SOON: Duration = Duration::from_secs(14 * 24 * 60 * 60);
+
+/// Where a secret stands relative to its expiry date.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Expiry {
+    Valid,
+    /// Expires within the next 14 days.
+    Soon,
+    Expired,
+}
+
+/// The `--expires` argument as a stored RFC 3339 timestamp, or an empty string for
+/// `never` (which removes an expiry). Accepts a duration from `now` such as `90d`,
+/// `12w` or `6months`, a date (`2025-06-30`, midnight UTC) or a full RFC 3339 time.
+pub fn parse(input: &str, now: SystemTime) -> Result<String, EnjectError> {
+    let input = input.trim();
+    if input == "never" {
+        return Ok(String::new());
+    }
+    let invalid = || EnjectError::InvalidExpiry(input.to_string());
+
+    let at = if input.starts_with(|c: char| c.is_ascii_digit()) && input.contains('-') {
+        let stamp = if input.len() == 10 {
+            format!("{}T00:00:00Z", input)
+        } else {
+            input.to_string()
+        };
+        humantime::parse_rfc3339_weak(&stamp).map_err(|_| invalid())?
+    } else {
+        let duration = humantime::parse_duration(input).map_err(|_| invalid())?;
+        if duration.is_zero() {
+            return Err(invalid());
+        }
+        now.checked_add(duration).ok_or_else(invalid)?
+    };
+    if at <= now {
+        return Err(EnjectError::Config(format!(
+            "Expiry {} is in the past.",
+            input
+        )));
+    }
+    Ok(humantime::format_rfc3339_seconds(at).to_string())
+}
+
+/// Whether a stored `expires_at` has passed at `now`. A timestamp that can't be
+/// read counts as expired, so a damaged entry is noticed rather than ignored.
+pub fn status(expires_at: &str, now: SystemTime) -> Expiry {
+    match humantime::parse_rfc3339_weak(expires_at) {
+        Ok(at) if at <= now => Expiry::Expired,
+        Ok(at) if at <= now + EXPIRING_SOON => Expiry::Soon,
+        Ok(_) => Expiry::Valid,
+        Err(_) => Expiry::Expired,
+    }
+}
+
+/// Every secret in `store` that has expired at `now`, with its `expires_at`.
+pub fn expired(
+    store: &dyn Store,
+    now: SystemTime,
+) -> Result<BTreeMap<String, String>, EnjectError> {
+    let mut expired = BTreeMap::new();
+    for key in store.list()? {
+        if let Some(expires_at) = store.metadata(&key)?.and_then(|m| m.expires_at) {
+            if status(&expires_at, now) == Expiry::Expired {
+                expired.insert(key, expires_at);
+            }
+        }
+    }
+    Ok(expired)
+}
+
+/// The date part of a stored timestamp, e.g. `2025-06-30`.
+pub fn date(stamp: &str) -> &str {
+    stamp.get(..10).unwrap_or(stamp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn now() -> SystemTime {
+        humantime::parse_rfc3339("2025-01-01T12:00:00Z").unwrap()
+    }
+
+    #[test]
+    fn test_parse_durations() {
+        assert_eq!(parse("90d", now()).unwrap(), "2025-04-01T12:00:00Z");
+        assert_eq!(parse("2w", now()).unwrap(), "2025-01-15T12:00:00Z");
+        assert_eq!(parse("12h", now()).unwrap(), "2025-01-02T00:00:00Z");
+        assert_eq!(parse("never", now()).unwrap(), "");
+    }
+
+    #[test]
+    fn test_parse_dates() {
+        assert_eq!(parse("2025-06-30", now()).unwrap(), "2025-06-30T00:00:00Z");
+        assert_eq!(
+            parse("2025-06-30T08:30:00Z", now()).unwrap(),
+            "2025-06-30T08:30:00Z"
+        );
+    }
+
+    #[test]
+    fn test_parse_rejects_bad_and_past_input() {
+        for input in ["", "soon", "90", "0d", "2025-13-01", "2025-6-1"] {
+            assert!(
+                matches!(parse(input, now()), Err(EnjectError::InvalidExpiry(_))),
+                "{:?}",
+                input
+            );
+        }
+        assert!(parse("2024-12-31", now())
+            .unwrap_err()
+            .to_string()
+            .contains("in the past"));
+    }
+
+    #[test]
+    fn test_status() {
+        assert_eq!(status("2024-12-31T00:00:00Z", now()), Expiry::Expired);
+        assert_eq!(status("2025-01-10T00:00:00Z", now()), Expiry::Soon);
+        assert_eq!(status("2025-03-01T00:00:00Z", now()), Expiry::Valid);
+        assert_eq!(status("garbage", now()), Expiry::Expired);
+        assert_eq!(date("2025-03-01T00:00:00Z"), "2025-03-01");
+    }
+}
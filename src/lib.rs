@@ -0,0 +1,87 @@
+//! The store, config and `.env` template handling behind the `enject` CLI, for
+//! programs that resolve their own secrets at startup instead of running under
+//! `enject run`.
+//!
+//! [`resolve_project`] does what `enject run` does for a project's `.env`; the
+//! modules give finer control. Nothing here prompts or prints: legacy `.enveil/`
+//! directories and `ev://` references are read as they are.
+//!
+//! ```no_run
+//! use secrecy::{ExposeSecret, SecretString};
+//!
+//! let password = SecretString::new(std::env::var("APP_STORE_PASSWORD")?);
+//! let env = enject::resolve_project(std::path::Path::new("."), &password)?;
+//! let database_url = env["DATABASE_URL"].expose_secret();
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+#![deny(missing_docs)]
+
+pub mod config;
+pub mod env_template;
+pub mod error;
+pub mod migration;
+pub mod store;
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use secrecy::{ExposeSecret, SecretString};
+use zeroize::Zeroize;
+
+pub use config::Config;
+pub use env_template::EnvLine;
+pub use error::EnjectError;
+pub use store::password::{KdfParams, PasswordStore};
+pub use store::Store;
+
+/// Resolve the `.env` template in the project at `root` with the secrets in its
+/// password store, unlocked read-only with `password`. Returns every variable the
+/// template assigns, plain values included.
+///
+/// Only the project store is opened: a template with `en://global/` references is
+/// an error, as is one with a reference the store can't resolve.
+pub fn resolve_project(
+    root: &Path,
+    password: &SecretString,
+) -> Result<HashMap<String, SecretString>, EnjectError> {
+    let config = config::read(root)?;
+    if config.backend != "password" {
+        return Err(EnjectError::Config(format!(
+            "resolve_project opens password stores only; this one uses the {} backend.",
+            config.backend
+        )));
+    }
+    let lines: Vec<EnvLine> = env_template::parse_file(&root.join(".env"))?
+        .into_iter()
+        .map(|parsed| parsed.line)
+        .collect();
+    if env_template::has_global_refs(&lines) {
+        return Err(EnjectError::Config(
+            "The template references the global store, which resolve_project does not open.".into(),
+        ));
+    }
+
+    let mut store = PasswordStore::new(
+        config::store_path_in(&config::enject_dir(root)),
+        config.kdf_params(),
+        config.salt_bytes()?,
+    )
+    .with_cipher(config.cipher()?);
+    store.unlock_read_only(password)?;
+    let mut secrets = HashMap::new();
+    for key in store.list()? {
+        if let Some(value) = store.get(&key)? {
+            secrets.insert(key, value.expose_secret().clone());
+        }
+    }
+
+    let resolved = env_template::resolve(&lines, &secrets, &HashMap::new());
+    for value in secrets.values_mut() {
+        value.zeroize();
+    }
+    Ok(resolved?
+        .into_iter()
+        .map(|(key, value)| (key, SecretString::new(value)))
+        .collect())
+}
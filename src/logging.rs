@@ -0,0 +1,33 @@
+//! Diagnostics on stderr for `-v`/`-vv`, and the `--quiet` switch.
+//!
+//! Log events carry paths, key names, counts and timings. Secret values stay in
+//! `SecretString` or `Zeroizing` buffers and are never passed to a log macro.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use tracing::level_filters::LevelFilter;
+
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// Send log events to stderr: debug events at `-v`, and timings, which are trace
+/// events, from `-vv`. Without `-v` nothing is logged. `quiet` turns off the
+/// informational messages commands print with `commands::inform` and `inform_stderr`.
+pub fn init(verbose: u8, quiet: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+    let level = match verbose {
+        0 => return,
+        1 => LevelFilter::DEBUG,
+        _ => LevelFilter::TRACE,
+    };
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .with_max_level(level)
+        .with_target(false)
+        .without_time()
+        .init();
+}
+
+/// True when `--quiet` was given.
+pub fn quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
@@ -1,26 +1,158 @@
+mod agent;
+mod auth;
+mod backup;
 mod cli;
 mod commands;
-mod config;
-mod env_template;
-mod error;
+mod expiry;
+mod logging;
+mod mask;
+mod procfile;
 mod runner;
-mod store;
+mod ui;
+mod watch;
 
-use anyhow::Result;
+use anyhow::{bail, Result};
 use clap::Parser;
-use cli::{Cli, Command};
+use cli::{Cli, Command, GlobalCommand, OutputFormat};
+use commands::StoreTarget;
+use enject::{config, env_template, error, migration, store};
+use env_template::Scope;
+use error::EnjectError;
 
-fn main() -> Result<()> {
+fn main() {
     let cli = Cli::parse();
+    if let Err(e) = run(cli) {
+        ui::error(format_args!("{:?}", e));
+        std::process::exit(exit_code(&e));
+    }
+}
+
+/// The exit code for a failed command: that of the first `EnjectError` among the
+/// error and its causes, or 1.
+fn exit_code(e: &anyhow::Error) -> i32 {
+    e.chain()
+        .find_map(|cause| cause.downcast_ref::<EnjectError>())
+        .map_or(error::EXIT_FAILURE, EnjectError::exit_code)
+}
+
+fn run(cli: Cli) -> Result<()> {
+    let opts = &cli.global;
+    logging::init(opts.verbose, opts.quiet);
+    ui::init(opts.color);
+    let format = opts.format;
+    if format == OutputFormat::Json && !cli.command.supports_json() {
+        bail!("This command has no --format json output.");
+    }
 
     match cli.command {
-        Command::Init => commands::init::run()?,
-        Command::Set { key } => commands::set::run(&key)?,
-        Command::List => commands::list::run()?,
-        Command::Delete { key } => commands::delete::run(&key)?,
-        Command::Run { cmd } => commands::run::run(cmd)?,
-        Command::Import { file } => commands::import::run(&file)?,
-        Command::Rotate => commands::rotate::run()?,
+        Command::Init(args) => commands::init::run(opts, &args)?,
+        Command::Set(args) => commands::set::run(StoreTarget::local(opts)?, &args, format)?,
+        Command::List(args) => {
+            commands::list::run(&StoreTarget::local(opts)?, Scope::Local, &args, format)?
+        }
+        Command::Info { key } => commands::info::run(&StoreTarget::local(opts)?, &key)?,
+        Command::History { key } => commands::history::run(&StoreTarget::local(opts)?, &key)?,
+        Command::RestoreVersion { key, version } => {
+            commands::restore_version::run(&StoreTarget::local(opts)?, &key, version)?
+        }
+        Command::Delete(args) => {
+            commands::delete::run(&StoreTarget::local(opts)?, Scope::Local, &args, format)?
+        }
+        Command::Rename { old, new, force } => {
+            commands::rename::run(&StoreTarget::local(opts)?, &old, &new, force)?
+        }
+        Command::Run(args) => {
+            let status = commands::run::run(opts, args)?;
+            // Exit only once run has returned and dropped the resolved secrets
+            std::process::exit(runner::exit_code(status));
+        }
+        Command::Check {
+            profile,
+            allow_duplicates,
+            strict_expiry,
+        } => commands::check::run(
+            opts,
+            profile.as_deref(),
+            allow_duplicates,
+            strict_expiry,
+            format,
+        )?,
+        Command::Diff { profile } => commands::diff::run(opts, profile.as_deref(), format)?,
+        Command::Scan {
+            paths,
+            install_hook,
+            force,
+        } => {
+            if install_hook {
+                commands::scan::run_install_hook(opts, force)?
+            } else {
+                commands::scan::run(opts, &paths)?
+            }
+        }
+        Command::Template {
+            example: _,
+            output,
+            show_refs,
+            blank_all,
+            force,
+        } => commands::template::run_example(opts, output.as_deref(), show_refs, blank_all, force)?,
+        Command::Completions { shell } => commands::completions::run(shell)?,
+        Command::Status { unlock } => commands::status::run(opts, unlock, format)?,
+        Command::Doctor { unlock } => {
+            let code = commands::doctor::run(opts, unlock)?;
+            std::process::exit(code);
+        }
+        Command::Migrate { yes } => commands::migrate::run(opts, yes)?,
+        Command::Import(args) => commands::import::run(opts, &args)?,
+        Command::Rotate(args) => commands::rotate::run(&StoreTarget::local(opts)?, &args)?,
+        Command::Verify { deep } => commands::verify::run(&StoreTarget::local(opts)?, deep)?,
+        Command::Backup { output } => commands::backup::run(opts, &output)?,
+        Command::Restore { file, force } => commands::backup::run_restore(opts, &file, force)?,
+        Command::Unlock { remember } => {
+            commands::unlock::run(&StoreTarget::local(opts)?, remember)?
+        }
+        Command::Lock => commands::unlock::run_lock(&StoreTarget::local(opts)?)?,
+        Command::Agent {
+            ttl,
+            foreground,
+            stop,
+        } => commands::agent::run(ttl.as_deref(), foreground, stop)?,
+        Command::Recipients { command } => {
+            commands::recipients::run(&StoreTarget::local(opts)?, &command)?
+        }
+        Command::Member { command } => commands::member::run(&StoreTarget::local(opts)?, &command)?,
+        Command::Global { command } => match command {
+            GlobalCommand::Init(args) => commands::init::run_global(&args)?,
+            GlobalCommand::Set(args) => {
+                commands::set::run(StoreTarget::global(opts)?, &args, format)?
+            }
+            GlobalCommand::List(args) => {
+                commands::list::run(&StoreTarget::global(opts)?, Scope::Global, &args, format)?
+            }
+            GlobalCommand::Info { key } => commands::info::run(&StoreTarget::global(opts)?, &key)?,
+            GlobalCommand::History { key } => {
+                commands::history::run(&StoreTarget::global(opts)?, &key)?
+            }
+            GlobalCommand::RestoreVersion { key, version } => {
+                commands::restore_version::run(&StoreTarget::global(opts)?, &key, version)?
+            }
+            GlobalCommand::Delete(args) => {
+                commands::delete::run(&StoreTarget::global(opts)?, Scope::Global, &args, format)?
+            }
+            GlobalCommand::Rotate(args) => {
+                commands::rotate::run(&StoreTarget::global(opts)?, &args)?
+            }
+            GlobalCommand::Unlock { remember } => {
+                commands::unlock::run(&StoreTarget::global(opts)?, remember)?
+            }
+            GlobalCommand::Lock => commands::unlock::run_lock(&StoreTarget::global(opts)?)?,
+            GlobalCommand::Recipients { command } => {
+                commands::recipients::run(&StoreTarget::global(opts)?, &command)?
+            }
+            GlobalCommand::Member { command } => {
+                commands::member::run(&StoreTarget::global(opts)?, &command)?
+            }
+        },
     }
 
     Ok(())
@@ -0,0 +1,184 @@
+//! Masking secret values in a subprocess's output for `enject run --mask`.
+
+use std::io::{self, Read, Write};
+
+use aho_corasick::{AhoCorasick, MatchKind};
+use zeroize::Zeroizing;
+
+/// Values shorter than this are not masked: they would match all over ordinary output.
+pub const MIN_MASKED_LEN: usize = 6;
+
+/// Replaces every occurrence of a set of secret values in a byte stream with
+/// `***MASKED(<key>)***`.
+pub struct Masker {
+    automaton: AhoCorasick,
+    /// The key each pattern is reported as, by pattern index.
+    keys: Vec<String>,
+    values: Vec<Zeroizing<Vec<u8>>>,
+}
+
+impl std::fmt::Debug for Masker {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Masker")
+            .field("keys", &self.keys)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Masker {
+    /// Mask each value under its key. Values shorter than `MIN_MASKED_LEN` are
+    /// skipped, and a value given twice is reported under its first key.
+    pub fn new<'a>(secrets: impl IntoIterator<Item = (&'a str, &'a str)>) -> io::Result<Self> {
+        let mut keys = Vec::new();
+        let mut values: Vec<Zeroizing<Vec<u8>>> = Vec::new();
+        for (key, value) in secrets {
+            if value.len() < MIN_MASKED_LEN
+                || values.iter().any(|v| v.as_slice() == value.as_bytes())
+            {
+                continue;
+            }
+            keys.push(key.to_string());
+            values.push(Zeroizing::new(value.as_bytes().to_vec()));
+        }
+        let automaton = AhoCorasick::builder()
+            .match_kind(MatchKind::LeftmostLongest)
+            .build(values.iter().map(|v| v.as_slice()))
+            .map_err(io::Error::other)?;
+        Ok(Self {
+            automaton,
+            keys,
+            values,
+        })
+    }
+
+    /// True when there is nothing to mask.
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Copy `reader` to `writer` until end of input, masking as it goes. Output is
+    /// written as soon as it arrives, except for a trailing part that could still
+    /// turn out to be the start of a value split across reads.
+    pub fn copy(&self, mut reader: impl Read, mut writer: impl Write) -> io::Result<()> {
+        let mut pending = Zeroizing::new(Vec::new());
+        let mut buf = Zeroizing::new([0u8; 8192]);
+        loop {
+            let n = match reader.read(&mut *buf) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            pending.extend_from_slice(&buf[..n]);
+            let hold = self.partial_suffix_len(&pending);
+            let done = self.write_masked(&pending, pending.len() - hold, &mut writer)?;
+            pending.drain(..done);
+            writer.flush()?;
+        }
+        self.write_masked(&pending, pending.len(), &mut writer)?;
+        writer.flush()
+    }
+
+    /// Write `data` masked, up to `cut` or the end of a value that starts before it,
+    /// and return how many bytes of `data` that consumed.
+    fn write_masked(&self, data: &[u8], cut: usize, writer: &mut impl Write) -> io::Result<usize> {
+        let mut pos = 0;
+        for found in self.automaton.find_iter(data) {
+            if found.start() >= cut {
+                break;
+            }
+            writer.write_all(&data[pos..found.start()])?;
+            write!(
+                writer,
+                "***MASKED({})***",
+                self.keys[found.pattern().as_usize()]
+            )?;
+            pos = found.end();
+        }
+        let end = pos.max(cut);
+        writer.write_all(&data[pos..end])?;
+        Ok(end)
+    }
+
+    /// The length of the longest suffix of `data` that is a proper prefix of some
+    /// value, so may become a match once more input arrives.
+    fn partial_suffix_len(&self, data: &[u8]) -> usize {
+        let longest = self.values.iter().map(|v| v.len()).max().unwrap_or(0);
+        (1..longest.min(data.len() + 1))
+            .rev()
+            .find(|&len| {
+                let suffix = &data[data.len() - len..];
+                self.values
+                    .iter()
+                    .any(|v| v.len() > len && v.starts_with(suffix))
+            })
+            .unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feed `chunks` through `masker` one read at a time.
+    fn mask_chunks(masker: &Masker, chunks: &[&[u8]]) -> String {
+        struct Chunks<'a>(std::slice::Iter<'a, &'a [u8]>);
+        impl Read for Chunks<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                match self.0.next() {
+                    Some(chunk) => {
+                        buf[..chunk.len()].copy_from_slice(chunk);
+                        Ok(chunk.len())
+                    }
+                    None => Ok(0),
+                }
+            }
+        }
+        let mut out = Vec::new();
+        masker.copy(Chunks(chunks.iter()), &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_masks_values_and_skips_short_ones() {
+        let masker = Masker::new([
+            ("DB_PASS", "hunter2hunter2"),
+            ("PORT", "3000"),
+            ("TOKEN", "tok_abcdef"),
+        ])
+        .unwrap();
+        assert_eq!(
+            mask_chunks(
+                &masker,
+                &[b"connecting with hunter2hunter2 on 3000, tok_abcdef\n"]
+            ),
+            "connecting with ***MASKED(DB_PASS)*** on 3000, ***MASKED(TOKEN)***\n"
+        );
+    }
+
+    #[test]
+    fn test_masks_values_split_across_reads() {
+        let masker = Masker::new([("KEY", "supersecret")]).unwrap();
+        assert_eq!(
+            mask_chunks(&masker, &[b"a=super", b"sec", b"ret b=supe", b"rb"]),
+            "a=***MASKED(KEY)*** b=superb"
+        );
+    }
+
+    #[test]
+    fn test_prefers_the_longest_value() {
+        let masker = Masker::new([("SHORT", "abcdef"), ("LONG", "abcdefghij")]).unwrap();
+        assert_eq!(
+            mask_chunks(&masker, &[b"abcdef", b"ghij abcdef!"]),
+            "***MASKED(LONG)*** ***MASKED(SHORT)***!"
+        );
+    }
+
+    #[test]
+    fn test_output_is_not_held_back_without_a_partial_match() {
+        let masker = Masker::new([("KEY", "supersecret")]).unwrap();
+        assert_eq!(masker.partial_suffix_len(b"prompt> "), 0);
+        assert_eq!(masker.partial_suffix_len(b"value: supers"), 6);
+        assert!(Masker::new([("PORT", "3000")]).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,259 @@
+//! What enject inherited from enveil: a `.enveil/` store directory and `ev://`
+//! references in templates. Both keep working as they are. `pending` finds them
+//! and `apply` updates them, keeping a backup of whatever it changes; neither
+//! prompts, so the caller decides whether to ask first.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::config::{self, CONFIG_DIR, LEGACY_CONFIG_DIR};
+use crate::env_template;
+use crate::error::EnjectError;
+
+const LEGACY_PREFIX: &str = "ev://";
+
+/// A legacy artifact in a project, and the change that updates it.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MigrationNeeded {
+    /// The store is in `.enveil/`, here, and there is no `.enject/` beside it.
+    /// Renamed, after copying it to `.enveil.bak/`.
+    LegacyDir(PathBuf),
+    /// A template still using `ev://` references. Rewritten to `en://`, after
+    /// copying it to `<path>.bak`.
+    LegacyRefs {
+        /// The template.
+        path: PathBuf,
+        /// The lines the references are on.
+        lines: Vec<usize>,
+    },
+}
+
+impl MigrationNeeded {
+    /// The directory or template this changes.
+    pub fn path(&self) -> &Path {
+        match self {
+            MigrationNeeded::LegacyDir(dir) => dir,
+            MigrationNeeded::LegacyRefs { path, .. } => path,
+        }
+    }
+}
+
+impl fmt::Display for MigrationNeeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MigrationNeeded::LegacyDir(dir) => write!(
+                f,
+                "rename {} to {} (backup at {})",
+                dir.display(),
+                CONFIG_DIR,
+                dir_backup(dir).display()
+            ),
+            MigrationNeeded::LegacyRefs { path, lines } => write!(
+                f,
+                "update the ev:// reference(s) on line(s) {} of {} to en:// (backup at {})",
+                line_list(lines),
+                path.display(),
+                file_backup(path).display()
+            ),
+        }
+    }
+}
+
+/// The `.enveil/` directory of the project at `project_root`, if its store is
+/// still there.
+pub fn legacy_dir(project_root: &Path) -> Option<MigrationNeeded> {
+    config::uses_legacy_dir(project_root)
+        .then(|| MigrationNeeded::LegacyDir(project_root.join(LEGACY_CONFIG_DIR)))
+}
+
+/// The `ev://` references in `content`, read from the template at `path`, if it
+/// has any. Only whole-value references count: `ev://` in a comment or inside a
+/// longer value is left alone.
+pub fn legacy_refs(path: &Path, content: &str) -> Option<MigrationNeeded> {
+    let lines: Vec<usize> = env_template::parse_all(content)
+        .0
+        .iter()
+        .filter(|parsed| parsed.legacy)
+        .map(|parsed| parsed.line_no)
+        .collect();
+    (!lines.is_empty()).then(|| MigrationNeeded::LegacyRefs {
+        path: path.to_path_buf(),
+        lines,
+    })
+}
+
+/// `lines` as a comma-separated list, for messages.
+pub fn line_list(lines: &[usize]) -> String {
+    let lines: Vec<String> = lines.iter().map(ToString::to_string).collect();
+    lines.join(", ")
+}
+
+/// Everything to migrate in the project at `project_root`: its store directory,
+/// then its `.env` and `.env.*` templates in name order. Backups are skipped.
+pub fn pending(project_root: &Path) -> Result<Vec<MigrationNeeded>, EnjectError> {
+    let mut pending: Vec<MigrationNeeded> = legacy_dir(project_root).into_iter().collect();
+    let mut templates = Vec::new();
+    for entry in std::fs::read_dir(project_root)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let is_template = name == ".env" || name.starts_with(".env.");
+        if is_template && !name.ends_with(".bak") && entry.file_type()?.is_file() {
+            templates.push(entry.path());
+        }
+    }
+    templates.sort();
+    for path in templates {
+        let content = std::fs::read_to_string(&path)?;
+        pending.extend(legacy_refs(&path, &content));
+    }
+    Ok(pending)
+}
+
+/// Make `migration`'s change, keeping a backup. Returns where the backup is.
+pub fn apply(migration: &MigrationNeeded) -> Result<PathBuf, EnjectError> {
+    match migration {
+        MigrationNeeded::LegacyDir(dir) => {
+            let backup = dir_backup(dir);
+            let new_dir = dir.with_file_name(CONFIG_DIR);
+            if new_dir.exists() {
+                return Err(EnjectError::Config(format!(
+                    "{} already exists; move one of the two store directories aside first.",
+                    new_dir.display()
+                )));
+            }
+            copy_dir_all(dir, &backup)?;
+            if let Err(e) = std::fs::rename(dir, &new_dir) {
+                let _ = std::fs::remove_dir_all(&backup);
+                return Err(e.into());
+            }
+            Ok(backup)
+        }
+        MigrationNeeded::LegacyRefs { path, lines } => {
+            let content = std::fs::read_to_string(path)?;
+            let backup = file_backup(path);
+            std::fs::copy(path, &backup)?;
+            env_template::write_atomic(path, &rewrite_lines(&content, lines))?;
+            Ok(backup)
+        }
+    }
+}
+
+/// `content` with the `ev://` that starts the value on each of `lines` made `en://`.
+fn rewrite_lines(content: &str, lines: &[usize]) -> String {
+    content
+        .split_inclusive('\n')
+        .enumerate()
+        .map(|(idx, line)| {
+            let Some(eq) = line.find('=').filter(|_| lines.contains(&(idx + 1))) else {
+                return line.to_string();
+            };
+            let (assignment, value) = line.split_at(eq);
+            format!(
+                "{}{}",
+                assignment,
+                value.replacen(LEGACY_PREFIX, "en://", 1)
+            )
+        })
+        .collect()
+}
+
+fn dir_backup(dir: &Path) -> PathBuf {
+    dir.with_file_name(".enveil.bak")
+}
+
+fn file_backup(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_owned();
+    name.push(".bak");
+    path.with_file_name(name)
+}
+
+fn copy_dir_all(src: &Path, dst: &Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(dst)?;
+    for entry in std::fs::read_dir(src)? {
+        let entry = entry?;
+        let target = dst.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir_all(&entry.path(), &target)?;
+        } else {
+            std::fs::copy(entry.path(), &target)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_pending_finds_legacy_dir_and_templates() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path();
+        assert!(pending(root).unwrap().is_empty());
+
+        std::fs::create_dir(root.join(".enveil")).unwrap();
+        std::fs::write(
+            root.join(".env"),
+            "# was ev://b\nA=ev://a\nB=dev://b\nexport C=\"ev://c\"\n",
+        )
+        .unwrap();
+        std::fs::write(root.join(".env.staging"), "A=en://a\n").unwrap();
+        std::fs::write(root.join(".env.ci"), "A=ev://global/a\n").unwrap();
+        std::fs::write(root.join(".env.bak"), "A=ev://a\n").unwrap();
+        assert_eq!(
+            pending(root).unwrap(),
+            vec![
+                MigrationNeeded::LegacyDir(root.join(".enveil")),
+                MigrationNeeded::LegacyRefs {
+                    path: root.join(".env"),
+                    lines: vec![2, 4],
+                },
+                MigrationNeeded::LegacyRefs {
+                    path: root.join(".env.ci"),
+                    lines: vec![1],
+                },
+            ]
+        );
+
+        // A .enject/ beside it is the one in use
+        std::fs::create_dir(root.join(".enject")).unwrap();
+        assert!(legacy_dir(root).is_none());
+    }
+
+    #[test]
+    fn test_apply_keeps_backups() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path();
+        std::fs::create_dir(root.join(".enveil")).unwrap();
+        std::fs::write(root.join(".enveil").join("config.toml"), "version = 2\n").unwrap();
+        let template = "# was ev://a\nA=ev://a\nB=dev://b\r\nexport C=\"ev://global/c\"\n";
+        std::fs::write(root.join(".env"), template).unwrap();
+
+        for migration in pending(root).unwrap() {
+            apply(&migration).unwrap();
+        }
+        assert!(!root.join(".enveil").exists());
+        assert!(root.join(".enject").join("config.toml").exists());
+        assert!(root.join(".enveil.bak").join("config.toml").exists());
+        assert_eq!(
+            std::fs::read_to_string(root.join(".env")).unwrap(),
+            "# was ev://a\nA=en://a\nB=dev://b\r\nexport C=\"en://global/c\"\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(root.join(".env.bak")).unwrap(),
+            template
+        );
+        assert!(pending(root).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_apply_refuses_to_overwrite_an_enject_dir() {
+        let dir = TempDir::new().unwrap();
+        let legacy = dir.path().join(".enveil");
+        std::fs::create_dir(&legacy).unwrap();
+        std::fs::create_dir(dir.path().join(".enject")).unwrap();
+        assert!(apply(&MigrationNeeded::LegacyDir(legacy.clone())).is_err());
+        assert!(legacy.exists());
+    }
+}
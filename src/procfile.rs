@@ -0,0 +1,81 @@
+//! Parsing Procfiles for `enject run --procfile`.
+
+use anyhow::{bail, Result};
+
+/// One `name: command` line of a Procfile.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Process {
+    pub name: String,
+    /// Run through the platform shell (`runner::shell_command`), so it may use
+    /// pipes, `&&` and variables.
+    pub command: String,
+}
+
+/// Parse Procfile `content`: one `name: command` per line, where the name is made of
+/// letters, digits, `_` and `-`. Blank lines and lines starting with `#` are skipped.
+pub fn parse(content: &str) -> Result<Vec<Process>> {
+    let mut processes: Vec<Process> = Vec::new();
+    for (i, line) in content.lines().enumerate() {
+        let line_no = i + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let Some((name, command)) = line.split_once(':') else {
+            bail!("line {}: expected `name: command`", line_no);
+        };
+        let (name, command) = (name.trim(), command.trim());
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            bail!(
+                "line {}: '{}' is not a valid process name (letters, digits, '_' and '-')",
+                line_no,
+                name
+            );
+        }
+        if command.is_empty() {
+            bail!("line {}: process '{}' has no command", line_no, name);
+        }
+        if processes.iter().any(|p| p.name == name) {
+            bail!("line {}: process '{}' is defined twice", line_no, name);
+        }
+        processes.push(Process {
+            name: name.to_string(),
+            command: command.to_string(),
+        });
+    }
+    if processes.is_empty() {
+        bail!("no processes defined");
+    }
+    Ok(processes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_procfile() {
+        let processes = parse(
+            "# dev processes\nweb: npm run dev -- --port 3000\n\nworker:bundle exec sidekiq\nredis-1: redis-server --port 6380\n",
+        )
+        .unwrap();
+        let names: Vec<&str> = processes.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, vec!["web", "worker", "redis-1"]);
+        assert_eq!(processes[0].command, "npm run dev -- --port 3000");
+        assert_eq!(processes[1].command, "bundle exec sidekiq");
+    }
+
+    #[test]
+    fn test_parse_procfile_errors() {
+        let err = |content: &str| parse(content).unwrap_err().to_string();
+        assert!(err("web npm start\n").contains("line 1"));
+        assert!(err("web app: npm start\n").contains("not a valid process name"));
+        assert!(err("web:\n").contains("has no command"));
+        assert!(err("web: a\nweb: b\n").contains("line 2: process 'web' is defined twice"));
+        assert!(err("# nothing\n").contains("no processes"));
+    }
+}
@@ -1,20 +1,564 @@
 use std::collections::HashMap;
-use std::process::Command;
+use std::ffi::OsString;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
 
-use anyhow::{bail, Result};
+use anyhow::{anyhow, bail, Result};
 
-/// Spawn a subprocess inheriting the parent environment, with `extra_env` layered on top.
-/// Blocks until the subprocess exits, then exits the current process with the same code.
-pub fn exec(cmd: &[String], extra_env: &HashMap<String, String>) -> Result<()> {
+use crate::auth;
+use crate::commands;
+use crate::mask::Masker;
+
+/// Parent variables an isolated command still gets, so it can be found and behaves
+/// sensibly in a terminal.
+pub const ISOLATED_ALLOWLIST: &[&str] = &[
+    "PATH",
+    "HOME",
+    "TERM",
+    "LANG",
+    // Windows can't start most programs without these
+    #[cfg(windows)]
+    "SYSTEMROOT",
+    #[cfg(windows)]
+    "PATHEXT",
+];
+
+/// Which parts of enject's own environment the command inherits.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum Inherit {
+    /// The whole environment, minus enject's password variables.
+    #[default]
+    All,
+    /// Only the named variables (still never the password variables).
+    Only(Vec<String>),
+}
+
+/// How `exec` starts the command.
+#[derive(Debug, Clone, Default)]
+pub struct ExecOptions {
+    /// Replace enject with the command (Unix) instead of running it as a child.
+    /// Ignored when masking, which needs enject to read the command's output.
+    pub replace_process: bool,
+    pub inherit: Inherit,
+    /// Start the command in this directory instead of enject's own.
+    pub current_dir: Option<PathBuf>,
+    /// Pipe the command's stdout and stderr through this instead of letting it
+    /// write to enject's own.
+    pub mask: Option<Arc<Masker>>,
+    /// Write the command's output a line at a time, each starting with this, so it
+    /// can share the terminal with other commands. Ignores `replace_process`.
+    pub prefix: Option<String>,
+    /// Stop the command if it is still running after this long: see `exec`.
+    pub timeout: Option<Duration>,
+    /// How long a timed-out command gets between SIGTERM and SIGKILL.
+    pub kill_after: Duration,
+}
+
+impl ExecOptions {
+    /// Whether enject can replace itself with the command: only when nothing needs
+    /// it to stay around as the parent.
+    fn can_replace(&self) -> bool {
+        self.replace_process
+            && self.mask.is_none()
+            && self.prefix.is_none()
+            && self.timeout.is_none()
+    }
+}
+
+/// The exit code for a command stopped by `ExecOptions::timeout`, as GNU timeout
+/// reports it.
+pub const TIMED_OUT_CODE: i32 = 124;
+
+/// Run a subprocess with `extra_env` layered on top of the environment
+/// `options.inherit` passes through.
+///
+/// With `options.replace_process` on Unix, the current process is replaced by the command
+/// via execvp(3) and this only returns on failure. Otherwise the command is spawned
+/// as a child and this blocks until it exits, returning its status. SIGINT, SIGTERM
+/// and SIGHUP received in the meantime are forwarded to it.
+///
+/// With `options.timeout`, a command still running when it expires gets SIGTERM, and
+/// SIGKILL `options.kill_after` later; the status returned is then `TIMED_OUT_CODE`.
+pub fn exec(
+    cmd: &[String],
+    extra_env: &HashMap<String, String>,
+    options: &ExecOptions,
+) -> Result<ExitStatus> {
+    #[cfg(unix)]
+    if options.can_replace() {
+        use std::os::unix::process::CommandExt;
+        let mut command = prepare_command(cmd, extra_env, options)?;
+        // exec only returns if the program could not be started
+        let e = command.exec();
+        return Err(start_error(&cmd[0], e));
+    }
+
+    // Register before spawning so there is no window in which a signal kills
+    // enject and leaves the subprocess orphaned.
+    #[cfg(unix)]
+    let signals = forward::register()?;
+
+    let running = spawn(cmd, extra_env, options)?;
+
+    #[cfg(unix)]
+    let forwarder = forward::spawn(signals, running.id());
+
+    let status = match options.timeout {
+        Some(timeout) => match running.wait_timeout(timeout)? {
+            Ok(status) => status,
+            Err(running) => {
+                commands::inform_stderr(format_args!(
+                    "The command timed out after {}; stopping it.",
+                    humantime::format_duration(timeout)
+                ));
+                running.terminate(options.kill_after)?;
+                timed_out_status()
+            }
+        },
+        None => running.wait()?,
+    };
+
+    #[cfg(unix)]
+    forwarder.close();
+
+    Ok(status)
+}
+
+/// A command started by `spawn`, with the threads copying its masked output.
+pub struct Running {
+    child: Child,
+    copies: Vec<JoinHandle<io::Result<()>>>,
+}
+
+impl Running {
+    pub fn id(&self) -> u32 {
+        self.child.id()
+    }
+
+    /// The command's status if it has exited, without waiting.
+    pub fn try_wait(&mut self) -> io::Result<Option<ExitStatus>> {
+        self.child.try_wait()
+    }
+
+    /// Wait for the command to exit and its output to be copied.
+    pub fn wait(mut self) -> io::Result<ExitStatus> {
+        let status = self.child.wait();
+        for copy in self.copies {
+            // A closed stdout or stderr on our side only loses output
+            let _ = copy.join();
+        }
+        status
+    }
+
+    /// Like `wait`, but give up after `timeout` and hand the still-running command back.
+    pub fn wait_timeout(mut self, timeout: Duration) -> io::Result<Result<ExitStatus, Self>> {
+        let deadline = Instant::now() + timeout;
+        while self.child.try_wait()?.is_none() {
+            if Instant::now() >= deadline {
+                return Ok(Err(self));
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        self.wait().map(Ok)
+    }
+
+    /// Stop the command: SIGTERM first on Unix, then a kill if it is still running
+    /// after `grace`. Elsewhere it is killed straight away.
+    pub fn terminate(mut self, grace: Duration) -> io::Result<ExitStatus> {
+        if self.child.try_wait()?.is_none() {
+            #[cfg(unix)]
+            {
+                // SAFETY: kill(2) has no memory-safety preconditions, and the child
+                // hasn't been reaped, so its pid is still its own.
+                unsafe {
+                    libc::kill(self.child.id() as libc::pid_t, libc::SIGTERM);
+                }
+                let deadline = Instant::now() + grace;
+                while self.child.try_wait()?.is_none() && Instant::now() < deadline {
+                    std::thread::sleep(Duration::from_millis(50));
+                }
+            }
+            #[cfg(not(unix))]
+            let _ = grace;
+            if self.child.try_wait()?.is_none() {
+                self.child.kill()?;
+            }
+        }
+        self.wait()
+    }
+}
+
+/// Start the command as a child of enject, as `exec` does when it doesn't replace
+/// the process, and return without waiting for it.
+pub fn spawn(
+    cmd: &[String],
+    extra_env: &HashMap<String, String>,
+    options: &ExecOptions,
+) -> Result<Running> {
+    let mut command = prepare_command(cmd, extra_env, options)?;
+    if options.mask.is_some() || options.prefix.is_some() {
+        command.stdout(Stdio::piped()).stderr(Stdio::piped());
+    }
+    let mut child = command.spawn().map_err(|e| start_error(&cmd[0], e))?;
+
+    let mut copies = Vec::new();
+    if let Some(out) = child.stdout.take() {
+        let (mask, prefix) = (options.mask.clone(), options.prefix.clone());
+        copies.push(std::thread::spawn(move || {
+            copy_output(out, io::stdout(), mask.as_deref(), prefix.as_deref())
+        }));
+    }
+    if let Some(err) = child.stderr.take() {
+        let (mask, prefix) = (options.mask.clone(), options.prefix.clone());
+        copies.push(std::thread::spawn(move || {
+            copy_output(err, io::stderr(), mask.as_deref(), prefix.as_deref())
+        }));
+    }
+    Ok(Running { child, copies })
+}
+
+/// Copy a command's output to `writer`, masked and line-prefixed as asked.
+fn copy_output(
+    mut reader: impl Read,
+    mut writer: impl Write,
+    mask: Option<&Masker>,
+    prefix: Option<&str>,
+) -> io::Result<()> {
+    let Some(prefix) = prefix else {
+        return match mask {
+            Some(masker) => masker.copy(reader, writer),
+            None => io::copy(&mut reader, &mut writer).map(drop),
+        };
+    };
+    let mut lines = LinePrefixer::new(prefix, writer);
+    match mask {
+        Some(masker) => masker.copy(reader, &mut lines)?,
+        None => {
+            io::copy(&mut reader, &mut lines)?;
+        }
+    }
+    lines.finish()
+}
+
+/// A writer that puts `prefix` in front of every line, writing whole lines at once
+/// so lines from commands sharing a terminal don't interleave.
+struct LinePrefixer<W: Write> {
+    prefix: String,
+    writer: W,
+    pending: Vec<u8>,
+}
+
+impl<W: Write> LinePrefixer<W> {
+    /// Lines longer than this are written in pieces rather than held back.
+    const MAX_PENDING: usize = 8192;
+
+    fn new(prefix: &str, writer: W) -> Self {
+        Self {
+            prefix: prefix.to_string(),
+            writer,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Write out a last line with no newline at the end.
+    fn finish(mut self) -> io::Result<()> {
+        if !self.pending.is_empty() {
+            self.pending.push(b'\n');
+            let len = self.pending.len();
+            self.write_lines(len)?;
+        }
+        self.writer.flush()
+    }
+
+    /// Write the first `len` pending bytes, prefixing each line.
+    fn write_lines(&mut self, len: usize) -> io::Result<()> {
+        let mut out = Vec::new();
+        for line in self.pending[..len].split_inclusive(|&b| b == b'\n') {
+            out.extend_from_slice(self.prefix.as_bytes());
+            out.extend_from_slice(line);
+        }
+        if !out.ends_with(b"\n") {
+            out.push(b'\n');
+        }
+        self.pending.drain(..len);
+        self.writer.write_all(&out)
+    }
+}
+
+impl<W: Write> Write for LinePrefixer<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        let complete = match self.pending.iter().rposition(|&b| b == b'\n') {
+            Some(last) => last + 1,
+            None if self.pending.len() > Self::MAX_PENDING => self.pending.len(),
+            None => 0,
+        };
+        if complete > 0 {
+            self.write_lines(complete)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Colors for `exec_group` prefixes, as ANSI SGR codes: cyan, yellow, green,
+/// magenta, blue, red.
+const GROUP_COLORS: [u8; 6] = [36, 33, 32, 35, 34, 31];
+
+/// Run several named commands at once, each with `extra_env` and its output lines
+/// prefixed with its name, colored as `--color` says. When one exits, the others
+/// get SIGTERM and are killed if still running after `grace`; the status is that
+/// of the one that exited. SIGINT, SIGTERM and SIGHUP stop them all, and the
+/// status is then the first non-zero one.
+pub fn exec_group(
+    commands: &[(String, Vec<String>)],
+    extra_env: &HashMap<String, String>,
+    options: &ExecOptions,
+    grace: Duration,
+) -> Result<ExitStatus> {
+    let stop = Arc::new(AtomicBool::new(false));
+    #[cfg(unix)]
+    let signals = {
+        let stop = Arc::clone(&stop);
+        catch_signals(move |_| stop.store(true, Ordering::SeqCst))?
+    };
+
+    let color = crate::ui::stdout_colored();
+    let width = commands
+        .iter()
+        .map(|(name, _)| name.len())
+        .max()
+        .unwrap_or(0);
+    let mut running = Vec::new();
+    for (i, (name, cmd)) in commands.iter().enumerate() {
+        let mut prefix = format!("{:<width$} | ", name, width = width);
+        if color {
+            prefix = format!(
+                "\x1b[{}m{}\x1b[0m",
+                GROUP_COLORS[i % GROUP_COLORS.len()],
+                prefix
+            );
+        }
+        let options = ExecOptions {
+            prefix: Some(prefix),
+            ..options.clone()
+        };
+        match spawn(cmd, extra_env, &options) {
+            Ok(child) => running.push((name, child)),
+            Err(e) => {
+                for (_, child) in running {
+                    let _ = child.terminate(grace);
+                }
+                return Err(e.context(format!("Failed to start '{}'", name)));
+            }
+        }
+    }
+
+    let mut exited = None;
+    while exited.is_none() && !stop.load(Ordering::SeqCst) {
+        for (i, (_, child)) in running.iter_mut().enumerate() {
+            if child.try_wait()?.is_some() {
+                exited = Some(i);
+                break;
+            }
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+
+    let first = match exited {
+        Some(i) => {
+            let (name, child) = running.remove(i);
+            let status = child.wait()?;
+            commands::inform_stderr(format_args!(
+                "{} exited ({}); stopping the others.",
+                name, status
+            ));
+            Some(status)
+        }
+        None => None,
+    };
+    let mut statuses = Vec::new();
+    for (_, child) in running {
+        statuses.push(child.terminate(grace)?);
+    }
+
+    #[cfg(unix)]
+    signals.close();
+
+    Ok(first
+        .or_else(|| statuses.iter().copied().find(|status| !status.success()))
+        .unwrap_or_default())
+}
+
+fn prepare_command(
+    cmd: &[String],
+    extra_env: &HashMap<String, String>,
+    options: &ExecOptions,
+) -> Result<Command> {
     if cmd.is_empty() {
         bail!("No command provided.");
     }
+    let mut command = build_command(cmd, extra_env, &options.inherit)?;
+    if let Some(dir) = &options.current_dir {
+        command.current_dir(dir);
+    }
+    Ok(command)
+}
+
+/// Catch the signals `exec` forwards, calling `on_signal` with each one from a
+/// background thread until the returned handle is closed. From here on they no
+/// longer terminate enject.
+#[cfg(unix)]
+pub fn catch_signals(
+    on_signal: impl Fn(i32) + Send + 'static,
+) -> io::Result<signal_hook::iterator::Handle> {
+    let mut signals = forward::register()?;
+    let handle = signals.handle();
+    std::thread::spawn(move || {
+        for signal in signals.forever() {
+            on_signal(signal);
+        }
+    });
+    Ok(handle)
+}
+
+/// `line` run by the user's shell: `$SHELL -c`, or `/bin/sh -c` when SHELL is unset
+/// or empty. On Windows, `%COMSPEC% /C`, or `cmd.exe /C`.
+pub fn user_shell_command(line: &str) -> Vec<String> {
+    let var = if cfg!(windows) { "COMSPEC" } else { "SHELL" };
+    user_shell_command_from(std::env::var(var).ok(), line)
+}
+
+fn user_shell_command_from(shell: Option<String>, line: &str) -> Vec<String> {
+    let (fallback, flag) = if cfg!(windows) {
+        ("cmd.exe", "/C")
+    } else {
+        ("/bin/sh", "-c")
+    };
+    let shell = shell
+        .filter(|shell| !shell.trim().is_empty())
+        .unwrap_or_else(|| fallback.to_string());
+    vec![shell, flag.to_string(), line.to_string()]
+}
+
+/// An exit status with code `TIMED_OUT_CODE`.
+fn timed_out_status() -> ExitStatus {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        // A wait status holds the exit code in its second byte
+        ExitStatus::from_raw(TIMED_OUT_CODE << 8)
+    }
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::ExitStatusExt;
+        ExitStatus::from_raw(TIMED_OUT_CODE as u32)
+    }
+}
 
-    let (program, args) = cmd.split_first().unwrap();
+/// `line` as a command run by the platform shell: `sh -c` (`cmd /C` on Windows).
+pub fn shell_command(line: &str) -> Vec<String> {
+    let shell = if cfg!(windows) {
+        ["cmd", "/C"]
+    } else {
+        ["sh", "-c"]
+    };
+    vec![shell[0].to_string(), shell[1].to_string(), line.to_string()]
+}
 
-    let mut command = Command::new(program);
+/// A readable error for a command that could not be started.
+fn start_error(program: &str, e: io::Error) -> anyhow::Error {
+    if e.kind() == io::ErrorKind::NotFound {
+        anyhow!("Command not found: {}", program)
+    } else {
+        anyhow!(e).context(format!("Failed to start '{}'", program))
+    }
+}
+
+/// The exit code enject reports for the subprocess. A subprocess killed by a
+/// signal maps to `128 + signal`, as a shell would report it.
+pub fn exit_code(status: ExitStatus) -> i32 {
+    if let Some(code) = status.code() {
+        return code;
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        if let Some(signal) = status.signal() {
+            return 128 + signal;
+        }
+    }
+    1
+}
+
+#[cfg(unix)]
+mod forward {
+    use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM};
+    use signal_hook::iterator::{Handle, Signals};
+
+    /// Termination signals enject relays to the subprocess instead of dying on.
+    const FORWARDED: [i32; 3] = [SIGINT, SIGTERM, SIGHUP];
+
+    /// Catch the forwarded signals. From here on they no longer terminate enject.
+    pub fn register() -> std::io::Result<Signals> {
+        Signals::new(FORWARDED)
+    }
+
+    /// Relay every caught signal to `pid` from a background thread until the
+    /// returned handle is closed.
+    pub fn spawn(mut signals: Signals, pid: u32) -> Handle {
+        let handle = signals.handle();
+        std::thread::spawn(move || {
+            for signal in signals.forever() {
+                // SAFETY: kill(2) has no memory-safety preconditions.
+                unsafe {
+                    libc::kill(pid as libc::pid_t, signal);
+                }
+            }
+        });
+        handle
+    }
+}
+
+/// Construct the subprocess: the parent environment `inherit` lets through, minus
+/// enject's own password variables, with `extra_env` layered on top.
+fn build_command(
+    cmd: &[String],
+    extra_env: &HashMap<String, String>,
+    inherit: &Inherit,
+) -> Result<Command> {
+    let (program, args) = cmd
+        .split_first()
+        .ok_or_else(|| anyhow::anyhow!("No command provided."))?;
+
+    let mut command = Command::new(resolve_program(program));
     command.args(args);
 
+    if let Inherit::Only(keep) = inherit {
+        command.env_clear();
+        let keep = keep
+            .iter()
+            .filter(|var| !auth::PASSWORD_ENV_VARS.contains(&var.as_str()));
+        for var in keep {
+            if let Some(value) = std::env::var_os(var) {
+                command.env(var, value);
+            }
+        }
+    }
+
+    // The store passwords must never leak into the subprocess.
+    for var in auth::PASSWORD_ENV_VARS {
+        command.env_remove(var);
+    }
+
     // Layer .env resolved values on top of the inherited parent environment.
     // std::process::Command inherits the full parent env by default; we just
     // add/override with the resolved secrets.
@@ -22,34 +566,75 @@ pub fn exec(cmd: &[String], extra_env: &HashMap<String, String>) -> Result<()> {
         command.env(key, value);
     }
 
-    let status = command.status()?;
+    Ok(command)
+}
+
+/// Windows only appends `.exe` when looking up a bare program name, so `npm`
+/// (really `npm.cmd`) would not be found. Resolve it against PATH and PATHEXT the
+/// way cmd.exe does; std then runs `.cmd`/`.bat` files through `cmd /C` itself.
+#[cfg(windows)]
+fn resolve_program(program: &str) -> OsString {
+    let pathext = std::env::var("PATHEXT").unwrap_or_else(|_| ".COM;.EXE;.BAT;.CMD".into());
+    let dirs = std::env::var_os("PATH").unwrap_or_default();
+    find_with_pathext(program, std::env::split_paths(&dirs), &pathext)
+        .map(OsString::from)
+        .unwrap_or_else(|| program.into())
+}
+
+#[cfg(not(windows))]
+fn resolve_program(program: &str) -> OsString {
+    program.into()
+}
 
-    let code = status.code().unwrap_or(1);
-    std::process::exit(code);
+/// The first `dir/program.EXT` that exists, trying each PATH directory in order and
+/// each PATHEXT extension within it. `None` for names that already carry one of the
+/// extensions or contain a path separator: those are run as given.
+#[cfg_attr(not(windows), allow(dead_code))]
+fn find_with_pathext(
+    program: &str,
+    dirs: impl IntoIterator<Item = PathBuf>,
+    pathext: &str,
+) -> Option<PathBuf> {
+    let exts: Vec<&str> = pathext.split(';').filter(|e| !e.is_empty()).collect();
+    let has_ext = Path::new(program)
+        .extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| {
+            exts.iter()
+                .any(|x| x.trim_start_matches('.').eq_ignore_ascii_case(e))
+        });
+    if has_ext || program.contains(['/', '\\']) {
+        return None;
+    }
+    dirs.into_iter().find_map(|dir| {
+        exts.iter()
+            .map(|ext| dir.join(format!("{}{}", program, ext.to_ascii_lowercase())))
+            .find(|candidate| candidate.is_file())
+    })
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
     use std::collections::HashMap;
 
-    /// Helper: run a subprocess and capture its stdout.
-    /// Does NOT call std::process::exit — used only for inspection in tests.
-    fn run_capture(cmd: &[&str], extra_env: &HashMap<String, String>) -> (i32, String) {
-        let program = cmd[0];
-        let args = &cmd[1..];
-
-        let mut command = std::process::Command::new(program);
-        command.args(args);
-        for (k, v) in extra_env {
-            command.env(k, v);
-        }
-
-        let output = command.output().expect("Failed to run command");
-        let stdout = String::from_utf8_lossy(&output.stdout).to_string();
-        let code = output.status.code().unwrap_or(1);
-        (code, stdout)
+    /// Helper: run `script` in the platform shell (`sh -c` or `cmd /C`) through
+    /// `exec` as a child and return its exit code.
+    fn run_sh(script: &str, extra_env: &HashMap<String, String>) -> i32 {
+        let shell = if cfg!(windows) {
+            ["cmd", "/C"]
+        } else {
+            ["sh", "-c"]
+        };
+        let cmd = vec![
+            shell[0].to_string(),
+            shell[1].to_string(),
+            script.to_string(),
+        ];
+        exit_code(exec(&cmd, extra_env, &ExecOptions::default()).unwrap())
     }
 
+    #[cfg(unix)]
     #[test]
     fn test_subprocess_receives_injected_env_var() {
         let mut extra = HashMap::new();
@@ -58,27 +643,43 @@ mod tests {
             "hello-from-enject".to_string(),
         );
 
-        let (code, stdout) = run_capture(&["sh", "-c", "echo $ENVEIL_TEST_VAR"], &extra);
+        let code = run_sh(r#"test "$ENVEIL_TEST_VAR" = hello-from-enject"#, &extra);
+        assert_eq!(code, 0);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_multiline_value_reaches_subprocess_intact() {
+        let mut extra = HashMap::new();
+        extra.insert(
+            "ENVEIL_TEST_PEM".to_string(),
+            "-----BEGIN KEY-----\nabc\n-----END KEY-----".to_string(),
+        );
+        let code = run_sh(
+            r#"test "$ENVEIL_TEST_PEM" = "$(printf -- '-----BEGIN KEY-----\nabc\n-----END KEY-----')""#,
+            &extra,
+        );
         assert_eq!(code, 0);
-        assert!(stdout.trim() == "hello-from-enject");
     }
 
+    #[cfg(unix)]
     #[test]
     fn test_subprocess_inherits_path() {
         // PATH must be inherited so basic commands work
         let extra = HashMap::new();
-        let (code, _) = run_capture(&["sh", "-c", "which sh"], &extra);
-        assert_eq!(code, 0);
+        assert_eq!(run_sh("which sh", &extra), 0);
     }
 
+    #[cfg(unix)]
     #[test]
     fn test_subprocess_env_var_not_set_without_injection() {
         let extra = HashMap::new();
         // ENVEIL_TEST_UNSET is not in parent env and not injected
-        let (_, stdout) = run_capture(&["sh", "-c", "echo ${ENVEIL_TEST_UNSET:-MISSING}"], &extra);
-        assert_eq!(stdout.trim(), "MISSING");
+        let code = run_sh(r#"test "${ENVEIL_TEST_UNSET:-MISSING}" = MISSING"#, &extra);
+        assert_eq!(code, 0);
     }
 
+    #[cfg(unix)]
     #[test]
     fn test_injected_var_overrides_parent() {
         // Set a var in the test process env, then override it via extra_env
@@ -86,8 +687,249 @@ mod tests {
         let mut extra = HashMap::new();
         extra.insert("ENVEIL_OVERRIDE_TEST".to_string(), "overridden".to_string());
 
-        let (code, stdout) = run_capture(&["sh", "-c", "echo $ENVEIL_OVERRIDE_TEST"], &extra);
+        let code = run_sh(r#"test "$ENVEIL_OVERRIDE_TEST" = overridden"#, &extra);
+        assert_eq!(code, 0);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_windows_subprocess_receives_injected_env_var() {
+        let mut extra = HashMap::new();
+        extra.insert(
+            "ENVEIL_TEST_VAR".to_string(),
+            "hello-from-enject".to_string(),
+        );
+        let code = run_sh(
+            "if %ENVEIL_TEST_VAR%==hello-from-enject (exit 0) else (exit 1)",
+            &extra,
+        );
+        assert_eq!(code, 0);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_windows_injected_var_overrides_parent() {
+        std::env::set_var("ENVEIL_OVERRIDE_TEST", "original");
+        let mut extra = HashMap::new();
+        extra.insert("ENVEIL_OVERRIDE_TEST".to_string(), "overridden".to_string());
+        let code = run_sh(
+            "if %ENVEIL_OVERRIDE_TEST%==overridden (exit 0) else (exit 1)",
+            &extra,
+        );
         assert_eq!(code, 0);
-        assert_eq!(stdout.trim(), "overridden");
+    }
+
+    #[test]
+    fn test_pathext_finds_cmd_shim_in_path_order() {
+        let first = tempfile::TempDir::new().unwrap();
+        let second = tempfile::TempDir::new().unwrap();
+        std::fs::write(second.path().join("npm.cmd"), "").unwrap();
+        std::fs::write(second.path().join("npm.exe"), "").unwrap();
+        let dirs = vec![first.path().to_path_buf(), second.path().to_path_buf()];
+
+        let found = find_with_pathext("npm", dirs.clone(), ".COM;.EXE;.BAT;.CMD");
+        assert_eq!(found, Some(second.path().join("npm.exe")));
+        assert_eq!(find_with_pathext("yarn", dirs.clone(), ".EXE;.CMD"), None);
+        // Already has an extension, or is a path: run as given
+        assert_eq!(
+            find_with_pathext("npm.cmd", dirs.clone(), ".EXE;.CMD"),
+            None
+        );
+        assert_eq!(find_with_pathext("./npm", dirs, ".EXE;.CMD"), None);
+    }
+
+    #[test]
+    fn test_exit_code_passes_through_normal_exit() {
+        assert_eq!(run_sh("exit 7", &HashMap::new()), 7);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_exit_code_for_signal_is_128_plus_signal() {
+        assert_eq!(
+            run_sh("kill -TERM $$", &HashMap::new()),
+            128 + libc::SIGTERM
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_isolated_subprocess_gets_only_the_allowlist() {
+        std::env::set_var("ENJECT_ISOLATION_TEST", "from-parent");
+        std::env::set_var("ENJECT_ISOLATION_KEEP", "kept");
+        let cmd = |script: &str| vec!["sh".to_string(), "-c".to_string(), script.to_string()];
+        let mut extra = HashMap::new();
+        extra.insert("INJECTED".to_string(), "yes".to_string());
+        let mut keep: Vec<String> = ISOLATED_ALLOWLIST.iter().map(|s| s.to_string()).collect();
+        let isolated = ExecOptions {
+            inherit: Inherit::Only(keep.clone()),
+            ..Default::default()
+        };
+
+        // Inherited by default, absent when isolated
+        let script = r#"test "$ENJECT_ISOLATION_TEST" = from-parent"#;
+        let status = exec(&cmd(script), &extra, &ExecOptions::default()).unwrap();
+        assert_eq!(exit_code(status), 0);
+        let script = r#"test -z "$ENJECT_ISOLATION_TEST" && test "$INJECTED" = yes"#;
+        assert_eq!(exit_code(exec(&cmd(script), &extra, &isolated).unwrap()), 0);
+
+        // PATH survives, so programs are still found by name
+        let script = r#"test -n "$PATH" && command -v sh"#;
+        assert_eq!(exit_code(exec(&cmd(script), &extra, &isolated).unwrap()), 0);
+
+        keep.push("ENJECT_ISOLATION_KEEP".to_string());
+        let kept = ExecOptions {
+            inherit: Inherit::Only(keep),
+            ..Default::default()
+        };
+        let script = r#"test "$ENJECT_ISOLATION_KEEP" = kept"#;
+        assert_eq!(exit_code(exec(&cmd(script), &extra, &kept).unwrap()), 0);
+    }
+
+    #[test]
+    fn test_isolated_never_keeps_password_env_vars() {
+        let cmd = vec!["true".to_string()];
+        let keep = auth::PASSWORD_ENV_VARS
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let command = build_command(&cmd, &HashMap::new(), &Inherit::Only(keep)).unwrap();
+        let envs: HashMap<_, _> = command.get_envs().collect();
+        for var in auth::PASSWORD_ENV_VARS {
+            assert!(!matches!(
+                envs.get(std::ffi::OsStr::new(var)),
+                Some(Some(_))
+            ));
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_masked_subprocess_keeps_its_exit_code() {
+        let cmd = vec![
+            "sh".to_string(),
+            "-c".to_string(),
+            "echo $SECRET; exit 4".to_string(),
+        ];
+        let mut extra = HashMap::new();
+        extra.insert("SECRET".to_string(), "hunter2hunter2".to_string());
+        let options = ExecOptions {
+            mask: Some(Arc::new(
+                Masker::new([("SECRET", "hunter2hunter2")]).unwrap(),
+            )),
+            replace_process: true,
+            ..Default::default()
+        };
+        assert_eq!(exit_code(exec(&cmd, &extra, &options).unwrap()), 4);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_terminate_sends_sigterm_then_kills() {
+        let sh = |script: &str| vec!["sh".to_string(), "-c".to_string(), script.to_string()];
+        let options = ExecOptions::default();
+
+        let running = spawn(&sh("exec sleep 30"), &HashMap::new(), &options).unwrap();
+        let status = running.terminate(Duration::from_secs(5)).unwrap();
+        assert_eq!(exit_code(status), 128 + libc::SIGTERM);
+
+        let script = "trap '' TERM; while :; do sleep 0.1; done";
+        let mut running = spawn(&sh(script), &HashMap::new(), &options).unwrap();
+        // Wait for the trap to be set
+        std::thread::sleep(Duration::from_millis(300));
+        assert!(running.try_wait().unwrap().is_none());
+        let started = Instant::now();
+        let status = running.terminate(Duration::from_millis(200)).unwrap();
+        assert_eq!(exit_code(status), 128 + libc::SIGKILL);
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_line_prefixer_writes_whole_prefixed_lines() {
+        let mut out = Vec::new();
+        let mut lines = LinePrefixer::new("web | ", &mut out);
+        lines.write_all(b"one\ntw").unwrap();
+        lines.write_all(b"o\nthree").unwrap();
+        lines.finish().unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "web | one\nweb | two\nweb | three\n"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_timeout_stops_the_command_with_code_124() {
+        let cmd = vec!["sleep".to_string(), "30".to_string()];
+        let options = ExecOptions {
+            timeout: Some(Duration::from_millis(200)),
+            kill_after: Duration::from_secs(5),
+            replace_process: true,
+            ..Default::default()
+        };
+        let started = Instant::now();
+        let status = exec(&cmd, &HashMap::new(), &options).unwrap();
+        assert_eq!(exit_code(status), TIMED_OUT_CODE);
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        // A command that finishes in time keeps its own status
+        let cmd = vec!["sh".to_string(), "-c".to_string(), "exit 5".to_string()];
+        let options = ExecOptions {
+            timeout: Some(Duration::from_secs(30)),
+            ..Default::default()
+        };
+        assert_eq!(exit_code(exec(&cmd, &HashMap::new(), &options).unwrap()), 5);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_user_shell_falls_back_to_bin_sh() {
+        let line = "echo a && echo b";
+        assert_eq!(
+            user_shell_command_from(Some("/usr/bin/zsh".into()), line),
+            vec!["/usr/bin/zsh", "-c", line]
+        );
+        for unset in [None, Some(String::new()), Some(" ".into())] {
+            assert_eq!(
+                user_shell_command_from(unset, line),
+                vec!["/bin/sh", "-c", line]
+            );
+        }
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_user_shell_falls_back_to_cmd() {
+        assert_eq!(
+            user_shell_command_from(None, "echo a"),
+            vec!["cmd.exe", "/C", "echo a"]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_subprocess_runs_in_current_dir() {
+        let dir = tempfile::TempDir::new().unwrap();
+        std::fs::write(dir.path().join("marker"), "").unwrap();
+        let cmd = vec!["test".to_string(), "-f".to_string(), "marker".to_string()];
+        let options = ExecOptions {
+            current_dir: Some(dir.path().to_path_buf()),
+            ..Default::default()
+        };
+        assert_eq!(exit_code(exec(&cmd, &HashMap::new(), &options).unwrap()), 0);
+    }
+
+    #[test]
+    fn test_password_env_vars_removed_from_subprocess() {
+        let cmd = vec!["true".to_string()];
+        let command = build_command(&cmd, &HashMap::new(), &Inherit::All).unwrap();
+        let envs: HashMap<_, _> = command.get_envs().collect();
+        for var in auth::PASSWORD_ENV_VARS {
+            assert_eq!(envs.get(std::ffi::OsStr::new(var)), Some(&None));
+        }
+        assert_eq!(
+            envs.get(std::ffi::OsStr::new("ENJECT_MEMBER_PASSWORD")),
+            Some(&None)
+        );
     }
 }
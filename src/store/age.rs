@@ -0,0 +1,480 @@
+//! Secrets encrypted to age or SSH public keys instead of a master password. Anyone
+//! holding the private key of one of the store's recipients can unlock it.
+
+use std::fs::File;
+use std::io::{BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+
+use age::secrecy::ExposeSecret as _;
+use secrecy::{ExposeSecret, SecretString};
+use zeroize::Zeroizing;
+
+use crate::error::EnjectError;
+use crate::store::entries::{Entries, DEFAULT_HISTORY_DEPTH};
+use crate::store::file::{self, FileStamp};
+use crate::store::password::PasswordStore;
+use crate::store::{Annotation, Result, SecretMetadata, SecretVersion, Store};
+
+/// A `Store` kept in one age-encrypted file, holding the same JSON a password store
+/// encrypts. Every save encrypts to all of `recipients`.
+pub struct AgeStore {
+    store_path: PathBuf,
+    recipients: Vec<String>,
+    identity_paths: Vec<PathBuf>,
+    /// Pass over identity files that can't be read or parsed instead of failing.
+    skip_unusable_identities: bool,
+    /// Identities held in memory rather than read from a file, such as a member's
+    /// key unwrapped with their password.
+    extra_identities: Vec<age::x25519::Identity>,
+    /// Decrypted secrets, populated after `unlock()`.
+    secrets: Option<Entries>,
+    history_depth: usize,
+    /// Advisory lock on `store.lock`, held from `unlock` until the store is dropped.
+    lock: Option<File>,
+    read_only: bool,
+    /// The store file as `unlock` found it (or as the last save left it).
+    on_disk: Option<FileStamp>,
+    /// Save even if the file changed on disk since it was unlocked.
+    overwrite_changes: bool,
+}
+
+impl AgeStore {
+    /// A store at `store_path` encrypted to `recipients` (age `age1...` or SSH public
+    /// keys) and opened with the identities in `identity_paths`.
+    pub fn new(store_path: PathBuf, recipients: Vec<String>, identity_paths: Vec<PathBuf>) -> Self {
+        Self {
+            store_path,
+            recipients,
+            identity_paths,
+            skip_unusable_identities: false,
+            extra_identities: Vec::new(),
+            secrets: None,
+            history_depth: DEFAULT_HISTORY_DEPTH,
+            lock: None,
+            read_only: false,
+            on_disk: None,
+            overwrite_changes: false,
+        }
+    }
+
+    /// Keep `depth` previous values per secret (0 keeps none).
+    pub fn with_history_depth(mut self, depth: usize) -> Self {
+        self.history_depth = depth;
+        self
+    }
+
+    /// Pass over identity files that can't be read or parsed, for identities found by
+    /// searching the usual places rather than named by the user.
+    pub fn skipping_unusable_identities(mut self) -> Self {
+        self.skip_unusable_identities = true;
+        self
+    }
+
+    /// Also try the age secret key `identity` (`AGE-SECRET-KEY-1...`).
+    pub fn with_identity(mut self, identity: &SecretString) -> Result<Self> {
+        let parsed = identity
+            .expose_secret()
+            .parse::<age::x25519::Identity>()
+            .map_err(|_| EnjectError::Age("not an age secret key.".into()))?;
+        self.extra_identities.push(parsed);
+        Ok(self)
+    }
+
+    /// Lock and decrypt the store file with the first identity that matches one of
+    /// its recipients. A missing file is an empty store. With `read_only`, only
+    /// writers are kept out and `persist` refuses to write.
+    pub fn unlock(&mut self, read_only: bool) -> Result<()> {
+        self.lock = Some(file::lock(
+            &self.store_path,
+            !read_only,
+            file::DEFAULT_LOCK_TIMEOUT,
+        )?);
+        self.read_only = read_only;
+        let loaded = commit_conversion(&self.store_path)
+            .and_then(|()| self.load())
+            .and_then(|()| FileStamp::read(&self.store_path));
+        match loaded {
+            Ok(stamp) => {
+                self.on_disk = Some(stamp);
+                Ok(())
+            }
+            Err(e) => {
+                self.lock = None;
+                self.read_only = false;
+                Err(e)
+            }
+        }
+    }
+
+    fn load(&mut self) -> Result<()> {
+        let ciphertext = match std::fs::read(&self.store_path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                self.secrets = Some(Entries::default());
+                return Ok(());
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let mut identities: Vec<Box<dyn age::Identity>> = self
+            .extra_identities
+            .iter()
+            .map(|i| Box::new(i.clone()) as Box<dyn age::Identity>)
+            .collect();
+        for path in &self.identity_paths {
+            match load_identities(path) {
+                Ok(found) => identities.extend(found),
+                Err(_) if self.skip_unusable_identities => {}
+                Err(e) => return Err(e),
+            }
+        }
+        let plaintext = decrypt(&ciphertext, &identities)?;
+        let secrets = serde_json::from_slice(&plaintext)
+            .map_err(|e| EnjectError::CorruptStore(e.to_string()))?;
+        self.secrets = Some(secrets);
+        Ok(())
+    }
+
+    /// Encrypt to `recipients` from the next save on.
+    pub fn set_recipients(&mut self, recipients: Vec<String>) -> Result<()> {
+        for recipient in &recipients {
+            parse_recipient(recipient)?;
+        }
+        self.recipients = recipients;
+        Ok(())
+    }
+
+    /// Create a new empty store file encrypted to `recipients`. No identity is needed
+    /// to write one.
+    pub fn create_empty(store_path: &Path, recipients: Vec<String>) -> Result<Self> {
+        let mut store = Self::new(store_path.to_path_buf(), recipients, Vec::new());
+        store.secrets = Some(Entries::default());
+        store.persist()?;
+        Ok(store)
+    }
+
+    /// Write the secrets of an unlocked password store, history and all, encrypted
+    /// to `recipients` beside it. The caller switches the config to the age backend,
+    /// then `unlock` moves the converted store into place, so a crash in between
+    /// leaves whichever store the config names.
+    pub fn convert(from: &mut PasswordStore, recipients: Vec<String>) -> Result<()> {
+        let (store_path, secrets) = from.take_secrets()?;
+        let mut store = Self::new(converted_path(&store_path), recipients, Vec::new());
+        store.secrets = Some(secrets);
+        store.persist()
+    }
+
+    fn secrets_mut(&mut self) -> Result<&mut Entries> {
+        self.secrets
+            .as_mut()
+            .ok_or_else(|| EnjectError::CorruptStore("Store not unlocked.".into()))
+    }
+
+    fn secrets_ref(&self) -> Result<&Entries> {
+        self.secrets
+            .as_ref()
+            .ok_or_else(|| EnjectError::CorruptStore("Store not unlocked.".into()))
+    }
+}
+
+fn converted_path(store_path: &Path) -> PathBuf {
+    store_path.with_extension("converted")
+}
+
+/// Move a store written by `AgeStore::convert` into place, once the config names
+/// the age backend. `unlock` does this too, in case a conversion was interrupted.
+pub fn commit_conversion(store_path: &Path) -> Result<()> {
+    let converted = converted_path(store_path);
+    if converted.exists() {
+        std::fs::rename(&converted, store_path)?;
+    }
+    Ok(())
+}
+
+/// A new age key pair: the secret key (`AGE-SECRET-KEY-1...`) and its recipient.
+pub fn generate_identity() -> (SecretString, String) {
+    let identity = age::x25519::Identity::generate();
+    let secret = SecretString::new(identity.to_string().expose_secret().to_string());
+    (secret, identity.to_public().to_string())
+}
+
+/// Check that `recipient` is an age (`age1...`) or SSH public key.
+pub fn check_recipient(recipient: &str) -> Result<()> {
+    parse_recipient(recipient).map(|_| ())
+}
+
+fn parse_recipient(recipient: &str) -> Result<Box<dyn age::Recipient + Send>> {
+    if let Ok(parsed) = recipient.parse::<age::x25519::Recipient>() {
+        return Ok(Box::new(parsed));
+    }
+    match recipient.parse::<age::ssh::Recipient>() {
+        Ok(parsed) => Ok(Box::new(parsed)),
+        Err(_) => Err(EnjectError::Age(format!(
+            "'{}' is not an age (age1...) or SSH public key.",
+            recipient
+        ))),
+    }
+}
+
+/// The identities in `path`: an age identity file or an unencrypted SSH private key.
+fn load_identities(path: &Path) -> Result<Vec<Box<dyn age::Identity>>> {
+    let name = path.display().to_string();
+    let unreadable =
+        |e: std::io::Error| EnjectError::Age(format!("cannot read identity file {}: {}", name, e));
+    let contents = Zeroizing::new(std::fs::read(path).map_err(unreadable)?);
+    if contents.starts_with(b"-----BEGIN") {
+        let identity =
+            age::ssh::Identity::from_buffer(BufReader::new(&contents[..]), Some(name.clone()))
+                .map_err(unreadable)?;
+        return Ok(vec![Box::new(identity)]);
+    }
+    age::IdentityFile::from_buffer(BufReader::new(&contents[..]))
+        .map_err(unreadable)?
+        .into_identities()
+        .map_err(|e| EnjectError::Age(e.to_string()))
+}
+
+fn encrypt(plaintext: &[u8], recipients: &[String]) -> Result<Vec<u8>> {
+    let parsed = recipients
+        .iter()
+        .map(|r| parse_recipient(r))
+        .collect::<Result<Vec<_>>>()?;
+    let encryptor =
+        age::Encryptor::with_recipients(parsed.iter().map(|r| r.as_ref() as &dyn age::Recipient))
+            .map_err(|e| EnjectError::Age(e.to_string()))?;
+    let mut ciphertext = Vec::new();
+    let mut writer = encryptor.wrap_output(&mut ciphertext)?;
+    writer.write_all(plaintext)?;
+    writer.finish()?;
+    Ok(ciphertext)
+}
+
+fn decrypt(ciphertext: &[u8], identities: &[Box<dyn age::Identity>]) -> Result<Zeroizing<Vec<u8>>> {
+    if identities.is_empty() {
+        return Err(EnjectError::NoMatchingIdentity);
+    }
+    let decryptor =
+        age::Decryptor::new(ciphertext).map_err(|e| EnjectError::CorruptStore(e.to_string()))?;
+    let mut reader = decryptor
+        .decrypt(identities.iter().map(|i| i.as_ref()))
+        .map_err(|e| match e {
+            age::DecryptError::NoMatchingKeys => EnjectError::NoMatchingIdentity,
+            e => EnjectError::Age(e.to_string()),
+        })?;
+    let mut plaintext = Zeroizing::new(Vec::new());
+    reader
+        .read_to_end(&mut plaintext)
+        .map_err(|e| EnjectError::CorruptStore(e.to_string()))?;
+    Ok(plaintext)
+}
+
+impl Store for AgeStore {
+    fn get(&self, key: &str) -> Result<Option<SecretString>> {
+        Ok(self.secrets_ref()?.get(key))
+    }
+
+    fn set(&mut self, key: &str, value: SecretString) -> Result<()> {
+        let depth = self.history_depth;
+        self.secrets_mut()?.set(key, value, depth);
+        Ok(())
+    }
+
+    fn delete(&mut self, key: &str) -> Result<bool> {
+        Ok(self.secrets_mut()?.delete(key))
+    }
+
+    fn list(&self) -> Result<Vec<String>> {
+        Ok(self.secrets_ref()?.list())
+    }
+
+    fn metadata(&self, key: &str) -> Result<Option<SecretMetadata>> {
+        Ok(self.secrets_ref()?.metadata(key))
+    }
+
+    fn annotate(&mut self, key: &str, annotation: Annotation) -> Result<bool> {
+        Ok(self.secrets_mut()?.annotate(key, annotation))
+    }
+
+    fn history(&self, key: &str) -> Result<Vec<SecretVersion>> {
+        Ok(self.secrets_ref()?.history(key))
+    }
+
+    fn restore_version(&mut self, key: &str, number: usize) -> Result<bool> {
+        let depth = self.history_depth;
+        Ok(self.secrets_mut()?.restore_version(key, number, depth))
+    }
+
+    fn delete_keeping_history(&mut self, key: &str) -> Result<bool> {
+        let depth = self.history_depth;
+        Ok(self.secrets_mut()?.delete_keeping_history(key, depth))
+    }
+
+    /// Encrypt to every recipient and replace the store file atomically. Fails if
+    /// the file changed on disk since `unlock`.
+    fn persist(&mut self) -> Result<()> {
+        if self.read_only {
+            return Err(EnjectError::Config(
+                "Store was unlocked read-only and cannot be saved.".into(),
+            ));
+        }
+        if let Some(stamp) = &self.on_disk {
+            if !self.overwrite_changes && FileStamp::read(&self.store_path)? != *stamp {
+                return Err(EnjectError::StoreChangedOnDisk);
+            }
+        }
+        let json = Zeroizing::new(
+            serde_json::to_vec(self.secrets_ref()?)
+                .map_err(|e| EnjectError::Serialization(e.to_string()))?,
+        );
+        let ciphertext = encrypt(&json, &self.recipients)?;
+        file::write_atomic(&self.store_path, &[&ciphertext])?;
+        if self.on_disk.is_some() {
+            self.on_disk = Some(FileStamp::read(&self.store_path)?);
+        }
+        Ok(())
+    }
+
+    fn overwrite_changes(&mut self) {
+        self.overwrite_changes = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    /// A fresh age identity saved in `dir`, and its recipient.
+    fn identity(dir: &TempDir, name: &str) -> (PathBuf, String) {
+        let identity = age::x25519::Identity::generate();
+        let path = dir.path().join(name);
+        std::fs::write(&path, identity.to_string().expose_secret()).unwrap();
+        (path, identity.to_public().to_string())
+    }
+
+    fn secret(value: &str) -> SecretString {
+        SecretString::new(value.to_string())
+    }
+
+    #[test]
+    fn test_save_and_reopen() {
+        let dir = TempDir::new().unwrap();
+        let (key, recipient) = identity(&dir, "key.txt");
+        let path = dir.path().join("store");
+
+        let mut store = AgeStore::create_empty(&path, vec![recipient.clone()]).unwrap();
+        store.set("API_KEY", secret("abc")).unwrap();
+        store.persist().unwrap();
+        drop(store);
+
+        let mut store = AgeStore::new(path, vec![recipient], vec![key]);
+        store.unlock(true).unwrap();
+        assert_eq!(
+            store.get("API_KEY").unwrap().unwrap().expose_secret(),
+            "abc"
+        );
+    }
+
+    #[test]
+    fn test_any_recipient_can_unlock() {
+        let dir = TempDir::new().unwrap();
+        let (_, alice) = identity(&dir, "alice.txt");
+        let (bob_key, bob) = identity(&dir, "bob.txt");
+        let path = dir.path().join("store");
+
+        let mut store = AgeStore::create_empty(&path, vec![alice, bob.clone()]).unwrap();
+        store.set("k", secret("v")).unwrap();
+        store.persist().unwrap();
+        drop(store);
+
+        let mut store = AgeStore::new(path, vec![bob], vec![bob_key]);
+        store.unlock(false).unwrap();
+        assert_eq!(store.get("k").unwrap().unwrap().expose_secret(), "v");
+    }
+
+    #[test]
+    fn test_removed_recipient_cannot_unlock() {
+        let dir = TempDir::new().unwrap();
+        let (alice_key, alice) = identity(&dir, "alice.txt");
+        let (bob_key, bob) = identity(&dir, "bob.txt");
+        let path = dir.path().join("store");
+        AgeStore::create_empty(&path, vec![alice.clone(), bob]).unwrap();
+
+        let mut store = AgeStore::new(path.clone(), vec![], vec![alice_key.clone()]);
+        store.unlock(false).unwrap();
+        store.set_recipients(vec![alice.clone()]).unwrap();
+        store.persist().unwrap();
+        drop(store);
+
+        let mut store = AgeStore::new(path.clone(), vec![alice.clone()], vec![bob_key]);
+        assert!(matches!(
+            store.unlock(true),
+            Err(EnjectError::NoMatchingIdentity)
+        ));
+        let mut store = AgeStore::new(path, vec![alice], vec![alice_key]);
+        store.unlock(true).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_recipient_rejected() {
+        let dir = TempDir::new().unwrap();
+        let result = AgeStore::create_empty(&dir.path().join("store"), vec!["nope".into()]);
+        assert!(matches!(result, Err(EnjectError::Age(_))));
+        assert!(!dir.path().join("store").exists());
+    }
+
+    #[test]
+    fn test_read_only_cannot_persist() {
+        let dir = TempDir::new().unwrap();
+        let (key, recipient) = identity(&dir, "key.txt");
+        let path = dir.path().join("store");
+        AgeStore::create_empty(&path, vec![recipient.clone()]).unwrap();
+
+        let mut store = AgeStore::new(path, vec![recipient], vec![key]);
+        store.unlock(true).unwrap();
+        assert!(store.persist().is_err());
+    }
+
+    #[test]
+    fn test_in_memory_identity_unlocks() {
+        let dir = TempDir::new().unwrap();
+        let (key, recipient) = generate_identity();
+        let path = dir.path().join("store");
+        AgeStore::create_empty(&path, vec![recipient.clone()]).unwrap();
+
+        let mut store = AgeStore::new(path, vec![recipient], vec![])
+            .with_identity(&key)
+            .unwrap();
+        store.unlock(true).unwrap();
+        assert!(store.list().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_convert_keeps_history_and_moves_into_place_on_unlock() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("store");
+        let password = SecretString::new("test-password-do-not-use".to_string());
+        let params = crate::store::password::KdfParams {
+            m_cost: 8192,
+            t_cost: 1,
+            p_cost: 1,
+        };
+        let mut old = PasswordStore::new(path.clone(), params, vec![7u8; 32]);
+        old.unlock(&password).unwrap();
+        old.set("k", secret("v1")).unwrap();
+        old.set("k", secret("v2")).unwrap();
+        old.save(&password).unwrap();
+
+        let (key, recipient) = generate_identity();
+        AgeStore::convert(&mut old, vec![recipient.clone()]).unwrap();
+        drop(old);
+        assert!(path.with_extension("converted").exists());
+
+        let mut store = AgeStore::new(path.clone(), vec![recipient], vec![])
+            .with_identity(&key)
+            .unwrap();
+        store.unlock(true).unwrap();
+        assert!(!path.with_extension("converted").exists());
+        assert_eq!(store.get("k").unwrap().unwrap().expose_secret(), "v2");
+        assert_eq!(store.history("k").unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,309 @@
+use std::collections::HashMap;
+use std::time::SystemTime;
+
+use secrecy::{ExposeSecret, SecretString};
+use serde::{Deserialize, Serialize};
+use zeroize::{Zeroize, Zeroizing};
+
+#[cfg(feature = "keyring")]
+use crate::error::EnjectError;
+#[cfg(feature = "keyring")]
+use crate::store::Result;
+use crate::store::{Annotation, SecretMetadata, SecretVersion};
+
+/// Previous values kept per secret unless the store is configured otherwise.
+pub(super) const DEFAULT_HISTORY_DEPTH: usize = 3;
+
+/// Every secret a backend holds, with its metadata and history, keyed by name.
+/// Backends keep one of these in memory and implement `Store` on top of it; it
+/// serializes as a plain map of entries.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub(super) struct Entries(HashMap<String, SecretEntry>);
+
+/// One secret as serialized inside the encrypted store. Descriptions and tags are
+/// encrypted along with the value.
+#[derive(Serialize, Deserialize)]
+pub(super) struct SecretEntry {
+    value: String,
+    created_at: String,
+    updated_at: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    expires_at: Option<String>,
+    /// Previous values, newest first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    history: Vec<PreviousValue>,
+    /// Set when the secret was deleted with `--keep-history`: the entry only holds
+    /// history and is invisible to `get` and `list`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    deleted_at: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PreviousValue {
+    value: String,
+    set_at: String,
+}
+
+impl SecretEntry {
+    fn new(value: String, now: String) -> Self {
+        Self {
+            value,
+            created_at: now.clone(),
+            updated_at: now,
+            description: None,
+            tags: Vec::new(),
+            expires_at: None,
+            history: Vec::new(),
+            deleted_at: None,
+        }
+    }
+
+    /// Serialize, parse back and serialize again, which must give the same bytes.
+    fn round_trip(&self) -> std::result::Result<(), String> {
+        let json = Zeroizing::new(serde_json::to_vec(self).map_err(|e| e.to_string())?);
+        let parsed: SecretEntry = serde_json::from_slice(&json).map_err(|e| e.to_string())?;
+        let again = Zeroizing::new(serde_json::to_vec(&parsed).map_err(|e| e.to_string())?);
+        if *json != *again {
+            return Err("changes when serialized again".into());
+        }
+        Ok(())
+    }
+
+    fn is_live(&self) -> bool {
+        self.deleted_at.is_none()
+    }
+
+    /// Replace the current value, moving the old one (if live) into the history.
+    fn replace_value(&mut self, value: String, now: String, depth: usize) {
+        let old = std::mem::replace(&mut self.value, value);
+        let old_set_at = std::mem::replace(&mut self.updated_at, now);
+        if self.is_live() {
+            self.history.insert(
+                0,
+                PreviousValue {
+                    value: old,
+                    set_at: old_set_at,
+                },
+            );
+        }
+        self.deleted_at = None;
+        for mut dropped in self.history.drain(depth.min(self.history.len())..) {
+            dropped.value.zeroize();
+        }
+    }
+}
+
+impl Entries {
+    /// Entries for a flat key/value map, all stamped `stamp`.
+    pub(super) fn from_values(values: HashMap<String, String>, stamp: &str) -> Self {
+        Self(
+            values
+                .into_iter()
+                .map(|(key, value)| (key, SecretEntry::new(value, stamp.to_string())))
+                .collect(),
+        )
+    }
+
+    /// `key`'s entry, unless it is missing or only kept for its history.
+    fn live_entry(&self, key: &str) -> Option<&SecretEntry> {
+        self.0.get(key).filter(|e| e.is_live())
+    }
+
+    pub(super) fn get(&self, key: &str) -> Option<SecretString> {
+        self.live_entry(key)
+            .map(|entry| SecretString::new(entry.value.clone()))
+    }
+
+    /// Insert or replace `key`. A replaced secret keeps its `created_at`, description
+    /// and tags, and its old value goes into the history (at most `depth` values). A
+    /// secret deleted with its history kept starts afresh but keeps that history.
+    pub(super) fn set(&mut self, key: &str, value: SecretString, depth: usize) {
+        let now = rfc3339(SystemTime::now());
+        let value = value.expose_secret().to_string();
+        match self.0.get_mut(key) {
+            Some(entry) if entry.is_live() => entry.replace_value(value, now, depth),
+            Some(entry) => {
+                let history = std::mem::take(&mut entry.history);
+                *entry = SecretEntry {
+                    history,
+                    ..SecretEntry::new(value, now)
+                };
+            }
+            None => {
+                self.0.insert(key.to_string(), SecretEntry::new(value, now));
+            }
+        }
+    }
+
+    /// Remove `key` and everything kept about it, history included.
+    pub(super) fn delete(&mut self, key: &str) -> bool {
+        self.0.remove(key).is_some_and(|entry| entry.is_live())
+    }
+
+    /// Live keys, sorted.
+    pub(super) fn list(&self) -> Vec<String> {
+        let mut keys: Vec<String> = self
+            .0
+            .iter()
+            .filter(|(_, entry)| entry.is_live())
+            .map(|(key, _)| key.clone())
+            .collect();
+        keys.sort();
+        keys
+    }
+
+    pub(super) fn metadata(&self, key: &str) -> Option<SecretMetadata> {
+        self.live_entry(key).map(|entry| SecretMetadata {
+            created_at: entry.created_at.clone(),
+            updated_at: entry.updated_at.clone(),
+            description: entry.description.clone(),
+            tags: entry.tags.clone(),
+            expires_at: entry.expires_at.clone(),
+        })
+    }
+
+    pub(super) fn annotate(&mut self, key: &str, annotation: Annotation) -> bool {
+        let Some(entry) = self.0.get_mut(key).filter(|e| e.is_live()) else {
+            return false;
+        };
+        if let Some(description) = annotation.description {
+            entry.description = Some(description).filter(|d| !d.is_empty());
+        }
+        if let Some(tags) = annotation.tags {
+            entry.tags = tags;
+        }
+        if let Some(expires_at) = annotation.expires_at {
+            entry.expires_at = Some(expires_at).filter(|e| !e.is_empty());
+        }
+        true
+    }
+
+    pub(super) fn history(&self, key: &str) -> Vec<SecretVersion> {
+        let Some(entry) = self.0.get(key) else {
+            return Vec::new();
+        };
+        let mut versions = Vec::new();
+        if entry.is_live() {
+            versions.push(SecretVersion {
+                number: 0,
+                value: SecretString::new(entry.value.clone()),
+                set_at: entry.updated_at.clone(),
+            });
+        }
+        versions.extend(
+            entry
+                .history
+                .iter()
+                .enumerate()
+                .map(|(i, previous)| SecretVersion {
+                    number: i + 1,
+                    value: SecretString::new(previous.value.clone()),
+                    set_at: previous.set_at.clone(),
+                }),
+        );
+        versions
+    }
+
+    pub(super) fn restore_version(&mut self, key: &str, number: usize, depth: usize) -> bool {
+        let Some(entry) = self.0.get_mut(key) else {
+            return false;
+        };
+        if number == 0 || number > entry.history.len() {
+            return false;
+        }
+        let restored = entry.history.remove(number - 1);
+        // The replaced value takes the restored one's place at the front
+        entry.replace_value(restored.value, rfc3339(SystemTime::now()), depth.max(1));
+        true
+    }
+
+    pub(super) fn delete_keeping_history(&mut self, key: &str, depth: usize) -> bool {
+        let Some(entry) = self.0.get_mut(key).filter(|e| e.is_live()) else {
+            return false;
+        };
+        let now = rfc3339(SystemTime::now());
+        // A deleted secret keeps at least its last value, whatever the depth
+        entry.replace_value(String::new(), now.clone(), depth.max(1));
+        entry.deleted_at = Some(now);
+        true
+    }
+
+    /// Structural problems with the entries, sorted by key: an entry whose JSON
+    /// doesn't survive a round trip unchanged, or a timestamp that isn't RFC 3339.
+    /// Values are compared only as serialized bytes and never reported.
+    pub(super) fn problems(&self) -> Vec<String> {
+        let mut keys: Vec<&String> = self.0.keys().collect();
+        keys.sort();
+        let mut problems = Vec::new();
+        for key in keys {
+            let entry = &self.0[key];
+            if key.is_empty() {
+                problems.push("an entry has an empty name".to_string());
+            }
+            if let Err(reason) = entry.round_trip() {
+                problems.push(format!("'{}': {}", key, reason));
+            }
+            let history = entry.history.iter().map(|previous| &previous.set_at);
+            let stamps = [&entry.created_at, &entry.updated_at]
+                .into_iter()
+                .chain(entry.expires_at.as_ref())
+                .chain(entry.deleted_at.as_ref())
+                .chain(history);
+            for stamp in stamps {
+                if humantime::parse_rfc3339_weak(stamp).is_err() {
+                    problems.push(format!("'{}': bad timestamp {:?}", key, stamp));
+                }
+            }
+        }
+        problems
+    }
+
+    /// Backdate `key`'s timestamps.
+    #[cfg(test)]
+    pub(super) fn set_timestamps(&mut self, key: &str, at: &str) {
+        if let Some(entry) = self.0.get_mut(key) {
+            entry.created_at = at.to_string();
+            entry.updated_at = at.to_string();
+        }
+    }
+}
+
+#[cfg(feature = "keyring")]
+impl Entries {
+    /// Each entry serialized on its own, history-only ones included, for backends
+    /// that keep every secret in a separate slot.
+    pub(super) fn to_json_each(&self) -> Result<HashMap<String, Zeroizing<String>>> {
+        self.0
+            .iter()
+            .map(|(key, entry)| {
+                let json = serde_json::to_string(entry)
+                    .map_err(|e| EnjectError::Serialization(e.to_string()))?;
+                Ok((key.clone(), Zeroizing::new(json)))
+            })
+            .collect()
+    }
+
+    /// The inverse of `to_json_each`.
+    pub(super) fn from_json_each(slots: &HashMap<String, Zeroizing<String>>) -> Result<Self> {
+        slots
+            .iter()
+            .map(|(key, json)| {
+                let entry = serde_json::from_str(json).map_err(|e| {
+                    EnjectError::CorruptStore(format!("Entry for '{}': {}", key, e))
+                })?;
+                Ok((key.clone(), entry))
+            })
+            .collect::<Result<_>>()
+            .map(Self)
+    }
+}
+
+/// `time` as RFC 3339 UTC to the second, as stored in entries.
+pub(super) fn rfc3339(time: SystemTime) -> String {
+    humantime::format_rfc3339_seconds(time).to_string()
+}
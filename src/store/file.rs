@@ -0,0 +1,90 @@
+//! File handling shared by the backends that keep their secrets in one store file:
+//! advisory locking, change detection and atomic replacement.
+
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::Write;
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime};
+
+use blake2::{Blake2b512, Digest};
+
+use crate::error::EnjectError;
+use crate::store::Result;
+
+pub(super) const DEFAULT_LOCK_TIMEOUT: Duration = Duration::from_secs(10);
+const LOCK_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// What the store file looked like at one point: missing, or its modification time
+/// and a digest of its bytes.
+#[derive(Debug, PartialEq)]
+pub(super) enum FileStamp {
+    Missing,
+    Present {
+        modified: Option<SystemTime>,
+        digest: Vec<u8>,
+    },
+}
+
+impl FileStamp {
+    pub(super) fn read(path: &Path) -> Result<Self> {
+        let bytes = match std::fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(FileStamp::Missing),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(FileStamp::Present {
+            modified: std::fs::metadata(path)?.modified().ok(),
+            digest: Blake2b512::digest(&bytes).to_vec(),
+        })
+    }
+}
+
+/// Take the advisory lock on `<store>.lock`, polling until `timeout`. The lock is
+/// held until the returned file is dropped.
+pub(super) fn lock(store_path: &Path, exclusive: bool, timeout: Duration) -> Result<File> {
+    let path = store_path.with_extension("lock");
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&path)?;
+    let started = Instant::now();
+    loop {
+        let attempt = if exclusive {
+            file.try_lock()
+        } else {
+            file.try_lock_shared()
+        };
+        match attempt {
+            Ok(()) => return Ok(file),
+            Err(TryLockError::WouldBlock) if started.elapsed() < timeout => {
+                std::thread::sleep(LOCK_POLL_INTERVAL)
+            }
+            Err(TryLockError::WouldBlock) => {
+                return Err(EnjectError::StoreLocked(path.display().to_string()))
+            }
+            Err(TryLockError::Error(e)) => return Err(e.into()),
+        }
+    }
+}
+
+/// Replace `dest` with `parts` written one after another: write a temp file beside
+/// it, fsync, then rename over it, so readers see the old file or the new one.
+pub(super) fn write_atomic(dest: &Path, parts: &[&[u8]]) -> Result<()> {
+    let parent = dest
+        .parent()
+        .ok_or_else(|| EnjectError::Config("Store has no parent directory.".into()))?;
+
+    let tmp_path = parent.join(format!(".store.tmp.{}", rand::random::<u64>()));
+
+    {
+        let mut tmp = File::create(&tmp_path)?;
+        for part in parts {
+            tmp.write_all(part)?;
+        }
+        tmp.sync_all()?;
+    }
+
+    std::fs::rename(&tmp_path, dest)?;
+    Ok(())
+}
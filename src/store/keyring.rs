@@ -0,0 +1,313 @@
+//! Secrets kept in the platform credential manager (macOS Keychain, Windows
+//! Credential Manager, the Linux kernel keyring) instead of an encrypted file, so
+//! no master password is needed.
+
+use std::collections::HashMap;
+
+use secrecy::SecretString;
+use zeroize::Zeroizing;
+
+use crate::error::EnjectError;
+use crate::store::entries::{Entries, DEFAULT_HISTORY_DEPTH};
+use crate::store::password::KEY_LEN;
+use crate::store::{Annotation, Result, SecretMetadata, SecretVersion, Store};
+
+/// Service name every enject credential is filed under.
+const SERVICE: &str = "enject";
+
+/// Named text slots a `KeyringStore` reads and writes.
+trait Vault {
+    fn read(&self, name: &str) -> Result<Option<String>>;
+    fn write(&self, name: &str, value: &str) -> Result<()>;
+    fn remove(&self, name: &str) -> Result<()>;
+}
+
+/// The platform credential manager, through the `keyring` crate.
+struct OsKeychain;
+
+impl OsKeychain {
+    fn entry(name: &str) -> Result<::keyring::Entry> {
+        ::keyring::Entry::new(SERVICE, name).map_err(keychain_error)
+    }
+}
+
+impl Vault for OsKeychain {
+    fn read(&self, name: &str) -> Result<Option<String>> {
+        match Self::entry(name)?.get_password() {
+            Ok(value) => Ok(Some(value)),
+            Err(::keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(keychain_error(e)),
+        }
+    }
+
+    fn write(&self, name: &str, value: &str) -> Result<()> {
+        Self::entry(name)?
+            .set_password(value)
+            .map_err(keychain_error)
+    }
+
+    fn remove(&self, name: &str) -> Result<()> {
+        match Self::entry(name)?.delete_credential() {
+            Ok(()) | Err(::keyring::Error::NoEntry) => Ok(()),
+            Err(e) => Err(keychain_error(e)),
+        }
+    }
+}
+
+fn keychain_error(e: ::keyring::Error) -> EnjectError {
+    EnjectError::Keychain(e.to_string())
+}
+
+/// The keychain name for a password store's cached key: `enject/unlock/<fingerprint>`.
+fn key_slot(fingerprint: &str) -> String {
+    format!("unlock/{}", fingerprint)
+}
+
+/// The store key `remember_key` cached for the store with salt `fingerprint`.
+pub fn remembered_key(fingerprint: &str) -> Result<Option<Zeroizing<[u8; KEY_LEN]>>> {
+    let Some(hex_key) = OsKeychain.read(&key_slot(fingerprint))? else {
+        return Ok(None);
+    };
+    let hex_key = Zeroizing::new(hex_key);
+    let mut key = Zeroizing::new([0u8; KEY_LEN]);
+    hex::decode_to_slice(hex_key.as_bytes(), &mut key[..])
+        .map_err(|_| EnjectError::Keychain("the cached store key is malformed".into()))?;
+    Ok(Some(key))
+}
+
+/// Cache a password store's derived key so later commands needn't ask for the password.
+pub fn remember_key(fingerprint: &str, key: &[u8; KEY_LEN]) -> Result<()> {
+    OsKeychain.write(&key_slot(fingerprint), &Zeroizing::new(hex::encode(key)))
+}
+
+/// Remove a cached key. Returns false if there was none.
+pub fn forget_key(fingerprint: &str) -> Result<bool> {
+    let slot = key_slot(fingerprint);
+    if OsKeychain.read(&slot)?.is_none() {
+        return Ok(false);
+    }
+    OsKeychain.remove(&slot)?;
+    Ok(true)
+}
+
+/// A `Store` in the OS keychain. Each secret is one credential, `enject/<project-id>/<key>`,
+/// holding its value, metadata and history. Keychains can't list their contents, so
+/// an index credential, `enject/<project-id>`, names the keys.
+pub struct KeyringStore {
+    vault: Box<dyn Vault>,
+    project_id: String,
+    entries: Entries,
+    /// Each entry as last read from or written to the keychain, so `persist` only
+    /// touches the credentials that changed. `None` until an index exists.
+    stored: Option<HashMap<String, Zeroizing<String>>>,
+    history_depth: usize,
+}
+
+impl KeyringStore {
+    /// Read the secrets filed under `project_id`. A project with no index yet is empty.
+    pub fn open(project_id: &str) -> Result<Self> {
+        Self::load(Box::new(OsKeychain), project_id)
+    }
+
+    fn load(vault: Box<dyn Vault>, project_id: &str) -> Result<Self> {
+        let Some(index) = vault.read(project_id)? else {
+            return Ok(Self {
+                vault,
+                project_id: project_id.to_string(),
+                entries: Entries::default(),
+                stored: None,
+                history_depth: DEFAULT_HISTORY_DEPTH,
+            });
+        };
+        let keys: Vec<String> = serde_json::from_str(&index)
+            .map_err(|e| EnjectError::CorruptStore(format!("Keychain index: {}", e)))?;
+
+        let mut stored = HashMap::new();
+        for key in keys {
+            let json = vault.read(&slot(project_id, &key))?.ok_or_else(|| {
+                EnjectError::CorruptStore(format!(
+                    "The keychain index lists '{}' but it has no credential.",
+                    key
+                ))
+            })?;
+            stored.insert(key, Zeroizing::new(json));
+        }
+        Ok(Self {
+            entries: Entries::from_json_each(&stored)?,
+            vault,
+            project_id: project_id.to_string(),
+            stored: Some(stored),
+            history_depth: DEFAULT_HISTORY_DEPTH,
+        })
+    }
+
+    /// Keep `depth` previous values per secret (0 keeps none).
+    pub fn with_history_depth(mut self, depth: usize) -> Self {
+        self.history_depth = depth;
+        self
+    }
+}
+
+/// The credential name for `key` in project `project_id`.
+fn slot(project_id: &str, key: &str) -> String {
+    format!("{}/{}", project_id, key)
+}
+
+impl Store for KeyringStore {
+    fn get(&self, key: &str) -> Result<Option<SecretString>> {
+        Ok(self.entries.get(key))
+    }
+
+    fn set(&mut self, key: &str, value: SecretString) -> Result<()> {
+        self.entries.set(key, value, self.history_depth);
+        Ok(())
+    }
+
+    fn delete(&mut self, key: &str) -> Result<bool> {
+        Ok(self.entries.delete(key))
+    }
+
+    fn list(&self) -> Result<Vec<String>> {
+        Ok(self.entries.list())
+    }
+
+    fn metadata(&self, key: &str) -> Result<Option<SecretMetadata>> {
+        Ok(self.entries.metadata(key))
+    }
+
+    fn annotate(&mut self, key: &str, annotation: Annotation) -> Result<bool> {
+        Ok(self.entries.annotate(key, annotation))
+    }
+
+    fn history(&self, key: &str) -> Result<Vec<SecretVersion>> {
+        Ok(self.entries.history(key))
+    }
+
+    fn restore_version(&mut self, key: &str, number: usize) -> Result<bool> {
+        Ok(self
+            .entries
+            .restore_version(key, number, self.history_depth))
+    }
+
+    fn delete_keeping_history(&mut self, key: &str) -> Result<bool> {
+        Ok(self.entries.delete_keeping_history(key, self.history_depth))
+    }
+
+    /// Write changed credentials, then the index, then remove credentials the index
+    /// no longer names: an interruption never leaves the index naming a missing one.
+    fn persist(&mut self) -> Result<()> {
+        let current = self.entries.to_json_each()?;
+        let empty = HashMap::new();
+        let before = self.stored.as_ref().unwrap_or(&empty);
+
+        for (key, json) in &current {
+            if before.get(key) != Some(json) {
+                self.vault.write(&slot(&self.project_id, key), json)?;
+            }
+        }
+        let same_keys = self.stored.is_some()
+            && current.len() == before.len()
+            && current.keys().all(|key| before.contains_key(key));
+        if !same_keys {
+            let mut keys: Vec<&String> = current.keys().collect();
+            keys.sort();
+            let index = serde_json::to_string(&keys)
+                .map_err(|e| EnjectError::Serialization(e.to_string()))?;
+            self.vault.write(&self.project_id, &index)?;
+        }
+        for key in before.keys().filter(|key| !current.contains_key(*key)) {
+            self.vault.remove(&slot(&self.project_id, key))?;
+        }
+
+        self.stored = Some(current);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use secrecy::ExposeSecret;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /// An in-memory keychain that outlives the stores opened on it.
+    #[derive(Clone, Default)]
+    struct FakeKeychain(Rc<RefCell<HashMap<String, String>>>);
+
+    impl Vault for FakeKeychain {
+        fn read(&self, name: &str) -> Result<Option<String>> {
+            Ok(self.0.borrow().get(name).cloned())
+        }
+
+        fn write(&self, name: &str, value: &str) -> Result<()> {
+            self.0
+                .borrow_mut()
+                .insert(name.to_string(), value.to_string());
+            Ok(())
+        }
+
+        fn remove(&self, name: &str) -> Result<()> {
+            self.0.borrow_mut().remove(name);
+            Ok(())
+        }
+    }
+
+    fn open(keychain: &FakeKeychain) -> KeyringStore {
+        KeyringStore::load(Box::new(keychain.clone()), "proj").unwrap()
+    }
+
+    fn secret(value: &str) -> SecretString {
+        SecretString::new(value.to_string())
+    }
+
+    #[test]
+    fn test_persist_then_reopen() {
+        let keychain = FakeKeychain::default();
+        let mut store = open(&keychain);
+        store.set("b", secret("2")).unwrap();
+        store.set("a", secret("1")).unwrap();
+        store.persist().unwrap();
+
+        let store = open(&keychain);
+        assert_eq!(store.list().unwrap(), vec!["a", "b"]);
+        assert_eq!(store.get("a").unwrap().unwrap().expose_secret(), "1");
+        let names: Vec<String> = {
+            let mut names: Vec<String> = keychain.0.borrow().keys().cloned().collect();
+            names.sort();
+            names
+        };
+        assert_eq!(names, vec!["proj", "proj/a", "proj/b"]);
+    }
+
+    #[test]
+    fn test_persist_removes_deleted_credentials() {
+        let keychain = FakeKeychain::default();
+        let mut store = open(&keychain);
+        store.set("a", secret("1")).unwrap();
+        store.set("b", secret("2")).unwrap();
+        store.persist().unwrap();
+        store.delete("a").unwrap();
+        store.persist().unwrap();
+
+        assert!(!keychain.0.borrow().contains_key("proj/a"));
+        assert_eq!(open(&keychain).list().unwrap(), vec!["b"]);
+    }
+
+    #[test]
+    fn test_empty_project_writes_an_index() {
+        let keychain = FakeKeychain::default();
+        open(&keychain).persist().unwrap();
+        assert_eq!(keychain.0.borrow().get("proj").unwrap(), "[]");
+    }
+
+    #[test]
+    fn test_index_naming_a_missing_credential_is_corrupt() {
+        let keychain = FakeKeychain::default();
+        keychain.write("proj", r#"["gone"]"#).unwrap();
+        assert!(matches!(
+            KeyringStore::load(Box::new(keychain), "proj"),
+            Err(EnjectError::CorruptStore(_))
+        ));
+    }
+}
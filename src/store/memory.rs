@@ -0,0 +1,144 @@
+//! A store held in memory only.
+
+use secrecy::SecretString;
+
+use crate::store::entries::{Entries, DEFAULT_HISTORY_DEPTH};
+use crate::store::{Annotation, Result, SecretMetadata, SecretVersion, Store};
+
+/// A `Store` that lives only in process memory and is never persisted. Useful for
+/// tests and for callers that assemble secrets from elsewhere; it behaves exactly
+/// like an unlocked `PasswordStore`, history and metadata included.
+pub struct MemoryStore {
+    entries: Entries,
+    history_depth: usize,
+}
+
+impl MemoryStore {
+    /// An empty store.
+    pub fn new() -> Self {
+        Self {
+            entries: Entries::default(),
+            history_depth: DEFAULT_HISTORY_DEPTH,
+        }
+    }
+
+    /// Keep `depth` previous values per secret (0 keeps none).
+    pub fn with_history_depth(mut self, depth: usize) -> Self {
+        self.history_depth = depth;
+        self
+    }
+}
+
+impl Default for MemoryStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Store for MemoryStore {
+    fn get(&self, key: &str) -> Result<Option<SecretString>> {
+        Ok(self.entries.get(key))
+    }
+
+    fn set(&mut self, key: &str, value: SecretString) -> Result<()> {
+        self.entries.set(key, value, self.history_depth);
+        Ok(())
+    }
+
+    fn delete(&mut self, key: &str) -> Result<bool> {
+        Ok(self.entries.delete(key))
+    }
+
+    fn list(&self) -> Result<Vec<String>> {
+        Ok(self.entries.list())
+    }
+
+    fn metadata(&self, key: &str) -> Result<Option<SecretMetadata>> {
+        Ok(self.entries.metadata(key))
+    }
+
+    fn annotate(&mut self, key: &str, annotation: Annotation) -> Result<bool> {
+        Ok(self.entries.annotate(key, annotation))
+    }
+
+    fn history(&self, key: &str) -> Result<Vec<SecretVersion>> {
+        Ok(self.entries.history(key))
+    }
+
+    fn restore_version(&mut self, key: &str, number: usize) -> Result<bool> {
+        Ok(self
+            .entries
+            .restore_version(key, number, self.history_depth))
+    }
+
+    fn delete_keeping_history(&mut self, key: &str) -> Result<bool> {
+        Ok(self.entries.delete_keeping_history(key, self.history_depth))
+    }
+
+    /// Nothing to write: the entries only ever live in memory.
+    fn persist(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use secrecy::ExposeSecret;
+
+    fn secret(value: &str) -> SecretString {
+        SecretString::new(value.to_string())
+    }
+
+    #[test]
+    fn test_set_get_delete() {
+        let mut store = MemoryStore::new();
+        store.set("api_key", secret("abc")).unwrap();
+        assert_eq!(
+            store.get("api_key").unwrap().unwrap().expose_secret(),
+            "abc"
+        );
+        assert!(store.delete("api_key").unwrap());
+        assert!(store.get("api_key").unwrap().is_none());
+        assert!(!store.delete("api_key").unwrap());
+    }
+
+    #[test]
+    fn test_list_is_sorted() {
+        let mut store = MemoryStore::new();
+        for key in ["zeta", "alpha", "mid"] {
+            store.set(key, secret("v")).unwrap();
+        }
+        assert_eq!(store.list().unwrap(), vec!["alpha", "mid", "zeta"]);
+    }
+
+    #[test]
+    fn test_history_and_restore() {
+        let mut store = MemoryStore::new().with_history_depth(2);
+        for value in ["v1", "v2", "v3", "v4"] {
+            store.set("k", secret(value)).unwrap();
+        }
+        let values: Vec<String> = store
+            .history("k")
+            .unwrap()
+            .iter()
+            .map(|v| v.value.expose_secret().to_string())
+            .collect();
+        assert_eq!(values, vec!["v4", "v3", "v2"]);
+
+        assert!(store.restore_version("k", 2).unwrap());
+        assert_eq!(store.get("k").unwrap().unwrap().expose_secret(), "v2");
+        assert!(!store.restore_version("k", 5).unwrap());
+    }
+
+    #[test]
+    fn test_delete_keeping_history_hides_the_key() {
+        let mut store = MemoryStore::new();
+        store.set("k", secret("v1")).unwrap();
+        assert!(store.delete_keeping_history("k").unwrap());
+        assert!(store.list().unwrap().is_empty());
+        assert!(store.metadata("k").unwrap().is_none());
+        assert!(store.restore_version("k", 1).unwrap());
+        assert_eq!(store.get("k").unwrap().unwrap().expose_secret(), "v1");
+    }
+}
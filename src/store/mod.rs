@@ -1,14 +1,85 @@
+//! Where secrets live: the `Store` trait and its backends.
+
+#[cfg(feature = "age")]
+pub mod age;
+mod entries;
+mod file;
+#[cfg(feature = "keyring")]
+pub mod keyring;
+pub mod memory;
 pub mod password;
 
 use crate::error::EnjectError;
 use secrecy::SecretString;
 
+/// The result of a store operation.
 pub type Result<T> = std::result::Result<T, EnjectError>;
 
+/// Everything stored about a secret except its value. Timestamps record when it was
+/// first stored and last changed, as RFC 3339 UTC.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SecretMetadata {
+    /// When the secret was first stored.
+    pub created_at: String,
+    /// When its value or metadata last changed.
+    pub updated_at: String,
+    /// A note on what the secret is for.
+    pub description: Option<String>,
+    /// Labels for grouping secrets.
+    pub tags: Vec<String>,
+    /// RFC 3339 UTC time after which the secret should be rotated.
+    pub expires_at: Option<String>,
+}
+
+/// One value a secret has held. `number` 0 is the current value, 1 the one before it.
+#[derive(Debug)]
+pub struct SecretVersion {
+    /// How many values ago this one was current.
+    pub number: usize,
+    /// The value itself.
+    pub value: SecretString,
+    /// When this value was stored (RFC 3339 UTC).
+    pub set_at: String,
+}
+
+/// Changes to a secret's metadata. Fields left `None` keep their current value;
+/// an empty description or expiry removes it.
+#[derive(Clone, Debug, Default)]
+pub struct Annotation {
+    /// A new description.
+    pub description: Option<String>,
+    /// A new set of tags, replacing the old ones.
+    pub tags: Option<Vec<String>>,
+    /// A new expiry (RFC 3339 UTC).
+    pub expires_at: Option<String>,
+}
+
 /// Core abstraction for secret storage. Commands interact only with this trait.
 pub trait Store {
+    /// The current value of `key`, if it is stored.
     fn get(&self, key: &str) -> Result<Option<SecretString>>;
+    /// Store `value` under `key`, keeping the value it replaces in the history.
     fn set(&mut self, key: &str, value: SecretString) -> Result<()>;
+    /// Delete `key` and its history. Returns false if `key` is not stored.
     fn delete(&mut self, key: &str) -> Result<bool>;
+    /// The names of every stored secret, sorted.
     fn list(&self) -> Result<Vec<String>>;
+    /// What is known about `key` besides its value, if it is stored.
+    fn metadata(&self, key: &str) -> Result<Option<SecretMetadata>>;
+    /// Update the metadata of an existing secret. Returns false if `key` is not stored.
+    fn annotate(&mut self, key: &str, annotation: Annotation) -> Result<bool>;
+    /// The current and previous values of `key`, newest first. A key deleted with its
+    /// history kept has no version 0. Empty if nothing is known about `key`.
+    fn history(&self, key: &str) -> Result<Vec<SecretVersion>>;
+    /// Make previous version `number` of `key` current again, keeping the replaced
+    /// value in the history. Returns false if there is no such version.
+    fn restore_version(&mut self, key: &str, number: usize) -> Result<bool>;
+    /// Delete `key` but keep its previous values so `restore_version` can bring it back.
+    /// Returns false if `key` is not stored.
+    fn delete_keeping_history(&mut self, key: &str) -> Result<bool>;
+    /// Write the changes made since the store was opened back to where it lives.
+    fn persist(&mut self) -> Result<()>;
+    /// Let the next `persist` replace changes another process made in the meantime.
+    /// Backends that can't detect such changes ignore this.
+    fn overwrite_changes(&mut self) {}
 }
@@ -1,36 +1,269 @@
+//! The default backend: one file encrypted with a key derived from a master
+//! password by Argon2id.
+
 use std::collections::HashMap;
-use std::io::Write;
+use std::fs::File;
 use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
 
 use aes_gcm::{
-    aead::{Aead, KeyInit},
+    aead::{Aead, KeyInit, Payload},
     Aes256Gcm, Nonce,
 };
 use argon2::{Algorithm, Argon2, Params, Version};
+use blake2::{Blake2b512, Digest};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
 use rand::RngCore;
 use secrecy::{ExposeSecret, SecretString};
-use zeroize::Zeroize;
+use serde::Deserialize;
+use zeroize::{Zeroize, Zeroizing};
 
 use crate::error::EnjectError;
-use crate::store::{Result, Store};
+use crate::store::entries::{rfc3339, Entries, DEFAULT_HISTORY_DEPTH};
+use crate::store::file::{self, FileStamp};
+use crate::store::{Annotation, Result, SecretMetadata, SecretVersion, Store};
+
+/// Length in bytes of the key Argon2id derives from the password.
+pub const KEY_LEN: usize = 32;
 
-const NONCE_LEN: usize = 12;
-const KEY_LEN: usize = 32;
+/// First bytes of every store file that carries a header. Files without them are
+/// headerless `nonce || ciphertext` stores, upgraded on their next save.
+const MAGIC: &[u8; 6] = b"ENJECT";
+const FORMAT_VERSION: u8 = 1;
+const KDF_ARGON2ID: u8 = 1;
 
-/// AES-256-GCM + Argon2id password-based secret store.
+/// AES-256-GCM (or XChaCha20-Poly1305) + Argon2id password-based secret store.
 pub struct PasswordStore {
     store_path: PathBuf,
+    /// Cipher, KDF parameters and salt for a new store file. `unlock` replaces them
+    /// with the ones in the file's header, so they follow the file.
+    cipher: Cipher,
     kdf_params: KdfParams,
-    /// 32-byte salt for Argon2id key derivation. Generated once at init, never changes.
+    /// 32-byte salt for Argon2id key derivation. Generated at init and by `rotate`.
     salt: Vec<u8>,
     /// Decrypted secrets, populated after `unlock()`.
-    secrets: Option<HashMap<String, String>>,
+    secrets: Option<Entries>,
+    /// How many previous values each secret keeps.
+    history_depth: usize,
+    /// Open `store.lock`, holding an advisory lock from `unlock` until the store is
+    /// dropped: exclusive for a store that will be saved, shared for a read-only one.
+    lock: Option<File>,
+    read_only: bool,
+    /// How long `unlock` waits for another process to release the lock.
+    lock_timeout: Duration,
+    /// The store file as `unlock` found it (or as the last save left it), so `save`
+    /// can tell when something that ignores the lock has replaced it since.
+    on_disk: Option<FileStamp>,
+    /// Save even if the file changed on disk since it was unlocked.
+    overwrite_changes: bool,
+    /// The key `unlock` derived, so saving under the same password skips Argon2.
+    cached_key: Option<CachedKey>,
+    /// The password `unlock` succeeded with, which `persist` saves under when there
+    /// was no file to derive a key for.
+    unlocked_with: Option<SecretString>,
+}
+
+/// What `decrypt_file` got out of one store file.
+struct OpenedFile {
+    secrets: Entries,
+    /// `None` for a headerless file.
+    header: Option<Header>,
+    key: Zeroizing<[u8; KEY_LEN]>,
+}
+
+/// A derived key together with the password it came from, unless the store was
+/// opened with the key itself. Both are zeroized on drop.
+struct CachedKey {
+    password: Option<SecretString>,
+    key: Zeroizing<[u8; KEY_LEN]>,
+}
+
+/// What a store is opened with: its password, or a key derived from it earlier
+/// (as cached in the OS keychain by `enject unlock --remember`).
+#[derive(Clone, Copy)]
+enum Credential<'a> {
+    Password(&'a SecretString),
+    Key(&'a [u8; KEY_LEN]),
+}
+
+/// The decrypted store contents: the current entry map, or the flat key/value map
+/// written by config version 1.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredSecrets {
+    Entries(Entries),
+    Legacy(HashMap<String, String>),
+}
+
+/// The authenticated cipher a store file is encrypted with. Both take the same
+/// 256-bit Argon2id key; they differ in nonce size.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Cipher {
+    /// AES-256-GCM with a random 96-bit nonce.
+    #[default]
+    Aes256Gcm,
+    /// XChaCha20-Poly1305 with a random 192-bit nonce.
+    XChaCha20Poly1305,
+}
+
+impl Cipher {
+    /// The name used in `config.toml`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Cipher::Aes256Gcm => "aes256gcm",
+            Cipher::XChaCha20Poly1305 => "xchacha20",
+        }
+    }
+
+    /// The cipher `config.toml` names `name`.
+    pub fn from_name(name: &str) -> Result<Self> {
+        match name {
+            "aes256gcm" => Ok(Cipher::Aes256Gcm),
+            "xchacha20" => Ok(Cipher::XChaCha20Poly1305),
+            other => Err(EnjectError::Config(format!(
+                "Unknown cipher {:?} (expected aes256gcm or xchacha20)",
+                other
+            ))),
+        }
+    }
+
+    /// The id stored in the file header.
+    fn id(self) -> u8 {
+        match self {
+            Cipher::Aes256Gcm => 1,
+            Cipher::XChaCha20Poly1305 => 2,
+        }
+    }
+
+    fn from_id(id: u8) -> Option<Self> {
+        match id {
+            1 => Some(Cipher::Aes256Gcm),
+            2 => Some(Cipher::XChaCha20Poly1305),
+            _ => None,
+        }
+    }
+
+    fn nonce_len(self) -> usize {
+        match self {
+            Cipher::Aes256Gcm => 12,
+            Cipher::XChaCha20Poly1305 => 24,
+        }
+    }
+
+    fn encrypt(self, key: &[u8; KEY_LEN], nonce: &[u8], payload: Payload) -> Result<Vec<u8>> {
+        let invalid_key = |_| EnjectError::CorruptStore("Invalid key length.".into());
+        match self {
+            Cipher::Aes256Gcm => Aes256Gcm::new_from_slice(key)
+                .map_err(invalid_key)?
+                .encrypt(Nonce::from_slice(nonce), payload),
+            Cipher::XChaCha20Poly1305 => XChaCha20Poly1305::new_from_slice(key)
+                .map_err(invalid_key)?
+                .encrypt(XNonce::from_slice(nonce), payload),
+        }
+        .map_err(|_| EnjectError::CorruptStore("Encryption failed.".into()))
+    }
+
+    fn decrypt(self, key: &[u8; KEY_LEN], nonce: &[u8], payload: Payload) -> Result<Vec<u8>> {
+        let invalid_key = |_| EnjectError::CorruptStore("Invalid key length.".into());
+        match self {
+            Cipher::Aes256Gcm => Aes256Gcm::new_from_slice(key)
+                .map_err(invalid_key)?
+                .decrypt(Nonce::from_slice(nonce), payload),
+            Cipher::XChaCha20Poly1305 => XChaCha20Poly1305::new_from_slice(key)
+                .map_err(invalid_key)?
+                .decrypt(XNonce::from_slice(nonce), payload),
+        }
+        .map_err(|_| EnjectError::DecryptionFailed)
+    }
+}
+
+/// The plaintext start of a store file: everything needed to derive its key. It is
+/// authenticated as associated data, so changing any byte of it fails decryption.
+///
+/// Layout: magic, format version, cipher id, KDF id, `m_cost`, `t_cost` and `p_cost`
+/// as little-endian u32s, the salt length as one byte, then the salt.
+#[derive(Debug, PartialEq)]
+struct Header {
+    cipher: Cipher,
+    kdf_params: KdfParams,
+    salt: Vec<u8>,
+}
+
+impl Header {
+    fn encode(&self) -> Result<Vec<u8>> {
+        let salt_len = u8::try_from(self.salt.len())
+            .map_err(|_| EnjectError::Config("Salt is longer than 255 bytes.".into()))?;
+        let mut bytes = Vec::with_capacity(MAGIC.len() + 16 + self.salt.len());
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&[FORMAT_VERSION, self.cipher.id(), KDF_ARGON2ID]);
+        bytes.extend_from_slice(&self.kdf_params.m_cost.to_le_bytes());
+        bytes.extend_from_slice(&self.kdf_params.t_cost.to_le_bytes());
+        bytes.extend_from_slice(&self.kdf_params.p_cost.to_le_bytes());
+        bytes.push(salt_len);
+        bytes.extend_from_slice(&self.salt);
+        Ok(bytes)
+    }
+
+    /// The header at the start of `file` and its length in bytes, or `None` for a
+    /// headerless file.
+    fn decode(file: &[u8]) -> Result<Option<(Header, usize)>> {
+        let Some(rest) = file.strip_prefix(MAGIC.as_slice()) else {
+            return Ok(None);
+        };
+        let truncated = || EnjectError::CorruptStore("Store header is truncated.".into());
+        let (&[version, cipher, kdf], rest) =
+            rest.split_first_chunk::<3>().ok_or_else(truncated)?;
+        if version != FORMAT_VERSION {
+            return Err(EnjectError::UnsupportedStoreFormat(format!(
+                "format version {} (this enject reads version {}); upgrade enject",
+                version, FORMAT_VERSION
+            )));
+        }
+        let cipher = Cipher::from_id(cipher)
+            .ok_or_else(|| EnjectError::UnsupportedStoreFormat(format!("cipher id {}", cipher)))?;
+        if kdf != KDF_ARGON2ID {
+            return Err(EnjectError::UnsupportedStoreFormat(format!(
+                "KDF id {}",
+                kdf
+            )));
+        }
+        let (costs, rest) = rest.split_first_chunk::<12>().ok_or_else(truncated)?;
+        let cost =
+            |i: usize| u32::from_le_bytes([costs[i], costs[i + 1], costs[i + 2], costs[i + 3]]);
+        let (&salt_len, rest) = rest.split_first().ok_or_else(truncated)?;
+        if rest.len() < salt_len as usize {
+            return Err(truncated());
+        }
+        let (salt, body) = rest.split_at(salt_len as usize);
+        let kdf_params = KdfParams {
+            m_cost: cost(0),
+            t_cost: cost(4),
+            p_cost: cost(8),
+        };
+        // Low costs stay readable: stores made before the minimum, and test stores
+        if let Some(reason) = kdf_params.above_max() {
+            return Err(EnjectError::CorruptStore(format!(
+                "Store header: {}.",
+                reason
+            )));
+        }
+        let header = Header {
+            cipher,
+            kdf_params,
+            salt: salt.to_vec(),
+        };
+        Ok(Some((header, file.len() - body.len())))
+    }
 }
 
-#[derive(Clone, Debug)]
+/// Argon2id cost parameters.
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct KdfParams {
+    /// Memory cost in KiB.
     pub m_cost: u32,
+    /// Number of iterations.
     pub t_cost: u32,
+    /// Degree of parallelism.
     pub p_cost: u32,
 }
 
@@ -44,128 +277,502 @@ impl Default for KdfParams {
     }
 }
 
+/// Argon2id memory cost below 19 MiB (the OWASP minimum) is refused for new parameters.
+pub const MIN_M_COST: u32 = 19456;
+/// `calibrate` never asks for more than 1 GiB.
+const MAX_CALIBRATED_M_COST: u32 = 1 << 20;
+/// The highest costs a store may use. A header asking for more is read as corrupt,
+/// since the costs are used before decryption can authenticate them: 4 GiB of memory.
+const MAX_M_COST: u32 = 1 << 22;
+/// The most passes a store may use, likewise.
+const MAX_T_COST: u32 = 256;
+/// The most lanes a store may use, likewise.
+const MAX_P_COST: u32 = 256;
+
+impl KdfParams {
+    /// 19 MiB, the OWASP minimum for Argon2id, for machines where the default is too slow.
+    pub fn interactive() -> Self {
+        Self {
+            m_cost: MIN_M_COST,
+            t_cost: 2,
+            p_cost: 1,
+        }
+    }
+
+    /// The default parameters.
+    pub fn moderate() -> Self {
+        Self::default()
+    }
+
+    /// 256 MiB and four passes.
+    pub fn paranoid() -> Self {
+        Self {
+            m_cost: 262144,
+            t_cost: 4,
+            p_cost: 4,
+        }
+    }
+}
+
+impl KdfParams {
+    /// Refuse parameters too weak to slow down a password guess, or that Argon2 rejects.
+    pub fn validate(&self) -> Result<()> {
+        if self.m_cost < MIN_M_COST {
+            return Err(EnjectError::UnsafeKdf(format!(
+                "m_cost {} KiB is below the {} KiB (19 MiB) minimum",
+                self.m_cost, MIN_M_COST
+            )));
+        }
+        if self.t_cost == 0 {
+            return Err(EnjectError::UnsafeKdf("t_cost must be at least 1".into()));
+        }
+        if self.p_cost == 0 {
+            return Err(EnjectError::UnsafeKdf("p_cost must be at least 1".into()));
+        }
+        if let Some(reason) = self.above_max() {
+            return Err(EnjectError::UnsafeKdf(reason));
+        }
+        Params::new(self.m_cost, self.t_cost, self.p_cost, Some(KEY_LEN))
+            .map_err(|e| EnjectError::UnsafeKdf(e.to_string()))?;
+        Ok(())
+    }
+}
+
+impl KdfParams {
+    /// Why these costs are above what a store may use, if they are.
+    fn above_max(&self) -> Option<String> {
+        if self.m_cost > MAX_M_COST {
+            Some(format!(
+                "m_cost {} KiB is above the {} KiB (4 GiB) maximum",
+                self.m_cost, MAX_M_COST
+            ))
+        } else if self.t_cost > MAX_T_COST {
+            Some(format!(
+                "t_cost {} is above the maximum of {}",
+                self.t_cost, MAX_T_COST
+            ))
+        } else if self.p_cost > MAX_P_COST {
+            Some(format!(
+                "p_cost {} is above the maximum of {}",
+                self.p_cost, MAX_P_COST
+            ))
+        } else {
+            None
+        }
+    }
+}
+
+/// Pick Argon2id parameters that take about `target` to derive a key on this machine.
+/// One derivation at the minimum memory cost is timed; memory is scaled up from there
+/// (time grows roughly linearly with it), and once memory reaches 1 GiB the remaining
+/// factor goes into extra passes. Never returns less than `KdfParams::interactive()`.
+pub fn calibrate(target: Duration) -> Result<KdfParams> {
+    let base = KdfParams::interactive();
+    let started = Instant::now();
+    let mut key = derive_key(b"calibration", &[0u8; 32], &base)?;
+    let elapsed = started.elapsed();
+    key.zeroize();
+
+    let scale = target.as_secs_f64() / elapsed.as_secs_f64().max(0.001);
+    if scale <= 1.0 {
+        return Ok(base);
+    }
+    let m_cost = (base.m_cost as f64 * scale).min(MAX_CALIBRATED_M_COST as f64) as u32;
+    let remaining = scale * base.m_cost as f64 / m_cost as f64;
+    let t_cost = ((base.t_cost as f64 * remaining).round() as u32).clamp(base.t_cost, MAX_T_COST);
+    Ok(KdfParams {
+        m_cost,
+        t_cost,
+        p_cost: base.p_cost,
+    })
+}
+
 impl PasswordStore {
+    /// A locked store kept at `store_path`, whose key is derived with `kdf_params`
+    /// and `salt`. Nothing is read until it is unlocked.
     pub fn new(store_path: PathBuf, kdf_params: KdfParams, salt: Vec<u8>) -> Self {
         Self {
             store_path,
+            cipher: Cipher::default(),
             kdf_params,
             salt,
             secrets: None,
+            history_depth: DEFAULT_HISTORY_DEPTH,
+            lock: None,
+            read_only: false,
+            lock_timeout: file::DEFAULT_LOCK_TIMEOUT,
+            on_disk: None,
+            overwrite_changes: false,
+            cached_key: None,
+            unlocked_with: None,
+        }
+    }
+
+    /// Encrypt a new store file with `cipher`.
+    pub fn with_cipher(mut self, cipher: Cipher) -> Self {
+        self.cipher = cipher;
+        self
+    }
+
+    /// The cipher the next save uses: the unlocked file's, unless `rekey` changed it.
+    pub fn cipher(&self) -> Cipher {
+        self.cipher
+    }
+
+    /// The KDF parameters the next save uses, like `cipher`.
+    pub fn kdf_params(&self) -> &KdfParams {
+        &self.kdf_params
+    }
+
+    /// Keep `depth` previous values per secret (0 keeps none).
+    pub fn with_history_depth(mut self, depth: usize) -> Self {
+        self.history_depth = depth;
+        self
+    }
+
+    /// Give up on `unlock` after waiting `timeout` for another process's lock.
+    #[cfg(test)]
+    fn with_lock_timeout(mut self, timeout: Duration) -> Self {
+        self.lock_timeout = timeout;
+        self
+    }
+
+    /// Lock the store for writing, then decrypt it and load secrets into memory.
+    /// Other processes can neither read nor write it until this store is dropped.
+    pub fn unlock(&mut self, password: &SecretString) -> Result<()> {
+        self.lock(true)?;
+        self.load_or_release(Credential::Password(password))
+    }
+
+    /// Like `unlock`, but only keeps writers out, so readers don't wait on each other.
+    /// `save` refuses to write a store unlocked this way.
+    pub fn unlock_read_only(&mut self, password: &SecretString) -> Result<()> {
+        self.lock(false)?;
+        self.read_only = true;
+        self.load_or_release(Credential::Password(password))
+    }
+
+    /// `unlock` or `unlock_read_only` with a key derived earlier instead of the
+    /// password. Fails with `DecryptionFailed` if the key doesn't open the store, or
+    /// if there is no store file yet to check it against.
+    pub fn unlock_with_key(&mut self, key: &[u8; KEY_LEN], read_only: bool) -> Result<()> {
+        self.lock(!read_only)?;
+        self.read_only = read_only;
+        self.load_or_release(Credential::Key(key))
+    }
+
+    /// The key the store file was opened with, once unlocked.
+    pub fn derived_key(&self) -> Option<&[u8; KEY_LEN]> {
+        self.cached_key.as_ref().map(|cached| &*cached.key)
+    }
+
+    /// Structural problems in the unlocked entries that a save would write back
+    /// (see `Entries::problems`). Empty for a healthy store.
+    pub fn problems(&self) -> Result<Vec<String>> {
+        Ok(self.secrets_ref()?.problems())
+    }
+
+    /// `load`, dropping the lock again if the store doesn't open.
+    fn load_or_release(&mut self, credential: Credential) -> Result<()> {
+        let result = self
+            .load(credential)
+            .and_then(|()| FileStamp::read(&self.store_path));
+        match result {
+            Ok(stamp) => {
+                self.on_disk = Some(stamp);
+                if let Credential::Password(password) = credential {
+                    self.unlocked_with = Some(SecretString::new(password.expose_secret().clone()));
+                }
+                Ok(())
+            }
+            Err(e) => {
+                self.lock = None;
+                self.read_only = false;
+                self.cached_key = None;
+                Err(e)
+            }
         }
     }
 
+    /// Refuse to replace a store file that changed since it was unlocked or saved.
+    fn check_unchanged(&self) -> Result<()> {
+        match &self.on_disk {
+            Some(stamp) if !self.overwrite_changes => {
+                if FileStamp::read(&self.store_path)? != *stamp {
+                    return Err(EnjectError::StoreChangedOnDisk);
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Take the advisory lock on `store.lock`, polling until `lock_timeout`.
+    fn lock(&mut self, exclusive: bool) -> Result<()> {
+        self.lock = Some(file::lock(&self.store_path, exclusive, self.lock_timeout)?);
+        Ok(())
+    }
+
     /// Decrypt the store file and load secrets into memory.
     /// If the store file does not exist yet, initializes an empty in-memory map.
-    pub fn unlock(&mut self, password: &SecretString) -> Result<()> {
-        if !self.store_path.exists() {
-            self.secrets = Some(HashMap::new());
+    /// The file's header picks the cipher, salt and KDF parameters, which are kept for
+    /// later saves; a headerless file is AES-256-GCM with the ones this store was built with.
+    ///
+    /// If a rotation was interrupted after writing the re-keyed store, whichever of
+    /// the two files `credential` opens is the real store: a re-keyed store that opens
+    /// is moved into place, and a leftover one that doesn't is removed once the old
+    /// store has opened. A read-only unlock shares the lock with other readers, so it
+    /// opens whichever file decrypts and leaves both where they are.
+    fn load(&mut self, credential: Credential) -> Result<()> {
+        let pending = self.pending_path();
+        if !self.store_path.exists() && !pending.exists() {
+            if let Credential::Key(_) = credential {
+                return Err(EnjectError::DecryptionFailed);
+            }
+            self.secrets = Some(Entries::default());
             return Ok(());
         }
 
-        let ciphertext_with_nonce = std::fs::read(&self.store_path)?;
-        if ciphertext_with_nonce.len() < NONCE_LEN {
+        let OpenedFile {
+            secrets,
+            header,
+            key,
+        } = if pending.exists() && self.read_only {
+            match self.decrypt_file(&pending, credential) {
+                Ok(opened) => opened,
+                Err(_) => self.decrypt_file(&self.store_path, credential)?,
+            }
+        } else if pending.exists() {
+            match self.decrypt_file(&pending, credential) {
+                Ok(opened) => {
+                    std::fs::rename(&pending, &self.store_path)?;
+                    opened
+                }
+                Err(_) => {
+                    let opened = self.decrypt_file(&self.store_path, credential)?;
+                    std::fs::remove_file(&pending)?;
+                    opened
+                }
+            }
+        } else {
+            self.decrypt_file(&self.store_path, credential)?
+        };
+
+        if let Some(header) = header {
+            self.cipher = header.cipher;
+            self.kdf_params = header.kdf_params;
+            self.salt = header.salt;
+        }
+        self.secrets = Some(secrets);
+        self.cached_key = Some(CachedKey {
+            password: match credential {
+                Credential::Password(password) => {
+                    Some(SecretString::new(password.expose_secret().clone()))
+                }
+                Credential::Key(_) => None,
+            },
+            key,
+        });
+        Ok(())
+    }
+
+    /// Decrypt and parse one store file, keeping its header and the key that opened it.
+    fn decrypt_file(&self, path: &Path, credential: Credential) -> Result<OpenedFile> {
+        let file = std::fs::read(path)?;
+        let (header, header_len) = match Header::decode(&file)? {
+            Some((header, len)) => (Some(header), len),
+            None => (None, 0),
+        };
+        let (aad, ciphertext_with_nonce) = file.split_at(header_len);
+        let (cipher, kdf_params, salt) = match &header {
+            Some(header) => (header.cipher, &header.kdf_params, &header.salt),
+            None => (Cipher::Aes256Gcm, &self.kdf_params, &self.salt),
+        };
+        if ciphertext_with_nonce.len() < cipher.nonce_len() {
             return Err(EnjectError::CorruptStore(
                 "Store file too short to contain a nonce.".into(),
             ));
         }
 
-        let (nonce_bytes, ciphertext) = ciphertext_with_nonce.split_at(NONCE_LEN);
+        let (nonce_bytes, ciphertext) = ciphertext_with_nonce.split_at(cipher.nonce_len());
 
-        let mut key = derive_key(
-            password.expose_secret().as_bytes(),
-            &self.salt,
-            &self.kdf_params,
-        )?;
-
-        let plaintext_result = {
-            let cipher = Aes256Gcm::new_from_slice(&key)
-                .map_err(|_| EnjectError::CorruptStore("Invalid key length.".into()))?;
-            let nonce = Nonce::from_slice(nonce_bytes);
-            cipher
-                .decrypt(nonce, ciphertext)
-                .map_err(|_| EnjectError::DecryptionFailed)
+        let key = match credential {
+            Credential::Password(password) => Zeroizing::new(derive_key(
+                password.expose_secret().as_bytes(),
+                salt,
+                kdf_params,
+            )?),
+            Credential::Key(key) => Zeroizing::new(*key),
         };
+        let started = Instant::now();
+        let plaintext = Zeroizing::new(cipher.decrypt(
+            &key,
+            nonce_bytes,
+            Payload {
+                msg: ciphertext,
+                aad,
+            },
+        )?);
+        tracing::trace!(
+            "Decrypted {} ({} bytes, {}) in {:?}",
+            path.display(),
+            file.len(),
+            cipher.name(),
+            started.elapsed()
+        );
 
-        key.zeroize();
+        let stored: StoredSecrets = serde_json::from_slice(&plaintext)
+            .map_err(|e| EnjectError::CorruptStore(e.to_string()))?;
 
-        let plaintext = plaintext_result?;
+        let secrets = match stored {
+            StoredSecrets::Entries(entries) => entries,
+            StoredSecrets::Legacy(values) => {
+                // The last write is the best guess there is for both timestamps
+                let modified = std::fs::metadata(path)
+                    .and_then(|m| m.modified())
+                    .unwrap_or_else(|_| SystemTime::now());
+                Entries::from_values(values, &rfc3339(modified))
+            }
+        };
+        Ok(OpenedFile {
+            secrets,
+            header,
+            key,
+        })
+    }
 
-        let secrets: HashMap<String, String> = serde_json::from_slice(&plaintext)
-            .map_err(|e| EnjectError::CorruptStore(e.to_string()))?;
+    /// Encrypt the in-memory secrets and write them atomically to disk.
+    /// Fails if the file changed on disk since `unlock` (see `overwrite_changes`).
+    pub fn save(&mut self, password: &SecretString) -> Result<()> {
+        let key = self.key_for(password)?;
+        self.save_with_key(&key)
+    }
 
-        self.secrets = Some(secrets);
+    fn save_with_key(&mut self, key: &[u8; KEY_LEN]) -> Result<()> {
+        self.check_unchanged()?;
+        self.write_to(&self.store_path, key)?;
+        if self.on_disk.is_some() {
+            self.on_disk = Some(FileStamp::read(&self.store_path)?);
+        }
         Ok(())
     }
 
-    /// Encrypt the in-memory secrets and write them atomically to disk.
-    pub fn save(&self, password: &SecretString) -> Result<()> {
+    /// Use a new cipher, salt and KDF parameters for every later save. The file on
+    /// disk keeps the old ones until `save_pending` and `commit_pending`.
+    pub fn rekey(&mut self, cipher: Cipher, kdf_params: KdfParams, salt: Vec<u8>) {
+        self.cipher = cipher;
+        self.kdf_params = kdf_params;
+        self.salt = salt;
+        self.cached_key = None;
+        self.unlocked_with = None;
+    }
+
+    /// Write the store beside the current one, leaving the current one untouched.
+    /// The caller records the new salt and KDF parameters in the config, then calls
+    /// `commit_pending`; `unlock` sorts out a crash in between.
+    pub fn save_pending(&self, password: &SecretString) -> Result<()> {
+        let key = self.key_for(password)?;
+        self.write_to(&self.pending_path(), &key)
+    }
+
+    /// Replace the store with the file written by `save_pending`.
+    pub fn commit_pending(&mut self) -> Result<()> {
+        self.check_unchanged()?;
+        std::fs::rename(self.pending_path(), &self.store_path)?;
+        if self.on_disk.is_some() {
+            self.on_disk = Some(FileStamp::read(&self.store_path)?);
+        }
+        Ok(())
+    }
+
+    fn pending_path(&self) -> PathBuf {
+        self.store_path.with_extension("rekeyed")
+    }
+
+    fn write_to(&self, dest: &Path, key: &[u8; KEY_LEN]) -> Result<()> {
+        if self.read_only {
+            return Err(EnjectError::Config(
+                "Store was unlocked read-only and cannot be saved.".into(),
+            ));
+        }
         let secrets = self.secrets_ref()?;
 
         let mut json_bytes =
             serde_json::to_vec(secrets).map_err(|e| EnjectError::Serialization(e.to_string()))?;
 
-        let mut key = derive_key(
-            password.expose_secret().as_bytes(),
-            &self.salt,
-            &self.kdf_params,
-        )?;
+        let header = Header {
+            cipher: self.cipher,
+            kdf_params: self.kdf_params.clone(),
+            salt: self.salt.clone(),
+        }
+        .encode()?;
 
-        let mut nonce_bytes = [0u8; NONCE_LEN];
+        let mut nonce_bytes = vec![0u8; self.cipher.nonce_len()];
         rand::thread_rng().fill_bytes(&mut nonce_bytes);
-        let nonce = Nonce::from_slice(&nonce_bytes);
-
-        let ciphertext_result = {
-            let cipher = Aes256Gcm::new_from_slice(&key)
-                .map_err(|_| EnjectError::CorruptStore("Invalid key length.".into()))?;
-            cipher
-                .encrypt(nonce, json_bytes.as_ref())
-                .map_err(|_| EnjectError::CorruptStore("Encryption failed.".into()))
-        };
 
-        key.zeroize();
+        let ciphertext_result = self.cipher.encrypt(
+            key,
+            &nonce_bytes,
+            Payload {
+                msg: &json_bytes,
+                aad: &header,
+            },
+        );
+
         json_bytes.zeroize();
 
         let ciphertext = ciphertext_result?;
 
-        // Atomic write: write to temp file → fsync → rename
-        let parent = self
-            .store_path
-            .parent()
-            .ok_or_else(|| EnjectError::Config("Store has no parent directory.".into()))?;
-
-        let tmp_path = parent.join(format!(".store.tmp.{}", rand::random::<u64>()));
+        file::write_atomic(dest, &[&header, &nonce_bytes, &ciphertext])
+    }
 
-        {
-            let mut tmp = std::fs::File::create(&tmp_path)?;
-            tmp.write_all(&nonce_bytes)?;
-            tmp.write_all(&ciphertext)?;
-            tmp.sync_all()?;
+    /// The key for `password` under the current salt and KDF parameters: the one
+    /// `unlock` derived when the password is the same, otherwise a fresh derivation.
+    fn key_for(&self, password: &SecretString) -> Result<Zeroizing<[u8; KEY_LEN]>> {
+        if let Some(cached) = &self.cached_key {
+            let same = cached.password.as_ref().map(|p| p.expose_secret());
+            if same == Some(password.expose_secret()) {
+                return Ok(cached.key.clone());
+            }
         }
-
-        std::fs::rename(&tmp_path, &self.store_path)?;
-        Ok(())
+        Ok(Zeroizing::new(derive_key(
+            password.expose_secret().as_bytes(),
+            &self.salt,
+            &self.kdf_params,
+        )?))
     }
 
     /// Create a new empty store file, encrypted with the given password.
     pub fn create_empty(
         store_path: &Path,
+        cipher: Cipher,
         kdf_params: KdfParams,
         salt: Vec<u8>,
         password: &SecretString,
     ) -> Result<Self> {
-        let mut store = Self::new(store_path.to_path_buf(), kdf_params, salt);
-        store.secrets = Some(HashMap::new());
+        let mut store = Self::new(store_path.to_path_buf(), kdf_params, salt).with_cipher(cipher);
+        store.secrets = Some(Entries::default());
         store.save(password)?;
         Ok(store)
     }
 
-    fn secrets_mut(&mut self) -> Result<&mut HashMap<String, String>> {
+    /// Hand the unlocked secrets over to another backend, leaving this store empty.
+    #[cfg(feature = "age")]
+    pub(super) fn take_secrets(&mut self) -> Result<(PathBuf, Entries)> {
+        let secrets = self
+            .secrets
+            .take()
+            .ok_or_else(|| EnjectError::CorruptStore("Store not unlocked.".into()))?;
+        Ok((self.store_path.clone(), secrets))
+    }
+
+    fn secrets_mut(&mut self) -> Result<&mut Entries> {
         self.secrets
             .as_mut()
             .ok_or_else(|| EnjectError::CorruptStore("Store not unlocked.".into()))
     }
 
-    fn secrets_ref(&self) -> Result<&HashMap<String, String>> {
+    fn secrets_ref(&self) -> Result<&Entries> {
         self.secrets
             .as_ref()
             .ok_or_else(|| EnjectError::CorruptStore("Store not unlocked.".into()))
@@ -174,41 +781,95 @@ impl PasswordStore {
 
 impl Store for PasswordStore {
     fn get(&self, key: &str) -> Result<Option<SecretString>> {
-        let secrets = self.secrets_ref()?;
-        Ok(secrets.get(key).map(|v| SecretString::new(v.clone())))
+        Ok(self.secrets_ref()?.get(key))
     }
 
     fn set(&mut self, key: &str, value: SecretString) -> Result<()> {
-        let secrets = self.secrets_mut()?;
-        secrets.insert(key.to_string(), value.expose_secret().to_string());
+        let depth = self.history_depth;
+        self.secrets_mut()?.set(key, value, depth);
         Ok(())
     }
 
     fn delete(&mut self, key: &str) -> Result<bool> {
-        let secrets = self.secrets_mut()?;
-        Ok(secrets.remove(key).is_some())
+        Ok(self.secrets_mut()?.delete(key))
     }
 
     fn list(&self) -> Result<Vec<String>> {
-        let secrets = self.secrets_ref()?;
-        let mut keys: Vec<String> = secrets.keys().cloned().collect();
-        keys.sort();
-        Ok(keys)
+        Ok(self.secrets_ref()?.list())
+    }
+
+    fn metadata(&self, key: &str) -> Result<Option<SecretMetadata>> {
+        Ok(self.secrets_ref()?.metadata(key))
+    }
+
+    fn annotate(&mut self, key: &str, annotation: Annotation) -> Result<bool> {
+        Ok(self.secrets_mut()?.annotate(key, annotation))
+    }
+
+    fn history(&self, key: &str) -> Result<Vec<SecretVersion>> {
+        Ok(self.secrets_ref()?.history(key))
+    }
+
+    fn restore_version(&mut self, key: &str, number: usize) -> Result<bool> {
+        let depth = self.history_depth;
+        Ok(self.secrets_mut()?.restore_version(key, number, depth))
+    }
+
+    fn delete_keeping_history(&mut self, key: &str) -> Result<bool> {
+        let depth = self.history_depth;
+        Ok(self.secrets_mut()?.delete_keeping_history(key, depth))
+    }
+
+    /// Save under the password or key the store was unlocked with.
+    fn persist(&mut self) -> Result<()> {
+        let key = match (&self.cached_key, &self.unlocked_with) {
+            (Some(cached), _) => cached.key.clone(),
+            (None, Some(password)) => self.key_for(password)?,
+            (None, None) => return Err(EnjectError::CorruptStore("Store not unlocked.".into())),
+        };
+        self.save_with_key(&key)
+    }
+
+    /// Let `save` replace the store file even if it changed on disk since `unlock`.
+    fn overwrite_changes(&mut self) {
+        self.overwrite_changes = true;
     }
 }
 
+/// A short, non-secret name for a store derived from its salt: hex of the first 8
+/// bytes of its BLAKE2b-512 hash. Identifies the store's cached key in the OS keychain.
+pub fn salt_fingerprint(salt: &[u8]) -> String {
+    hex::encode(&Blake2b512::digest(salt)[..8])
+}
+
+#[cfg(test)]
+thread_local! {
+    // How many times `derive_key` ran on this thread
+    static DERIVATIONS: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
+
 /// Derive a 32-byte AES key from the given password and salt using Argon2id.
 /// The caller is responsible for zeroizing the returned array after use.
 fn derive_key(password: &[u8], salt: &[u8], params: &KdfParams) -> Result<[u8; KEY_LEN]> {
+    #[cfg(test)]
+    DERIVATIONS.with(|count| count.set(count.get() + 1));
     let argon2_params = Params::new(params.m_cost, params.t_cost, params.p_cost, Some(KEY_LEN))
         .map_err(|e| EnjectError::Config(e.to_string()))?;
 
     let argon2 = Argon2::new(Algorithm::Argon2id, Version::V0x13, argon2_params);
 
+    let started = Instant::now();
     let mut key = [0u8; KEY_LEN];
     argon2
         .hash_password_into(password, salt, &mut key)
         .map_err(|e| EnjectError::Config(e.to_string()))?;
+    tracing::trace!(
+        "Argon2id key derivation (m_cost={}, t_cost={}, p_cost={}) took {:?}",
+        params.m_cost,
+        params.t_cost,
+        params.p_cost,
+        started.elapsed()
+    );
 
     Ok(key)
 }
@@ -232,6 +893,37 @@ mod tests {
         (0u8..32).collect()
     }
 
+    #[test]
+    fn test_validate_rejects_weak_params() {
+        assert!(KdfParams::default().validate().is_ok());
+        assert!(KdfParams::interactive().validate().is_ok());
+        assert!(matches!(
+            test_params().validate(),
+            Err(EnjectError::UnsafeKdf(_))
+        ));
+        let no_passes = KdfParams {
+            t_cost: 0,
+            ..KdfParams::default()
+        };
+        assert!(matches!(
+            no_passes.validate(),
+            Err(EnjectError::UnsafeKdf(_))
+        ));
+        // Nor can a store be given costs its header would be refused for
+        let huge = KdfParams {
+            m_cost: MAX_M_COST + 1,
+            ..KdfParams::default()
+        };
+        assert!(matches!(huge.validate(), Err(EnjectError::UnsafeKdf(_))));
+    }
+
+    #[test]
+    fn test_calibrate_never_goes_below_minimum() {
+        let params = calibrate(Duration::ZERO).unwrap();
+        assert_eq!(params, KdfParams::interactive());
+        assert!(params.validate().is_ok());
+    }
+
     fn test_password() -> SecretString {
         SecretString::new("test-password-do-not-use".to_string())
     }
@@ -260,12 +952,67 @@ mod tests {
         store.save(&password).unwrap();
 
         // Reload from disk
+        drop(store);
         let mut store2 = PasswordStore::new(store_path, test_params(), test_salt());
         store2.unlock(&password).unwrap();
         let retrieved = store2.get("my_key").unwrap().expect("key should exist");
         assert_eq!(retrieved.expose_secret(), "super-secret-value");
     }
 
+    #[test]
+    fn test_persist_saves_under_the_unlock_password() {
+        let dir = TempDir::new().unwrap();
+        let mut store = setup_unlocked_store(&dir);
+        store.set("k", SecretString::new("v".to_string())).unwrap();
+        store.persist().unwrap();
+        drop(store);
+
+        let store = setup_unlocked_store(&dir);
+        assert_eq!(store.get("k").unwrap().unwrap().expose_secret(), "v");
+
+        let mut locked = PasswordStore::new(dir.path().join("other"), test_params(), test_salt());
+        assert!(locked.persist().is_err());
+    }
+
+    #[test]
+    fn test_unlock_with_derived_key() {
+        let dir = TempDir::new().unwrap();
+        // No file yet, so there is nothing to check a key against
+        let mut store = PasswordStore::new(dir.path().join("store"), test_params(), test_salt());
+        assert!(matches!(
+            store.unlock_with_key(&[1u8; KEY_LEN], true),
+            Err(EnjectError::DecryptionFailed)
+        ));
+        drop(store);
+
+        let mut store = setup_unlocked_store(&dir);
+
+        store.set("k", SecretString::new("v1".to_string())).unwrap();
+        store.save(&test_password()).unwrap();
+        drop(store);
+
+        let store = setup_unlocked_store(&dir);
+        let key = *store.derived_key().unwrap();
+        drop(store);
+
+        let mut store = PasswordStore::new(dir.path().join("store"), test_params(), test_salt());
+        store.unlock_with_key(&key, false).unwrap();
+        store.set("k", SecretString::new("v2".to_string())).unwrap();
+        store.persist().unwrap();
+        drop(store);
+
+        // Saved under the same key, so the password still opens it
+        let store = setup_unlocked_store(&dir);
+        assert_eq!(store.get("k").unwrap().unwrap().expose_secret(), "v2");
+        drop(store);
+
+        let mut store = PasswordStore::new(dir.path().join("store"), test_params(), test_salt());
+        assert!(matches!(
+            store.unlock_with_key(&[0u8; KEY_LEN], true),
+            Err(EnjectError::DecryptionFailed)
+        ));
+    }
+
     #[test]
     fn test_wrong_password_returns_err() {
         let dir = TempDir::new().unwrap();
@@ -280,6 +1027,7 @@ mod tests {
             .unwrap();
         store.save(&password).unwrap();
 
+        drop(store);
         let mut store2 = PasswordStore::new(store_path, test_params(), test_salt());
         let result = store2.unlock(&wrong);
         assert!(result.is_err(), "Wrong password should return Err");
@@ -298,16 +1046,301 @@ mod tests {
             .unwrap();
         store.save(&password).unwrap();
 
-        // Flip a byte in the ciphertext region (past the nonce)
+        // Flip a byte in the ciphertext region (past the header and nonce)
         let mut bytes = std::fs::read(&store_path).unwrap();
-        bytes[NONCE_LEN + 5] ^= 0xFF;
+        let (_, header_len) = Header::decode(&bytes).unwrap().unwrap();
+        bytes[header_len + Cipher::Aes256Gcm.nonce_len() + 5] ^= 0xFF;
         std::fs::write(&store_path, bytes).unwrap();
 
+        drop(store);
         let mut store2 = PasswordStore::new(store_path, test_params(), test_salt());
         let result = store2.unlock(&password);
         assert!(result.is_err(), "Tampered ciphertext should return Err");
     }
 
+    #[test]
+    fn test_header_carries_kdf_params_and_salt() {
+        let dir = TempDir::new().unwrap();
+        let store_path = dir.path().join("store");
+        let password = test_password();
+        let mut store = PasswordStore::new(store_path.clone(), test_params(), test_salt());
+        store.unlock(&password).unwrap();
+        store.set("k", SecretString::new("v".to_string())).unwrap();
+        store.save(&password).unwrap();
+
+        let bytes = std::fs::read(&store_path).unwrap();
+        let (header, _) = Header::decode(&bytes).unwrap().unwrap();
+        assert_eq!(
+            header,
+            Header {
+                cipher: Cipher::Aes256Gcm,
+                kdf_params: test_params(),
+                salt: test_salt(),
+            }
+        );
+
+        // A config that drifted from the store doesn't matter: the header wins
+        drop(store);
+        let mut store2 = PasswordStore::new(store_path, KdfParams::default(), vec![9u8; 32]);
+        store2.unlock(&password).unwrap();
+        assert_eq!(store2.get("k").unwrap().unwrap().expose_secret(), "v");
+        assert_eq!(store2.kdf_params, test_params());
+        assert_eq!(store2.salt, test_salt());
+    }
+
+    #[test]
+    fn test_xchacha_store_roundtrips_and_is_not_read_as_aes() {
+        let dir = TempDir::new().unwrap();
+        let store_path = dir.path().join("store");
+        let password = test_password();
+        let mut store = PasswordStore::new(store_path.clone(), test_params(), test_salt())
+            .with_cipher(Cipher::XChaCha20Poly1305);
+        store.unlock(&password).unwrap();
+        store.set("k", SecretString::new("v".to_string())).unwrap();
+        store.save(&password).unwrap();
+
+        // The header picks the cipher, whatever the store was built with
+        drop(store);
+        let mut store2 = PasswordStore::new(store_path.clone(), test_params(), test_salt());
+        store2.unlock(&password).unwrap();
+        assert_eq!(store2.cipher(), Cipher::XChaCha20Poly1305);
+        assert_eq!(store2.get("k").unwrap().unwrap().expose_secret(), "v");
+        drop(store2);
+
+        // Relabelling the file as AES-256-GCM must fail, not misread it
+        let original = std::fs::read(&store_path).unwrap();
+        let cipher_at = MAGIC.len() + 1;
+        assert_eq!(original[cipher_at], Cipher::XChaCha20Poly1305.id());
+        let mut bytes = original.clone();
+        bytes[cipher_at] = Cipher::Aes256Gcm.id();
+        std::fs::write(&store_path, &bytes).unwrap();
+        let mut store3 = PasswordStore::new(store_path.clone(), test_params(), test_salt());
+        assert!(matches!(
+            store3.unlock(&password),
+            Err(EnjectError::DecryptionFailed)
+        ));
+    }
+
+    #[test]
+    fn test_aes_store_is_not_read_as_xchacha() {
+        let dir = TempDir::new().unwrap();
+        let store_path = dir.path().join("store");
+        let password = test_password();
+        let mut store = PasswordStore::new(store_path.clone(), test_params(), test_salt());
+        store.unlock(&password).unwrap();
+        store.set("k", SecretString::new("v".to_string())).unwrap();
+        store.save(&password).unwrap();
+
+        let mut bytes = std::fs::read(&store_path).unwrap();
+        bytes[MAGIC.len() + 1] = Cipher::XChaCha20Poly1305.id();
+        std::fs::write(&store_path, &bytes).unwrap();
+        drop(store);
+        let mut store2 = PasswordStore::new(store_path, test_params(), test_salt())
+            .with_cipher(Cipher::XChaCha20Poly1305);
+        assert!(matches!(
+            store2.unlock(&password),
+            Err(EnjectError::DecryptionFailed)
+        ));
+    }
+
+    #[test]
+    fn test_tampered_or_unknown_header_returns_err() {
+        let dir = TempDir::new().unwrap();
+        let store_path = dir.path().join("store");
+        let password = test_password();
+        let mut store = PasswordStore::new(store_path.clone(), test_params(), test_salt());
+        store.unlock(&password).unwrap();
+        store.save(&password).unwrap();
+        let original = std::fs::read(&store_path).unwrap();
+        let (_, header_len) = Header::decode(&original).unwrap().unwrap();
+
+        // Flip the last salt byte
+        let mut bytes = original.clone();
+        bytes[header_len - 1] ^= 0x01;
+        std::fs::write(&store_path, &bytes).unwrap();
+        drop(store);
+        let mut store2 = PasswordStore::new(store_path.clone(), test_params(), test_salt());
+        assert!(matches!(
+            store2.unlock(&password),
+            Err(EnjectError::DecryptionFailed)
+        ));
+
+        // Oversized costs are refused before any key derivation is attempted
+        for offset in [0, 4, 8] {
+            let mut bytes = original.clone();
+            let at = MAGIC.len() + 3 + offset;
+            bytes[at..at + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+            std::fs::write(&store_path, &bytes).unwrap();
+            let mut store = PasswordStore::new(store_path.clone(), test_params(), test_salt());
+            assert!(matches!(
+                store.unlock(&password),
+                Err(EnjectError::CorruptStore(_))
+            ));
+        }
+
+        // A newer format version is refused rather than misread
+        let mut bytes = original;
+        bytes[MAGIC.len()] = FORMAT_VERSION + 1;
+        std::fs::write(&store_path, &bytes).unwrap();
+        let mut store3 = PasswordStore::new(store_path, test_params(), test_salt());
+        assert!(matches!(
+            store3.unlock(&password),
+            Err(EnjectError::UnsupportedStoreFormat(_))
+        ));
+    }
+
+    #[test]
+    fn test_concurrent_writers_lose_no_updates() {
+        let dir = TempDir::new().unwrap();
+        let store_path = dir.path().join("store");
+        let barrier = std::sync::Arc::new(std::sync::Barrier::new(2));
+
+        let writers: Vec<_> = ["a", "b"]
+            .into_iter()
+            .map(|writer| {
+                let store_path = store_path.clone();
+                let barrier = barrier.clone();
+                std::thread::spawn(move || {
+                    barrier.wait();
+                    for i in 0..5 {
+                        let password = test_password();
+                        let mut store =
+                            PasswordStore::new(store_path.clone(), test_params(), test_salt());
+                        store.unlock(&password).unwrap();
+                        store
+                            .set(
+                                &format!("{}{}", writer, i),
+                                SecretString::new("v".to_string()),
+                            )
+                            .unwrap();
+                        store.save(&password).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        let mut store = PasswordStore::new(store_path, test_params(), test_salt());
+        store.unlock(&test_password()).unwrap();
+        assert_eq!(store.list().unwrap().len(), 10);
+    }
+
+    #[test]
+    fn test_lock_blocks_writers_but_not_readers() {
+        let dir = TempDir::new().unwrap();
+        let store_path = dir.path().join("store");
+        let password = test_password();
+        let open = || {
+            PasswordStore::new(store_path.clone(), test_params(), test_salt())
+                .with_lock_timeout(Duration::from_millis(100))
+        };
+
+        let mut reader = open();
+        reader.unlock_read_only(&password).unwrap();
+        let mut other_reader = open();
+        other_reader.unlock_read_only(&password).unwrap();
+        assert!(matches!(
+            open().unlock(&password),
+            Err(EnjectError::StoreLocked(_))
+        ));
+        assert!(reader.save(&password).is_err());
+        drop(reader);
+        drop(other_reader);
+
+        let mut writer = open();
+        writer.unlock(&password).unwrap();
+        assert!(matches!(
+            open().unlock_read_only(&password),
+            Err(EnjectError::StoreLocked(_))
+        ));
+        drop(writer);
+        open().unlock(&password).unwrap();
+    }
+
+    #[test]
+    fn test_save_refuses_a_store_replaced_on_disk() {
+        let dir = TempDir::new().unwrap();
+        let store_path = dir.path().join("store");
+        let password = test_password();
+        let mut store = PasswordStore::new(store_path.clone(), test_params(), test_salt());
+        store.unlock(&password).unwrap();
+        store.set("a", SecretString::new("1".to_string())).unwrap();
+        store.save(&password).unwrap();
+        // The stamp follows our own saves
+        store.set("b", SecretString::new("2".to_string())).unwrap();
+        store.save(&password).unwrap();
+        let ours = std::fs::read(&store_path).unwrap();
+
+        // Something that ignores the lock puts an older copy back
+        let mut other = PasswordStore::new(store_path.clone(), test_params(), test_salt());
+        other.secrets = Some(Entries::default());
+        other.save(&password).unwrap();
+        let theirs = std::fs::read(&store_path).unwrap();
+        assert_ne!(ours, theirs);
+
+        store.set("c", SecretString::new("3".to_string())).unwrap();
+        assert!(matches!(
+            store.save(&password),
+            Err(EnjectError::StoreChangedOnDisk)
+        ));
+        assert_eq!(std::fs::read(&store_path).unwrap(), theirs);
+
+        store.overwrite_changes();
+        store.save(&password).unwrap();
+        drop(store);
+        let mut reloaded = PasswordStore::new(store_path, test_params(), test_salt());
+        reloaded.unlock(&password).unwrap();
+        assert_eq!(reloaded.list().unwrap(), vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_save_refuses_a_store_created_after_unlock() {
+        let dir = TempDir::new().unwrap();
+        let store_path = dir.path().join("store");
+        let password = test_password();
+        let mut store = PasswordStore::new(store_path.clone(), test_params(), test_salt());
+        store.unlock(&password).unwrap();
+        std::fs::write(&store_path, b"someone else's store").unwrap();
+        assert!(matches!(
+            store.save(&password),
+            Err(EnjectError::StoreChangedOnDisk)
+        ));
+    }
+
+    #[test]
+    fn test_save_after_unlock_reuses_the_derived_key() {
+        let dir = TempDir::new().unwrap();
+        let store_path = dir.path().join("store");
+        let password = test_password();
+        let mut store = PasswordStore::new(store_path.clone(), test_params(), test_salt());
+        store.unlock(&password).unwrap();
+        store.save(&password).unwrap();
+        drop(store);
+
+        let derivations = || DERIVATIONS.with(|count| count.get());
+        let before = derivations();
+        let mut store = PasswordStore::new(store_path.clone(), test_params(), test_salt());
+        store.unlock(&password).unwrap();
+        store.set("k", SecretString::new("v".to_string())).unwrap();
+        store.save(&password).unwrap();
+        assert_eq!(derivations() - before, 1);
+
+        // A different password, or new parameters, means deriving again
+        let other = SecretString::new("other-test-password-do-not-use".to_string());
+        store.save(&other).unwrap();
+        assert_eq!(derivations() - before, 2);
+        store.rekey(Cipher::Aes256Gcm, test_params(), vec![3u8; 32]);
+        store.save(&password).unwrap();
+        assert_eq!(derivations() - before, 3);
+        drop(store);
+
+        let mut reloaded = PasswordStore::new(store_path, test_params(), test_salt());
+        reloaded.unlock(&password).unwrap();
+        assert_eq!(reloaded.get("k").unwrap().unwrap().expose_secret(), "v");
+    }
+
     #[test]
     fn test_list_returns_sorted_keys() {
         let dir = TempDir::new().unwrap();
@@ -353,6 +1386,192 @@ mod tests {
         assert!(store.get("missing").unwrap().is_none());
     }
 
+    #[test]
+    fn test_set_keeps_created_at_and_bumps_updated_at() {
+        let dir = TempDir::new().unwrap();
+        let mut store = setup_unlocked_store(&dir);
+
+        store.set("k", SecretString::new("v1".to_string())).unwrap();
+        let first = store.metadata("k").unwrap().unwrap();
+        store
+            .secrets_mut()
+            .unwrap()
+            .set_timestamps("k", "2000-01-01T00:00:00Z");
+
+        store.set("k", SecretString::new("v2".to_string())).unwrap();
+        let second = store.metadata("k").unwrap().unwrap();
+        assert_eq!(second.created_at, "2000-01-01T00:00:00Z");
+        assert_eq!(second.updated_at.len(), first.updated_at.len());
+        assert_ne!(second.updated_at, "2000-01-01T00:00:00Z");
+        assert!(store.metadata("missing").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_problems_reports_malformed_entries() {
+        let dir = TempDir::new().unwrap();
+        let mut store = setup_unlocked_store(&dir);
+        store.set("k", SecretString::new("v1".to_string())).unwrap();
+        store.set("k", SecretString::new("v2".to_string())).unwrap();
+        assert!(store.problems().unwrap().is_empty());
+
+        store
+            .secrets_mut()
+            .unwrap()
+            .set_timestamps("k", "yesterday");
+        let problems = store.problems().unwrap();
+        assert_eq!(problems.len(), 2, "{:?}", problems);
+        assert!(problems[0].starts_with("'k': bad timestamp"));
+        assert!(problems
+            .iter()
+            .all(|p| !p.contains("v1") && !p.contains("v2")));
+    }
+
+    #[test]
+    fn test_annotations_survive_a_new_value_and_a_reload() {
+        let dir = TempDir::new().unwrap();
+        let store_path = dir.path().join("store");
+        let password = test_password();
+        let mut store = PasswordStore::new(store_path.clone(), test_params(), test_salt());
+        store.unlock(&password).unwrap();
+
+        let describe = |description: &str| Annotation {
+            description: Some(description.to_string()),
+            ..Default::default()
+        };
+        assert!(!store.annotate("k", describe("x")).unwrap());
+        store.set("k", SecretString::new("v1".to_string())).unwrap();
+        let tags = vec!["payments".to_string(), "prod".to_string()];
+        let annotation = Annotation {
+            tags: Some(tags.clone()),
+            expires_at: Some("2030-01-01T00:00:00Z".to_string()),
+            ..describe("Stripe key")
+        };
+        assert!(store.annotate("k", annotation).unwrap());
+        store.set("k", SecretString::new("v2".to_string())).unwrap();
+        store.save(&password).unwrap();
+
+        drop(store);
+        let mut store2 = PasswordStore::new(store_path, test_params(), test_salt());
+        store2.unlock(&password).unwrap();
+        let meta = store2.metadata("k").unwrap().unwrap();
+        assert_eq!(meta.description.as_deref(), Some("Stripe key"));
+        assert_eq!(meta.tags, tags);
+        assert_eq!(meta.expires_at.as_deref(), Some("2030-01-01T00:00:00Z"));
+
+        store2.annotate("k", describe("")).unwrap();
+        let meta = store2.metadata("k").unwrap().unwrap();
+        assert_eq!(meta.description, None);
+        assert_eq!(meta.tags, tags);
+    }
+
+    fn values(store: &PasswordStore, key: &str) -> Vec<(usize, String)> {
+        store
+            .history(key)
+            .unwrap()
+            .into_iter()
+            .map(|v| (v.number, v.value.expose_secret().clone()))
+            .collect()
+    }
+
+    #[test]
+    fn test_set_keeps_bounded_history_and_restore_swaps() {
+        let dir = TempDir::new().unwrap();
+        let mut store = setup_unlocked_store(&dir).with_history_depth(2);
+        for value in ["v1", "v2", "v3", "v4"] {
+            store
+                .set("k", SecretString::new(value.to_string()))
+                .unwrap();
+        }
+        assert_eq!(
+            values(&store, "k"),
+            vec![(0, "v4".into()), (1, "v3".into()), (2, "v2".into())]
+        );
+
+        assert!(!store.restore_version("k", 3).unwrap());
+        assert!(!store.restore_version("k", 0).unwrap());
+        assert!(store.restore_version("k", 2).unwrap());
+        assert_eq!(
+            values(&store, "k"),
+            vec![(0, "v2".into()), (1, "v4".into()), (2, "v3".into())]
+        );
+        assert!(store.history("missing").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_delete_keeping_history_hides_key_until_restored() {
+        let dir = TempDir::new().unwrap();
+        let store_path = dir.path().join("store");
+        let password = test_password();
+        let mut store = setup_unlocked_store(&dir);
+        store.set("k", SecretString::new("v1".to_string())).unwrap();
+        store.set("k", SecretString::new("v2".to_string())).unwrap();
+
+        assert!(store.delete_keeping_history("k").unwrap());
+        assert!(!store.delete_keeping_history("k").unwrap());
+        store.save(&password).unwrap();
+
+        drop(store);
+        let mut store = PasswordStore::new(store_path, test_params(), test_salt());
+        store.unlock(&password).unwrap();
+        assert!(store.get("k").unwrap().is_none());
+        assert!(store.list().unwrap().is_empty());
+        assert_eq!(
+            values(&store, "k"),
+            vec![(1, "v2".into()), (2, "v1".into())]
+        );
+
+        assert!(store.restore_version("k", 1).unwrap());
+        assert_eq!(store.get("k").unwrap().unwrap().expose_secret(), "v2");
+        assert_eq!(
+            values(&store, "k"),
+            vec![(0, "v2".into()), (1, "v1".into())]
+        );
+
+        // A plain delete forgets the history too
+        assert!(store.delete("k").unwrap());
+        assert!(store.history("k").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_legacy_flat_store_is_migrated_on_unlock() {
+        let dir = TempDir::new().unwrap();
+        let store_path = dir.path().join("store");
+        let password = test_password();
+
+        // Write a version 1 store: a flat key/value map
+        let mut key = derive_key(
+            password.expose_secret().as_bytes(),
+            &test_salt(),
+            &test_params(),
+        )
+        .unwrap();
+        let cipher = Aes256Gcm::new_from_slice(&key).unwrap();
+        key.zeroize();
+        let nonce_bytes = [7u8; 12];
+        let ciphertext = cipher
+            .encrypt(
+                Nonce::from_slice(&nonce_bytes),
+                br#"{"old":"value"}"#.as_ref(),
+            )
+            .unwrap();
+        std::fs::write(&store_path, [&nonce_bytes[..], &ciphertext].concat()).unwrap();
+
+        let mut store = PasswordStore::new(store_path.clone(), test_params(), test_salt());
+        store.unlock(&password).unwrap();
+        assert_eq!(store.get("old").unwrap().unwrap().expose_secret(), "value");
+        let meta = store.metadata("old").unwrap().unwrap();
+        assert_eq!(meta.created_at, meta.updated_at);
+        assert!(meta.created_at.ends_with('Z'));
+
+        // Saving writes the current format, which reads back with the same timestamps
+        store.save(&password).unwrap();
+        assert!(std::fs::read(&store_path).unwrap().starts_with(MAGIC));
+        drop(store);
+        let mut store2 = PasswordStore::new(store_path, test_params(), test_salt());
+        store2.unlock(&password).unwrap();
+        assert_eq!(store2.metadata("old").unwrap().unwrap(), meta);
+    }
+
     #[test]
     fn test_nonce_changes_on_each_save() {
         let dir = TempDir::new().unwrap();
@@ -364,9 +1583,14 @@ mod tests {
         store.set("k", SecretString::new("v".to_string())).unwrap();
         store.save(&password).unwrap();
 
-        let nonce1 = std::fs::read(&store_path).unwrap()[..NONCE_LEN].to_vec();
+        let nonce = || {
+            let bytes = std::fs::read(&store_path).unwrap();
+            let (_, header_len) = Header::decode(&bytes).unwrap().unwrap();
+            bytes[header_len..header_len + Cipher::Aes256Gcm.nonce_len()].to_vec()
+        };
+        let nonce1 = nonce();
         store.save(&password).unwrap();
-        let nonce2 = std::fs::read(&store_path).unwrap()[..NONCE_LEN].to_vec();
+        let nonce2 = nonce();
 
         // Nonces should almost certainly differ (probability of collision is negligible)
         assert_ne!(
@@ -374,4 +1598,46 @@ mod tests {
             "Nonce should be freshly generated on every write"
         );
     }
+
+    #[test]
+    fn test_read_only_unlock_leaves_an_interrupted_rotation_alone() {
+        let dir = TempDir::new().unwrap();
+        let store_path = dir.path().join("store");
+        let pending = dir.path().join("store.rekeyed");
+        let old = test_password();
+        let new = SecretString::new("new-test-password-do-not-use".to_string());
+        let mut store = setup_unlocked_store(&dir);
+        store.set("k", SecretString::new("v".to_string())).unwrap();
+        store.save(&old).unwrap();
+        store.rekey(Cipher::Aes256Gcm, test_params(), vec![9u8; 32]);
+        store.save_pending(&new).unwrap();
+        drop(store);
+
+        let barrier = std::sync::Arc::new(std::sync::Barrier::new(2));
+        let readers: Vec<_> = [old.expose_secret().clone(), new.expose_secret().clone()]
+            .into_iter()
+            .map(|password| {
+                let store_path = store_path.clone();
+                let barrier = barrier.clone();
+                std::thread::spawn(move || {
+                    let mut store = PasswordStore::new(store_path, test_params(), test_salt());
+                    barrier.wait();
+                    store
+                        .unlock_read_only(&SecretString::new(password))
+                        .unwrap();
+                    assert_eq!(store.get("k").unwrap().unwrap().expose_secret(), "v");
+                })
+            })
+            .collect();
+        for reader in readers {
+            reader.join().unwrap();
+        }
+        assert!(store_path.exists());
+        assert!(pending.exists());
+
+        // A writer still finishes the rotation
+        let mut store = PasswordStore::new(store_path, test_params(), test_salt());
+        store.unlock(&new).unwrap();
+        assert!(!pending.exists());
+    }
 }
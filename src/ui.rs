@@ -0,0 +1,68 @@
+//! Messages for people rather than scripts: warnings and errors on stderr,
+//! confirmations on stdout, colored as `--color` says.
+//!
+//! `auto`, the default, colors a stream only when it is a terminal and `NO_COLOR`
+//! is unset. Warnings never go to stdout, which stays free for the results a
+//! script reads: JSON, key names, `check` findings.
+
+use std::fmt::Arguments;
+use std::io;
+
+use anstream::{eprintln, println, AutoStream};
+use anstyle::{AnsiColor, Style};
+
+use crate::cli::ColorChoice;
+
+const WARN: Style = AnsiColor::Yellow.on_default().bold();
+const DANGER: Style = AnsiColor::Red.on_default().bold();
+const SUCCESS: Style = AnsiColor::Green.on_default();
+
+/// Apply `--color` to everything printed from here on.
+pub fn init(choice: ColorChoice) {
+    match choice {
+        ColorChoice::Auto => anstream::ColorChoice::Auto,
+        ColorChoice::Always => anstream::ColorChoice::Always,
+        ColorChoice::Never => anstream::ColorChoice::Never,
+    }
+    .write_global();
+}
+
+/// True when text written to stdout is colored, for output that writes its own
+/// escape codes rather than going through these helpers.
+pub fn stdout_colored() -> bool {
+    AutoStream::choice(&io::stdout()) != anstream::ColorChoice::Never
+}
+
+/// Print a warning on stderr: something worth fixing that doesn't stop the command.
+pub fn warn(message: Arguments) {
+    eprintln!(
+        "{}Warning:{} {}",
+        WARN.render(),
+        WARN.render_reset(),
+        message
+    );
+}
+
+/// Print the error a command failed with on stderr.
+pub fn error(message: Arguments) {
+    eprintln!(
+        "{}Error:{} {}",
+        DANGER.render(),
+        DANGER.render_reset(),
+        message
+    );
+}
+
+/// Print a line about something destructive or unsafe on stderr, such as what a
+/// command is about to overwrite or a plaintext copy left on disk.
+pub fn danger(message: Arguments) {
+    eprintln!("{}{}{}", DANGER.render(), message, DANGER.render_reset());
+}
+
+/// Print on stdout that a command did what was asked. `--quiet` turns these off,
+/// like `commands::inform`.
+pub fn success(message: Arguments) {
+    if !crate::logging::quiet() {
+        println!("{}{}{}", SUCCESS.render(), message, SUCCESS.render_reset());
+    }
+}
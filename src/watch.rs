@@ -0,0 +1,84 @@
+//! Watching the template and store files for `enject run --watch`.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use notify::event::{AccessKind, AccessMode, MetadataKind, ModifyKind};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+/// Call `on_change` from a background thread whenever one of `files` is written,
+/// replaced or removed, until the returned watcher is dropped.
+///
+/// The directories holding the files are watched rather than the files themselves,
+/// so a file an editor saves by writing a new copy and renaming it over the old one
+/// keeps being watched.
+pub fn files(
+    files: &[PathBuf],
+    on_change: impl Fn() + Send + 'static,
+) -> Result<RecommendedWatcher> {
+    let files = files
+        .iter()
+        .map(std::path::absolute)
+        .collect::<std::io::Result<Vec<_>>>()?;
+    let mut dirs: Vec<&Path> = files.iter().filter_map(|file| file.parent()).collect();
+    dirs.sort();
+    dirs.dedup();
+    let dirs: Vec<PathBuf> = dirs.into_iter().map(Path::to_path_buf).collect();
+
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+        // A watcher error may mean missed events, so treat it as a change
+        let Ok(event) = event else {
+            return on_change();
+        };
+        if is_change(&event.kind) && event.paths.iter().any(|path| files.contains(path)) {
+            on_change();
+        }
+    })
+    .context("Failed to start watching for changes")?;
+    for dir in &dirs {
+        watcher
+            .watch(dir, RecursiveMode::NonRecursive)
+            .with_context(|| format!("Failed to watch {}", dir.display()))?;
+    }
+    Ok(watcher)
+}
+
+/// Whether an event can change a file's contents. Reads are not changes: enject
+/// itself reads the files it watches after every change.
+fn is_change(kind: &EventKind) -> bool {
+    match kind {
+        EventKind::Access(access) => *access == AccessKind::Close(AccessMode::Write),
+        EventKind::Modify(ModifyKind::Metadata(MetadataKind::AccessTime)) => false,
+        EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_) => true,
+        EventKind::Any | EventKind::Other => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use notify::event::{CreateKind, DataChange, RenameMode};
+
+    #[test]
+    fn test_reads_are_not_changes() {
+        assert!(!is_change(&EventKind::Access(AccessKind::Open(
+            AccessMode::Read
+        ))));
+        assert!(!is_change(&EventKind::Access(AccessKind::Close(
+            AccessMode::Read
+        ))));
+        assert!(!is_change(&EventKind::Modify(ModifyKind::Metadata(
+            MetadataKind::AccessTime
+        ))));
+        assert!(is_change(&EventKind::Access(AccessKind::Close(
+            AccessMode::Write
+        ))));
+        assert!(is_change(&EventKind::Modify(ModifyKind::Data(
+            DataChange::Any
+        ))));
+        assert!(is_change(&EventKind::Modify(ModifyKind::Name(
+            RenameMode::To
+        ))));
+        assert!(is_change(&EventKind::Create(CreateKind::File)));
+    }
+}
@@ -0,0 +1,139 @@
+//! End-to-end tests for `enject agent`: the password is entered once, then later
+//! commands get the key from the agent.
+#![cfg(unix)]
+
+use std::path::PathBuf;
+use std::process::Output;
+
+use tempfile::TempDir;
+
+mod common;
+use common::{command, output_with_stdin};
+
+fn setup_project() -> TempDir {
+    let dir = common::setup_project();
+    std::fs::create_dir_all(dir.path().join("run")).unwrap();
+    dir
+}
+
+/// Run enject with the agent enabled and its socket inside the project. `password`
+/// goes in ENJECT_PASSWORD when given; `stdin` is piped in.
+fn enject(dir: &TempDir, args: &[&str], password: Option<&str>, stdin: &[u8]) -> Output {
+    let mut command = command(dir, args);
+    command
+        .env("XDG_RUNTIME_DIR", dir.path().join("run"))
+        .env("ENJECT_AGENT", "1");
+    if let Some(password) = password {
+        command.env("ENJECT_PASSWORD", password);
+    }
+    output_with_stdin(&mut command, stdin)
+}
+
+/// Stops the agent however the test ends.
+struct Agent<'a>(&'a TempDir);
+
+impl Drop for Agent<'_> {
+    fn drop(&mut self) {
+        enject(self.0, &["agent", "--stop"], None, b"");
+    }
+}
+
+fn socket(dir: &TempDir) -> PathBuf {
+    dir.path().join("run").join("enject").join("agent.sock")
+}
+
+#[test]
+fn test_agent_supplies_key_until_lock() {
+    let dir = setup_project();
+    let output = enject(
+        &dir,
+        &["set", "api_key"],
+        Some("test-password-do-not-use"),
+        b"abc",
+    );
+    assert!(output.status.success(), "{:?}", output);
+
+    let output = enject(&dir, &["agent", "--ttl", "1m"], None, b"");
+    assert!(output.status.success(), "{:?}", output);
+    let _agent = Agent(&dir);
+    assert!(socket(&dir).exists());
+
+    // Without the password, nothing opens the store yet
+    let output = enject(&dir, &["list"], None, b"");
+    assert!(!output.status.success());
+
+    let output = enject(&dir, &["unlock"], Some("test-password-do-not-use"), b"");
+    assert!(output.status.success(), "{:?}", output);
+    let output = enject(&dir, &["list"], None, b"");
+    assert!(output.status.success(), "{:?}", output);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("api_key"));
+
+    let output = enject(&dir, &["lock"], None, b"");
+    assert!(output.status.success(), "{:?}", output);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("agent forgot"));
+    let output = enject(&dir, &["list"], None, b"");
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_agent_stop() {
+    let dir = setup_project();
+    let output = enject(&dir, &["agent"], None, b"");
+    assert!(output.status.success(), "{:?}", output);
+    let output = enject(&dir, &["agent"], None, b"");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("already listening"));
+
+    let output = enject(&dir, &["agent", "--stop"], None, b"");
+    assert!(String::from_utf8_lossy(&output.stdout).contains("stopped"));
+    let output = enject(&dir, &["agent", "--stop"], None, b"");
+    assert!(String::from_utf8_lossy(&output.stdout).contains("No enject agent"));
+}
+
+/// The environment of the agent whose socket is in `dir`, found through /proc.
+#[cfg(target_os = "linux")]
+fn agent_environ(dir: &TempDir) -> Vec<String> {
+    let runtime = format!("XDG_RUNTIME_DIR={}", dir.path().join("run").display());
+    for entry in std::fs::read_dir("/proc").unwrap().flatten() {
+        let Ok(environ) = std::fs::read(entry.path().join("environ")) else {
+            continue;
+        };
+        let Ok(cmdline) = std::fs::read(entry.path().join("cmdline")) else {
+            continue;
+        };
+        let vars: Vec<String> = environ
+            .split(|&b| b == 0)
+            .map(|var| String::from_utf8_lossy(var).into_owned())
+            .collect();
+        if vars.contains(&runtime) && String::from_utf8_lossy(&cmdline).contains("--foreground") {
+            return vars;
+        }
+    }
+    panic!("no agent process found");
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_agent_environment_holds_no_password() {
+    let dir = setup_project();
+    let mut command = command(&dir, &["agent"]);
+    command
+        .env("XDG_RUNTIME_DIR", dir.path().join("run"))
+        .env("ENJECT_AGENT", "1")
+        .env("ENJECT_PASSWORD", "test-password-do-not-use")
+        .env("ENJECT_GLOBAL_PASSWORD", "global-password-do-not-use")
+        .env("ENJECT_MEMBER_PASSWORD", "member-password-do-not-use");
+    let output = output_with_stdin(&mut command, b"");
+    assert!(output.status.success(), "{:?}", output);
+    let _agent = Agent(&dir);
+
+    let environ = agent_environ(&dir);
+    for var in [
+        "ENJECT_PASSWORD",
+        "ENJECT_GLOBAL_PASSWORD",
+        "ENJECT_MEMBER_PASSWORD",
+    ] {
+        let prefix = format!("{}=", var);
+        assert!(!environ.iter().any(|v| v.starts_with(&prefix)), "{}", var);
+    }
+}
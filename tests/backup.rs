@@ -0,0 +1,80 @@
+//! End-to-end tests for `enject backup` and `enject restore`.
+
+mod common;
+use common::{enject, setup_project, PASSWORD};
+
+#[test]
+fn test_backup_wipe_restore_roundtrip() {
+    let dir = setup_project();
+    let output = enject(&dir, &["set", "api_key"], PASSWORD, b"abc");
+    assert!(output.status.success(), "{:?}", output);
+
+    let backup = dir.path().join("enject.backup");
+    let output = enject(&dir, &["backup", "enject.backup"], PASSWORD, b"");
+    assert!(output.status.success(), "{:?}", output);
+    let packed = std::fs::read(&backup).unwrap();
+    assert!(packed.starts_with(b"ENJECTBK\x01"));
+    // The value only exists encrypted
+    assert!(!packed.windows(3).any(|w| w == b"abc"));
+
+    // Never overwrites an existing file
+    let output = enject(&dir, &["backup", "enject.backup"], PASSWORD, b"");
+    assert!(!output.status.success());
+
+    std::fs::remove_dir_all(dir.path().join(".enject")).unwrap();
+    let output = enject(&dir, &["restore", "enject.backup"], PASSWORD, b"");
+    assert!(output.status.success(), "{:?}", output);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Restored 1 secret(s)"));
+
+    let output = enject(&dir, &["list", "--quiet"], PASSWORD, b"");
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "api_key");
+}
+
+#[test]
+fn test_restore_checks_the_password_and_keeps_the_current_store() {
+    let dir = setup_project();
+    let output = enject(&dir, &["set", "old_key"], PASSWORD, b"abc");
+    assert!(output.status.success(), "{:?}", output);
+    let output = enject(&dir, &["backup", "enject.backup"], PASSWORD, b"");
+    assert!(output.status.success(), "{:?}", output);
+    let output = enject(&dir, &["set", "new_key"], PASSWORD, b"def");
+    assert!(output.status.success(), "{:?}", output);
+
+    let output = enject(
+        &dir,
+        &["restore", "enject.backup", "--force"],
+        "wrong-password",
+        b"",
+    );
+    assert!(!output.status.success());
+    assert!(!dir.path().join(".enject.restoring").exists());
+
+    // Not a terminal and no --force: refused
+    let output = enject(&dir, &["restore", "enject.backup"], PASSWORD, b"");
+    assert!(!output.status.success());
+    let output = enject(&dir, &["list", "--quiet"], PASSWORD, b"");
+    assert!(String::from_utf8_lossy(&output.stdout).contains("new_key"));
+
+    let output = enject(
+        &dir,
+        &["restore", "enject.backup", "--force"],
+        PASSWORD,
+        b"",
+    );
+    assert!(output.status.success(), "{:?}", output);
+    let output = enject(&dir, &["list", "--quiet"], PASSWORD, b"");
+    assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "old_key");
+    let archived = std::fs::read_dir(dir.path())
+        .unwrap()
+        .filter(|entry| {
+            entry
+                .as_ref()
+                .unwrap()
+                .file_name()
+                .to_string_lossy()
+                .starts_with(".enject.old-")
+        })
+        .count();
+    assert_eq!(archived, 1);
+}
@@ -0,0 +1,72 @@
+//! Fixtures shared by the end-to-end tests.
+
+// Each test file compiles its own copy and uses only part of it
+#![allow(dead_code)]
+
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+use tempfile::TempDir;
+
+/// Low-cost KDF params so the tests don't pay for a real Argon2 run.
+pub const TEST_CONFIG: &str = r#"backend = "password"
+version = 1
+kdf = "argon2id"
+m_cost = 8192
+t_cost = 1
+p_cost = 1
+salt = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f"
+"#;
+
+pub const PASSWORD: &str = "test-password-do-not-use";
+
+/// A project with `TEST_CONFIG` in `store_dir` and no store file yet.
+pub fn project_in(store_dir: &str) -> TempDir {
+    let dir = TempDir::new().unwrap();
+    std::fs::create_dir_all(dir.path().join(store_dir)).unwrap();
+    std::fs::write(dir.path().join(store_dir).join("config.toml"), TEST_CONFIG).unwrap();
+    dir
+}
+
+/// A project with `TEST_CONFIG` in `.enject/` and no .env.
+pub fn setup_project() -> TempDir {
+    project_in(".enject")
+}
+
+/// `setup_project` with `env` as its .env.
+pub fn setup_project_with_env(env: &str) -> TempDir {
+    let dir = setup_project();
+    std::fs::write(dir.path().join(".env"), env).unwrap();
+    dir
+}
+
+/// enject in `dir` with no password, cached keys, agent or ENJECT_DIR from the
+/// environment the tests run in.
+pub fn command(dir: &TempDir, args: &[&str]) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_enject"));
+    command
+        .args(args)
+        .current_dir(dir.path())
+        .env("ENJECT_NO_KEYCHAIN", "1")
+        .env_remove("ENJECT_PASSWORD")
+        .env_remove("ENJECT_AGENT")
+        .env_remove("ENJECT_DIR");
+    command
+}
+
+/// Run `command` with `stdin` piped in, capturing stdout and stderr.
+pub fn output_with_stdin(command: &mut Command, stdin: &[u8]) -> Output {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(stdin).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+/// Run enject with `password` in the environment and `stdin` piped in.
+pub fn enject(dir: &TempDir, args: &[&str], password: &str, stdin: &[u8]) -> Output {
+    output_with_stdin(command(dir, args).env("ENJECT_PASSWORD", password), stdin)
+}
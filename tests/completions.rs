@@ -0,0 +1,17 @@
+//! End-to-end tests for `enject completions`.
+
+use std::process::Command;
+
+#[test]
+fn test_completions_for_each_shell() {
+    for shell in ["bash", "zsh", "fish", "powershell"] {
+        let output = Command::new(env!("CARGO_BIN_EXE_enject"))
+            .args(["completions", shell])
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{}: {:?}", shell, output);
+        let script = String::from_utf8_lossy(&output.stdout);
+        assert!(script.contains("enject"), "{}", shell);
+        assert!(script.contains("rename"), "{}", shell);
+    }
+}
@@ -0,0 +1,78 @@
+//! End-to-end tests for `enject delete` against a throwaway project directory.
+
+use std::process::Output;
+
+use tempfile::TempDir;
+
+mod common;
+use common::{setup_project_with_env, PASSWORD};
+
+/// Run enject with the password in the environment and `stdin` piped in.
+fn enject(dir: &TempDir, args: &[&str], stdin: &[u8]) -> Output {
+    common::enject(dir, args, PASSWORD, stdin)
+}
+
+fn stored(dir: &TempDir) -> String {
+    String::from_utf8_lossy(&enject(dir, &["list"], b"").stdout).to_string()
+}
+
+#[test]
+fn test_delete_several_keys_reports_missing_ones() {
+    let dir = setup_project_with_env("PORT=3000\n");
+    for key in ["stripe_sk", "stripe_pk", "db_url"] {
+        assert!(enject(&dir, &["set", key], b"value\n").status.success());
+    }
+
+    let output = enject(
+        &dir,
+        &["delete", "--force", "stripe_sk", "gone", "stripe_pk"],
+        b"",
+    );
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Secret 'gone' not found."), "{}", stdout);
+    assert!(stdout.contains("Deleted 2 secret(s): stripe_sk, stripe_pk."));
+
+    let list = stored(&dir);
+    assert!(
+        list.contains("db_url") && !list.contains("stripe_"),
+        "{}",
+        list
+    );
+}
+
+#[test]
+fn test_glob_delete_without_force_needs_a_terminal() {
+    let dir = setup_project_with_env("PORT=3000\n");
+    for key in ["stripe_sk", "stripe_pk"] {
+        assert!(enject(&dir, &["set", key], b"value\n").status.success());
+    }
+
+    let output = enject(&dir, &["delete", "--glob", "stripe_*"], b"y\n");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Matched 2 secret(s):"));
+    assert!(stored(&dir).contains("stripe_sk"));
+}
+
+#[test]
+fn test_delete_without_force_fails_and_warns_about_references() {
+    let dir = setup_project_with_env("DATABASE_URL=en://database_url\n");
+    assert!(enject(&dir, &["set", "database_url"], b"value\n")
+        .status
+        .success());
+
+    let output = enject(&dir, &["delete", "database_url"], b"y\n");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Re-run with --force"));
+    assert!(stored(&dir).contains("database_url"));
+
+    let output = enject(&dir, &["delete", "-f", "database_url"], b"");
+    assert!(output.status.success(), "{:?}", output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Warning: .env line 1 (DATABASE_URL) still references en://database_url"),
+        "{}",
+        stderr
+    );
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Secret 'database_url' deleted."));
+}
@@ -0,0 +1,35 @@
+//! End-to-end tests for `enject diff`.
+
+mod common;
+use common::{enject, setup_project, PASSWORD};
+
+#[test]
+fn test_diff_reports_each_section_and_fails_on_missing_secrets() {
+    let dir = setup_project();
+    for key in ["db_url", "api_key", "old_token"] {
+        let output = enject(&dir, &["set", key], PASSWORD, b"value");
+        assert!(output.status.success(), "{:?}", output);
+    }
+    std::fs::write(
+        dir.path().join(".env"),
+        "DATABASE_URL=en://db_url\nAPI_KEY=pasted-back\nREDIS=en://redis_url\n",
+    )
+    .unwrap();
+
+    let output = enject(&dir, &["diff", "--format", "json"], PASSWORD, b"");
+    assert!(!output.status.success());
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["missing"][0]["secret"], "redis_url");
+    assert_eq!(report["missing"][0]["line"], 3);
+    assert_eq!(
+        report["unreferenced"],
+        serde_json::json!(["api_key", "old_token"])
+    );
+    assert_eq!(report["shadowed"][0]["key"], "API_KEY");
+    assert!(!String::from_utf8_lossy(&output.stdout).contains("pasted-back"));
+
+    std::fs::write(dir.path().join(".env"), "DATABASE_URL=en://db_url\n").unwrap();
+    let output = enject(&dir, &["diff"], PASSWORD, b"");
+    assert!(output.status.success(), "{:?}", output);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Missing secrets (0):"));
+}
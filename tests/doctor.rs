@@ -0,0 +1,60 @@
+//! End-to-end tests for `enject doctor`.
+
+use tempfile::TempDir;
+
+mod common;
+use common::{enject, setup_project, PASSWORD};
+
+#[cfg(unix)]
+fn restrict(dir: &TempDir) {
+    use std::os::unix::fs::PermissionsExt;
+    for name in ["config.toml", "store"] {
+        let path = dir.path().join(".enject").join(name);
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600)).unwrap();
+    }
+}
+
+#[cfg(not(unix))]
+fn restrict(_dir: &TempDir) {}
+
+#[test]
+fn test_doctor_passes_on_a_healthy_project() {
+    let dir = setup_project();
+    let output = enject(&dir, &["set", "db_url"], PASSWORD, b"postgres://");
+    assert!(output.status.success(), "{:?}", output);
+    std::fs::write(dir.path().join(".env"), "DATABASE_URL=en://db_url\n").unwrap();
+    restrict(&dir);
+
+    let output = enject(&dir, &["doctor", "--unlock"], PASSWORD, b"");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(0), "{}", stdout);
+    assert!(stdout.contains("opens, 1 secret(s)"), "{}", stdout);
+    assert!(!stdout.contains("WARN") && !stdout.contains("FAIL"));
+}
+
+#[test]
+fn test_doctor_exit_code_reflects_the_worst_finding() {
+    let dir = setup_project();
+    let output = enject(&dir, &["set", "db_url"], PASSWORD, b"postgres://");
+    assert!(output.status.success(), "{:?}", output);
+    std::fs::write(dir.path().join(".env"), "DATABASE_URL=en://db_url\n").unwrap();
+    restrict(&dir);
+    std::fs::write(dir.path().join(".env.bak"), "DATABASE_URL=postgres://\n").unwrap();
+
+    let output = enject(&dir, &["doctor"], PASSWORD, b"");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(1), "{}", stdout);
+    assert!(stdout.contains("WARN  backups"), "{}", stdout);
+
+    let output = enject(&dir, &["doctor", "--unlock"], "wrong-password", b"");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("FAIL  unlock"));
+}
+
+#[test]
+fn test_doctor_without_a_store() {
+    let dir = TempDir::new().unwrap();
+    let output = enject(&dir, &["doctor"], PASSWORD, b"");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("enject init"));
+}
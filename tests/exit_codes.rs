@@ -0,0 +1,148 @@
+//! End-to-end tests for the exit codes listed in `enject --help` and for the
+//! global `--format json` flag.
+
+use std::process::Output;
+
+use tempfile::TempDir;
+
+mod common;
+use common::{setup_project_with_env, PASSWORD};
+
+fn enject(dir: &TempDir, args: &[&str], stdin: &[u8]) -> Output {
+    common::enject(dir, args, PASSWORD, stdin)
+}
+
+fn json(output: &Output) -> serde_json::Value {
+    assert!(output.status.success(), "{:?}", output);
+    serde_json::from_slice(&output.stdout).unwrap()
+}
+
+#[test]
+fn test_exit_codes() {
+    let dir = TempDir::new().unwrap();
+    assert_eq!(enject(&dir, &["list"], b"").status.code(), Some(3));
+    assert_eq!(
+        enject(&dir, &["list", "--no-such-flag"], b"").status.code(),
+        Some(2)
+    );
+
+    let dir = setup_project_with_env("DB=en://db\n");
+    assert!(enject(&dir, &["set", "api_key"], b"s3cret-value\n")
+        .status
+        .success());
+    let output = common::enject(&dir, &["list"], "wrong-password", b"");
+    assert_eq!(output.status.code(), Some(4), "{:?}", output);
+    let output = enject(&dir, &["run", "--", "true"], b"");
+    assert_eq!(output.status.code(), Some(5), "{:?}", output);
+
+    // Every malformed line is reported at once, with its line number
+    std::fs::write(dir.path().join(".env"), "NOT A LINE\nA=1\n=nokey\n").unwrap();
+    let output = enject(&dir, &["run", "--", "true"], b"");
+    assert_eq!(output.status.code(), Some(6), "{:?}", output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("line 1: Malformed"), "{}", stderr);
+    assert!(stderr.contains("line 3: Malformed"), "{}", stderr);
+
+    // Anything else is 1, including asking for JSON from a command without it
+    let output = enject(&dir, &["--format", "json", "info", "api_key"], b"");
+    assert_eq!(output.status.code(), Some(1), "{:?}", output);
+
+    let help = enject(&dir, &["--help"], b"");
+    assert!(String::from_utf8_lossy(&help.stdout).contains("5  Secret not found"));
+}
+
+#[test]
+fn test_format_json_for_set_delete_and_status() {
+    let dir = setup_project_with_env("API_KEY=en://api_key\n");
+
+    let report = json(&enject(
+        &dir,
+        &["--format", "json", "set", "api_key"],
+        b"s3cret-value\n",
+    ));
+    assert_eq!(report["created"][0], "api_key");
+    let output = enject(
+        &dir,
+        &["set", "api_key", "--if-absent", "--format", "json"],
+        b"other-value\n",
+    );
+    assert_eq!(json(&output)["unchanged"][0], "api_key");
+    assert!(!String::from_utf8_lossy(&output.stdout).contains("value"));
+
+    let status = json(&enject(
+        &dir,
+        &["status", "--unlock", "--format", "json"],
+        b"",
+    ));
+    assert_eq!(status["store"]["state"], "initialized");
+    assert_eq!(status["store"]["secrets"], 1);
+    assert_eq!(status["store"]["encryption"]["kdf"], "argon2id");
+    assert_eq!(status["env"]["local_refs"], 1);
+
+    // The warning about the .env reference goes to stderr, leaving stdout parseable
+    let output = enject(
+        &dir,
+        &[
+            "delete",
+            "api_key",
+            "missing_key",
+            "--force",
+            "--format",
+            "json",
+        ],
+        b"",
+    );
+    let report = json(&output);
+    assert_eq!(report["deleted"][0], "api_key");
+    assert_eq!(report["not_found"][0], "missing_key");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("still references"));
+}
+
+/// A pseudo-terminal as (master, slave): a child with the slave as stdin sees a
+/// terminal there, and the test types its answers into the master.
+#[cfg(unix)]
+fn pty() -> (std::fs::File, std::fs::File) {
+    use std::os::fd::FromRawFd;
+    let (mut master, mut slave) = (0, 0);
+    let rc = unsafe {
+        libc::openpty(
+            &mut master,
+            &mut slave,
+            std::ptr::null_mut(),
+            std::ptr::null(),
+            std::ptr::null(),
+        )
+    };
+    assert_eq!(rc, 0, "openpty failed");
+    unsafe {
+        (
+            std::fs::File::from_raw_fd(master),
+            std::fs::File::from_raw_fd(slave),
+        )
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn test_format_json_keeps_confirmation_prompts_off_stdout() {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    let dir = setup_project_with_env("PORT=3000\n");
+    let output = enject(&dir, &["set", "api_key"], b"abc");
+    assert!(output.status.success(), "{:?}", output);
+
+    let (mut master, slave) = pty();
+    let child = common::command(&dir, &["delete", "api_key", "--format", "json"])
+        .env("ENJECT_PASSWORD", PASSWORD)
+        .stdin(slave)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    master.write_all(b"y\n").unwrap();
+    let output = child.wait_with_output().unwrap();
+    let report = json(&output);
+    assert_eq!(report["deleted"][0], "api_key");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("[y/N]"));
+}
@@ -0,0 +1,112 @@
+//! End-to-end tests for `enject import` against a throwaway project directory.
+
+use std::process::Output;
+
+use tempfile::TempDir;
+
+mod common;
+use common::{setup_project_with_env, PASSWORD};
+
+/// Run enject with the password in the environment and stdin not a terminal.
+fn enject(dir: &TempDir, args: &[&str]) -> Output {
+    common::enject(dir, args, PASSWORD, b"")
+}
+
+#[test]
+fn test_non_interactive_import_without_yes_fails_and_leaves_file() {
+    let dir = setup_project_with_env("API_KEY=abc123\n");
+    let output = enject(&dir, &["import", ".env"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("WARNING: enject import will"));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--yes"));
+    assert_eq!(
+        std::fs::read_to_string(dir.path().join(".env")).unwrap(),
+        "API_KEY=abc123\n"
+    );
+}
+
+#[test]
+fn test_import_yes_rewrites_template_without_backup() {
+    let dir = setup_project_with_env("API_KEY=abc123\n");
+    let output = enject(&dir, &["import", ".env", "--yes"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        std::fs::read_to_string(dir.path().join(".env")).unwrap(),
+        "API_KEY=en://API_KEY\n"
+    );
+    assert!(!dir.path().join(".env.env.bak").exists());
+
+    let output = enject(&dir, &["check"]);
+    assert!(output.status.success(), "{:?}", output);
+}
+
+#[test]
+fn test_import_yes_with_backup() {
+    let dir = setup_project_with_env("API_KEY=abc123\n");
+    let output = enject(&dir, &["import", ".env", "--yes", "--backup"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        std::fs::read_to_string(dir.path().join(".env.env.bak")).unwrap(),
+        "API_KEY=abc123\n"
+    );
+}
+
+#[test]
+fn test_import_dry_run_changes_nothing() {
+    let dir = setup_project_with_env("API_KEY=abc123\n");
+    let output = enject(&dir, &["import", ".env", "--dry-run"]);
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("+API_KEY=en://API_KEY"), "{}", stdout);
+    assert!(!stdout.contains("abc123"));
+    assert_eq!(
+        std::fs::read_to_string(dir.path().join(".env")).unwrap(),
+        "API_KEY=abc123\n"
+    );
+    assert!(!dir.path().join(".enject").join("store").exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_reimport_conflicts_follow_on_conflict() {
+    let dir = setup_project_with_env("API_KEY=first\n");
+    assert!(enject(&dir, &["import", ".env", "--yes"]).status.success());
+
+    let value = |dir: &TempDir| {
+        let output = enject(dir, &["run", "--", "sh", "-c", "echo $API_KEY"]);
+        String::from_utf8_lossy(&output.stdout).trim().to_string()
+    };
+
+    std::fs::write(dir.path().join(".env"), "API_KEY=second\n").unwrap();
+    let output = enject(&dir, &["import", ".env", "--yes"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--on-conflict"));
+
+    let output = enject(&dir, &["import", ".env", "--yes", "--on-conflict", "abort"]);
+    assert!(!output.status.success());
+    assert_eq!(
+        std::fs::read_to_string(dir.path().join(".env")).unwrap(),
+        "API_KEY=second\n"
+    );
+
+    let output = enject(&dir, &["import", ".env", "--yes", "--on-conflict", "keep"]);
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Imported 0 secret(s)"), "{}", stdout);
+    assert!(stdout.contains("Conflicts that kept the store value: API_KEY"));
+    assert_eq!(value(&dir), "first");
+
+    std::fs::write(dir.path().join(".env"), "API_KEY=second\n").unwrap();
+    let output = enject(
+        &dir,
+        &["import", ".env", "--yes", "--on-conflict", "overwrite"],
+    );
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(value(&dir), "second");
+
+    // The same value again is a no-op
+    std::fs::write(dir.path().join(".env"), "API_KEY=second\n").unwrap();
+    let output = enject(&dir, &["import", ".env", "--yes"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Imported 0 secret(s)"));
+}
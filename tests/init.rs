@@ -0,0 +1,264 @@
+//! End-to-end tests for `enject init` in a throwaway project directory.
+
+use std::process::{Output, Stdio};
+
+use tempfile::TempDir;
+
+mod common;
+use common::{command, output_with_stdin, PASSWORD};
+
+/// Run enject with the password in the environment and stdin not a terminal.
+fn enject(dir: &TempDir, args: &[&str]) -> Output {
+    command(dir, args)
+        .env("ENJECT_PASSWORD", PASSWORD)
+        .stdin(Stdio::null())
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn test_init_rejects_weak_kdf_params() {
+    let dir = TempDir::new().unwrap();
+    let output = enject(&dir, &["init", "--m-cost", "1024"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("below the 19456 KiB"), "{}", stderr);
+    assert!(!dir.path().join(".enject").exists());
+
+    let output = enject(&dir, &["init", "--t-cost", "0"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("t_cost"));
+}
+
+#[test]
+fn test_init_rejects_a_weak_password() {
+    let dir = TempDir::new().unwrap();
+    let output = command(&dir, &["init", "--kdf-preset", "interactive"])
+        .env("ENJECT_PASSWORD", "password123")
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("too weak"), "{}", stderr);
+    assert!(!dir.path().join(".enject").exists());
+
+    let output = command(
+        &dir,
+        &[
+            "init",
+            "--kdf-preset",
+            "interactive",
+            "--allow-weak-password",
+        ],
+    )
+    .env("ENJECT_PASSWORD", "password123")
+    .stdin(Stdio::null())
+    .output()
+    .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+}
+
+#[test]
+fn test_init_kdf_preset_is_written_to_config() {
+    let dir = TempDir::new().unwrap();
+    let output = enject(&dir, &["init", "--kdf-preset", "interactive"]);
+    assert!(output.status.success(), "{:?}", output);
+    let config = std::fs::read_to_string(dir.path().join(".enject").join("config.toml")).unwrap();
+    assert!(config.contains("m_cost = 19456"), "{}", config);
+    assert!(config.contains("t_cost = 2"), "{}", config);
+    assert!(config.contains("p_cost = 1"), "{}", config);
+
+    let output = enject(&dir, &["list"]);
+    assert!(output.status.success(), "{:?}", output);
+}
+
+#[test]
+fn test_init_with_xchacha_cipher() {
+    let dir = TempDir::new().unwrap();
+    let output = enject(
+        &dir,
+        &[
+            "init",
+            "--kdf-preset",
+            "interactive",
+            "--cipher",
+            "xchacha20",
+        ],
+    );
+    assert!(output.status.success(), "{:?}", output);
+    let config = std::fs::read_to_string(dir.path().join(".enject").join("config.toml")).unwrap();
+    assert!(config.contains("cipher = \"xchacha20\""), "{}", config);
+
+    let output = enject(&dir, &["list"]);
+    assert!(output.status.success(), "{:?}", output);
+}
+
+#[cfg(not(feature = "keyring"))]
+#[test]
+fn test_init_keyring_backend_needs_the_feature() {
+    let dir = TempDir::new().unwrap();
+    let output = enject(&dir, &["init", "--backend", "keyring"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("--features keyring"), "{}", stderr);
+    assert!(!dir.path().join(".enject").exists());
+}
+
+#[test]
+fn test_rotate_refuses_a_keyring_store() {
+    let dir = TempDir::new().unwrap();
+    let output = enject(&dir, &["init", "--kdf-preset", "interactive"]);
+    assert!(output.status.success(), "{:?}", output);
+    let path = dir.path().join(".enject").join("config.toml");
+    let config = std::fs::read_to_string(&path).unwrap();
+    let config = config.replace("backend = \"password\"", "backend = \"keyring\"");
+    std::fs::write(&path, config + "project_id = \"0123\"\n").unwrap();
+
+    let output = enject(&dir, &["rotate", "--keep-password"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("'keyring' backend"), "{}", stderr);
+}
+
+#[test]
+fn test_store_dir_outside_the_project() {
+    let dir = TempDir::new().unwrap();
+    let store_dir = TempDir::new().unwrap();
+    let elsewhere = store_dir.path().join("enject-store");
+    let run = |args: &[&str], env_dir: Option<&std::path::Path>| {
+        let mut command = command(&dir, args);
+        command
+            .env("ENJECT_PASSWORD", PASSWORD)
+            .stdin(Stdio::null());
+        if let Some(env_dir) = env_dir {
+            command.env("ENJECT_DIR", env_dir);
+        }
+        command.output().unwrap()
+    };
+
+    let output = run(&["list"], Some(&elsewhere));
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("has no config.toml"), "{}", stderr);
+
+    // init creates the directory
+    let output = run(&["init", "--kdf-preset", "interactive"], Some(&elsewhere));
+    assert!(output.status.success(), "{:?}", output);
+    assert!(elsewhere.join("config.toml").exists());
+    assert!(elsewhere.join("store").exists());
+    assert!(!dir.path().join(".enject").exists());
+
+    let output = run(&["list"], Some(&elsewhere));
+    assert!(output.status.success(), "{:?}", output);
+
+    // --store-dir wins over ENJECT_DIR
+    let flag = elsewhere.to_str().unwrap();
+    let output = run(
+        &["--store-dir", flag, "list"],
+        Some(&store_dir.path().join("missing")),
+    );
+    assert!(output.status.success(), "{:?}", output);
+}
+
+#[test]
+fn test_init_adds_the_store_to_gitignore() {
+    let dir = TempDir::new().unwrap();
+    std::fs::create_dir(dir.path().join(".git")).unwrap();
+    std::fs::write(dir.path().join(".gitignore"), "target/").unwrap();
+
+    let output = enject(&dir, &["init", "--kdf-preset", "interactive"]);
+    assert!(output.status.success(), "{:?}", output);
+    let gitignore = std::fs::read_to_string(dir.path().join(".gitignore")).unwrap();
+    assert_eq!(
+        gitignore,
+        "target/\n# enject\n.enject/\n.enject.old-*/\n.env.bak\n*.env.bak\n"
+    );
+}
+
+#[test]
+fn test_init_leaves_gitignore_alone_when_asked_or_outside_git() {
+    let dir = TempDir::new().unwrap();
+    std::fs::write(dir.path().join(".gitignore"), "target/\n").unwrap();
+    let output = enject(&dir, &["init", "--kdf-preset", "interactive"]);
+    assert!(output.status.success(), "{:?}", output);
+    let gitignore = std::fs::read_to_string(dir.path().join(".gitignore")).unwrap();
+    assert_eq!(gitignore, "target/\n");
+
+    let dir = TempDir::new().unwrap();
+    std::fs::create_dir(dir.path().join(".git")).unwrap();
+    let output = enject(
+        &dir,
+        &["init", "--kdf-preset", "interactive", "--no-gitignore"],
+    );
+    assert!(output.status.success(), "{:?}", output);
+    assert!(!dir.path().join(".gitignore").exists());
+
+    // Without a .gitignore, a non-interactive init only suggests one
+    let dir = TempDir::new().unwrap();
+    std::fs::create_dir(dir.path().join(".git")).unwrap();
+    let output = enject(&dir, &["init", "--kdf-preset", "interactive"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert!(!dir.path().join(".gitignore").exists());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("No .gitignore found"));
+}
+
+/// `enject` with `answer` piped to stdin.
+fn enject_answering(dir: &TempDir, args: &[&str], answer: &str) -> Output {
+    output_with_stdin(
+        command(dir, args).env("ENJECT_PASSWORD", PASSWORD),
+        answer.as_bytes(),
+    )
+}
+
+#[test]
+fn test_init_force_archives_the_old_store() {
+    let dir = TempDir::new().unwrap();
+    let output = enject(&dir, &["init", "--kdf-preset", "interactive"]);
+    assert!(output.status.success(), "{:?}", output);
+    let output = enject_answering(&dir, &["set", "api_key"], "abc");
+    assert!(output.status.success(), "{:?}", output);
+    let old_store = std::fs::read(dir.path().join(".enject").join("store")).unwrap();
+
+    let output = enject(&dir, &["init"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--force"));
+
+    // Without the right name, nothing changes
+    let output = enject(&dir, &["init", "--force"]);
+    assert!(!output.status.success());
+    let output = enject_answering(&dir, &["init", "--force"], "wrong-name\n");
+    assert!(!output.status.success());
+    assert_eq!(
+        std::fs::read(dir.path().join(".enject").join("store")).unwrap(),
+        old_store
+    );
+
+    let name = dir.path().file_name().unwrap().to_str().unwrap();
+    let output = enject_answering(
+        &dir,
+        &["init", "--force", "--kdf-preset", "interactive"],
+        &format!("{}\n", name),
+    );
+    assert!(output.status.success(), "{:?}", output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Secrets: 1"), "{}", stderr);
+
+    let archives: Vec<_> = std::fs::read_dir(dir.path())
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| {
+            path.file_name()
+                .unwrap()
+                .to_string_lossy()
+                .starts_with(".enject.old-")
+        })
+        .collect();
+    assert_eq!(archives.len(), 1, "{:?}", archives);
+    assert_eq!(std::fs::read(archives[0].join("store")).unwrap(), old_store);
+    assert!(archives[0].join("config.toml").exists());
+
+    let output = enject(&dir, &["list", "--quiet"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert!(!String::from_utf8_lossy(&output.stdout).contains("api_key"));
+}
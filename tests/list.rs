@@ -0,0 +1,138 @@
+//! End-to-end tests for `enject list` against a throwaway project directory.
+
+use std::process::Output;
+
+use tempfile::TempDir;
+
+mod common;
+use common::{setup_project_with_env, PASSWORD};
+
+/// Run enject with the password in the environment and `stdin` piped in.
+fn enject(dir: &TempDir, args: &[&str], stdin: &[u8]) -> Output {
+    common::enject(dir, args, PASSWORD, stdin)
+}
+
+#[test]
+fn test_json_lists_names_and_store_without_values() {
+    let dir = setup_project_with_env("PORT=3000\n");
+    assert!(enject(&dir, &["set", "api_key"], b"s3cret-value\n")
+        .status
+        .success());
+
+    let output = enject(&dir, &["list", "--format", "json"], b"");
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(!stdout.contains("s3cret-value"));
+    let listing: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    assert_eq!(listing["backend"], "password");
+    assert!(listing["store"].as_str().unwrap().ends_with("store"));
+    assert_eq!(listing["secrets"][0]["name"], "api_key");
+    let updated = listing["secrets"][0]["updated_at"].as_str().unwrap();
+    assert_eq!(listing["secrets"][0]["created_at"], updated);
+
+    let output = enject(&dir, &["list", "--long"], b"");
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        format!("api_key  {}\n", &updated[..10])
+    );
+}
+
+#[test]
+fn test_empty_store_json_and_quiet() {
+    let dir = setup_project_with_env("PORT=3000\n");
+    let output = enject(&dir, &["list", "--format", "json"], b"");
+    assert!(output.status.success(), "{:?}", output);
+    let listing: serde_json::Value =
+        serde_json::from_str(&String::from_utf8_lossy(&output.stdout)).unwrap();
+    assert_eq!(listing["secrets"], serde_json::json!([]));
+
+    let output = enject(&dir, &["list", "--quiet"], b"");
+    assert!(output.status.success(), "{:?}", output);
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn test_filter_by_pattern_and_prefix() {
+    let dir = setup_project_with_env("PORT=3000\n");
+    for key in ["stripe_sk", "db_url", "stripe_pk"] {
+        assert!(enject(&dir, &["set", key], b"value\n").status.success());
+    }
+
+    let output = enject(&dir, &["list", "stripe_*"], b"");
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "stripe_pk\nstripe_sk\n"
+    );
+
+    let output = enject(&dir, &["list", "--prefix", "db_"], b"");
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "db_url\n");
+
+    let output = enject(&dir, &["list", "--prefix", "sentry_"], b"");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn test_unused_and_missing() {
+    let dir = setup_project_with_env("DB=en://db_url\nAPI=en://api_key\n");
+    std::fs::write(dir.path().join(".env.staging"), "SENTRY=en://sentry_dsn\n").unwrap();
+    for key in ["db_url", "old_token", "sentry_dsn"] {
+        assert!(enject(&dir, &["set", key], b"value\n").status.success());
+    }
+
+    let output = enject(&dir, &["list", "--unused"], b"");
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "old_token\n");
+
+    let output = enject(&dir, &["list", "--missing"], b"");
+    assert!(!output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "api_key\n");
+
+    assert!(enject(&dir, &["set", "api_key"], b"value\n")
+        .status
+        .success());
+    let output = enject(&dir, &["list", "--missing"], b"");
+    assert!(output.status.success(), "{:?}", output);
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn test_tags_descriptions_and_info() {
+    let dir = setup_project_with_env("PORT=3000\n");
+    let args = [
+        "set",
+        "svc_token_2",
+        "--description",
+        "Billing service token",
+        "--tag",
+        "prod",
+        "--tag",
+        "billing",
+    ];
+    assert!(enject(&dir, &args, b"first-value\n").status.success());
+    assert!(enject(&dir, &["set", "dev_token"], b"other\n")
+        .status
+        .success());
+    // A new value keeps the description and tags
+    let output = enject(&dir, &["set", "svc_token_2", "--force"], b"second-value\n");
+    assert!(output.status.success(), "{:?}", output);
+
+    let output = enject(&dir, &["info", "svc_token_2"], b"");
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("Description: Billing service token"),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("Tags:        billing, prod"), "{}", stdout);
+    assert!(!stdout.contains("value"));
+
+    let output = enject(&dir, &["list", "--tag", "prod"], b"");
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "svc_token_2\n");
+
+    let output = enject(&dir, &["info", "gone"], b"");
+    assert!(!output.status.success());
+}
@@ -0,0 +1,117 @@
+//! End-to-end tests for `enject member`: sharing a store without sharing a password.
+
+use std::process::Output;
+
+use tempfile::TempDir;
+
+mod common;
+use common::{command, output_with_stdin};
+
+const ALICE_PASSWORD: &str = "test-password-do-not-use";
+const BOB_PASSWORD: &str = "bob-test-password-do-not-use";
+
+/// Run enject with `password` as the store password, `stdin` piped in, and HOME
+/// inside the project so no real identity or keychain is picked up.
+fn enject(dir: &TempDir, args: &[&str], password: &str, stdin: &[u8]) -> Output {
+    output_with_stdin(
+        command(dir, args)
+            .env("HOME", dir.path())
+            .env_remove("XDG_CONFIG_HOME")
+            .env("ENJECT_PASSWORD", password)
+            .env("ENJECT_MEMBER_PASSWORD", BOB_PASSWORD),
+        stdin,
+    )
+}
+
+#[cfg(not(feature = "age"))]
+#[test]
+fn test_member_needs_the_age_feature() {
+    let dir = TempDir::new().unwrap();
+    let output = enject(
+        &dir,
+        &["init", "--kdf-preset", "interactive"],
+        ALICE_PASSWORD,
+        b"",
+    );
+    assert!(output.status.success(), "{:?}", output);
+    let output = enject(
+        &dir,
+        &["member", "add", "bob", "--as", "alice"],
+        ALICE_PASSWORD,
+        b"",
+    );
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("--features age"), "{}", stderr);
+}
+
+#[cfg(feature = "age")]
+#[test]
+fn test_converted_store_opens_with_each_members_password() {
+    let dir = TempDir::new().unwrap();
+    let output = enject(
+        &dir,
+        &["init", "--kdf-preset", "interactive"],
+        ALICE_PASSWORD,
+        b"",
+    );
+    assert!(output.status.success(), "{:?}", output);
+    let output = enject(&dir, &["set", "api_key"], ALICE_PASSWORD, b"abc");
+    assert!(output.status.success(), "{:?}", output);
+
+    let output = enject(
+        &dir,
+        &["member", "add", "bob", "--as", "alice"],
+        ALICE_PASSWORD,
+        b"",
+    );
+    assert!(output.status.success(), "{:?}", output);
+    let config = std::fs::read_to_string(dir.path().join(".enject").join("config.toml")).unwrap();
+    assert!(config.contains("backend = \"age\""), "{}", config);
+
+    for password in [ALICE_PASSWORD, BOB_PASSWORD] {
+        let output = enject(&dir, &["list"], password, b"");
+        assert!(output.status.success(), "{:?}", output);
+        assert!(String::from_utf8_lossy(&output.stdout).contains("api_key"));
+    }
+    let output = enject(&dir, &["list"], "not-a-member-password", b"");
+    assert!(!output.status.success());
+
+    let output = enject(&dir, &["member", "list"], ALICE_PASSWORD, b"");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("alice  (password)"), "{}", stdout);
+    assert!(stdout.contains("bob  (password)"), "{}", stdout);
+}
+
+#[cfg(feature = "age")]
+#[test]
+fn test_removed_member_loses_access() {
+    let dir = TempDir::new().unwrap();
+    let output = enject(
+        &dir,
+        &["init", "--kdf-preset", "interactive"],
+        ALICE_PASSWORD,
+        b"",
+    );
+    assert!(output.status.success(), "{:?}", output);
+    let output = enject(
+        &dir,
+        &["member", "add", "bob", "--as", "alice"],
+        ALICE_PASSWORD,
+        b"",
+    );
+    assert!(output.status.success(), "{:?}", output);
+
+    let output = enject(&dir, &["member", "remove", "bob"], ALICE_PASSWORD, b"");
+    assert!(output.status.success(), "{:?}", output);
+    assert!(!dir.path().join(".enject/members/bob.key").exists());
+    let output = enject(&dir, &["list"], BOB_PASSWORD, b"");
+    assert!(!output.status.success());
+    let output = enject(&dir, &["rotate"], ALICE_PASSWORD, b"");
+    assert!(output.status.success(), "{:?}", output);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("new data key"));
+
+    let output = enject(&dir, &["member", "remove", "alice"], ALICE_PASSWORD, b"");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("last member"));
+}
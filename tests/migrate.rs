@@ -0,0 +1,97 @@
+//! End-to-end tests for `enject migrate`, and that no other command migrates.
+
+use std::process::Output;
+
+use tempfile::TempDir;
+
+mod common;
+use common::{project_in, PASSWORD};
+
+/// A project with its store in `.enveil/` and `ev://` references in `.env` and
+/// `.env.staging`.
+fn setup_legacy_project() -> TempDir {
+    let dir = project_in(".enveil");
+    std::fs::write(dir.path().join(".env"), "API_KEY=ev://api_key\n").unwrap();
+    std::fs::write(
+        dir.path().join(".env.staging"),
+        "API_KEY=ev://api_key\nDB=en://db\n",
+    )
+    .unwrap();
+    dir
+}
+
+/// Run enject with the password in the environment and `stdin` piped in.
+fn enject(dir: &TempDir, args: &[&str], stdin: &[u8]) -> Output {
+    common::enject(dir, args, PASSWORD, stdin)
+}
+
+fn read(dir: &TempDir, name: &str) -> String {
+    std::fs::read_to_string(dir.path().join(name)).unwrap()
+}
+
+#[test]
+fn test_migrate_yes_renames_and_rewrites_with_backups() {
+    let dir = setup_legacy_project();
+    let output = enject(&dir, &["set", "api_key"], b"sk-test-123\n");
+    assert!(output.status.success(), "{:?}", output);
+
+    let output = enject(&dir, &["migrate", "--yes"], b"");
+    assert!(output.status.success(), "{:?}", output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("rename"), "{}", stderr);
+    assert!(stderr.contains(".env.staging"), "{}", stderr);
+
+    assert!(!dir.path().join(".enveil").exists());
+    assert!(dir.path().join(".enject").join("store").exists());
+    assert!(dir.path().join(".enveil.bak").join("store").exists());
+    assert_eq!(read(&dir, ".env"), "API_KEY=en://api_key\n");
+    assert_eq!(read(&dir, ".env.bak"), "API_KEY=ev://api_key\n");
+    assert_eq!(
+        read(&dir, ".env.staging"),
+        "API_KEY=en://api_key\nDB=en://db\n"
+    );
+    assert_eq!(
+        read(&dir, ".env.staging.bak"),
+        "API_KEY=ev://api_key\nDB=en://db\n"
+    );
+
+    // The migrated project opens without warnings
+    let output = enject(&dir, &["run", "--", "printenv", "API_KEY"], b"");
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "sk-test-123\n");
+    assert!(!String::from_utf8_lossy(&output.stderr).contains("Warning"));
+
+    let output = enject(&dir, &["migrate"], b"");
+    assert!(output.status.success(), "{:?}", output);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Nothing to migrate."));
+}
+
+#[test]
+fn test_migrate_without_yes_needs_a_terminal() {
+    let dir = setup_legacy_project();
+    let output = enject(&dir, &["migrate"], b"y\n");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--yes"));
+
+    assert!(dir.path().join(".enveil").exists());
+    assert!(!dir.path().join(".enject").exists());
+    assert_eq!(read(&dir, ".env"), "API_KEY=ev://api_key\n");
+    assert!(!dir.path().join(".env.bak").exists());
+}
+
+#[test]
+fn test_run_leaves_piped_stdin_to_the_command() {
+    let dir = setup_legacy_project();
+    let output = enject(&dir, &["set", "api_key"], b"sk-test-123\n");
+    assert!(output.status.success(), "{:?}", output);
+
+    // Nothing competes with the command for stdin
+    let output = enject(&dir, &["run", "--", "cat"], b"y\nsecond line\n");
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "y\nsecond line\n");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Run `enject migrate`"), "{}", stderr);
+
+    assert!(dir.path().join(".enveil").exists());
+    assert_eq!(read(&dir, ".env"), "API_KEY=ev://api_key\n");
+}
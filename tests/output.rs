@@ -0,0 +1,96 @@
+//! End-to-end tests that warnings stay off stdout, and for the global `--color` flag.
+
+use std::process::Output;
+
+use tempfile::TempDir;
+
+mod common;
+use common::{command, output_with_stdin, project_in, PASSWORD};
+
+/// A project whose store is still in the legacy `.enveil/` and whose .env uses
+/// legacy `ev://` references, so every command that loads them warns.
+fn setup_legacy_project() -> TempDir {
+    let dir = project_in(".enveil");
+    std::fs::write(dir.path().join(".env"), "API_KEY=ev://api_key\n").unwrap();
+    dir
+}
+
+/// Run enject with the password in the environment, `stdin` piped in, and color
+/// left to `--color` alone.
+fn enject(dir: &TempDir, args: &[&str], stdin: &[u8]) -> Output {
+    output_with_stdin(
+        command(dir, args)
+            .env("ENJECT_PASSWORD", PASSWORD)
+            .env_remove("NO_COLOR")
+            .env_remove("CLICOLOR_FORCE"),
+        stdin,
+    )
+}
+
+fn json(output: &Output) -> serde_json::Value {
+    assert!(output.status.success(), "{:?}", output);
+    serde_json::from_slice(&output.stdout).unwrap()
+}
+
+#[test]
+fn test_machine_output_stays_clean_with_legacy_warnings() {
+    let dir = setup_legacy_project();
+    let output = enject(&dir, &["set", "api_key"], b"s3cret-value\n");
+    assert!(output.status.success(), "{:?}", output);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Warning: found legacy .enveil/"));
+
+    let output = enject(&dir, &["list"], b"");
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "api_key\n");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Warning:"));
+
+    let listing = json(&enject(&dir, &["list", "--format", "json"], b""));
+    assert!(listing.to_string().contains("api_key"));
+
+    let output = enject(&dir, &["check", "--format", "json"], b"");
+    assert!(serde_json::from_slice::<serde_json::Value>(&output.stdout).is_ok());
+
+    // `run` hands stdout to the command, which here prints nothing
+    let output = enject(&dir, &["run", "--", "true"], b"");
+    assert!(output.status.success(), "{:?}", output);
+    assert!(output.stdout.is_empty(), "{:?}", output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("legacy ev:// reference(s)"), "{}", stderr);
+
+    // Only `enject migrate` rewrites anything
+    assert!(dir.path().join(".enveil").exists());
+    assert_eq!(
+        std::fs::read_to_string(dir.path().join(".env")).unwrap(),
+        "API_KEY=ev://api_key\n"
+    );
+}
+
+#[test]
+fn test_color_flag() {
+    let dir = setup_legacy_project();
+
+    // Not a terminal: no color unless asked for
+    let output = enject(&dir, &["set", "api_key"], b"s3cret-value\n");
+    assert!(!output.stdout.contains(&0x1b), "{:?}", output);
+    assert!(!output.stderr.contains(&0x1b), "{:?}", output);
+
+    let output = enject(&dir, &["--color", "always", "set", "other"], b"value\n");
+    assert!(output.stdout.starts_with(b"\x1b["), "{:?}", output);
+    assert!(output.stderr.starts_with(b"\x1b["), "{:?}", output);
+
+    let output = enject(&dir, &["list", "--color=never"], b"");
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "api_key\nother\n");
+    assert!(!output.stderr.contains(&0x1b));
+
+    // JSON is never colored
+    let output = enject(
+        &dir,
+        &["--color", "always", "list", "--format", "json"],
+        b"",
+    );
+    assert!(!output.stdout.contains(&0x1b));
+    json(&output);
+
+    let output = enject(&dir, &["--color", "sometimes", "list"], b"");
+    assert_eq!(output.status.code(), Some(2));
+}
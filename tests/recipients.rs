@@ -0,0 +1,137 @@
+//! End-to-end tests for age-encrypted stores and `enject recipients`.
+
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+use tempfile::TempDir;
+
+/// Run enject with `stdin` piped in and HOME inside the project, so no real
+/// identity or global store is picked up.
+fn enject(dir: &TempDir, args: &[&str], stdin: &[u8]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_enject"))
+        .args(args)
+        .current_dir(dir.path())
+        .env("HOME", dir.path())
+        .env_remove("XDG_CONFIG_HOME")
+        .env("ENJECT_PASSWORD", "test-password-do-not-use")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(stdin).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[cfg(not(feature = "age"))]
+#[test]
+fn test_init_age_backend_needs_the_feature() {
+    let dir = TempDir::new().unwrap();
+    let recipient = "age1ql3z7hjy54pw3hyww5ayyfg7zqgvc7w3j2elw8zmrj2kg5sfn9aqmcac8p";
+    let output = enject(
+        &dir,
+        &["init", "--backend", "age", "--recipient", recipient],
+        b"",
+    );
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("--features age"), "{}", stderr);
+    assert!(!dir.path().join(".enject").exists());
+}
+
+#[test]
+fn test_recipients_refuses_a_password_store() {
+    let dir = TempDir::new().unwrap();
+    let output = enject(&dir, &["init", "--kdf-preset", "interactive"], b"");
+    assert!(output.status.success(), "{:?}", output);
+
+    let output = enject(&dir, &["recipients", "list"], b"");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("'password' backend"), "{}", stderr);
+}
+
+#[cfg(feature = "age")]
+mod age_store {
+    use super::*;
+    use age::secrecy::ExposeSecret;
+    use std::path::PathBuf;
+
+    /// A fresh age identity saved outside the default search paths, and its recipient.
+    fn identity(dir: &TempDir, name: &str) -> (PathBuf, String) {
+        let identity = age::x25519::Identity::generate();
+        let path = dir.path().join(name);
+        std::fs::write(&path, identity.to_string().expose_secret()).unwrap();
+        (path, identity.to_public().to_string())
+    }
+
+    #[test]
+    fn test_init_needs_a_recipient() {
+        let dir = TempDir::new().unwrap();
+        let output = enject(&dir, &["init", "--backend", "age"], b"");
+        assert!(!output.status.success());
+        assert!(String::from_utf8_lossy(&output.stderr).contains("--recipient"));
+        assert!(!dir.path().join(".enject").exists());
+    }
+
+    #[test]
+    fn test_added_and_removed_recipients() {
+        let dir = TempDir::new().unwrap();
+        let (alice_key, alice) = identity(&dir, "alice.txt");
+        let (bob_key, bob) = identity(&dir, "bob.txt");
+        let alice_key = alice_key.to_str().unwrap();
+        let bob_key = bob_key.to_str().unwrap();
+
+        let output = enject(
+            &dir,
+            &["init", "--backend", "age", "--recipient", &alice],
+            b"",
+        );
+        assert!(output.status.success(), "{:?}", output);
+        let output = enject(&dir, &["set", "api_key", "--identity", alice_key], b"abc");
+        assert!(output.status.success(), "{:?}", output);
+
+        let output = enject(
+            &dir,
+            &["recipients", "add", &bob, "--identity", alice_key],
+            b"",
+        );
+        assert!(output.status.success(), "{:?}", output);
+        let output = enject(&dir, &["list", "--identity", bob_key], b"");
+        assert!(output.status.success(), "{:?}", output);
+        assert!(String::from_utf8_lossy(&output.stdout).contains("api_key"));
+
+        let output = enject(
+            &dir,
+            &["recipients", "remove", &alice, "--identity", bob_key],
+            b"",
+        );
+        assert!(output.status.success(), "{:?}", output);
+        let output = enject(&dir, &["recipients", "list"], b"");
+        assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), bob);
+        let output = enject(&dir, &["list", "--identity", alice_key], b"");
+        assert!(!output.status.success());
+    }
+
+    #[test]
+    fn test_default_identity_is_found() {
+        let dir = TempDir::new().unwrap();
+        std::fs::create_dir_all(dir.path().join(".config").join("age")).unwrap();
+        let (_, recipient) = identity(&dir, ".config/age/keys.txt");
+        // Files that aren't identities are passed over
+        std::fs::write(dir.path().join(".config/age/recipients.txt"), &recipient).unwrap();
+
+        let output = enject(
+            &dir,
+            &["init", "--backend", "age", "--recipient", &recipient],
+            b"",
+        );
+        assert!(output.status.success(), "{:?}", output);
+        let output = enject(&dir, &["set", "api_key"], b"abc");
+        assert!(output.status.success(), "{:?}", output);
+        let output = enject(&dir, &["status", "--unlock"], b"");
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(stdout.contains("age (1 recipient(s))"), "{}", stdout);
+        assert!(stdout.contains("Secrets:     1"), "{}", stdout);
+    }
+}
@@ -0,0 +1,955 @@
+//! End-to-end tests for `enject run` against a throwaway project directory.
+
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+use tempfile::TempDir;
+
+mod common;
+use common::setup_project_with_env;
+
+fn run_with_stdin(dir: &TempDir, args: &[&str], stdin: &[u8]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_enject"))
+        .args(args)
+        .current_dir(dir.path())
+        .env_remove("ENJECT_PASSWORD")
+        .env_remove("ENJECT_GLOBAL_PASSWORD")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(stdin).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[cfg(unix)]
+#[test]
+fn test_piped_password_line_is_not_passed_to_child() {
+    let dir = setup_project_with_env("PORT=3000\n");
+    let output = run_with_stdin(
+        &dir,
+        &["run", "--", "cat"],
+        b"test-password-do-not-use\nfirst payload line\nsecond payload line\n",
+    );
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "first payload line\nsecond payload line\n"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_run_from_a_subdirectory_finds_the_project() {
+    let dir = setup_project_with_env("GREETING=hello-from-the-root\n");
+    let nested = dir.path().join("backend").join("tests");
+    std::fs::create_dir_all(&nested).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_enject"))
+        .args(["run", "--", "sh", "-c", "echo $GREETING"])
+        .current_dir(&nested)
+        .env("ENJECT_PASSWORD", "test-password-do-not-use")
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "hello-from-the-root\n"
+    );
+
+    let output = Command::new(env!("CARGO_BIN_EXE_enject"))
+        .args(["--no-discover", "run", "--", "true"])
+        .current_dir(&nested)
+        .env("ENJECT_PASSWORD", "test-password-do-not-use")
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_project_dir_flag_from_an_unrelated_cwd() {
+    let dir = setup_project_with_env("GREETING=hello-from-elsewhere\n");
+    let elsewhere = TempDir::new().unwrap();
+    std::fs::write(elsewhere.path().join("extra.env"), "EXTRA=from-cwd\n").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_enject"))
+        .arg("-C")
+        .arg(dir.path())
+        .args([
+            "run",
+            "--env-file",
+            "extra.env",
+            "--",
+            "sh",
+            "-c",
+            "echo $EXTRA",
+        ])
+        .current_dir(elsewhere.path())
+        .env("ENJECT_PASSWORD", "test-password-do-not-use")
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    // --env-file stays relative to the real cwd
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "from-cwd\n");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_enject"))
+        .arg("--project-dir")
+        .arg(dir.path())
+        .args(["run", "--", "sh", "-c", "echo $GREETING"])
+        .current_dir(elsewhere.path())
+        .env("ENJECT_PASSWORD", "test-password-do-not-use")
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "hello-from-elsewhere\n"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_child_sees_env_and_remaining_stdin() {
+    let dir = setup_project_with_env("GREETING=hello-from-enject\n");
+    let output = run_with_stdin(
+        &dir,
+        &["run", "--", "sh", "-c", "echo $GREETING; cat"],
+        b"test-password-do-not-use\npayload",
+    );
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "hello-from-enject\npayload"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_isolated_run_drops_the_parent_environment() {
+    let dir = setup_project_with_env("GREETING=hello\n");
+    let script = "echo \"$GREETING|${FROM_PARENT:-unset}|${KEPT:-unset}\"; test -n \"$PATH\"";
+    let run = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_enject"))
+            .args(args)
+            .args(["--", "sh", "-c", script])
+            .current_dir(dir.path())
+            .env("ENJECT_PASSWORD", "test-password-do-not-use")
+            .env("FROM_PARENT", "leaked")
+            .env("KEPT", "kept")
+            .stdin(Stdio::null())
+            .output()
+            .unwrap()
+    };
+
+    let output = run(&["run"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "hello|leaked|kept\n"
+    );
+
+    let output = run(&["run", "--isolated"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "hello|unset|unset\n"
+    );
+
+    let output = run(&["run", "--isolated", "--keep", "KEPT"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "hello|unset|kept\n"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_only_and_except_filter_template_variables() {
+    let dir = setup_project_with_env("PORT=3000\nHOST=localhost\nMODE=dev\n");
+    let script = "echo \"${PORT:-unset}|${HOST:-unset}|${MODE:-unset}\"";
+    let run = |args: &[&str]| {
+        run_with_stdin(
+            &dir,
+            &[&["run"], args, &["--", "sh", "-c", script]].concat(),
+            b"test-password-do-not-use\n",
+        )
+    };
+
+    let output = run(&["--only", "PORT", "--only", "HO*"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "3000|localhost|unset\n"
+    );
+
+    let output = run(&["--except", "MODE"]);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "3000|localhost|unset\n"
+    );
+
+    let output = run(&["--only", "DATABASE_URL"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("DATABASE_URL"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_mask_hides_secret_values_in_output() {
+    let dir = setup_project_with_env("API_KEY=en://api_key\nPORT=3000\n");
+    let mut child = Command::new(env!("CARGO_BIN_EXE_enject"))
+        .args(["set", "api_key"])
+        .current_dir(dir.path())
+        .env("ENJECT_PASSWORD", "test-password-do-not-use")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"sk_live_abcdef123")
+        .unwrap();
+    assert!(child.wait().unwrap().success());
+
+    let output = Command::new(env!("CARGO_BIN_EXE_enject"))
+        .args([
+            "run",
+            "--mask",
+            "--",
+            "sh",
+            "-c",
+            "echo key=$API_KEY port=$PORT; echo err=$API_KEY >&2; exit 5",
+        ])
+        .current_dir(dir.path())
+        .env("ENJECT_PASSWORD", "test-password-do-not-use")
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(5));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "key=***MASKED(API_KEY)*** port=3000\n"
+    );
+    assert!(String::from_utf8_lossy(&output.stderr).contains("err=***MASKED(API_KEY)***"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_all_injects_every_secret_with_the_template_on_top() {
+    let dir = setup_project_with_env("");
+    std::fs::remove_file(dir.path().join(".env")).unwrap();
+    for (key, value) in [("db_url", "postgres://db"), ("api.key", "sk_live_abc1")] {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_enject"))
+            .args(["set", key])
+            .current_dir(dir.path())
+            .env("ENJECT_PASSWORD", "test-password-do-not-use")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .spawn()
+            .unwrap();
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(value.as_bytes())
+            .unwrap();
+        assert!(child.wait().unwrap().success());
+    }
+    let run = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_enject"))
+            .args(args)
+            .args([
+                "--",
+                "sh",
+                "-c",
+                "echo \"$S_DB_URL|$S_API_KEY|${PORT:-unset}\"",
+            ])
+            .current_dir(dir.path())
+            .env("ENJECT_PASSWORD", "test-password-do-not-use")
+            .stdin(Stdio::null())
+            .output()
+            .unwrap()
+    };
+
+    // Without --all, .env is still required
+    assert!(!run(&["run"]).status.success());
+
+    let output = run(&["run", "--all", "--prefix", "S_"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "postgres://db|sk_live_abc1|unset\n"
+    );
+    assert!(String::from_utf8_lossy(&output.stderr).contains("'api.key' is injected as S_API_KEY"));
+
+    std::fs::write(dir.path().join(".env"), "PORT=3000\nS_DB_URL=overridden\n").unwrap();
+    let output = run(&["run", "--all", "--prefix", "S_"]);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "overridden|sk_live_abc1|3000\n"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_cwd_runs_the_command_elsewhere_with_the_project_template() {
+    let dir = setup_project_with_env("GREETING=from-the-root\n");
+    std::fs::create_dir_all(dir.path().join("packages").join("api")).unwrap();
+    let run = |cwd: &str, password: &str| {
+        Command::new(env!("CARGO_BIN_EXE_enject"))
+            .args(["run", "--cwd", cwd, "--", "sh", "-c", "echo $GREETING; pwd"])
+            .current_dir(dir.path())
+            .env("ENJECT_PASSWORD", password)
+            .stdin(Stdio::null())
+            .output()
+            .unwrap()
+    };
+
+    // A bad --cwd fails before the store is unlocked, even with a wrong password
+    let output = run("packages/web", "wrong-password");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("--cwd packages/web is not a directory"),
+        "{}",
+        stderr
+    );
+
+    let output = run("packages/api", "test-password-do-not-use");
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut lines = stdout.lines();
+    assert_eq!(lines.next(), Some("from-the-root"));
+    assert!(
+        lines.next().unwrap().ends_with("packages/api"),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn test_empty_stdin_is_an_error() {
+    let dir = setup_project_with_env("PORT=3000\n");
+    let output = run_with_stdin(&dir, &["run", "--", "cat"], b"");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("No password on stdin"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_env_file_from_stdin() {
+    let dir = setup_project_with_env("PORT=3000\n");
+    let output = Command::new(env!("CARGO_BIN_EXE_enject"))
+        .args([
+            "run",
+            "--env-file",
+            "-",
+            "--",
+            "sh",
+            "-c",
+            "echo $FROM_STDIN",
+        ])
+        .current_dir(dir.path())
+        .env("ENJECT_PASSWORD", "test-password-do-not-use")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .and_then(|mut child| {
+            child
+                .stdin
+                .take()
+                .unwrap()
+                .write_all(b"FROM_STDIN=generated\n")?;
+            child.wait_with_output()
+        })
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "generated\n");
+}
+
+#[test]
+fn test_missing_explicit_env_file_is_an_error() {
+    let dir = setup_project_with_env("PORT=3000\n");
+    let output = run_with_stdin(
+        &dir,
+        &["run", "--env-file", "config/.env.missing", "--", "true"],
+        b"test-password-do-not-use\n",
+    );
+    assert!(!output.status.success());
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("Env file not found: config/.env.missing")
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_layered_env_files_later_overrides_earlier() {
+    let dir = setup_project_with_env("PORT=3000\nLOG=info\n");
+    std::fs::write(dir.path().join(".env.local"), "PORT=4000\n").unwrap();
+    let output = run_with_stdin(
+        &dir,
+        &[
+            "run",
+            "--env-file",
+            ".env",
+            "--env-file",
+            ".env.local",
+            "--",
+            "sh",
+            "-c",
+            "echo $PORT $LOG",
+        ],
+        b"test-password-do-not-use\n",
+    );
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "4000 info\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_profile_layers_env_profile_file() {
+    let dir = setup_project_with_env("PORT=3000\nLOG=info\n");
+    std::fs::write(dir.path().join(".env.staging"), "LOG=warn\n").unwrap();
+    let output = run_with_stdin(
+        &dir,
+        &[
+            "run",
+            "--profile",
+            "staging",
+            "--",
+            "sh",
+            "-c",
+            "echo $PORT $LOG",
+        ],
+        b"test-password-do-not-use\n",
+    );
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "3000 warn\n");
+}
+
+#[test]
+fn test_missing_profile_file_is_an_error() {
+    let dir = setup_project_with_env("PORT=3000\n");
+    let output = run_with_stdin(
+        &dir,
+        &["run", "--profile", "prod", "--", "true"],
+        b"test-password-do-not-use\n",
+    );
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Profile 'prod' not found"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_procfile_runs_processes_together_and_stops_on_first_exit() {
+    let dir = setup_project_with_env("GREETING=hello\n");
+    std::fs::write(
+        dir.path().join("Procfile"),
+        "# dev\nweb: echo \"web $GREETING\"; exec sleep 30\nworker: sleep 0.5; echo failing; exit 3\n",
+    )
+    .unwrap();
+    let started = std::time::Instant::now();
+    let output = run_with_stdin(
+        &dir,
+        &["run", "--procfile", "Procfile"],
+        b"test-password-do-not-use\n",
+    );
+    assert!(started.elapsed() < std::time::Duration::from_secs(10));
+    assert_eq!(output.status.code(), Some(3), "{:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("web    | web hello\n"), "{}", stdout);
+    assert!(stdout.contains("worker | failing\n"), "{}", stdout);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("worker exited"));
+
+    std::fs::write(dir.path().join("Procfile"), "web npm start\n").unwrap();
+    let output = run_with_stdin(
+        &dir,
+        &["run", "--procfile", "Procfile"],
+        b"test-password-do-not-use\n",
+    );
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("line 1"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_run_expands_scripts_from_config() {
+    let dir = setup_project_with_env("GREETING=hello\n");
+    let config = dir.path().join(".enject").join("config.toml");
+    let mut content = std::fs::read_to_string(&config).unwrap();
+    content.push_str(
+        "\n[scripts]\ngreet = [\"sh\", \"-c\", \"echo $GREETING $1\", \"sh\"]\nshout = \"echo $GREETING | tr a-z A-Z\"\n",
+    );
+    std::fs::write(&config, content).unwrap();
+    let run = |args: &[&str]| run_with_stdin(&dir, args, b"test-password-do-not-use\n");
+
+    let output = run(&["run", "greet", "world"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "hello world\n");
+
+    let output = run(&["run", "shout"]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "HELLO\n");
+
+    // Not a script name: the arguments are the command
+    let output = run(&["run", "echo", "-n", "plain"]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "plain");
+
+    let output = run(&["run", "--list-scripts"]);
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("greet  sh -c echo $GREETING $1 sh\n"),
+        "{}",
+        stdout
+    );
+    assert!(
+        stdout.contains("shout  echo $GREETING | tr a-z A-Z\n"),
+        "{}",
+        stdout
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_timeout_stops_a_hung_command_with_exit_code_124() {
+    let dir = setup_project_with_env("PORT=3000\n");
+    let started = std::time::Instant::now();
+    let output = run_with_stdin(
+        &dir,
+        &[
+            "run",
+            "--timeout",
+            "1",
+            "--kill-after",
+            "500ms",
+            "--",
+            "sh",
+            "-c",
+            "trap '' TERM; while :; do sleep 0.1; done",
+        ],
+        b"test-password-do-not-use\n",
+    );
+    assert!(started.elapsed() < std::time::Duration::from_secs(10));
+    assert_eq!(output.status.code(), Some(124), "{:?}", output);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("timed out after 1s"));
+
+    let output = run_with_stdin(
+        &dir,
+        &["run", "--timeout", "30s", "--", "sh", "-c", "exit 3"],
+        b"test-password-do-not-use\n",
+    );
+    assert_eq!(output.status.code(), Some(3));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_shell_runs_a_string_with_the_users_shell() {
+    let dir = setup_project_with_env("GREETING=hello\n");
+    let shell = |shell: Option<&str>| {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_enject"));
+        command
+            .args([
+                "run",
+                "--shell",
+                "echo \"$GREETING\" && echo \"$0\" && exit 7",
+            ])
+            .current_dir(dir.path())
+            .env("ENJECT_PASSWORD", "test-password-do-not-use")
+            .stdin(Stdio::null());
+        match shell {
+            Some(shell) => command.env("SHELL", shell),
+            None => command.env_remove("SHELL"),
+        };
+        command.output().unwrap()
+    };
+
+    let output = shell(Some("sh"));
+    assert_eq!(output.status.code(), Some(7), "{:?}", output);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "hello\nsh\n");
+
+    let output = shell(None);
+    assert_eq!(output.status.code(), Some(7), "{:?}", output);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "hello\n/bin/sh\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_subst_args_replaces_references_in_arguments() {
+    let dir = setup_project_with_env("PORT=3000\n");
+    let enject = |args: &[&str], stdin: &[u8]| {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_enject"))
+            .args(args)
+            .current_dir(dir.path())
+            .env("ENJECT_PASSWORD", "test-password-do-not-use")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child.stdin.take().unwrap().write_all(stdin).unwrap();
+        child.wait_with_output().unwrap()
+    };
+    assert!(enject(&["set", "api_token"], b"tok_123456\n")
+        .status
+        .success());
+
+    let output = enject(
+        &[
+            "run",
+            "--subst-args",
+            "--",
+            "echo",
+            "Bearer en://api_token",
+            "\\en://kept",
+        ],
+        b"",
+    );
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "Bearer tok_123456 en://kept\n"
+    );
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Warning: --subst-args"));
+
+    // Without the flag, arguments are passed as written
+    let output = enject(&["run", "--", "echo", "en://api_token"], b"");
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "en://api_token\n");
+
+    let output = enject(
+        &[
+            "run",
+            "--subst-args",
+            "--mask",
+            "--",
+            "echo",
+            "en://api_token",
+        ],
+        b"",
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "***MASKED(en://api_token)***\n"
+    );
+
+    let output = enject(&["run", "--subst-args", "--", "en://api_token"], b"");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("program name"));
+
+    let output = enject(
+        &[
+            "run",
+            "--subst-args",
+            "--",
+            "echo",
+            "en://nope",
+            "x=en://gone",
+        ],
+        b"",
+    );
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("enject set nope"), "{}", stderr);
+    assert!(stderr.contains("enject set gone"), "{}", stderr);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_every_missing_secret_is_reported_at_once() {
+    let dir =
+        setup_project_with_env("A=en://first\nB=en://present\nC=x:en://second\nD=en://first\n");
+    let enject = |args: &[&str], stdin: &[u8]| {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_enject"))
+            .args(args)
+            .current_dir(dir.path())
+            .env("ENJECT_PASSWORD", "test-password-do-not-use")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child.stdin.take().unwrap().write_all(stdin).unwrap();
+        child.wait_with_output().unwrap()
+    };
+    assert!(enject(&["set", "present"], b"value\n").status.success());
+
+    let output = enject(&["run", "--", "true"], b"");
+    assert_eq!(output.status.code(), Some(5), "{:?}", output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("2 secrets not found. Add them with:\n"),
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains("enject set first\n"), "{}", stderr);
+    assert!(stderr.contains("enject set second\n"), "{}", stderr);
+}
+
+/// Start `enject run --no-exec -- sh -c <script>` and wait until the script prints `ready`.
+#[cfg(unix)]
+fn spawn_until_ready(dir: &TempDir, script: &str) -> std::process::Child {
+    use std::io::{BufRead, BufReader};
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_enject"))
+        .args(["run", "--no-exec", "--", "sh", "-c", script])
+        .current_dir(dir.path())
+        .env("ENJECT_PASSWORD", "test-password-do-not-use")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut line = String::new();
+    BufReader::new(child.stdout.as_mut().unwrap())
+        .read_line(&mut line)
+        .unwrap();
+    assert_eq!(line, "ready\n");
+    child
+}
+
+#[cfg(unix)]
+fn send_signal(child: &std::process::Child, signal: &str) {
+    let status = Command::new("kill")
+        .args([&format!("-{}", signal), &child.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(status.success());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_sigterm_is_forwarded_and_exit_code_is_128_plus_signal() {
+    let dir = setup_project_with_env("PORT=3000\n");
+    let mut child = spawn_until_ready(&dir, "echo ready; exec sleep 30");
+    let started = std::time::Instant::now();
+    send_signal(&child, "TERM");
+    let status = child.wait().unwrap();
+    assert!(started.elapsed() < std::time::Duration::from_secs(10));
+    // SIGTERM is 15
+    assert_eq!(status.code(), Some(143));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_watch_restarts_the_command_when_env_or_store_changes() {
+    use std::io::{BufRead, BufReader, Read};
+
+    let dir = setup_project_with_env("GREETING=hello\nTOKEN=en://token\n");
+    let enject = |args: &[&str], stdin: &[u8]| {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_enject"))
+            .args(args)
+            .current_dir(dir.path())
+            .env("ENJECT_PASSWORD", "test-password-do-not-use")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child.stdin.take().unwrap().write_all(stdin).unwrap();
+        child.wait_with_output().unwrap()
+    };
+    assert!(enject(&["set", "token"], b"first-token\n").status.success());
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_enject"))
+        .args([
+            "run",
+            "--watch",
+            "--",
+            "sh",
+            "-c",
+            "echo \"$GREETING $TOKEN\"; exec sleep 30",
+        ])
+        .current_dir(dir.path())
+        .env("ENJECT_PASSWORD", "test-password-do-not-use")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+    let mut next_line = || {
+        let mut line = String::new();
+        stdout.read_line(&mut line).unwrap();
+        line
+    };
+    assert_eq!(next_line(), "hello first-token\n");
+
+    std::fs::write(dir.path().join(".env"), "GREETING=bye\nTOKEN=en://token\n").unwrap();
+    assert_eq!(next_line(), "bye first-token\n");
+
+    let output = enject(&["set", "token", "--force"], b"second-token\n");
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(next_line(), "bye second-token\n");
+
+    // A template that doesn't resolve leaves the command running as it was
+    std::fs::write(dir.path().join(".env"), "TOKEN=en://missing\n").unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(1500));
+    std::fs::write(dir.path().join(".env"), "GREETING=back\nTOKEN=en://token\n").unwrap();
+    assert_eq!(next_line(), "back second-token\n");
+
+    send_signal(&child, "TERM");
+    let status = child.wait().unwrap();
+    assert_eq!(status.code(), Some(143));
+    let mut stderr = String::new();
+    child
+        .stderr
+        .take()
+        .unwrap()
+        .read_to_string(&mut stderr)
+        .unwrap();
+    assert!(stderr.contains("'missing'"), "{}", stderr);
+    assert!(stderr.contains("keeps running with its previous environment"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_child_handling_a_forwarded_signal_sets_the_exit_code() {
+    let dir = setup_project_with_env("PORT=3000\n");
+    let mut child = spawn_until_ready(
+        &dir,
+        "sleep 30 & pid=$!; trap 'kill $pid; exit 42' HUP; echo ready; wait",
+    );
+    send_signal(&child, "HUP");
+    assert_eq!(child.wait().unwrap().code(), Some(42));
+}
+
+#[cfg(windows)]
+#[test]
+fn test_windows_child_sees_env_through_cmd() {
+    let dir = setup_project_with_env("GREETING=hello-from-enject\n");
+    let output = run_with_stdin(
+        &dir,
+        &[
+            "run",
+            "--",
+            "cmd",
+            "/C",
+            "if %GREETING%==hello-from-enject (exit 0) else (exit 1)",
+        ],
+        b"test-password-do-not-use\n",
+    );
+    assert!(output.status.success(), "{:?}", output);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_child_exit_code_is_passed_through() {
+    let dir = setup_project_with_env("PORT=3000\n");
+    let output = run_with_stdin(
+        &dir,
+        &["run", "--", "sh", "-c", "exit 3"],
+        b"test-password-do-not-use\n",
+    );
+    assert_eq!(output.status.code(), Some(3));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_run_replaces_itself_with_the_command() {
+    let dir = setup_project_with_env("PORT=3000\n");
+    let child = Command::new(env!("CARGO_BIN_EXE_enject"))
+        .args(["run", "--", "sh", "-c", "echo $$"])
+        .current_dir(dir.path())
+        .env("ENJECT_PASSWORD", "test-password-do-not-use")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let pid = child.id();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout).trim(),
+        pid.to_string()
+    );
+}
+
+#[test]
+fn test_missing_command_is_a_readable_error() {
+    let dir = setup_project_with_env("PORT=3000\n");
+    for args in [
+        &["run", "--", "enject-no-such-command"][..],
+        &["run", "--no-exec", "--", "enject-no-such-command"][..],
+    ] {
+        let output = run_with_stdin(&dir, args, b"test-password-do-not-use\n");
+        assert!(!output.status.success());
+        assert!(
+            String::from_utf8_lossy(&output.stderr)
+                .contains("Command not found: enject-no-such-command"),
+            "{:?}",
+            output
+        );
+    }
+}
+
+#[test]
+fn test_duplicate_keys_are_an_error() {
+    let dir = setup_project_with_env("DB=one\nPORT=3000\nDB=two\n");
+    let output = run_with_stdin(&dir, &["run", "--", "true"], b"test-password-do-not-use\n");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("'DB' is assigned on line 1 and again on line 3"),
+        "{}",
+        stderr
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_allow_duplicates_lets_last_assignment_win() {
+    let dir = setup_project_with_env("DB=one\nDB=two\n");
+    let output = run_with_stdin(
+        &dir,
+        &["run", "--allow-duplicates", "--", "sh", "-c", "echo $DB"],
+        b"test-password-do-not-use\n",
+    );
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "two\n");
+}
+
+#[test]
+fn test_expired_secret_warns_and_strict_expiry_fails() {
+    let dir = setup_project_with_env("TOKEN=en://api_token\n");
+    let enject = |args: &[&str], stdin: &[u8]| {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_enject"))
+            .args(args)
+            .current_dir(dir.path())
+            .env("ENJECT_PASSWORD", "test-password-do-not-use")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child.stdin.take().unwrap().write_all(stdin).unwrap();
+        child.wait_with_output().unwrap()
+    };
+    let output = enject(&["set", "api_token", "--expires", "1s"], b"value\n");
+    assert!(output.status.success(), "{:?}", output);
+    std::thread::sleep(std::time::Duration::from_millis(1500));
+
+    let output = enject(&["run", "--", "true"], b"");
+    assert!(output.status.success(), "{:?}", output);
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("Warning: secret 'api_token' expired on")
+    );
+
+    let output = enject(&["run", "--strict-expiry", "--", "true"], b"");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("api_token"));
+
+    assert!(enject(&["check"], b"").status.success());
+    assert!(!enject(&["check", "--strict-expiry"], b"").status.success());
+
+    let output = enject(&["list"], b"");
+    assert!(String::from_utf8_lossy(&output.stdout).starts_with("api_token  (expired "));
+}
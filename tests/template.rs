@@ -0,0 +1,48 @@
+//! End-to-end tests for `enject template --example`.
+
+use std::process::{Command, Output};
+
+use tempfile::TempDir;
+
+fn enject(dir: &TempDir, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_enject"))
+        .args(args)
+        .current_dir(dir.path())
+        .env("ENJECT_NO_KEYCHAIN", "1")
+        .env_remove("ENJECT_DIR")
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn test_template_example_writes_and_refuses_to_overwrite() {
+    let dir = TempDir::new().unwrap();
+    std::fs::write(
+        dir.path().join(".env"),
+        "# App\nDATABASE_URL=en://db_url\nPORT=3000\n",
+    )
+    .unwrap();
+
+    let output = enject(&dir, &["template", "--example"]);
+    assert!(output.status.success(), "{:?}", output);
+    let example = std::fs::read_to_string(dir.path().join(".env.example")).unwrap();
+    assert_eq!(example, "# App\nDATABASE_URL=\nPORT=3000\n");
+
+    let output = enject(&dir, &["template", "--example"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--force"));
+
+    let output = enject(
+        &dir,
+        &[
+            "template",
+            "--example",
+            "--show-refs",
+            "--blank-all",
+            "--force",
+        ],
+    );
+    assert!(output.status.success(), "{:?}", output);
+    let example = std::fs::read_to_string(dir.path().join(".env.example")).unwrap();
+    assert_eq!(example, "# App\nDATABASE_URL=<db_url>\nPORT=\n");
+}
@@ -0,0 +1,46 @@
+//! End-to-end tests for `enject unlock` and `enject lock` against a throwaway project directory.
+
+use std::process::Output;
+
+use tempfile::TempDir;
+
+mod common;
+use common::{enject as enject_with_stdin, PASSWORD};
+
+/// A project whose store holds one secret.
+fn setup_project() -> TempDir {
+    let dir = common::setup_project();
+    let output = enject_with_stdin(&dir, &["set", "k"], PASSWORD, b"value\n");
+    assert!(output.status.success(), "{:?}", output);
+    dir
+}
+
+/// Run enject with `password` in the environment and stdin not a terminal.
+fn enject(dir: &TempDir, args: &[&str], password: &str) -> Output {
+    enject_with_stdin(dir, args, password, b"")
+}
+
+#[test]
+fn test_unlock_checks_the_password() {
+    let dir = setup_project();
+    let output = enject(&dir, &["unlock"], PASSWORD);
+    assert!(output.status.success(), "{:?}", output);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("password is correct"));
+
+    let output = enject(&dir, &["unlock"], "wrong-password");
+    assert!(!output.status.success());
+}
+
+#[cfg(not(feature = "keyring"))]
+#[test]
+fn test_remember_needs_keychain_support() {
+    let dir = setup_project();
+    let output = enject(&dir, &["unlock", "--remember"], PASSWORD);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("--features keyring"), "{}", stderr);
+
+    let output = enject(&dir, &["lock"], PASSWORD);
+    assert!(output.status.success(), "{:?}", output);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("No Enject store key is cached"));
+}
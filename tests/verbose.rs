@@ -0,0 +1,77 @@
+//! End-to-end tests for the global `--verbose` and `--quiet` flags.
+
+use std::process::Output;
+
+use tempfile::TempDir;
+
+mod common;
+use common::{setup_project_with_env, PASSWORD};
+
+const SECRET: &str = "s3cret-value-do-not-log";
+
+/// Run enject with the password in the environment and `stdin` piped in.
+fn enject(dir: &TempDir, args: &[&str], stdin: &[u8]) -> Output {
+    common::enject(dir, args, PASSWORD, stdin)
+}
+
+#[test]
+fn test_verbose_logs_names_and_timings_never_values() {
+    let dir = setup_project_with_env("# app\nAPI_KEY=en://api_key\nPORT=3000\n");
+    let output = enject(
+        &dir,
+        &["set", "api_key"],
+        format!("{}\n", SECRET).as_bytes(),
+    );
+    assert!(output.status.success(), "{:?}", output);
+
+    let output = enject(&dir, &["-v", "run", "--", "true"], b"");
+    assert!(output.status.success(), "{:?}", output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("store directory"), "{}", stderr);
+    assert!(stderr.contains("1 plain, 1 local ref"), "{}", stderr);
+    assert!(stderr.contains("Injecting 2 variable(s): API_KEY, PORT"));
+    assert!(!stderr.contains("Argon2id"));
+
+    let output = enject(&dir, &["-vv", "run", "--", "true"], b"");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Argon2id key derivation"), "{}", stderr);
+    assert!(stderr.contains("Decrypted"));
+    assert!(!stderr.contains(SECRET));
+
+    // Without -v nothing is logged
+    let output = enject(&dir, &["run", "--", "true"], b"");
+    assert!(output.stderr.is_empty(), "{:?}", output);
+}
+
+#[test]
+fn test_quiet_drops_confirmations_but_not_results() {
+    let dir = setup_project_with_env("PORT=3000\n");
+    let output = enject(&dir, &["--quiet", "set", "api_key"], b"value\n");
+    assert!(output.status.success(), "{:?}", output);
+    assert!(output.stdout.is_empty(), "{:?}", output);
+
+    let output = enject(&dir, &["list", "-q"], b"");
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "api_key\n");
+
+    let output = enject(&dir, &["-q", "delete", "api_key", "--force"], b"");
+    assert!(output.status.success(), "{:?}", output);
+    assert!(output.stdout.is_empty(), "{:?}", output);
+
+    let output = enject(&dir, &["-v", "-q", "list"], b"");
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn test_quiet_drops_run_notes_on_stderr() {
+    let dir = setup_project_with_env("PORT=3000\n");
+    let output = enject(&dir, &["set", "api.key"], b"value\n");
+    assert!(output.status.success(), "{:?}", output);
+
+    let output = enject(&dir, &["run", "--all", "--", "true"], b"");
+    assert!(output.status.success(), "{:?}", output);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("'api.key' is injected as API_KEY"));
+
+    let output = enject(&dir, &["-q", "run", "--all", "--", "true"], b"");
+    assert!(output.status.success(), "{:?}", output);
+    assert!(output.stderr.is_empty(), "{:?}", output);
+}
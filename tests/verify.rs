@@ -0,0 +1,35 @@
+//! End-to-end tests for `enject verify`.
+
+mod common;
+use common::{enject, setup_project, PASSWORD};
+
+#[test]
+fn test_verify_reports_the_secret_count() {
+    let dir = setup_project();
+    for key in ["a", "b"] {
+        let output = enject(&dir, &["set", key], PASSWORD, b"value");
+        assert!(output.status.success(), "{:?}", output);
+    }
+    let output = enject(&dir, &["verify", "--deep"], PASSWORD, b"");
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("2 secret(s)"), "{}", stdout);
+    assert!(stdout.contains("bytes"), "{}", stdout);
+    assert!(!stdout.contains("value"));
+}
+
+#[test]
+fn test_verify_tells_a_wrong_password_from_a_corrupt_store() {
+    let dir = setup_project();
+    let output = enject(&dir, &["set", "a"], PASSWORD, b"value");
+    assert!(output.status.success(), "{:?}", output);
+
+    let output = enject(&dir, &["verify"], "wrong-password", b"");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("did not decrypt"));
+
+    std::fs::write(dir.path().join(".enject").join("store"), b"ENJ").unwrap();
+    let output = enject(&dir, &["verify"], PASSWORD, b"");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("store is corrupt"));
+}